@@ -13,6 +13,15 @@ CREATE TABLE IF NOT EXISTS channels (
   real_multiplier REAL NOT NULL DEFAULT 1.0,
   enabled INTEGER NOT NULL,
   auto_disabled_until_ms INTEGER NOT NULL DEFAULT 0,
+  model_map TEXT NOT NULL DEFAULT '{}',
+  weight INTEGER NOT NULL DEFAULT 1,
+  key_strategy TEXT NOT NULL DEFAULT 'round_robin' CHECK(key_strategy IN ('round_robin','least_used','random')),
+  failover_policy TEXT NULL,
+  timeouts TEXT NULL,
+  proxy_url TEXT NULL,
+  spend_limit TEXT NULL,
+  balance_spent_usd REAL NOT NULL DEFAULT 0,
+  rate_limit TEXT NULL,
   created_at_ms INTEGER NOT NULL,
   updated_at_ms INTEGER NOT NULL
 );
@@ -54,6 +63,7 @@ CREATE TABLE IF NOT EXISTS usage_events (
   route_id TEXT NULL,
   channel_id TEXT NOT NULL,
   model TEXT NULL,
+  upstream_model TEXT NULL,
   success INTEGER NOT NULL,
   http_status INTEGER NULL,
   error_kind TEXT NULL,
@@ -65,23 +75,112 @@ CREATE TABLE IF NOT EXISTS usage_events (
   total_tokens INTEGER NULL,
   cache_read_tokens INTEGER NULL,
   cache_write_tokens INTEGER NULL,
-  estimated_cost_usd TEXT NULL
+  estimated_cost_usd TEXT NULL,
+  key_fingerprint TEXT NULL,
+  cancelled INTEGER NOT NULL DEFAULT 0,
+  client_token_id TEXT NULL,
+  cache_hit INTEGER NOT NULL DEFAULT 0
 );
 
 CREATE INDEX IF NOT EXISTS idx_usage_ts ON usage_events(ts_ms);
 CREATE INDEX IF NOT EXISTS idx_usage_channel_ts ON usage_events(channel_id, ts_ms);
 CREATE INDEX IF NOT EXISTS idx_usage_success_ts ON usage_events(success, ts_ms);
 
+CREATE TABLE IF NOT EXISTS usage_rollup_hourly (
+  bucket_start_ms INTEGER NOT NULL,
+  channel_id TEXT NOT NULL,
+  model TEXT NOT NULL DEFAULT '',
+  protocol TEXT NOT NULL,
+  route_id TEXT NOT NULL DEFAULT '',
+  requests INTEGER NOT NULL DEFAULT 0,
+  requests_success INTEGER NOT NULL DEFAULT 0,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  attempts_success INTEGER NOT NULL DEFAULT 0,
+  latency_sum_ms INTEGER NOT NULL DEFAULT 0,
+  latency_count INTEGER NOT NULL DEFAULT 0,
+  prompt_tokens INTEGER NOT NULL DEFAULT 0,
+  completion_tokens INTEGER NOT NULL DEFAULT 0,
+  total_tokens INTEGER NOT NULL DEFAULT 0,
+  cost_usd REAL NOT NULL DEFAULT 0,
+  latency_hist TEXT NOT NULL DEFAULT '[]',
+  latency_max_ms INTEGER NOT NULL DEFAULT 0,
+  ttft_hist TEXT NOT NULL DEFAULT '[]',
+  ttft_max_ms INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (bucket_start_ms, channel_id, model, protocol, route_id)
+);
+
 CREATE TABLE IF NOT EXISTS channel_failures (
   id INTEGER PRIMARY KEY AUTOINCREMENT,
   channel_id TEXT NOT NULL,
+  key_fp TEXT NULL,
   at_ms INTEGER NOT NULL
 );
 
 CREATE INDEX IF NOT EXISTS idx_channel_failures_channel_ts ON channel_failures(channel_id, at_ms);
 
+CREATE TABLE IF NOT EXISTS channel_keys (
+  channel_id TEXT NOT NULL,
+  key_fp TEXT NOT NULL,
+  use_count INTEGER NOT NULL DEFAULT 0,
+  last_used_ms INTEGER NULL,
+  auto_disabled_until_ms INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (channel_id, key_fp)
+);
+
+CREATE TABLE IF NOT EXISTS client_tokens (
+  id TEXT PRIMARY KEY,
+  name TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  masked TEXT NOT NULL,
+  role TEXT NOT NULL DEFAULT 'proxy' CHECK(role IN ('proxy','read_only','admin')),
+  protocols TEXT NOT NULL DEFAULT '[]',
+  route_ids TEXT NOT NULL DEFAULT '[]',
+  enabled INTEGER NOT NULL DEFAULT 1,
+  expires_at_ms INTEGER NULL,
+  last_used_ms INTEGER NULL,
+  created_at_ms INTEGER NOT NULL,
+  updated_at_ms INTEGER NOT NULL,
+  requests_per_minute INTEGER NULL,
+  tokens_per_day INTEGER NULL,
+  usd_per_month REAL NULL
+);
+
 CREATE TABLE IF NOT EXISTS app_settings (
   key TEXT PRIMARY KEY,
   value TEXT NOT NULL,
   updated_at_ms INTEGER NOT NULL
 );
+
+CREATE TABLE IF NOT EXISTS response_cache (
+  cache_key TEXT PRIMARY KEY,
+  protocol TEXT NOT NULL,
+  model TEXT NULL,
+  channel_id TEXT NOT NULL,
+  body BLOB NOT NULL,
+  size_bytes INTEGER NOT NULL,
+  hits INTEGER NOT NULL DEFAULT 0,
+  created_at_ms INTEGER NOT NULL,
+  expires_at_ms INTEGER NOT NULL,
+  last_hit_ms INTEGER NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_response_cache_expires ON response_cache(expires_at_ms);
+
+CREATE TABLE IF NOT EXISTS request_captures (
+  request_id TEXT PRIMARY KEY,
+  ts_ms INTEGER NOT NULL,
+  protocol TEXT NOT NULL,
+  method TEXT NOT NULL,
+  path TEXT NOT NULL,
+  request_headers TEXT NOT NULL,
+  request_body BLOB NOT NULL,
+  request_truncated INTEGER NOT NULL DEFAULT 0,
+  channel_id TEXT NULL,
+  response_status INTEGER NULL,
+  response_sse INTEGER NOT NULL DEFAULT 0,
+  response_body BLOB NULL,
+  response_truncated INTEGER NOT NULL DEFAULT 0,
+  completed_at_ms INTEGER NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_request_captures_ts ON request_captures(ts_ms);
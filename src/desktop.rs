@@ -434,6 +434,9 @@ fn handle_user_event(
                         &serde_json::json!({ "at_ms": at_ms }),
                     );
                 }
+                AppEvent::ChannelSpendWarning(warning) => {
+                    dispatch_custom_event(webview, "cliswitch-channel-spend-warning", &warning);
+                }
             }
         }
     }
@@ -1,13 +1,28 @@
 use std::sync::{Mutex, OnceLock};
 
+use serde::Serialize;
 use tokio::sync::broadcast;
 
+use crate::storage::{RechargeCurrency, SpendLimitKind};
 use crate::update;
 
 #[derive(Debug, Clone)]
 pub enum AppEvent {
     UpdateStatus(update::UpdateStatus),
     UsageChanged { at_ms: i64 },
+    ChannelSpendWarning(ChannelSpendWarning),
+}
+
+// 渠道花费达到提醒阈值；exhausted 表示已达上限、渠道已被跳过
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelSpendWarning {
+    pub channel_id: String,
+    pub channel_name: String,
+    pub kind: SpendLimitKind,
+    pub spent: f64,
+    pub limit: f64,
+    pub currency: RechargeCurrency,
+    pub exhausted: bool,
 }
 
 fn sender() -> &'static broadcast::Sender<AppEvent> {
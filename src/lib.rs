@@ -3,6 +3,7 @@ pub mod autostart;
 pub mod events;
 pub mod log_files;
 pub mod logging;
+pub mod metrics;
 pub mod proxy;
 pub mod server;
 pub mod storage;
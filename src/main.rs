@@ -95,6 +95,12 @@ async fn async_main() -> anyhow::Result<()> {
         .await
         .unwrap_or_default();
     logging::init(&data_dir, settings.log_level)?;
+    if !storage::channel_secrets_encrypted(&db_path) {
+        tracing::warn!(
+            "OS keyring unavailable, channel secrets are stored in plaintext; set {} to encrypt them with a passphrase",
+            storage::SECRET_PASSPHRASE_ENV
+        );
+    }
 
     match cmd {
         Command::Serve { port, open } => {
@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Mutex, OnceLock};
+
+use crate::storage::{self, Channel, CreateUsageEvent, Protocol};
+
+// 秒；覆盖从快速失败到长时间流式输出
+const LATENCY_BUCKETS: &[f64] = &[
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+const TTFT_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SeriesKey {
+    protocol: &'static str,
+    channel: String,
+    model: String,
+}
+
+impl SeriesKey {
+    fn new(protocol: Protocol, channel: &str, model: Option<&str>) -> Self {
+        Self {
+            protocol: protocol.as_str(),
+            channel: channel.to_string(),
+            model: model.unwrap_or("").to_string(),
+        }
+    }
+
+    fn labels(&self) -> Vec<(&'static str, &str)> {
+        vec![
+            ("protocol", self.protocol),
+            ("channel", &self.channel),
+            ("model", &self.model),
+        ]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    buckets: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, v: f64) {
+        for (bound, n) in self.buckets.iter().zip(self.counts.iter_mut()) {
+            if v <= *bound {
+                *n += 1;
+            }
+        }
+        self.sum += v;
+        self.count += 1;
+    }
+}
+
+// 只保存在进程内，重启后从 0 开始计数（Prometheus 会按计数器重置处理）
+#[derive(Debug, Default)]
+struct Registry {
+    requests: BTreeMap<(SeriesKey, String), u64>,
+    latency: BTreeMap<SeriesKey, Histogram>,
+    ttft: BTreeMap<SeriesKey, Histogram>,
+    tokens: BTreeMap<(SeriesKey, &'static str), u64>,
+    cost_usd: BTreeMap<SeriesKey, f64>,
+    streams_in_flight: BTreeMap<(&'static str, String), i64>,
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Registry::default()))
+}
+
+fn status_label(event: &CreateUsageEvent) -> String {
+    if event.cancelled {
+        return "cancelled".to_string();
+    }
+    match event.http_status {
+        Some(s) => s.to_string(),
+        None => "error".to_string(),
+    }
+}
+
+// 每条用量记录写库前调用一次
+pub fn observe_usage(event: &CreateUsageEvent) {
+    let key = SeriesKey::new(event.protocol, &event.channel_id, event.model.as_deref());
+    let mut reg = registry().lock().unwrap_or_else(|e| e.into_inner());
+    *reg.requests
+        .entry((key.clone(), status_label(event)))
+        .or_default() += 1;
+    if !event.cancelled {
+        reg.latency
+            .entry(key.clone())
+            .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+            .observe(event.latency_ms.max(0) as f64 / 1000.0);
+    }
+    if let Some(ttft) = event.ttft_ms {
+        reg.ttft
+            .entry(key.clone())
+            .or_insert_with(|| Histogram::new(TTFT_BUCKETS))
+            .observe(ttft.max(0) as f64 / 1000.0);
+    }
+    for (kind, v) in [
+        ("prompt", event.prompt_tokens),
+        ("completion", event.completion_tokens),
+        ("cache_read", event.cache_read_tokens),
+        ("cache_write", event.cache_write_tokens),
+    ] {
+        if let Some(v) = v.filter(|v| *v > 0) {
+            *reg.tokens.entry((key.clone(), kind)).or_default() += v as u64;
+        }
+    }
+}
+
+// 费用在写库时才按价格表估算，写入成功后再计入
+pub fn observe_cost(protocol: Protocol, channel_id: &str, model: Option<&str>, cost_usd: f64) {
+    if !cost_usd.is_finite() || cost_usd <= 0.0 {
+        return;
+    }
+    let key = SeriesKey::new(protocol, channel_id, model);
+    let mut reg = registry().lock().unwrap_or_else(|e| e.into_inner());
+    *reg.cost_usd.entry(key).or_default() += cost_usd;
+}
+
+pub fn stream_started(protocol: Protocol, channel_id: &str) {
+    let mut reg = registry().lock().unwrap_or_else(|e| e.into_inner());
+    *reg.streams_in_flight
+        .entry((protocol.as_str(), channel_id.to_string()))
+        .or_default() += 1;
+}
+
+pub fn stream_finished(protocol: Protocol, channel_id: &str) {
+    let mut reg = registry().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(n) = reg
+        .streams_in_flight
+        .get_mut(&(protocol.as_str(), channel_id.to_string()))
+    {
+        *n = (*n - 1).max(0);
+    }
+}
+
+fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_labels(out: &mut String, labels: &[(&str, &str)]) {
+    if labels.is_empty() {
+        return;
+    }
+    out.push('{');
+    for (i, (k, v)) in labels.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{k}=\"{}\"", escape_label(v));
+    }
+    out.push('}');
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    value: impl std::fmt::Display,
+) {
+    out.push_str(name);
+    write_labels(out, labels);
+    let _ = writeln!(out, " {value}");
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn write_histograms(out: &mut String, name: &str, series: &BTreeMap<SeriesKey, Histogram>) {
+    for (key, h) in series {
+        let labels = key.labels();
+        for (bound, n) in h.buckets.iter().zip(&h.counts) {
+            let le = bound.to_string();
+            let mut with_le = labels.clone();
+            with_le.push(("le", &le));
+            write_sample(out, &format!("{name}_bucket"), &with_le, n);
+        }
+        let mut with_le = labels.clone();
+        with_le.push(("le", "+Inf"));
+        write_sample(out, &format!("{name}_bucket"), &with_le, h.count);
+        write_sample(out, &format!("{name}_sum"), &labels, h.sum);
+        write_sample(out, &format!("{name}_count"), &labels, h.count);
+    }
+}
+
+// Prometheus 文本格式（0.0.4）；渠道状态类指标按传入的渠道列表现算
+pub fn render(channels: &[Channel], now_ms: i64) -> String {
+    let mut out = String::new();
+    let reg = registry().lock().unwrap_or_else(|e| e.into_inner());
+
+    write_header(
+        &mut out,
+        "cliswitch_requests_total",
+        "counter",
+        "Proxy attempts by result status.",
+    );
+    for ((key, status), n) in &reg.requests {
+        let mut labels = key.labels();
+        labels.push(("status", status));
+        write_sample(&mut out, "cliswitch_requests_total", &labels, n);
+    }
+
+    write_header(
+        &mut out,
+        "cliswitch_request_duration_seconds",
+        "histogram",
+        "Time from sending the upstream request to the end of the response.",
+    );
+    write_histograms(&mut out, "cliswitch_request_duration_seconds", &reg.latency);
+
+    write_header(
+        &mut out,
+        "cliswitch_ttft_seconds",
+        "histogram",
+        "Time to first streamed byte.",
+    );
+    write_histograms(&mut out, "cliswitch_ttft_seconds", &reg.ttft);
+
+    write_header(
+        &mut out,
+        "cliswitch_tokens_total",
+        "counter",
+        "Tokens reported by upstream usage.",
+    );
+    for ((key, kind), n) in &reg.tokens {
+        let mut labels = key.labels();
+        labels.push(("type", kind));
+        write_sample(&mut out, "cliswitch_tokens_total", &labels, n);
+    }
+
+    write_header(
+        &mut out,
+        "cliswitch_cost_usd_total",
+        "counter",
+        "Estimated cost in USD at official prices.",
+    );
+    for (key, v) in &reg.cost_usd {
+        write_sample(&mut out, "cliswitch_cost_usd_total", &key.labels(), v);
+    }
+
+    write_header(
+        &mut out,
+        "cliswitch_streams_in_flight",
+        "gauge",
+        "Responses currently being streamed to clients.",
+    );
+    for ((protocol, channel), n) in &reg.streams_in_flight {
+        write_sample(
+            &mut out,
+            "cliswitch_streams_in_flight",
+            &[("protocol", protocol), ("channel", channel)],
+            n,
+        );
+    }
+    drop(reg);
+
+    write_header(
+        &mut out,
+        "cliswitch_channel_info",
+        "gauge",
+        "Channel metadata; value is 1 when the channel is enabled.",
+    );
+    for c in channels {
+        write_sample(
+            &mut out,
+            "cliswitch_channel_info",
+            &[
+                ("channel", &c.id),
+                ("name", &c.name),
+                ("protocol", c.protocol.as_str()),
+            ],
+            u8::from(c.enabled),
+        );
+    }
+
+    write_header(
+        &mut out,
+        "cliswitch_channel_auto_disabled",
+        "gauge",
+        "Whether the channel is currently auto-disabled after repeated failures.",
+    );
+    for c in channels {
+        write_sample(
+            &mut out,
+            "cliswitch_channel_auto_disabled",
+            &[("channel", &c.id)],
+            u8::from(storage::channel_is_auto_disabled(c, now_ms)),
+        );
+    }
+    out
+}
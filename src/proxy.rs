@@ -6,41 +6,42 @@ use futures_util::TryStreamExt as _;
 use reqwest::Url;
 use std::path::Path;
 use std::sync::Arc;
-use std::time::Instant;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
-use crate::storage::{self, Channel, Protocol};
-
+use crate::metrics;
+use crate::storage::{self, Channel, FailoverPolicy, Protocol, TimeoutPolicy};
+
+mod access;
+mod budget;
+mod cache;
+mod capture;
+mod client;
+mod hedge;
+mod keys;
+mod otel;
+mod prebuffer;
+mod quota;
+mod ratelimit;
+mod routing;
 mod stream;
-
+mod timeout;
+mod translate;
+
+pub(crate) use access::presented_token;
+pub(crate) use client::{channel_egress, client_for, validate_proxy_url};
+pub(crate) use otel::validate_otlp_endpoint;
+pub(crate) use ratelimit::{RateLimitState, rate_limit_state};
+pub use routing::ResolvedChannels;
 use stream::{InstrumentedStream, StreamRecordContext};
 
 const MAX_INBOUND_BODY_BYTES: usize = 64 * 1024 * 1024;
 const MAX_JSON_CAPTURE_BYTES: usize = 8 * 1024 * 1024;
 const MAX_ERROR_DETAIL_BYTES: usize = 256 * 1024;
-
-struct AttemptCtx<'a> {
-    db_path: &'a Path,
-    settings: &'a storage::AppSettings,
-    protocol: Protocol,
-    channel_id: &'a str,
-    attempt: usize,
-    total: usize,
-}
-
-impl AttemptCtx<'_> {
-    async fn fail(&self, err: &ProxyError, msg: &'static str) {
-        maybe_record_failure(self.db_path, self.settings, self.channel_id).await;
-        tracing::warn!(
-            protocol = self.protocol.as_str(),
-            channel_id = %self.channel_id,
-            attempt = self.attempt,
-            total = self.total,
-            err = %err,
-            "{msg}"
-        );
-    }
-}
+// 仅用于标识调用方，不转发给上游
+const CLIENT_TOKEN_HEADER: &str = "x-cliswitch-token";
+const REQUEST_ID_HEADER: &str = "x-cliswitch-request-id";
 
 #[derive(thiserror::Error, Debug)]
 pub enum ProxyError {
@@ -48,16 +49,549 @@ pub enum ProxyError {
     NoEnabledChannel(Protocol),
     #[error("无可用渠道（可能被自动禁用）：{0}")]
     NoAvailableChannel(Protocol),
+    #[error("没有与模型 {1} 匹配的路由：{0}")]
+    NoMatchingRoute(Protocol, String),
     #[error("渠道 base_url 无效：{0}")]
     InvalidBaseUrl(String),
     #[error("读取请求体失败：{0}")]
     ReadBody(String),
+    #[error("协议转换失败：{0}")]
+    Translate(String),
     #[error("发送上游请求失败：{0}")]
     Upstream(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    QuotaExceeded(String),
     #[error(transparent)]
     Storage(#[from] anyhow::Error),
 }
 
+type UpstreamBody = futures_util::stream::BoxStream<'static, Result<Bytes, std::io::Error>>;
+
+// 上游响应拆成头和体，响应体可能已被预读过一部分
+struct UpstreamResponse {
+    status: reqwest::StatusCode,
+    headers: reqwest::header::HeaderMap,
+    content_length: Option<u64>,
+    body: UpstreamBody,
+}
+
+impl UpstreamResponse {
+    fn new(upstream: reqwest::Response, attempt: &Attempt<'_>) -> Self {
+        let status = upstream.status();
+        let headers = upstream.headers().clone();
+        ratelimit::observe(
+            &attempt.channel.id,
+            attempt.key.fingerprint.as_deref(),
+            &headers,
+            storage::now_ms(),
+        );
+        let content_length = upstream.content_length();
+        let body = upstream
+            .bytes_stream()
+            .map_err(std::io::Error::other)
+            .boxed();
+        Self {
+            status,
+            headers,
+            content_length,
+            body: timeout::limit_body(body, attempt.timeouts, attempt.started),
+        }
+    }
+}
+
+// 发往上游失败：传输层错误或等待响应头超时
+struct SendError {
+    kind: String,
+    detail: String,
+}
+
+impl SendError {
+    fn timeout(kind: &str, after: Duration) -> Self {
+        Self {
+            kind: format!("timeout:{kind}"),
+            detail: format!("等待上游 {}ms 后超时（{kind}）", after.as_millis()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for SendError {
+    fn from(e: reqwest::Error) -> Self {
+        let detail = e.to_string();
+        let kind = if e.is_timeout() && e.is_connect() {
+            "timeout:connect".to_string()
+        } else if e.is_timeout() {
+            "timeout:request".to_string()
+        } else {
+            format!("upstream_error:{}", truncate(&detail, 240))
+        };
+        Self { kind, detail }
+    }
+}
+
+// 一次入站请求在各渠道尝试之间共享的上下文
+struct ForwardCtx<'a> {
+    client: &'a reqwest::Client,
+    db_path: &'a Path,
+    settings: &'a storage::AppSettings,
+    protocol: Protocol,
+    protocol_root: &'static str,
+    parts: &'a axum::http::request::Parts,
+    body: &'a Bytes,
+    method: &'a reqwest::Method,
+    model: Option<&'a str>,
+    route_id: Option<String>,
+    request_id: Arc<str>,
+    client_token_id: Option<String>,
+    // count_tokens 不记录用量、不计失败、不做故障转移
+    record_usage: bool,
+    cache: Option<cache::CacheRequest>,
+    capture: bool,
+    // 本次请求已写入的用量记录条数，汇总表据此判断是否为请求的首条记录
+    recorded: AtomicUsize,
+}
+
+// 已准备好发往某个渠道的一次尝试
+struct Attempt<'c> {
+    channel: &'c Channel,
+    upstream_model: Option<String>,
+    key: keys::SelectedKey,
+    translation: Option<translate::Translation>,
+    timeouts: TimeoutPolicy,
+    started: Instant,
+}
+
+// 只在单次尝试内短暂存在，不必为体积装箱
+#[allow(clippy::large_enum_variant)]
+enum Prepared<'c> {
+    // 无需请求上游，直接在本地应答（如跨协议的 count_tokens）
+    Local(Bytes),
+    Upstream(Attempt<'c>, reqwest::RequestBuilder),
+}
+
+struct PrepareError {
+    err: ProxyError,
+    msg: &'static str,
+    // 为 Some 时计入渠道失败；请求本身无法转换时为 None
+    key: Option<keys::SelectedKey>,
+}
+
+impl ForwardCtx<'_> {
+    async fn prepare<'c>(&self, channel: &'c Channel) -> Result<Prepared<'c>, PrepareError> {
+        let started = Instant::now();
+        let protocol = self.protocol;
+        let model = self.model;
+
+        let upstream_model = model
+            .and_then(|m| channel.upstream_model_for(m))
+            .filter(|m| Some(*m) != model)
+            .map(str::to_string);
+        let (attempt_uri, attempt_body) = match upstream_model.as_deref() {
+            Some(to) => rewrite_model(
+                protocol,
+                &self.parts.headers,
+                &self.parts.uri,
+                self.body,
+                to,
+            ),
+            None => (self.parts.uri.clone(), self.body.clone()),
+        };
+
+        if channel.protocol != protocol
+            && let Some(bytes) =
+                translate::local_response(protocol, channel.protocol, &attempt_uri, &attempt_body)
+        {
+            return Ok(Prepared::Local(bytes));
+        }
+
+        let (translation, attempt_uri, attempt_body) = if channel.protocol == protocol {
+            (None, attempt_uri, attempt_body)
+        } else {
+            match translate::translate_request(
+                protocol,
+                channel.protocol,
+                &attempt_uri,
+                &attempt_body,
+            ) {
+                Ok(t) => (Some(t.translation), t.uri, t.body),
+                Err(err) => {
+                    return Err(PrepareError {
+                        err,
+                        msg: "proxy attempt skipped (translate request)",
+                        key: None,
+                    });
+                }
+            }
+        };
+        let upstream_root = match &translation {
+            Some(t) => t.upstream().root(),
+            None => self.protocol_root,
+        };
+
+        let key = keys::select_key(
+            self.db_path,
+            channel,
+            storage::now_ms(),
+            self.settings.auto_disable_enabled,
+        )
+        .await;
+
+        let mut url = match build_upstream_url(&channel.base_url, &attempt_uri, upstream_root) {
+            Ok(v) => v,
+            Err(err) => {
+                return Err(PrepareError {
+                    err,
+                    msg: "proxy attempt failed (build url)",
+                    key: Some(key),
+                });
+            }
+        };
+
+        let mut out_headers = filtered_headers(&self.parts.headers);
+        if let Err(err) = apply_auth(&key.token, channel.protocol, &mut url, &mut out_headers) {
+            return Err(PrepareError {
+                err,
+                msg: "proxy attempt failed (apply auth)",
+                key: Some(key),
+            });
+        }
+
+        let timeouts = channel.timeouts.unwrap_or(self.settings.timeouts);
+        let egress = channel_egress(channel, self.settings);
+        let client = match client_for(self.client, timeouts.connect_ms, &egress) {
+            Ok(c) => c,
+            Err(e) => {
+                return Err(PrepareError {
+                    err: ProxyError::Upstream(format!("代理配置无效：{e}")),
+                    msg: "proxy attempt failed (build client)",
+                    key: Some(key),
+                });
+            }
+        };
+        // 开启追踪时以本次尝试的 span 作为上游的父 span，否则原样透传客户端的 traceparent
+        if self.record_usage
+            && let Some(v) = otel::attempt_traceparent(&self.request_id, &channel.id)
+        {
+            out_headers.insert(otel::TRACEPARENT_HEADER, v);
+        }
+        let request = client
+            .request(self.method.clone(), url)
+            .headers(out_headers)
+            .body(attempt_body);
+        ratelimit::acquire(channel, key.fingerprint.as_deref(), storage::now_ms());
+        Ok(Prepared::Upstream(
+            Attempt {
+                channel,
+                upstream_model,
+                key,
+                translation,
+                timeouts,
+                started,
+            },
+            request,
+        ))
+    }
+
+    fn next_attempt_index(&self) -> usize {
+        self.recorded.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn usage_event(&self, attempt: &Attempt<'_>) -> UsageEventParams {
+        UsageEventParams {
+            request_id: Some(self.request_id.clone()),
+            attempt_index: self.next_attempt_index(),
+            protocol: self.protocol,
+            route_id: self.route_id.clone(),
+            client_token_id: self.client_token_id.clone(),
+            channel_id: attempt.channel.id.clone(),
+            model: self.model.map(str::to_string),
+            upstream_model: attempt.upstream_model.clone(),
+            key_fingerprint: attempt.key.fingerprint.clone(),
+            success: false,
+            cancelled: false,
+            http_status: None,
+            error_kind: None,
+            error_detail: None,
+            latency_ms: attempt.started.elapsed().as_millis() as i64,
+            ttft_ms: None,
+            tokens: (None, None, None, None, None),
+        }
+    }
+
+    async fn record_transport_failure(
+        &self,
+        attempt: &Attempt<'_>,
+        attempt_no: usize,
+        total: usize,
+        e: &SendError,
+    ) {
+        if self.policy(attempt.channel).count_transport_errors {
+            maybe_record_failure(
+                self.db_path,
+                self.settings,
+                &attempt.channel.id,
+                &attempt.key,
+            )
+            .await;
+        }
+        tracing::warn!(
+            protocol = self.protocol.as_str(),
+            channel_id = %attempt.channel.id,
+            attempt = attempt_no,
+            total = total,
+            err = %e.detail,
+            error_kind = %e.kind,
+            "proxy attempt failed (request error)"
+        );
+        spawn_usage_event(
+            build_usage_event(UsageEventParams {
+                error_kind: Some(e.kind.clone()),
+                error_detail: Some(truncate(&e.detail, 2000)),
+                ..self.usage_event(attempt)
+            }),
+            self.db_path.to_path_buf(),
+        );
+    }
+
+    fn policy<'p>(&'p self, channel: &'p Channel) -> &'p FailoverPolicy {
+        channel
+            .failover_policy
+            .as_ref()
+            .unwrap_or(&self.settings.failover_policy)
+    }
+
+    // 按策略决定非 2xx 是否计入渠道失败，并记下上游要求的 Retry-After
+    async fn note_http_failure(&self, attempt: &Attempt<'_>, upstream: &reqwest::Response) {
+        let policy = self.policy(attempt.channel);
+        let status = upstream.status().as_u16();
+        ratelimit::observe(
+            &attempt.channel.id,
+            attempt.key.fingerprint.as_deref(),
+            upstream.headers(),
+            storage::now_ms(),
+        );
+        if policy.honor_retry_after
+            && let Some(secs) = retry_after_secs(upstream.headers())
+        {
+            let until_ms = storage::now_ms().saturating_add(secs.saturating_mul(1000));
+            routing::note_retry_after(&attempt.channel.id, until_ms);
+        }
+        if policy.counts_as_failure(status) {
+            maybe_record_failure(
+                self.db_path,
+                self.settings,
+                &attempt.channel.id,
+                &attempt.key,
+            )
+            .await;
+        }
+    }
+
+    // SSE 在首个有效内容之前中断或超时，按传输层错误处理
+    async fn record_stream_failure(
+        &self,
+        attempt: &Attempt<'_>,
+        attempt_no: usize,
+        total: usize,
+        reason: &str,
+    ) {
+        if self.policy(attempt.channel).count_transport_errors {
+            maybe_record_failure(
+                self.db_path,
+                self.settings,
+                &attempt.channel.id,
+                &attempt.key,
+            )
+            .await;
+        }
+        tracing::warn!(
+            protocol = self.protocol.as_str(),
+            channel_id = %attempt.channel.id,
+            attempt = attempt_no,
+            total = total,
+            err = %reason,
+            "proxy stream failed before first content, retry next channel"
+        );
+        spawn_usage_event(
+            build_usage_event(UsageEventParams {
+                http_status: Some(200),
+                error_kind: Some(truncate(reason, 240)),
+                error_detail: Some(truncate(reason, 2000)),
+                ..self.usage_event(attempt)
+            }),
+            self.db_path.to_path_buf(),
+        );
+    }
+
+    async fn record_http_failure(&self, attempt: &Attempt<'_>, upstream: reqwest::Response) {
+        let status = upstream.status();
+        self.note_http_failure(attempt, &upstream).await;
+        let error_detail = read_error_detail(attempt.channel.protocol, upstream).await;
+        spawn_usage_event(
+            build_usage_event(UsageEventParams {
+                http_status: Some(status.as_u16() as i64),
+                error_kind: Some(format!("upstream_http:{}", status.as_u16())),
+                error_detail,
+                ..self.usage_event(attempt)
+            }),
+            self.db_path.to_path_buf(),
+        );
+    }
+
+    // 对冲请求中落败、被取消的一方：只记录，不计入渠道失败
+    fn record_cancelled(&self, attempt: &Attempt<'_>) {
+        spawn_usage_event(
+            build_usage_event(UsageEventParams {
+                cancelled: true,
+                error_kind: Some("hedge_cancelled".to_string()),
+                ..self.usage_event(attempt)
+            }),
+            self.db_path.to_path_buf(),
+        );
+    }
+
+    async fn finish(
+        &self,
+        attempt: Attempt<'_>,
+        upstream: reqwest::Response,
+    ) -> Result<Response<Body>, ProxyError> {
+        let upstream = UpstreamResponse::new(upstream, &attempt);
+        self.finish_upstream(attempt, upstream).await
+    }
+
+    async fn finish_upstream(
+        &self,
+        attempt: Attempt<'_>,
+        upstream: UpstreamResponse,
+    ) -> Result<Response<Body>, ProxyError> {
+        let channel = attempt.channel;
+        if self.record_usage && upstream.status.is_success() {
+            let cleared = match attempt
+                .key
+                .fingerprint
+                .clone()
+                .filter(|_| attempt.key.is_pooled())
+            {
+                Some(fp) => {
+                    storage::clear_key_failures(self.db_path.to_path_buf(), channel.id.clone(), fp)
+                        .await
+                }
+                None => {
+                    storage::clear_channel_failures(self.db_path.to_path_buf(), channel.id.clone())
+                        .await
+                }
+            };
+            if let Err(e) = cleared {
+                tracing::warn!(
+                    channel_id = %channel.id,
+                    err = %e,
+                    "clear channel failures failed"
+                );
+            }
+        }
+
+        proxy_upstream_response(
+            upstream,
+            StreamRecordContext {
+                db_path: self.db_path.to_path_buf(),
+                protocol: self.protocol,
+                upstream_protocol: channel.protocol,
+                route_id: self.route_id.clone(),
+                client_token_id: self.client_token_id.clone(),
+                channel_id: channel.id.clone(),
+                model: self.model.map(str::to_string),
+                upstream_model: attempt.upstream_model,
+                key_fingerprint: attempt.key.fingerprint,
+                request_id: self.request_id.clone(),
+                attempt_index: self.next_attempt_index(),
+                http_status: 0,
+                status_is_success: false,
+                started: attempt.started,
+                parse_sse: false, // 将在内部按 Content-Type 决定
+                record_usage: self.record_usage,
+                capture: self.capture,
+            },
+            attempt.translation,
+            self.cache
+                .as_ref()
+                .filter(|c| !c.stream)
+                .map(|c| cache::CacheFill {
+                    key: c.key.clone(),
+                    ttl_ms: self.settings.response_cache_ttl_secs.saturating_mul(1000),
+                    max_bytes: self
+                        .settings
+                        .response_cache_max_mb
+                        .saturating_mul(1024 * 1024),
+                }),
+        )
+        .await
+    }
+
+    async fn fail_prepare(
+        &self,
+        channel: &Channel,
+        e: &PrepareError,
+        attempt_no: usize,
+        total: usize,
+    ) {
+        match &e.key {
+            Some(key) if self.record_usage => {
+                maybe_record_failure(self.db_path, self.settings, &channel.id, key).await;
+            }
+            Some(_) => return,
+            None => {}
+        }
+        tracing::warn!(
+            protocol = self.protocol.as_str(),
+            channel_id = %channel.id,
+            attempt = attempt_no,
+            total = total,
+            err = %e.err,
+            "{}",
+            e.msg
+        );
+    }
+}
+
+fn is_event_stream(headers: &reqwest::header::HeaderMap) -> bool {
+    headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().starts_with("text/event-stream"))
+}
+
+// 按入站协议构造错误响应，客户端 SDK 可以照常解析
+pub fn error_response(
+    protocol: Protocol,
+    status: axum::http::StatusCode,
+    message: &str,
+) -> Response<Body> {
+    let body = translate::error_body(protocol, status, message);
+    let mut resp = Response::new(Body::from(body.to_string()));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(
+        axum::http::header::CONTENT_TYPE,
+        axum::http::HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+fn local_json_response(bytes: Bytes) -> Result<Response<Body>, ProxyError> {
+    Response::builder()
+        .status(axum::http::StatusCode::OK)
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(bytes))
+        .map_err(|e| ProxyError::Upstream(e.to_string()))
+}
+
+fn is_count_tokens(protocol: Protocol, uri: &axum::http::Uri) -> bool {
+    protocol == Protocol::Anthropic
+        && uri.path().trim_end_matches('/') == "/v1/messages/count_tokens"
+}
+
 pub async fn forward(
     client: &reqwest::Client,
     db_path: std::path::PathBuf,
@@ -65,43 +599,174 @@ pub async fn forward(
     protocol_root: &'static str,
     req: Request<Body>,
 ) -> Result<Response<Body>, ProxyError> {
-    let db_path_ref = db_path.as_path();
     let request_id: Arc<str> = Arc::from(Uuid::new_v4().to_string());
     let settings = storage::get_app_settings(db_path.clone()).await?;
-    let now_ms = storage::now_ms();
-    let channels = list_available_channels(db_path.clone(), protocol, now_ms, &settings).await?;
-    let total_channels = channels.len();
+    if !is_count_tokens(protocol, req.uri()) {
+        otel::begin_request(
+            &settings,
+            &request_id,
+            protocol,
+            req.method(),
+            req.uri(),
+            req.headers(),
+        );
+    }
+    let result = forward_request(
+        client,
+        db_path,
+        &settings,
+        protocol,
+        protocol_root,
+        request_id.clone(),
+        req,
+    )
+    .await;
+    otel::end_request(&request_id, &result);
+    result
+}
 
+async fn forward_request(
+    client: &reqwest::Client,
+    db_path: std::path::PathBuf,
+    settings: &storage::AppSettings,
+    protocol: Protocol,
+    protocol_root: &'static str,
+    request_id: Arc<str>,
+    req: Request<Body>,
+) -> Result<Response<Body>, ProxyError> {
     let (parts, body) = req.into_parts();
-    let is_count_tokens = protocol == Protocol::Anthropic
-        && parts.uri.path().trim_end_matches('/') == "/v1/messages/count_tokens";
+    let caller =
+        access::authenticate(&db_path, settings, protocol, &parts.headers, &parts.uri).await?;
+    let is_count_tokens = is_count_tokens(protocol, &parts.uri);
     let body_bytes = to_bytes(body, MAX_INBOUND_BODY_BYTES)
         .await
         .map_err(|e| ProxyError::ReadBody(e.to_string()))?;
 
     let model = extract_model(protocol, &parts.headers, &parts.uri, &body_bytes);
+    let capture = settings.capture_enabled && !is_count_tokens;
+    if capture {
+        capture::record_request(&db_path, &request_id, protocol, &parts, &body_bytes).await;
+    }
+
+    let now_ms = storage::now_ms();
+    let resolved = routing::resolve_channels(
+        db_path.clone(),
+        protocol,
+        model.as_deref(),
+        now_ms,
+        settings,
+    )
+    .await?;
+    let route_id = resolved.route_id();
+    access::authorize_route(settings, caller.as_ref(), route_id.as_deref())?;
+    // 请求体和路由权限都通过后才占用配额
+    if let Some(token) = &caller {
+        quota::check(&db_path, token, now_ms).await?;
+    }
+    let client_token_id = caller.map(|t| t.id);
+
+    let cache_req = (settings.response_cache_enabled && !is_count_tokens)
+        .then(|| {
+            cache::cache_request(
+                protocol,
+                &parts.uri,
+                model.as_deref(),
+                route_id.as_deref(),
+                client_token_id.as_deref(),
+                &body_bytes,
+            )
+        })
+        .flatten();
+    if let Some(req) = &cache_req {
+        let started = Instant::now();
+        match storage::get_cached_response(db_path.clone(), req.key.clone(), now_ms).await {
+            Ok(Some(hit)) => {
+                // 命中照常计入客户端令牌的请求数和 token 配额；没有请求上游，费用记为 0
+                let mut event = build_usage_event(UsageEventParams {
+                    request_id: Some(request_id.clone()),
+                    attempt_index: 0,
+                    protocol,
+                    route_id: route_id.clone(),
+                    client_token_id: client_token_id.clone(),
+                    channel_id: hit.channel_id.clone(),
+                    model: model.clone(),
+                    upstream_model: None,
+                    key_fingerprint: None,
+                    success: true,
+                    cancelled: false,
+                    http_status: Some(200),
+                    error_kind: None,
+                    error_detail: None,
+                    latency_ms: started.elapsed().as_millis() as i64,
+                    ttft_ms: None,
+                    tokens: parse_usage_from_json(protocol, &hit.body).as_event_fields(),
+                });
+                event.cache_hit = true;
+                event.estimated_cost_usd = Some("0".to_string());
+                spawn_usage_event(event, db_path.clone());
+                if capture {
+                    let mut buf = capture::CaptureBuf::default();
+                    buf.push(&hit.body);
+                    buf.spawn_store(db_path.clone(), &request_id, &hit.channel_id, 200, false);
+                }
+                return cache::hit_response(protocol, req, hit.body);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!(err = %e, "load cached response failed"),
+        }
+    }
+
+    let channels = resolved.channels;
 
     let method = reqwest::Method::from_bytes(parts.method.as_str().as_bytes())
         .map_err(|e| ProxyError::Upstream(format!("invalid method: {e}")))?;
 
+    let ctx = ForwardCtx {
+        client,
+        db_path: db_path.as_path(),
+        settings,
+        protocol,
+        protocol_root,
+        parts: &parts,
+        body: &body_bytes,
+        method: &method,
+        model: model.as_deref(),
+        route_id,
+        request_id,
+        client_token_id,
+        record_usage: !is_count_tokens,
+        cache: cache_req,
+        capture,
+        recorded: AtomicUsize::new(0),
+    };
+
+    forward_channels(&ctx, &channels).await
+}
+
+// 按顺序尝试各渠道，可重试的失败转到下一个
+async fn forward_channels(
+    ctx: &ForwardCtx<'_>,
+    channels: &[Channel],
+) -> Result<Response<Body>, ProxyError> {
+    let total_channels = channels.len();
     let mut last_err: Option<ProxyError> = None;
+    let mut skip = 0;
+    if hedge::should_hedge(ctx, total_channels) {
+        match hedge::forward_hedged(ctx, channels).await {
+            hedge::HedgeOutcome::Done(resp) => return resp,
+            hedge::HedgeOutcome::Fallthrough { consumed, err } => {
+                skip = consumed;
+                last_err = err;
+            }
+        }
+    }
 
-    for (idx, channel) in channels.into_iter().enumerate() {
+    for (idx, channel) in channels.iter().enumerate().skip(skip) {
         let is_last = idx + 1 >= total_channels;
-        let started = Instant::now();
 
-        let attempt_ctx = AttemptCtx {
-            db_path: db_path_ref,
-            settings: &settings,
-            protocol,
-            channel_id: channel.id.as_str(),
-            attempt: idx + 1,
-            total: total_channels,
-        };
-
-        if !is_count_tokens {
+        if ctx.record_usage {
             tracing::debug!(
-                protocol = protocol.as_str(),
+                protocol = ctx.protocol.as_str(),
                 channel_id = %channel.id,
                 attempt = idx + 1,
                 total = total_channels,
@@ -109,79 +774,28 @@ pub async fn forward(
             );
         }
 
-        let mut url = match build_upstream_url(&channel.base_url, &parts.uri, protocol_root) {
-            Ok(v) => v,
+        let (attempt, request) = match ctx.prepare(channel).await {
+            Ok(Prepared::Local(bytes)) => return local_json_response(bytes),
+            Ok(Prepared::Upstream(attempt, request)) => (attempt, request),
             Err(e) => {
-                if !is_count_tokens {
-                    attempt_ctx
-                        .fail(&e, "proxy attempt failed (build url)")
-                        .await;
-                }
-                last_err = Some(e);
-                if is_count_tokens || is_last {
+                ctx.fail_prepare(channel, &e, idx + 1, total_channels).await;
+                last_err = Some(e.err);
+                if !ctx.record_usage || is_last {
                     break;
                 }
                 continue;
             }
         };
 
-        let mut out_headers = filtered_headers(&parts.headers);
-        if let Err(e) = apply_auth(&channel, protocol, &mut url, &mut out_headers) {
-            if !is_count_tokens {
-                attempt_ctx
-                    .fail(&e, "proxy attempt failed (apply auth)")
-                    .await;
-            }
-            last_err = Some(e);
-            if is_count_tokens || is_last {
-                break;
-            }
-            continue;
-        }
-
-        let upstream = match client
-            .request(method.clone(), url)
-            .headers(out_headers)
-            .body(body_bytes.clone())
-            .send()
-            .await
-        {
+        let upstream = match timeout::send(request, attempt.timeouts, attempt.started).await {
             Ok(r) => r,
             Err(e) => {
-                if !is_count_tokens {
-                    maybe_record_failure(db_path_ref, &settings, &channel.id).await;
-                    tracing::warn!(
-                        protocol = protocol.as_str(),
-                        channel_id = %channel.id,
-                        attempt = idx + 1,
-                        total = total_channels,
-                        err = %e,
-                        "proxy attempt failed (request error)"
-                    );
-                    spawn_usage_event(
-                        build_usage_event(UsageEventParams {
-                            request_id: Some(request_id.clone()),
-                            protocol,
-                            channel_id: channel.id.clone(),
-                            model: model.clone(),
-                            success: false,
-                            http_status: None,
-                            error_kind: Some(format!(
-                                "upstream_error:{}",
-                                truncate(&e.to_string(), 240)
-                            )),
-                            error_detail: Some(truncate(&e.to_string(), 2000)),
-                            latency_ms: started.elapsed().as_millis() as i64,
-                            ttft_ms: None,
-                            tokens: (None, None, None, None, None),
-                        }),
-                        db_path.clone(),
-                    );
+                if ctx.record_usage {
+                    ctx.record_transport_failure(&attempt, idx + 1, total_channels, &e)
+                        .await;
                 }
-
-                let err = ProxyError::Upstream(e.to_string());
-                last_err = Some(err);
-                if is_count_tokens || is_last {
+                last_err = Some(ProxyError::Upstream(e.detail));
+                if !ctx.record_usage || is_last || !ctx.policy(channel).retry_transport_errors {
                     break;
                 }
                 continue;
@@ -189,105 +803,199 @@ pub async fn forward(
         };
 
         let status = upstream.status();
-        if !is_count_tokens && !status.is_success() {
-            maybe_record_failure(db_path_ref, &settings, &channel.id).await;
-        }
-        if !is_count_tokens && !status.is_success() && !is_last {
-            tracing::warn!(
-                protocol = protocol.as_str(),
-                channel_id = %channel.id,
-                attempt = idx + 1,
-                total = total_channels,
-                http_status = status.as_u16(),
-                "proxy attempt got non-2xx, retry next channel"
-            );
-            let error_detail = read_error_detail(protocol, upstream).await;
-            spawn_usage_event(
-                build_usage_event(UsageEventParams {
-                    request_id: Some(request_id.clone()),
-                    protocol,
-                    channel_id: channel.id.clone(),
-                    model: model.clone(),
-                    success: false,
-                    http_status: Some(status.as_u16() as i64),
-                    error_kind: Some(format!("upstream_http:{}", status.as_u16())),
-                    error_detail,
-                    latency_ms: started.elapsed().as_millis() as i64,
-                    ttft_ms: None,
-                    tokens: (None, None, None, None, None),
-                }),
-                db_path.clone(),
-            );
-            continue;
+        if ctx.record_usage && !status.is_success() {
+            // 不可重试的状态码（如请求本身有误）直接返回给客户端
+            if !is_last && ctx.policy(channel).should_retry(status.as_u16()) {
+                tracing::warn!(
+                    protocol = ctx.protocol.as_str(),
+                    channel_id = %channel.id,
+                    attempt = idx + 1,
+                    total = total_channels,
+                    http_status = status.as_u16(),
+                    "proxy attempt got non-2xx, retry next channel"
+                );
+                ctx.record_http_failure(&attempt, upstream).await;
+                continue;
+            }
+            ctx.note_http_failure(&attempt, &upstream).await;
         }
 
-        if !is_count_tokens
+        let mut upstream = UpstreamResponse::new(upstream, &attempt);
+        if ctx.record_usage
             && status.is_success()
-            && let Err(e) =
-                storage::clear_channel_failures(db_path.clone(), channel.id.clone()).await
+            && ctx.settings.stream_failover_enabled
+            && is_event_stream(&upstream.headers)
         {
-            tracing::warn!(
-                channel_id = %channel.id,
-                err = %e,
-                "clear channel failures failed"
-            );
+            let timeout =
+                Duration::from_millis(ctx.settings.stream_first_content_timeout_ms.max(1) as u64);
+            match prebuffer::until_first_content(channel.protocol, upstream.body, timeout).await {
+                prebuffer::Prebuffered::Ready(body) => upstream.body = body,
+                prebuffer::Prebuffered::Failed { reason, replay } => {
+                    if is_last || !ctx.policy(channel).retry_transport_errors {
+                        upstream.body = replay;
+                    } else {
+                        ctx.record_stream_failure(&attempt, idx + 1, total_channels, &reason)
+                            .await;
+                        last_err = Some(ProxyError::Upstream(reason));
+                        continue;
+                    }
+                }
+            }
         }
 
-        return proxy_upstream_response(
-            upstream,
-            StreamRecordContext {
-                db_path: db_path.clone(),
-                protocol,
-                channel_id: channel.id.clone(),
-                model: model.clone(),
-                request_id: request_id.clone(),
-                http_status: 0,
-                status_is_success: false,
-                started,
-                parse_sse: false, // 将在内部按 Content-Type 决定
-                record_usage: !is_count_tokens,
-            },
-        )
-        .await;
+        return ctx.finish_upstream(attempt, upstream).await;
     }
 
     Err(last_err.unwrap_or_else(|| ProxyError::Upstream("all channels failed".to_string())))
 }
 
-async fn list_available_channels(
+// 将抓包记录的请求经指定渠道重新发送：跳过客户端鉴权、路由和缓存，用量照常记录
+pub async fn replay(
+    client: &reqwest::Client,
     db_path: std::path::PathBuf,
-    protocol: Protocol,
-    now_ms: i64,
-    settings: &storage::AppSettings,
-) -> Result<Vec<Channel>, ProxyError> {
-    let channels = storage::list_channels(db_path).await?;
-    let enabled: Vec<Channel> = channels
-        .into_iter()
-        .filter(|c| c.enabled && c.protocol == protocol)
-        .collect();
-    if enabled.is_empty() {
-        return Err(ProxyError::NoEnabledChannel(protocol));
+    captured: storage::RequestCapture,
+    channel: Channel,
+) -> Result<Response<Body>, ProxyError> {
+    if captured.request_truncated {
+        return Err(ProxyError::ReadBody(
+            "抓包时请求体已被截断，无法重放".to_string(),
+        ));
     }
-
-    if !settings.auto_disable_enabled {
-        return Ok(enabled);
+    let request_id: Arc<str> = Arc::from(Uuid::new_v4().to_string());
+    let settings = storage::get_app_settings(db_path.clone()).await?;
+    let protocol = captured.protocol;
+
+    // 脱敏的请求头不再发送，渠道密钥由 apply_auth 重新填入
+    let mut builder = Request::builder()
+        .method(captured.method.as_str())
+        .uri(captured.path.as_str());
+    for (name, value) in &captured.request_headers {
+        if value != capture::REDACTED {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+    }
+    let (parts, ()) = builder
+        .body(())
+        .map_err(|e| ProxyError::ReadBody(format!("抓包记录无法还原为请求：{e}")))?
+        .into_parts();
+    let body_bytes = Bytes::from(captured.request_body);
+    let is_count_tokens = is_count_tokens(protocol, &parts.uri);
+    let model = extract_model(protocol, &parts.headers, &parts.uri, &body_bytes);
+    let capture = settings.capture_enabled && !is_count_tokens;
+    if capture {
+        capture::record_request(&db_path, &request_id, protocol, &parts, &body_bytes).await;
     }
+    let method = reqwest::Method::from_bytes(parts.method.as_str().as_bytes())
+        .map_err(|e| ProxyError::Upstream(format!("invalid method: {e}")))?;
 
-    let out: Vec<Channel> = enabled
-        .into_iter()
-        .filter(|c| !storage::channel_is_auto_disabled(c, now_ms))
-        .collect();
-    if out.is_empty() {
-        return Err(ProxyError::NoAvailableChannel(protocol));
+    let ctx = ForwardCtx {
+        client,
+        db_path: db_path.as_path(),
+        settings: &settings,
+        protocol,
+        protocol_root: protocol.root(),
+        parts: &parts,
+        body: &body_bytes,
+        method: &method,
+        model: model.as_deref(),
+        route_id: None,
+        request_id: request_id.clone(),
+        client_token_id: None,
+        record_usage: !is_count_tokens,
+        cache: None,
+        capture,
+        recorded: AtomicUsize::new(0),
+    };
+    if !is_count_tokens {
+        otel::begin_request(
+            &settings,
+            &request_id,
+            protocol,
+            &parts.method,
+            &parts.uri,
+            &parts.headers,
+        );
     }
-    Ok(out)
+    let result = forward_channels(&ctx, std::slice::from_ref(&channel)).await;
+    otel::end_request(&request_id, &result);
+    let mut resp = result?;
+    if let Ok(v) = axum::http::HeaderValue::from_str(&request_id) {
+        resp.headers_mut().insert(REQUEST_ID_HEADER, v);
+    }
+    Ok(resp)
+}
+
+pub async fn resolve_route(
+    db_path: std::path::PathBuf,
+    protocol: Protocol,
+    model: Option<&str>,
+) -> Result<ResolvedChannels, ProxyError> {
+    let settings = storage::get_app_settings(db_path.clone()).await?;
+    routing::resolve_channels(db_path, protocol, model, storage::now_ms(), &settings).await
 }
 
-async fn maybe_record_failure(db_path: &Path, settings: &storage::AppSettings, channel_id: &str) {
+// 只支持秒数形式；HTTP 日期形式忽略。上限一小时，避免异常值把渠道长期压后
+fn retry_after_secs(headers: &reqwest::header::HeaderMap) -> Option<i64> {
+    let secs = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<i64>()
+        .ok()?;
+    (secs > 0).then_some(secs.min(3600))
+}
+
+async fn maybe_record_failure(
+    db_path: &Path,
+    settings: &storage::AppSettings,
+    channel_id: &str,
+    key: &keys::SelectedKey,
+) {
     if !settings.auto_disable_enabled {
         return;
     }
     let now_ms = storage::now_ms();
+    if let Some(key_fp) = key.fingerprint.as_deref().filter(|_| key.is_pooled()) {
+        match storage::record_key_failure_and_maybe_disable(
+            db_path.to_path_buf(),
+            channel_id.to_string(),
+            key_fp.to_string(),
+            key.pool.clone(),
+            now_ms,
+            settings.auto_disable_window_minutes,
+            settings.auto_disable_failure_times,
+            settings.auto_disable_disable_minutes,
+        )
+        .await
+        {
+            Ok(Some(disabled)) => {
+                tracing::warn!(
+                    channel_id = channel_id,
+                    key_fp = key_fp,
+                    disabled_until_ms = disabled.key_until_ms,
+                    "channel key auto disabled"
+                );
+                if let Some(until_ms) = disabled.channel_until_ms {
+                    tracing::warn!(
+                        channel_id = channel_id,
+                        disabled_until_ms = until_ms,
+                        "channel auto disabled (all keys disabled)"
+                    );
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(
+                    channel_id = channel_id,
+                    key_fp = key_fp,
+                    err = %e,
+                    "record channel key failure failed"
+                );
+            }
+        }
+        return;
+    }
     match storage::record_channel_failure_and_maybe_disable(
         db_path.to_path_buf(),
         channel_id.to_string(),
@@ -317,16 +1025,18 @@ async fn maybe_record_failure(db_path: &Path, settings: &storage::AppSettings, c
 }
 
 async fn proxy_upstream_response(
-    upstream: reqwest::Response,
+    upstream: UpstreamResponse,
     mut ctx: StreamRecordContext,
+    translation: Option<translate::Translation>,
+    cache_fill: Option<cache::CacheFill>,
 ) -> Result<Response<Body>, ProxyError> {
-    let status = upstream.status();
+    let status = upstream.status;
     ctx.http_status = status.as_u16() as i64;
     ctx.status_is_success = status.is_success();
 
-    let headers = filtered_headers(upstream.headers());
+    let headers = filtered_headers(&upstream.headers);
     let content_type = upstream
-        .headers()
+        .headers
         .get(axum::http::header::CONTENT_TYPE)
         .and_then(|v| v.to_str().ok())
         .unwrap_or("")
@@ -344,24 +1054,66 @@ async fn proxy_upstream_response(
     }
 
     if !ctx.record_usage {
-        let stream = upstream
-            .bytes_stream()
-            .map_err(std::io::Error::other)
-            .boxed();
+        let stream = upstream.body.map_err(std::io::Error::other).boxed();
         return resp
             .body(Body::from_stream(stream))
             .map_err(|e| ProxyError::Upstream(e.to_string()));
     }
 
-    let can_capture_json = match upstream.content_length() {
-        Some(n) => (n as usize) <= MAX_JSON_CAPTURE_BYTES,
+    // 跨协议的非流式响应不论 Content-Type 都必须完整读取后转换
+    let must_translate = translation.is_some() && !is_sse;
+    let capture_limit = if translation.is_some() {
+        MAX_INBOUND_BODY_BYTES
+    } else {
+        MAX_JSON_CAPTURE_BYTES
+    };
+    let can_capture_json = match upstream.content_length {
+        Some(n) => (n as usize) <= capture_limit,
         None => true,
     };
-    if !is_sse && is_json && can_capture_json {
+    if must_translate && !can_capture_json {
+        return Ok(untranslatable_response(
+            &ctx,
+            status,
+            &too_large_to_translate(),
+        ));
+    }
+    if !is_sse && (is_json || must_translate) && can_capture_json {
         let (captured, remainder) =
-            read_stream_prefix_or_all(upstream.bytes_stream().boxed(), MAX_JSON_CAPTURE_BYTES)
-                .await
-                .map_err(|e| ProxyError::Upstream(e.to_string()))?;
+            match read_stream_prefix_or_all(upstream.body, capture_limit).await {
+                Ok(v) => v,
+                Err(e) => {
+                    let msg = e.to_string();
+                    let error_kind = if msg.starts_with("timeout:") {
+                        truncate(&msg, 240)
+                    } else {
+                        format!("stream_error:{}", truncate(&msg, 240))
+                    };
+                    spawn_usage_event(
+                        build_usage_event(UsageEventParams {
+                            request_id: Some(ctx.request_id.clone()),
+                            attempt_index: ctx.attempt_index,
+                            protocol: ctx.protocol,
+                            route_id: ctx.route_id.clone(),
+                            client_token_id: ctx.client_token_id.clone(),
+                            channel_id: ctx.channel_id.clone(),
+                            model: ctx.model.clone(),
+                            upstream_model: ctx.upstream_model.clone(),
+                            key_fingerprint: ctx.key_fingerprint.clone(),
+                            success: false,
+                            cancelled: false,
+                            http_status: Some(status.as_u16() as i64),
+                            error_kind: Some(error_kind),
+                            error_detail: Some(truncate(&msg, 2000)),
+                            latency_ms: ctx.started.elapsed().as_millis() as i64,
+                            ttft_ms: None,
+                            tokens: (None, None, None, None, None),
+                        }),
+                        ctx.db_path.clone(),
+                    );
+                    return Err(ProxyError::Upstream(msg));
+                }
+            };
 
         let Some(remainder) = remainder else {
             let bytes = captured;
@@ -375,7 +1127,7 @@ async fn proxy_upstream_response(
             let response_preview = truncate(&response_one_line, 4096);
 
             let duration_ms = ctx.started.elapsed().as_millis() as i64;
-            let usage = parse_usage_from_json(ctx.protocol, &bytes);
+            let usage = parse_usage_from_json(ctx.upstream_protocol, &bytes);
             let (
                 prompt_tokens,
                 completion_tokens,
@@ -384,14 +1136,33 @@ async fn proxy_upstream_response(
                 cache_write_tokens,
             ) = usage.as_event_fields();
 
-            let http_status = Some(status.as_u16() as i64);
-            let success = status.is_success();
-            let error_kind = (!success).then(|| format!("upstream_http:{}", status.as_u16()));
-            let error_detail = (!success).then(|| {
-                let msg = parse_error_message(ctx.protocol, &bytes)
-                    .unwrap_or_else(|| String::from_utf8_lossy(&bytes).to_string());
-                truncate(&msg, 2000)
+            let translated = translation.as_ref().map(|t| {
+                if status.is_success() {
+                    t.response_json(&bytes)
+                        .ok_or_else(|| "上游响应不是有效的 JSON，无法转换协议".to_string())
+                } else {
+                    Ok(t.error_json(status, &bytes))
+                }
             });
+            let translate_error = match &translated {
+                Some(Err(msg)) => Some(msg.clone()),
+                _ => None,
+            };
+
+            let http_status = Some(status.as_u16() as i64);
+            let success = status.is_success() && translate_error.is_none();
+            let error_kind = match &translate_error {
+                Some(_) => Some("translate_error".to_string()),
+                None => (!success).then(|| format!("upstream_http:{}", status.as_u16())),
+            };
+            let error_detail = match &translate_error {
+                Some(msg) => Some(msg.clone()),
+                None => (!success).then(|| {
+                    let msg = parse_error_message(ctx.upstream_protocol, &bytes)
+                        .unwrap_or_else(|| String::from_utf8_lossy(&bytes).to_string());
+                    truncate(&msg, 2000)
+                }),
+            };
 
             tracing::debug!(
                 protocol = ctx.protocol.as_str(),
@@ -425,10 +1196,16 @@ async fn proxy_upstream_response(
             spawn_usage_event(
                 build_usage_event(UsageEventParams {
                     request_id: Some(ctx.request_id.clone()),
+                    attempt_index: ctx.attempt_index,
                     protocol: ctx.protocol,
+                    route_id: ctx.route_id.clone(),
+                    client_token_id: ctx.client_token_id.clone(),
                     channel_id: ctx.channel_id.clone(),
                     model: ctx.model.clone(),
+                    upstream_model: ctx.upstream_model.clone(),
+                    key_fingerprint: ctx.key_fingerprint.clone(),
                     success,
+                    cancelled: false,
                     http_status,
                     error_kind,
                     error_detail,
@@ -444,15 +1221,56 @@ async fn proxy_upstream_response(
                 }),
                 ctx.db_path.clone(),
             );
+            if ctx.capture {
+                let mut buf = capture::CaptureBuf::default();
+                buf.push(&bytes);
+                buf.spawn_store(
+                    ctx.db_path.clone(),
+                    &ctx.request_id,
+                    &ctx.channel_id,
+                    ctx.http_status,
+                    false,
+                );
+            }
 
+            if let Some(msg) = translate_error {
+                return Ok(error_response(
+                    ctx.protocol,
+                    axum::http::StatusCode::BAD_GATEWAY,
+                    &msg,
+                ));
+            }
+            let bytes = match translated {
+                Some(Ok(out)) => out,
+                _ => bytes,
+            };
+            if let Some(fill) = cache_fill.filter(|_| success) {
+                cache::spawn_fill(
+                    ctx.db_path.clone(),
+                    fill,
+                    ctx.protocol,
+                    ctx.model.clone(),
+                    ctx.channel_id.clone(),
+                    bytes.to_vec(),
+                );
+            }
             return resp
                 .body(Body::from(bytes))
                 .map_err(|e| ProxyError::Upstream(e.to_string()));
         };
 
+        if must_translate {
+            drop(remainder);
+            return Ok(untranslatable_response(
+                &ctx,
+                status,
+                &too_large_to_translate(),
+            ));
+        }
+
         let prefix = captured;
         let combined =
-            futures_util::stream::once(async move { Ok::<Bytes, reqwest::Error>(prefix) })
+            futures_util::stream::once(async move { Ok::<Bytes, std::io::Error>(prefix) })
                 .chain(remainder)
                 .boxed();
         let stream = InstrumentedStream::new(combined, ctx);
@@ -461,12 +1279,56 @@ async fn proxy_upstream_response(
             .map_err(|e| ProxyError::Upstream(e.to_string()));
     }
 
-    let stream = InstrumentedStream::new(upstream.bytes_stream().boxed(), ctx);
+    let stream = InstrumentedStream::new(upstream.body, ctx);
+    if let Some(t) = translation.filter(|_| is_sse) {
+        let stream = translate::TranslatedStream::new(stream.boxed(), t.stream_translator());
+        return resp
+            .body(Body::from_stream(stream))
+            .map_err(|e| ProxyError::Upstream(e.to_string()));
+    }
 
     resp.body(Body::from_stream(stream))
         .map_err(|e| ProxyError::Upstream(e.to_string()))
 }
 
+fn too_large_to_translate() -> String {
+    format!(
+        "上游响应超过 {} MiB，无法转换协议",
+        MAX_INBOUND_BODY_BYTES / 1024 / 1024
+    )
+}
+
+// 无法转换的跨协议响应记为失败，并以入站协议的 502 错误返回
+fn untranslatable_response(
+    ctx: &StreamRecordContext,
+    status: axum::http::StatusCode,
+    message: &str,
+) -> Response<Body> {
+    spawn_usage_event(
+        build_usage_event(UsageEventParams {
+            request_id: Some(ctx.request_id.clone()),
+            attempt_index: ctx.attempt_index,
+            protocol: ctx.protocol,
+            route_id: ctx.route_id.clone(),
+            client_token_id: ctx.client_token_id.clone(),
+            channel_id: ctx.channel_id.clone(),
+            model: ctx.model.clone(),
+            upstream_model: ctx.upstream_model.clone(),
+            key_fingerprint: ctx.key_fingerprint.clone(),
+            success: false,
+            cancelled: false,
+            http_status: Some(status.as_u16() as i64),
+            error_kind: Some("translate_error".to_string()),
+            error_detail: Some(message.to_string()),
+            latency_ms: ctx.started.elapsed().as_millis() as i64,
+            ttft_ms: None,
+            tokens: (None, None, None, None, None),
+        }),
+        ctx.db_path.clone(),
+    );
+    error_response(ctx.protocol, axum::http::StatusCode::BAD_GATEWAY, message)
+}
+
 fn build_upstream_url(
     base_url: &str,
     inbound_uri: &axum::http::Uri,
@@ -519,7 +1381,7 @@ fn filtered_headers(src: &HeaderMap) -> HeaderMap {
         if name == axum::http::header::HOST || name == axum::http::header::CONTENT_LENGTH {
             continue;
         }
-        if name == axum::http::header::ACCEPT_ENCODING {
+        if name == axum::http::header::ACCEPT_ENCODING || name == CLIENT_TOKEN_HEADER {
             continue;
         }
 
@@ -562,15 +1424,9 @@ async fn read_response_prefix_bytes(
 }
 
 async fn read_stream_prefix_or_all(
-    mut stream: futures_util::stream::BoxStream<'static, Result<Bytes, reqwest::Error>>,
+    mut stream: UpstreamBody,
     max_bytes: usize,
-) -> Result<
-    (
-        Bytes,
-        Option<futures_util::stream::BoxStream<'static, Result<Bytes, reqwest::Error>>>,
-    ),
-    reqwest::Error,
-> {
+) -> Result<(Bytes, Option<UpstreamBody>), std::io::Error> {
     let mut buf = BytesMut::new();
     while let Some(item) = stream.next().await {
         let chunk = item?;
@@ -598,12 +1454,12 @@ fn is_hop_by_hop(name: &HeaderName) -> bool {
 }
 
 pub(crate) fn apply_auth(
-    channel: &Channel,
+    token: &str,
     protocol: Protocol,
     url: &mut Url,
     headers: &mut HeaderMap,
 ) -> Result<(), ProxyError> {
-    let token = channel.auth_ref.trim();
+    let token = token.trim();
 
     let detected = detect_request_auth_kind(protocol, headers, url);
     let auth_kind = resolve_auth_kind(protocol, detected);
@@ -795,6 +1651,58 @@ fn extract_model(
     })
 }
 
+fn rewrite_model(
+    protocol: Protocol,
+    headers: &HeaderMap,
+    uri: &axum::http::Uri,
+    body: &Bytes,
+    upstream_model: &str,
+) -> (axum::http::Uri, Bytes) {
+    if extract_model_from_body(headers, body).is_some()
+        && let Ok(mut v) = serde_json::from_slice::<serde_json::Value>(body)
+        && let Some(obj) = v.as_object_mut()
+    {
+        obj.insert(
+            "model".to_string(),
+            serde_json::Value::String(upstream_model.to_string()),
+        );
+        if let Ok(out) = serde_json::to_vec(&v) {
+            return (uri.clone(), Bytes::from(out));
+        }
+    }
+
+    if protocol == Protocol::Gemini
+        && let Some(uri) = rewrite_gemini_model_in_uri(uri, upstream_model)
+    {
+        return (uri, body.clone());
+    }
+    (uri.clone(), body.clone())
+}
+
+fn rewrite_gemini_model_in_uri(
+    uri: &axum::http::Uri,
+    upstream_model: &str,
+) -> Option<axum::http::Uri> {
+    let path = uri.path();
+    for marker in ["/models/", "/tunedModels/"] {
+        if let Some(pos) = path.rfind(marker) {
+            let start = pos + marker.len();
+            let rest = &path[start..];
+            let end = rest.find([':', '/']).unwrap_or(rest.len());
+            if end == 0 {
+                continue;
+            }
+            let mut out = format!("{}{}{}", &path[..start], upstream_model, &rest[end..]);
+            if let Some(q) = uri.query() {
+                out.push('?');
+                out.push_str(q);
+            }
+            return out.parse().ok();
+        }
+    }
+    None
+}
+
 fn extract_gemini_model_from_uri(uri: &axum::http::Uri) -> Option<String> {
     let path = uri.path();
     for marker in ["/models/", "/tunedModels/"] {
@@ -1043,19 +1951,54 @@ fn extract_gemini_usage(v: &serde_json::Value) -> TokenUsage {
 }
 
 pub(super) fn spawn_usage_event(input: storage::CreateUsageEvent, db_path: std::path::PathBuf) {
+    // 缓存命中没有请求上游，不消耗渠道的 token 额度
+    if !input.cache_hit {
+        let tokens = input.total_tokens.unwrap_or_else(|| {
+            input.prompt_tokens.unwrap_or(0) + input.completion_tokens.unwrap_or(0)
+        });
+        ratelimit::consume_tokens(
+            &input.channel_id,
+            input.key_fingerprint.as_deref(),
+            tokens,
+            input.ts_ms,
+        );
+    }
+    metrics::observe_usage(&input);
+    let trace = otel::attempt_finished(&input);
+    let (protocol, channel_id, model) = (
+        input.protocol,
+        input.channel_id.clone(),
+        input.model.clone(),
+    );
     tokio::spawn(async move {
-        if let Err(e) = storage::insert_usage_event(db_path, input).await {
-            tracing::warn!(err = %e, "insert usage event failed");
+        let cost = match storage::insert_usage_event(db_path, input).await {
+            Ok(cost) => cost,
+            Err(e) => {
+                tracing::warn!(err = %e, "insert usage event failed");
+                None
+            }
+        };
+        if let Some(c) = cost.as_deref().and_then(|c| c.parse::<f64>().ok()) {
+            metrics::observe_cost(protocol, &channel_id, model.as_deref(), c);
+        }
+        if let Some(trace) = trace {
+            trace.finish(cost.as_deref());
         }
     });
 }
 
 pub(super) struct UsageEventParams {
     pub(super) request_id: Option<Arc<str>>,
+    pub(super) attempt_index: usize,
     pub(super) protocol: Protocol,
+    pub(super) route_id: Option<String>,
+    pub(super) client_token_id: Option<String>,
     pub(super) channel_id: String,
     pub(super) model: Option<String>,
+    pub(super) upstream_model: Option<String>,
+    pub(super) key_fingerprint: Option<String>,
     pub(super) success: bool,
+    pub(super) cancelled: bool,
     pub(super) http_status: Option<i64>,
     pub(super) error_kind: Option<String>,
     pub(super) error_detail: Option<String>,
@@ -1069,12 +2012,16 @@ pub(super) fn build_usage_event(params: UsageEventParams) -> storage::CreateUsag
         params.tokens;
     storage::CreateUsageEvent {
         request_id: params.request_id,
+        attempt_index: params.attempt_index,
         ts_ms: storage::now_ms(),
         protocol: params.protocol,
-        route_id: None,
+        route_id: params.route_id,
+        client_token_id: params.client_token_id,
         channel_id: params.channel_id,
         model: params.model,
+        upstream_model: params.upstream_model,
         success: params.success,
+        cancelled: params.cancelled,
         http_status: params.http_status,
         error_kind: params.error_kind,
         error_detail: params.error_detail,
@@ -1086,6 +2033,8 @@ pub(super) fn build_usage_event(params: UsageEventParams) -> storage::CreateUsag
         cache_read_tokens,
         cache_write_tokens,
         estimated_cost_usd: None,
+        key_fingerprint: params.key_fingerprint,
+        cache_hit: false,
     }
 }
 
@@ -0,0 +1,101 @@
+use axum::http::{HeaderMap, Uri};
+use std::path::Path;
+
+use crate::storage::{self, ClientToken, Protocol};
+
+use super::ProxyError;
+
+// 客户端在原本填写上游 key 的位置携带 cliswitch 签发的令牌，
+// 也可以用单独的请求头，以便 key 处仍填写其他内容
+pub(crate) fn presented_token(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    if let Some(v) = header(super::CLIENT_TOKEN_HEADER) {
+        return Some(v.to_string());
+    }
+    if let Some(v) = header("authorization") {
+        let v = v
+            .strip_prefix("Bearer ")
+            .or_else(|| v.strip_prefix("bearer "))
+            .unwrap_or(v);
+        return Some(v.trim().to_string());
+    }
+    if let Some(v) = header("x-api-key").or_else(|| header("x-goog-api-key")) {
+        return Some(v.to_string());
+    }
+    // 令牌只含字母数字和 `-`，无需解码
+    uri.query()?
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .find(|(k, _)| *k == "key")
+        .map(|(_, v)| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+// 未开启鉴权时只识别令牌用于归属统计，无效令牌不拦截
+pub(super) async fn authenticate(
+    db_path: &Path,
+    settings: &storage::AppSettings,
+    protocol: Protocol,
+    headers: &HeaderMap,
+    uri: &Uri,
+) -> Result<Option<ClientToken>, ProxyError> {
+    let required = settings.client_auth_enabled;
+    let Some(presented) = presented_token(headers, uri) else {
+        return if required {
+            Err(ProxyError::Unauthorized(
+                "缺少客户端令牌，请在 API key 处填写 cliswitch 令牌".to_string(),
+            ))
+        } else {
+            Ok(None)
+        };
+    };
+
+    let now_ms = storage::now_ms();
+    let token = storage::find_client_token(db_path.to_path_buf(), presented)
+        .await?
+        .filter(|t| t.is_active(now_ms));
+    let Some(token) = token else {
+        return if required {
+            Err(ProxyError::Unauthorized(
+                "客户端令牌无效、已停用或已过期".to_string(),
+            ))
+        } else {
+            Ok(None)
+        };
+    };
+    if required && !token.allows_protocol(protocol) {
+        return Err(ProxyError::Forbidden(format!(
+            "客户端令牌 {} 无权调用 {} 协议",
+            token.name,
+            protocol.as_str()
+        )));
+    }
+
+    let db_path = db_path.to_path_buf();
+    let id = token.id.clone();
+    tokio::spawn(async move {
+        if let Err(e) = storage::touch_client_token(db_path, id, now_ms).await {
+            tracing::warn!(err = %e, "touch client token failed");
+        }
+    });
+    Ok(Some(token))
+}
+
+pub(super) fn authorize_route(
+    settings: &storage::AppSettings,
+    token: Option<&ClientToken>,
+    route_id: Option<&str>,
+) -> Result<(), ProxyError> {
+    match token {
+        Some(t) if settings.client_auth_enabled && !t.allows_route(route_id) => Err(
+            ProxyError::Forbidden(format!("客户端令牌 {} 无权使用该路由", t.name)),
+        ),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use crate::events::{self, AppEvent, ChannelSpendWarning};
+use crate::storage::{self, Channel, ChannelSpend, SpendLimitKind};
+
+// 渠道 id、限额类型、是否已耗尽
+type WarnKey = (String, SpendLimitKind, bool);
+
+// 每个渠道、每类限额在同一周期内只提醒一次（阈值、耗尽各一次）
+fn warned() -> &'static Mutex<HashMap<WarnKey, i64>> {
+    static WARNED: OnceLock<Mutex<HashMap<WarnKey, i64>>> = OnceLock::new();
+    WARNED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// 剔除已达余额或限额的渠道；统计失败时不拦截
+pub(super) async fn filter_over_budget(
+    db_path: PathBuf,
+    channels: Vec<Channel>,
+    settings: &storage::AppSettings,
+) -> Vec<Channel> {
+    if channels.iter().all(|c| c.spend_limit.is_none()) {
+        return channels;
+    }
+    let day_start_ms = storage::local_day_start_ms();
+    let month_start_ms = storage::local_month_start_ms();
+    let spends: HashMap<String, ChannelSpend> =
+        match storage::channel_spend(db_path, day_start_ms, month_start_ms, settings.usd_cny_rate)
+            .await
+        {
+            Ok(v) => v.into_iter().map(|s| (s.channel_id.clone(), s)).collect(),
+            Err(e) => {
+                tracing::warn!(err = %e, "load channel spend failed");
+                return channels;
+            }
+        };
+
+    let threshold = settings.spend_warning_percent as f64 / 100.0;
+    channels
+        .into_iter()
+        .filter(|c| {
+            let Some(spend) = spends.get(&c.id) else {
+                return true;
+            };
+            let exhausted = spend.exhausted();
+            if let Some(&(kind, spent, limit)) = spend.usage().first()
+                && spent >= limit * threshold
+            {
+                let period = match kind {
+                    SpendLimitKind::Balance => spend.limit.balance_set_at_ms.unwrap_or(0),
+                    SpendLimitKind::Daily => day_start_ms,
+                    SpendLimitKind::Monthly => month_start_ms,
+                };
+                warn_once(c, spend, kind, spent, limit, exhausted, period);
+            }
+            !exhausted
+        })
+        .collect()
+}
+
+fn warn_once(
+    channel: &Channel,
+    spend: &ChannelSpend,
+    kind: SpendLimitKind,
+    spent: f64,
+    limit: f64,
+    exhausted: bool,
+    period: i64,
+) {
+    {
+        let mut map = warned().lock().unwrap_or_else(|e| e.into_inner());
+        let key = (channel.id.clone(), kind, exhausted);
+        if map.get(&key) == Some(&period) {
+            return;
+        }
+        map.insert(key, period);
+    }
+    tracing::warn!(
+        channel_id = %channel.id,
+        kind = ?kind,
+        spent,
+        limit,
+        currency = %spend.currency,
+        exhausted,
+        "channel spend warning"
+    );
+    events::publish(AppEvent::ChannelSpendWarning(ChannelSpendWarning {
+        channel_id: channel.id.clone(),
+        channel_name: channel.name.clone(),
+        kind,
+        spent,
+        limit,
+        currency: spend.currency,
+        exhausted,
+    }));
+}
@@ -0,0 +1,282 @@
+use axum::body::Body;
+use axum::http::{Response, Uri};
+use serde_json::{Value, json};
+use sha2::{Digest as _, Sha256};
+
+use crate::storage::{self, Protocol};
+
+use super::ProxyError;
+use super::translate::{write_sse, write_sse_json};
+
+pub(super) const CACHE_HEADER: &str = "x-cliswitch-cache";
+
+// 可缓存的请求：生成类接口且 temperature 显式为 0
+#[derive(Debug, Clone)]
+pub(super) struct CacheRequest {
+    pub(super) key: String,
+    // 客户端请求的是流式响应，命中时需要把缓存的 JSON 回放成 SSE
+    pub(super) stream: bool,
+    // Gemini 流式接口不带 alt=sse 时返回 JSON 数组
+    gemini_sse: bool,
+}
+
+// 缓存按客户端令牌和路由隔离：不同令牌、不同路由的相同请求互不命中
+pub(super) fn cache_request(
+    protocol: Protocol,
+    uri: &Uri,
+    model: Option<&str>,
+    route_id: Option<&str>,
+    client_token_id: Option<&str>,
+    body: &[u8],
+) -> Option<CacheRequest> {
+    let path = uri.path().trim_end_matches('/');
+    let gemini_stream = protocol == Protocol::Gemini && path.ends_with(":streamGenerateContent");
+    let eligible = match protocol {
+        Protocol::Openai => path == "/v1/chat/completions",
+        Protocol::Anthropic => path == "/v1/messages",
+        Protocol::Gemini => gemini_stream || path.ends_with(":generateContent"),
+    };
+    if !eligible {
+        return None;
+    }
+
+    let mut v: Value = serde_json::from_slice(body).ok()?;
+    let temperature = match protocol {
+        Protocol::Gemini => v.pointer("/generationConfig/temperature"),
+        _ => v.get("temperature"),
+    };
+    if temperature.and_then(Value::as_f64) != Some(0.0) {
+        return None;
+    }
+
+    let obj = v.as_object_mut()?;
+    let stream = gemini_stream || obj.get("stream").and_then(Value::as_bool) == Some(true);
+    // 流式与非流式共用同一份缓存
+    obj.remove("stream");
+    obj.remove("stream_options");
+    let path = path.replace(":streamGenerateContent", ":generateContent");
+
+    // serde_json 的 Map 按键排序，序列化结果即规范形式
+    let mut hasher = Sha256::new();
+    for part in [
+        protocol.as_str(),
+        path.as_str(),
+        model.unwrap_or(""),
+        route_id.unwrap_or(""),
+        client_token_id.unwrap_or(""),
+        &v.to_string(),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    Some(CacheRequest {
+        key: hex::encode(hasher.finalize()),
+        stream,
+        gemini_sse: uri
+            .query()
+            .is_some_and(|q| q.split('&').any(|kv| kv == "alt=sse")),
+    })
+}
+
+pub(super) fn hit_response(
+    protocol: Protocol,
+    req: &CacheRequest,
+    body: Vec<u8>,
+) -> Result<Response<Body>, ProxyError> {
+    let (content_type, bytes) = if !req.stream {
+        ("application/json", body)
+    } else {
+        let v: Value = serde_json::from_slice(&body)
+            .map_err(|e| ProxyError::Upstream(format!("缓存的响应无法解析：{e}")))?;
+        match protocol {
+            Protocol::Openai => ("text/event-stream", replay_openai(&v)),
+            Protocol::Anthropic => ("text/event-stream", replay_anthropic(&v)),
+            Protocol::Gemini if req.gemini_sse => {
+                let mut out = Vec::new();
+                write_sse_json(&mut out, None, &v);
+                ("text/event-stream", out)
+            }
+            Protocol::Gemini => ("application/json", json!([v]).to_string().into_bytes()),
+        }
+    };
+    Response::builder()
+        .status(axum::http::StatusCode::OK)
+        .header(axum::http::header::CONTENT_TYPE, content_type)
+        .header(CACHE_HEADER, "hit")
+        .body(Body::from(bytes))
+        .map_err(|e| ProxyError::Upstream(e.to_string()))
+}
+
+fn replay_openai(v: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    let chunk = |choices: Value| {
+        json!({
+            "id": v.get("id").cloned().unwrap_or(Value::Null),
+            "object": "chat.completion.chunk",
+            "created": v.get("created").cloned().unwrap_or(Value::Null),
+            "model": v.get("model").cloned().unwrap_or(Value::Null),
+            "choices": choices,
+        })
+    };
+    let choices = v
+        .get("choices")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    for (i, choice) in choices.iter().enumerate() {
+        let index = choice.get("index").cloned().unwrap_or(json!(i));
+        let mut delta = choice.get("message").cloned().unwrap_or_else(|| json!({}));
+        if let Some(calls) = delta.get_mut("tool_calls").and_then(Value::as_array_mut) {
+            for (n, call) in calls.iter_mut().enumerate() {
+                call["index"] = json!(n);
+            }
+        }
+        write_sse_json(
+            &mut out,
+            None,
+            &chunk(json!([{ "index": index, "delta": delta, "finish_reason": null }])),
+        );
+        write_sse_json(
+            &mut out,
+            None,
+            &chunk(json!([{
+                "index": index,
+                "delta": {},
+                "finish_reason": choice.get("finish_reason").cloned().unwrap_or(Value::Null),
+            }])),
+        );
+    }
+    if let Some(usage) = v.get("usage") {
+        let mut last = chunk(json!([]));
+        last["usage"] = usage.clone();
+        write_sse_json(&mut out, None, &last);
+    }
+    write_sse(&mut out, None, "[DONE]");
+    out
+}
+
+fn replay_anthropic(v: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut message = v.clone();
+    message["content"] = json!([]);
+    message["stop_reason"] = Value::Null;
+    message["stop_sequence"] = Value::Null;
+    write_sse_json(
+        &mut out,
+        Some("message_start"),
+        &json!({ "type": "message_start", "message": message }),
+    );
+
+    let blocks = v
+        .get("content")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    for (index, block) in blocks.iter().enumerate() {
+        let kind = block.get("type").and_then(Value::as_str).unwrap_or("");
+        let (start, delta) = match kind {
+            "text" => (
+                json!({ "type": "text", "text": "" }),
+                Some(json!({ "type": "text_delta", "text": block["text"] })),
+            ),
+            "thinking" => (
+                json!({ "type": "thinking", "thinking": "" }),
+                Some(json!({ "type": "thinking_delta", "thinking": block["thinking"] })),
+            ),
+            "tool_use" => {
+                let mut start = block.clone();
+                start["input"] = json!({});
+                let input = block.get("input").cloned().unwrap_or_else(|| json!({}));
+                (
+                    start,
+                    Some(json!({ "type": "input_json_delta", "partial_json": input.to_string() })),
+                )
+            }
+            _ => (block.clone(), None),
+        };
+        write_sse_json(
+            &mut out,
+            Some("content_block_start"),
+            &json!({ "type": "content_block_start", "index": index, "content_block": start }),
+        );
+        if let Some(delta) = delta {
+            write_sse_json(
+                &mut out,
+                Some("content_block_delta"),
+                &json!({ "type": "content_block_delta", "index": index, "delta": delta }),
+            );
+        }
+        if kind == "thinking"
+            && let Some(signature) = block.get("signature")
+        {
+            write_sse_json(
+                &mut out,
+                Some("content_block_delta"),
+                &json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": { "type": "signature_delta", "signature": signature },
+                }),
+            );
+        }
+        write_sse_json(
+            &mut out,
+            Some("content_block_stop"),
+            &json!({ "type": "content_block_stop", "index": index }),
+        );
+    }
+
+    write_sse_json(
+        &mut out,
+        Some("message_delta"),
+        &json!({
+            "type": "message_delta",
+            "delta": {
+                "stop_reason": v.get("stop_reason").cloned().unwrap_or(Value::Null),
+                "stop_sequence": v.get("stop_sequence").cloned().unwrap_or(Value::Null),
+            },
+            "usage": {
+                "output_tokens": v.pointer("/usage/output_tokens").cloned().unwrap_or(json!(0)),
+            },
+        }),
+    );
+    write_sse_json(
+        &mut out,
+        Some("message_stop"),
+        &json!({ "type": "message_stop" }),
+    );
+    out
+}
+
+// 上游成功返回完整 JSON 后写入缓存
+#[derive(Debug, Clone)]
+pub(super) struct CacheFill {
+    pub(super) key: String,
+    pub(super) ttl_ms: i64,
+    pub(super) max_bytes: i64,
+}
+
+pub(super) fn spawn_fill(
+    db_path: std::path::PathBuf,
+    fill: CacheFill,
+    protocol: Protocol,
+    model: Option<String>,
+    channel_id: String,
+    body: Vec<u8>,
+) {
+    tokio::spawn(async move {
+        let input = storage::CreateCachedResponse {
+            cache_key: fill.key,
+            protocol,
+            model,
+            channel_id,
+            body,
+            ttl_ms: fill.ttl_ms,
+        };
+        if let Err(e) =
+            storage::put_cached_response(db_path, input, storage::now_ms(), fill.max_bytes).await
+        {
+            tracing::warn!(err = %e, "store cached response failed");
+        }
+    });
+}
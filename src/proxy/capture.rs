@@ -0,0 +1,112 @@
+use axum::http::{HeaderMap, Uri};
+use bytes::Bytes;
+use std::path::Path;
+
+use crate::storage::{self, Protocol};
+
+// 单个请求体/响应体最多保存 4 MiB，超出部分丢弃并标记 truncated
+const MAX_CAPTURE_BYTES: usize = 4 * 1024 * 1024;
+pub(super) const REDACTED: &str = "REDACTED";
+
+const SECRET_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "x-api-key",
+    "x-goog-api-key",
+    "api-key",
+    "cookie",
+    super::CLIENT_TOKEN_HEADER,
+];
+
+fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).to_string()
+            };
+            (name.as_str().to_string(), value)
+        })
+        .collect()
+}
+
+// Gemini 允许把密钥放在 ?key= 中
+fn redact_uri(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.path().to_string();
+    };
+    let query = query
+        .split('&')
+        .map(|kv| match kv.split_once('=') {
+            Some(("key", _)) => format!("key={REDACTED}"),
+            _ => kv.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{query}", uri.path())
+}
+
+// 请求先落库，响应到达后再补全；写入失败不影响转发
+pub(super) async fn record_request(
+    db_path: &Path,
+    request_id: &str,
+    protocol: Protocol,
+    parts: &axum::http::request::Parts,
+    body: &Bytes,
+) {
+    let input = storage::CreateRequestCapture {
+        request_id: request_id.to_string(),
+        ts_ms: storage::now_ms(),
+        protocol,
+        method: parts.method.to_string(),
+        path: redact_uri(&parts.uri),
+        request_headers: redact_headers(&parts.headers),
+        request_body: body[..body.len().min(MAX_CAPTURE_BYTES)].to_vec(),
+        request_truncated: body.len() > MAX_CAPTURE_BYTES,
+    };
+    if let Err(e) = storage::insert_request_capture(db_path.to_path_buf(), input).await {
+        tracing::warn!(request_id, err = %e, "insert request capture failed");
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct CaptureBuf {
+    buf: Vec<u8>,
+    truncated: bool,
+}
+
+impl CaptureBuf {
+    pub(super) fn push(&mut self, bytes: &[u8]) {
+        let remain = MAX_CAPTURE_BYTES.saturating_sub(self.buf.len());
+        self.buf
+            .extend_from_slice(&bytes[..bytes.len().min(remain)]);
+        if bytes.len() > remain {
+            self.truncated = true;
+        }
+    }
+
+    pub(super) fn spawn_store(
+        self,
+        db_path: std::path::PathBuf,
+        request_id: &str,
+        channel_id: &str,
+        status: i64,
+        sse: bool,
+    ) {
+        let input = storage::CaptureResponse {
+            request_id: request_id.to_string(),
+            channel_id: channel_id.to_string(),
+            status,
+            sse,
+            body: self.buf,
+            truncated: self.truncated,
+        };
+        tokio::spawn(async move {
+            if let Err(e) = storage::update_capture_response(db_path, input).await {
+                tracing::warn!(err = %e, "store response capture failed");
+            }
+        });
+    }
+}
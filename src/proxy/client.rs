@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::storage::{self, Channel};
+
+// 渠道流量的出口
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Egress {
+    // 沿用系统代理环境变量（HTTP_PROXY 等）
+    System,
+    Direct,
+    Proxy(String),
+}
+
+// 渠道 proxy_url：空表示沿用全局设置，"direct" 表示直连
+const DIRECT: &str = "direct";
+
+pub(crate) fn channel_egress(channel: &Channel, settings: &storage::AppSettings) -> Egress {
+    match channel.proxy_url.as_deref().map(str::trim) {
+        Some(v) if v.eq_ignore_ascii_case(DIRECT) => Egress::Direct,
+        Some(v) if !v.is_empty() => Egress::Proxy(v.to_string()),
+        _ => match settings.proxy_url.trim() {
+            "" => Egress::System,
+            v if v.eq_ignore_ascii_case(DIRECT) => Egress::Direct,
+            v => Egress::Proxy(v.to_string()),
+        },
+    }
+}
+
+pub(crate) fn validate_proxy_url(v: &str) -> Result<(), String> {
+    let v = v.trim();
+    if v.is_empty() || v.eq_ignore_ascii_case(DIRECT) {
+        return Ok(());
+    }
+    let url = reqwest::Url::parse(v).map_err(|e| format!("proxy_url 无效：{e}"))?;
+    match url.scheme() {
+        "http" | "https" | "socks4" | "socks4a" | "socks5" | "socks5h" => Ok(()),
+        other => Err(format!(
+            "proxy_url 不支持的协议：{other}（支持 http/https/socks4/socks5）"
+        )),
+    }
+}
+
+// connect_timeout 和代理都只能设在 Client 上，按配置缓存以复用连接池
+pub(crate) fn client_for(
+    base: &reqwest::Client,
+    connect_ms: i64,
+    egress: &Egress,
+) -> Result<reqwest::Client, reqwest::Error> {
+    if connect_ms <= 0 && *egress == Egress::System {
+        return Ok(base.clone());
+    }
+    static CLIENTS: OnceLock<Mutex<HashMap<(i64, Egress), reqwest::Client>>> = OnceLock::new();
+    let mut clients = CLIENTS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let key = (connect_ms.max(0), egress.clone());
+    if let Some(c) = clients.get(&key) {
+        return Ok(c.clone());
+    }
+
+    let mut builder = reqwest::Client::builder();
+    if connect_ms > 0 {
+        builder = builder.connect_timeout(Duration::from_millis(connect_ms as u64));
+    }
+    builder = match egress {
+        Egress::System => builder,
+        Egress::Direct => builder.no_proxy(),
+        Egress::Proxy(url) => builder.proxy(reqwest::Proxy::all(url)?),
+    };
+    let client = builder.build()?;
+    clients.insert(key, client.clone());
+    Ok(client)
+}
@@ -0,0 +1,235 @@
+use axum::body::Body;
+use axum::http::Response;
+use std::time::Duration;
+
+use crate::storage::{self, Channel, Protocol};
+
+use super::{Attempt, ForwardCtx, Prepared, ProxyError, SendError};
+
+const P95_SAMPLE_LIMIT: i64 = 200;
+const P95_MIN_SAMPLES: usize = 20;
+const FALLBACK_DELAY_MS: i64 = 2_000;
+const MIN_DELAY_MS: i64 = 50;
+
+pub(super) enum HedgeOutcome {
+    Done(Result<Response<Body>, ProxyError>),
+    // 对冲阶段用掉的渠道数；剩余渠道按原顺序继续故障转移
+    Fallthrough {
+        consumed: usize,
+        err: Option<ProxyError>,
+    },
+}
+
+// 只对非流式请求做对冲：流式响应一旦开始转发就无法再换渠道
+pub(super) fn should_hedge(ctx: &ForwardCtx<'_>, total_channels: usize) -> bool {
+    ctx.record_usage
+        && ctx.settings.hedge_enabled
+        && total_channels >= 2
+        && !is_stream_request(ctx.protocol, ctx.parts.uri.path(), ctx.body)
+}
+
+fn is_stream_request(protocol: Protocol, path: &str, body: &[u8]) -> bool {
+    if protocol == Protocol::Gemini {
+        return path.contains(":streamGenerateContent");
+    }
+    serde_json::from_slice::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v.get("stream").and_then(|s| s.as_bool()))
+        .unwrap_or(false)
+}
+
+async fn hedge_delay(ctx: &ForwardCtx<'_>, primary: &Channel) -> Duration {
+    let ms = if ctx.settings.hedge_delay_ms > 0 {
+        ctx.settings.hedge_delay_ms
+    } else {
+        match storage::channel_latency_p95(
+            ctx.db_path.to_path_buf(),
+            primary.id.clone(),
+            P95_SAMPLE_LIMIT,
+            P95_MIN_SAMPLES,
+        )
+        .await
+        {
+            Ok(Some(p95)) => p95,
+            Ok(None) => FALLBACK_DELAY_MS,
+            Err(e) => {
+                tracing::warn!(channel_id = %primary.id, err = %e, "load channel p95 failed");
+                FALLBACK_DELAY_MS
+            }
+        }
+    };
+    Duration::from_millis(ms.max(MIN_DELAY_MS) as u64)
+}
+
+type SendFuture = std::pin::Pin<
+    Box<dyn std::future::Future<Output = Result<reqwest::Response, SendError>> + Send>,
+>;
+
+pub(super) async fn forward_hedged(ctx: &ForwardCtx<'_>, channels: &[Channel]) -> HedgeOutcome {
+    let total = channels.len();
+    // 准备失败（无法转换、base_url 无效等）按一次失败尝试记录，常规流程从下一个渠道继续
+    let (primary, request) = match ctx.prepare(&channels[0]).await {
+        Ok(Prepared::Upstream(attempt, request)) => (attempt, request),
+        Ok(Prepared::Local(bytes)) => return HedgeOutcome::Done(super::local_json_response(bytes)),
+        Err(e) => {
+            ctx.fail_prepare(&channels[0], &e, 1, total).await;
+            return HedgeOutcome::Fallthrough {
+                consumed: 1,
+                err: Some(e.err),
+            };
+        }
+    };
+    let delay = hedge_delay(ctx, primary.channel).await;
+    let mut primary_fut: SendFuture = Box::pin(super::timeout::send(
+        request,
+        primary.timeouts,
+        primary.started,
+    ));
+
+    tokio::select! {
+        r = &mut primary_fut => {
+            return settle_alone(ctx, primary, r, 1, total).await;
+        }
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    // 对冲渠道准备失败时同样计为已尝试，避免常规流程再次准备它
+    let (secondary, request) = match ctx.prepare(&channels[1]).await {
+        Ok(Prepared::Upstream(attempt, request)) => (attempt, request),
+        Ok(Prepared::Local(_)) => {
+            let r = primary_fut.await;
+            return settle_alone(ctx, primary, r, 1, total).await;
+        }
+        Err(e) => {
+            ctx.fail_prepare(&channels[1], &e, 2, total).await;
+            let r = primary_fut.await;
+            return match settle_alone(ctx, primary, r, 2, total).await {
+                HedgeOutcome::Fallthrough { consumed, err } => HedgeOutcome::Fallthrough {
+                    consumed,
+                    err: err.or(Some(e.err)),
+                },
+                done => done,
+            };
+        }
+    };
+    tracing::debug!(
+        protocol = ctx.protocol.as_str(),
+        request_id = %ctx.request_id,
+        channel_id = %primary.channel.id,
+        hedge_channel_id = %secondary.channel.id,
+        delay_ms = delay.as_millis() as u64,
+        "proxy hedge request launched"
+    );
+    let mut secondary_fut: SendFuture = Box::pin(super::timeout::send(
+        request,
+        secondary.timeouts,
+        secondary.started,
+    ));
+
+    let mut slots = [Some(primary), Some(secondary)];
+    let mut err: Option<ProxyError> = None;
+    // 传输错误按策略不可重试时，对冲结束后不再尝试剩余渠道
+    let mut stop = false;
+    loop {
+        let (idx, r) = tokio::select! {
+            r = &mut primary_fut, if slots[0].is_some() => (0, r),
+            r = &mut secondary_fut, if slots[1].is_some() => (1, r),
+        };
+        let Some(attempt) = slots[idx].take() else {
+            continue;
+        };
+        let other_pending = slots[1 - idx].is_some();
+
+        match r {
+            Ok(upstream) if upstream.status().is_success() => {
+                if let Some(loser) = slots[1 - idx].take() {
+                    ctx.record_cancelled(&loser);
+                }
+                return HedgeOutcome::Done(ctx.finish(attempt, upstream).await);
+            }
+            Ok(upstream) => {
+                // 不可重试的状态码直接返回；两个都失败且没有更多渠道时，把最后一个上游错误原样返回
+                let retryable = ctx
+                    .policy(attempt.channel)
+                    .should_retry(upstream.status().as_u16());
+                if !retryable || (!other_pending && total == 2) {
+                    if let Some(loser) = slots[1 - idx].take() {
+                        ctx.record_cancelled(&loser);
+                    }
+                    ctx.note_http_failure(&attempt, &upstream).await;
+                    return HedgeOutcome::Done(ctx.finish(attempt, upstream).await);
+                }
+                tracing::warn!(
+                    protocol = ctx.protocol.as_str(),
+                    channel_id = %attempt.channel.id,
+                    attempt = idx + 1,
+                    total = total,
+                    http_status = upstream.status().as_u16(),
+                    "proxy hedged attempt got non-2xx"
+                );
+                ctx.record_http_failure(&attempt, upstream).await;
+            }
+            Err(e) => {
+                ctx.record_transport_failure(&attempt, idx + 1, total, &e)
+                    .await;
+                stop |= !ctx.policy(attempt.channel).retry_transport_errors;
+                err = Some(ProxyError::Upstream(e.detail));
+            }
+        }
+
+        if slots.iter().all(Option::is_none) {
+            return HedgeOutcome::Fallthrough {
+                consumed: if stop { total } else { 2 },
+                err,
+            };
+        }
+    }
+}
+
+// 首个渠道单独结束（对冲未发出或对冲渠道准备失败），按常规单次尝试处理；
+// consumed 为已用掉的渠道数，用完所有渠道时上游错误原样返回
+async fn settle_alone(
+    ctx: &ForwardCtx<'_>,
+    attempt: Attempt<'_>,
+    r: Result<reqwest::Response, SendError>,
+    consumed: usize,
+    total: usize,
+) -> HedgeOutcome {
+    match r {
+        Ok(upstream) if upstream.status().is_success() => {
+            HedgeOutcome::Done(ctx.finish(attempt, upstream).await)
+        }
+        Ok(upstream)
+            if consumed >= total
+                || !ctx
+                    .policy(attempt.channel)
+                    .should_retry(upstream.status().as_u16()) =>
+        {
+            ctx.note_http_failure(&attempt, &upstream).await;
+            HedgeOutcome::Done(ctx.finish(attempt, upstream).await)
+        }
+        Ok(upstream) => {
+            tracing::warn!(
+                protocol = ctx.protocol.as_str(),
+                channel_id = %attempt.channel.id,
+                attempt = 1,
+                total = total,
+                http_status = upstream.status().as_u16(),
+                "proxy attempt got non-2xx, retry next channel"
+            );
+            ctx.record_http_failure(&attempt, upstream).await;
+            HedgeOutcome::Fallthrough {
+                consumed,
+                err: None,
+            }
+        }
+        Err(e) => {
+            ctx.record_transport_failure(&attempt, 1, total, &e).await;
+            let retryable = ctx.policy(attempt.channel).retry_transport_errors;
+            HedgeOutcome::Fallthrough {
+                consumed: if retryable { consumed } else { total },
+                err: Some(ProxyError::Upstream(e.detail)),
+            }
+        }
+    }
+}
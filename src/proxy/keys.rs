@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use crate::storage::{self, Channel, KeyStrategy};
+
+use super::ratelimit;
+
+pub(super) struct SelectedKey {
+    pub(super) token: String,
+    pub(super) fingerprint: Option<String>,
+    // 多 key 渠道的全部指纹；单 key 渠道为空，失败按渠道级别计数
+    pub(super) pool: Vec<String>,
+}
+
+impl SelectedKey {
+    pub(super) fn is_pooled(&self) -> bool {
+        !self.pool.is_empty()
+    }
+}
+
+fn round_robin_counters() -> &'static Mutex<HashMap<String, usize>> {
+    static COUNTERS: OnceLock<Mutex<HashMap<String, usize>>> = OnceLock::new();
+    COUNTERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub(super) async fn select_key(
+    db_path: &Path,
+    channel: &Channel,
+    now_ms: i64,
+    honor_auto_disable: bool,
+) -> SelectedKey {
+    let keys = channel.keys();
+    if keys.len() <= 1 {
+        let token = keys.first().copied().unwrap_or_default();
+        return SelectedKey {
+            token: token.to_string(),
+            fingerprint: (!token.is_empty()).then(|| storage::key_fingerprint(token)),
+            pool: Vec::new(),
+        };
+    }
+
+    let pool: Vec<String> = keys.iter().map(|k| storage::key_fingerprint(k)).collect();
+    let states: HashMap<String, storage::ChannelKeyState> =
+        match storage::list_channel_key_states(db_path.to_path_buf(), channel.id.clone()).await {
+            Ok(v) => v.into_iter().map(|s| (s.key_fp.clone(), s)).collect(),
+            Err(e) => {
+                tracing::warn!(channel_id = %channel.id, err = %e, "list channel keys failed");
+                HashMap::new()
+            }
+        };
+
+    let mut candidates: Vec<usize> = (0..keys.len())
+        .filter(|&i| {
+            !honor_auto_disable
+                || states
+                    .get(&pool[i])
+                    .is_none_or(|s| s.auto_disabled_until_ms <= now_ms)
+        })
+        .collect();
+    // 全部 key 都被禁用时退回整个池，渠道级别的禁用由路由过滤负责
+    if candidates.is_empty() {
+        candidates = (0..keys.len()).collect();
+    }
+    // 跳过被上游限速的 key；全部被限速时渠道已在路由阶段被剔除
+    let available: Vec<usize> = candidates
+        .iter()
+        .copied()
+        .filter(|&i| ratelimit::key_limited_until(&channel.id, &pool[i], now_ms).is_none())
+        .collect();
+    if !available.is_empty() {
+        candidates = available;
+    }
+
+    let picked = match channel.key_strategy {
+        KeyStrategy::RoundRobin => {
+            let mut counters = round_robin_counters()
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            let counter = counters.entry(channel.id.clone()).or_insert(0);
+            let idx = *counter % candidates.len();
+            *counter = counter.wrapping_add(1);
+            candidates[idx]
+        }
+        KeyStrategy::LeastUsed => candidates
+            .iter()
+            .copied()
+            .min_by_key(|&i| {
+                states
+                    .get(&pool[i])
+                    .map(|s| (s.use_count, s.last_used_ms.unwrap_or(0)))
+                    .unwrap_or((0, 0))
+            })
+            .unwrap_or(candidates[0]),
+        KeyStrategy::Random => {
+            let r = uuid::Uuid::new_v4().as_u128();
+            candidates[(r % candidates.len() as u128) as usize]
+        }
+    };
+
+    let fingerprint = pool[picked].clone();
+    if let Err(e) = storage::touch_channel_key(
+        db_path.to_path_buf(),
+        channel.id.clone(),
+        fingerprint.clone(),
+        now_ms,
+    )
+    .await
+    {
+        tracing::warn!(channel_id = %channel.id, err = %e, "touch channel key failed");
+    }
+
+    SelectedKey {
+        token: keys[picked].to_string(),
+        fingerprint: Some(fingerprint),
+        pool,
+    }
+}
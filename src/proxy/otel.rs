@@ -0,0 +1,404 @@
+use axum::http::HeaderValue;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::storage::{self, CreateUsageEvent, Protocol};
+
+pub(super) const TRACEPARENT_HEADER: &str = "traceparent";
+// 流被客户端中途丢弃时收不到用量记录，超过该时间仍未结束的追踪直接导出已有部分
+const STALE_TRACE_MS: i64 = 10 * 60 * 1000;
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SPAN_KIND_SERVER: i64 = 2;
+const SPAN_KIND_CLIENT: i64 = 3;
+const STATUS_OK: i64 = 1;
+const STATUS_ERROR: i64 = 2;
+
+// 一个入站请求对应的追踪：根 span 在 forward 返回时结束，各渠道尝试在用量记录写入后结束
+#[derive(Debug)]
+struct RequestTrace {
+    endpoint: String,
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    started_ms: i64,
+    attributes: Vec<(&'static str, Value)>,
+    ended: Option<(i64, Option<String>)>,
+    // channel_id -> (span_id, 开始时间)，已带着 traceparent 发往上游、尚未结束
+    attempts: HashMap<String, (String, i64)>,
+    // 用量记录已产生、正在等待费用计算的尝试
+    finishing: usize,
+    spans: Vec<Value>,
+}
+
+impl RequestTrace {
+    fn is_complete(&self) -> bool {
+        self.ended.is_some() && self.attempts.is_empty() && self.finishing == 0
+    }
+
+    fn root_span(&self) -> Value {
+        let (end_ms, error) = self.ended.clone().unwrap_or((storage::now_ms(), None));
+        span_json(SpanJson {
+            trace_id: &self.trace_id,
+            span_id: &self.span_id,
+            parent_span_id: self.parent_span_id.as_deref(),
+            name: "proxy request",
+            kind: SPAN_KIND_SERVER,
+            start_ms: self.started_ms,
+            end_ms,
+            attributes: &self.attributes,
+            error: error.as_deref(),
+        })
+    }
+}
+
+fn traces() -> &'static Mutex<HashMap<Arc<str>, RequestTrace>> {
+    static TRACES: OnceLock<Mutex<HashMap<Arc<str>, RequestTrace>>> = OnceLock::new();
+    TRACES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn new_id(bytes: usize) -> String {
+    hex::encode(&Uuid::new_v4().as_bytes()[..bytes])
+}
+
+// W3C traceparent：00-<32 位 trace-id>-<16 位 parent-id>-<flags>
+fn parse_traceparent(v: &str) -> Option<(String, String)> {
+    let mut parts = v.trim().split('-');
+    let (version, trace_id, parent_id, flags) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let is_hex = |s: &str, len: usize| {
+        s.len() == len
+            && s.bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    };
+    if !is_hex(version, 2) || version == "ff" || !is_hex(flags, 2) {
+        return None;
+    }
+    if !is_hex(trace_id, 32) || !is_hex(parent_id, 16) {
+        return None;
+    }
+    if trace_id.bytes().all(|b| b == b'0') || parent_id.bytes().all(|b| b == b'0') {
+        return None;
+    }
+    Some((trace_id.to_string(), parent_id.to_string()))
+}
+
+pub(crate) fn validate_otlp_endpoint(v: &str) -> Result<(), String> {
+    let url = reqwest::Url::parse(v.trim()).map_err(|e| format!("otlp_endpoint 无效：{e}"))?;
+    match url.scheme() {
+        "http" | "https" => Ok(()),
+        other => Err(format!(
+            "otlp_endpoint 不支持的协议：{other}（仅支持 http/https）"
+        )),
+    }
+}
+
+// 未开启时不登记，之后的调用都是空操作
+pub(super) fn begin_request(
+    settings: &storage::AppSettings,
+    request_id: &Arc<str>,
+    protocol: Protocol,
+    method: &axum::http::Method,
+    uri: &axum::http::Uri,
+    headers: &axum::http::HeaderMap,
+) {
+    if !settings.otlp_enabled || settings.otlp_endpoint.trim().is_empty() {
+        return;
+    }
+    let now_ms = storage::now_ms();
+    let (trace_id, parent_span_id) = match headers
+        .get(TRACEPARENT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_traceparent)
+    {
+        Some((trace_id, parent)) => (trace_id, Some(parent)),
+        None => (new_id(16), None),
+    };
+    let trace = RequestTrace {
+        endpoint: settings.otlp_endpoint.trim().to_string(),
+        trace_id,
+        span_id: new_id(8),
+        parent_span_id,
+        started_ms: now_ms,
+        attributes: vec![
+            ("cliswitch.request_id", json!(request_id.as_ref())),
+            ("cliswitch.protocol", json!(protocol.as_str())),
+            ("http.request.method", json!(method.as_str())),
+            ("url.path", json!(uri.path())),
+        ],
+        ended: None,
+        attempts: HashMap::new(),
+        finishing: 0,
+        spans: Vec::new(),
+    };
+
+    let stale = {
+        let mut map = traces().lock().unwrap_or_else(|e| e.into_inner());
+        let stale_ids: Vec<_> = map
+            .iter()
+            .filter(|(_, t)| now_ms - t.started_ms > STALE_TRACE_MS)
+            .map(|(id, _)| id.clone())
+            .collect();
+        let stale: Vec<_> = stale_ids.iter().filter_map(|id| map.remove(id)).collect();
+        map.insert(request_id.clone(), trace);
+        stale
+    };
+    for t in stale {
+        export(t);
+    }
+}
+
+pub(super) fn end_request<T>(
+    request_id: &str,
+    result: &Result<axum::http::Response<T>, super::ProxyError>,
+) {
+    let done = {
+        let mut map = traces().lock().unwrap_or_else(|e| e.into_inner());
+        let Some(t) = map.get_mut(request_id) else {
+            return;
+        };
+        let error = match result {
+            Ok(resp) => {
+                let status = resp.status().as_u16();
+                t.attributes
+                    .push(("http.response.status_code", json!(status)));
+                (status >= 400).then(|| format!("HTTP {status}"))
+            }
+            Err(e) => Some(e.to_string()),
+        };
+        t.ended = Some((storage::now_ms(), error));
+        if t.is_complete() {
+            map.remove(request_id)
+        } else {
+            None
+        }
+    };
+    if let Some(t) = done {
+        export(t);
+    }
+}
+
+// 为发往上游的一次尝试分配 span，返回要注入的 traceparent
+pub(super) fn attempt_traceparent(request_id: &str, channel_id: &str) -> Option<HeaderValue> {
+    let mut map = traces().lock().unwrap_or_else(|e| e.into_inner());
+    let t = map.get_mut(request_id)?;
+    let span_id = new_id(8);
+    let header = format!("00-{}-{span_id}-01", t.trace_id);
+    t.attempts
+        .insert(channel_id.to_string(), (span_id, storage::now_ms()));
+    HeaderValue::from_str(&header).ok()
+}
+
+// 用量记录产生时取出对应尝试；缓存命中等未经上游的记录也各自成为一个 span
+pub(super) struct PendingAttempt {
+    request_id: Arc<str>,
+    span_id: String,
+    started_ms: i64,
+    event: CreateUsageEvent,
+}
+
+pub(super) fn attempt_finished(event: &CreateUsageEvent) -> Option<PendingAttempt> {
+    let request_id = event.request_id.clone()?;
+    let mut map = traces().lock().unwrap_or_else(|e| e.into_inner());
+    let t = map.get_mut(&request_id)?;
+    let (span_id, started_ms) = t
+        .attempts
+        .remove(&event.channel_id)
+        .unwrap_or_else(|| (new_id(8), event.ts_ms - event.latency_ms.max(0)));
+    t.finishing += 1;
+    Some(PendingAttempt {
+        request_id,
+        span_id,
+        started_ms,
+        event: event.clone(),
+    })
+}
+
+impl PendingAttempt {
+    pub(super) fn finish(self, cost_usd: Option<&str>) {
+        let e = &self.event;
+        let mut attributes = vec![
+            ("cliswitch.channel.id", json!(e.channel_id)),
+            ("cliswitch.protocol", json!(e.protocol.as_str())),
+        ];
+        let mut push = |key: &'static str, v: Option<Value>| {
+            if let Some(v) = v {
+                attributes.push((key, v));
+            }
+        };
+        push("gen_ai.request.model", e.model.as_deref().map(|m| json!(m)));
+        push(
+            "cliswitch.upstream_model",
+            e.upstream_model.as_deref().map(|m| json!(m)),
+        );
+        push("http.response.status_code", e.http_status.map(|s| json!(s)));
+        push("error.type", e.error_kind.as_deref().map(|k| json!(k)));
+        push(
+            "gen_ai.usage.input_tokens",
+            e.prompt_tokens.map(|n| json!(n)),
+        );
+        push(
+            "gen_ai.usage.output_tokens",
+            e.completion_tokens.map(|n| json!(n)),
+        );
+        push(
+            "cliswitch.usage.cache_read_tokens",
+            e.cache_read_tokens.map(|n| json!(n)),
+        );
+        push(
+            "cliswitch.usage.cache_write_tokens",
+            e.cache_write_tokens.map(|n| json!(n)),
+        );
+        push("cliswitch.ttft_ms", e.ttft_ms.map(|n| json!(n)));
+        push(
+            "cliswitch.cost_usd",
+            cost_usd
+                .and_then(|c| c.parse::<f64>().ok())
+                .map(|c| json!(c)),
+        );
+        push("cliswitch.cancelled", e.cancelled.then_some(json!(true)));
+        push("cliswitch.cache_hit", e.cache_hit.then_some(json!(true)));
+
+        let error = (!e.success && !e.cancelled).then(|| {
+            e.error_detail
+                .clone()
+                .or_else(|| e.error_kind.clone())
+                .unwrap_or_else(|| "upstream_error".to_string())
+        });
+
+        let done = {
+            let mut map = traces().lock().unwrap_or_else(|e| e.into_inner());
+            let Some(t) = map.get_mut(&self.request_id) else {
+                return;
+            };
+            let span = span_json(SpanJson {
+                trace_id: &t.trace_id,
+                span_id: &self.span_id,
+                parent_span_id: Some(&t.span_id),
+                name: "proxy attempt",
+                kind: SPAN_KIND_CLIENT,
+                start_ms: self.started_ms,
+                end_ms: e.ts_ms.max(self.started_ms),
+                attributes: &attributes,
+                error: error.as_deref(),
+            });
+            t.spans.push(span);
+            if let Some(m) = e.model.as_deref()
+                && !t
+                    .attributes
+                    .iter()
+                    .any(|(k, _)| *k == "gen_ai.request.model")
+            {
+                t.attributes.push(("gen_ai.request.model", json!(m)));
+            }
+            t.finishing = t.finishing.saturating_sub(1);
+            if t.is_complete() {
+                map.remove(&self.request_id)
+            } else {
+                None
+            }
+        };
+        if let Some(t) = done {
+            export(t);
+        }
+    }
+}
+
+struct SpanJson<'a> {
+    trace_id: &'a str,
+    span_id: &'a str,
+    parent_span_id: Option<&'a str>,
+    name: &'a str,
+    kind: i64,
+    start_ms: i64,
+    end_ms: i64,
+    attributes: &'a [(&'static str, Value)],
+    error: Option<&'a str>,
+}
+
+fn attribute_value(v: &Value) -> Value {
+    match v {
+        Value::Bool(b) => json!({ "boolValue": b }),
+        // OTLP JSON 中 int64 以字符串表示
+        Value::Number(n) if n.is_i64() || n.is_u64() => json!({ "intValue": n.to_string() }),
+        Value::Number(n) => json!({ "doubleValue": n.as_f64() }),
+        Value::String(s) => json!({ "stringValue": s }),
+        other => json!({ "stringValue": other.to_string() }),
+    }
+}
+
+fn span_json(s: SpanJson<'_>) -> Value {
+    let nanos = |ms: i64| (ms.max(0) as u128 * 1_000_000).to_string();
+    let mut span = json!({
+        "traceId": s.trace_id,
+        "spanId": s.span_id,
+        "name": s.name,
+        "kind": s.kind,
+        "startTimeUnixNano": nanos(s.start_ms),
+        "endTimeUnixNano": nanos(s.end_ms),
+        "attributes": s
+            .attributes
+            .iter()
+            .map(|(k, v)| json!({ "key": k, "value": attribute_value(v) }))
+            .collect::<Vec<_>>(),
+        "status": match s.error {
+            Some(msg) => json!({ "code": STATUS_ERROR, "message": super::truncate(msg, 1024) }),
+            None => json!({ "code": STATUS_OK }),
+        },
+    });
+    if let Some(parent) = s.parent_span_id {
+        span["parentSpanId"] = json!(parent);
+    }
+    span
+}
+
+fn export_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(EXPORT_TIMEOUT)
+            .no_proxy()
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+// 每个请求的所有 span 一次性发出；collector 不可达时只记 debug 日志，不影响转发
+fn export(mut trace: RequestTrace) {
+    let mut spans = vec![trace.root_span()];
+    spans.append(&mut trace.spans);
+    let payload = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": "cliswitch" } },
+                    {
+                        "key": "service.version",
+                        "value": { "stringValue": env!("CARGO_PKG_VERSION") },
+                    },
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "cliswitch.proxy" },
+                "spans": spans,
+            }],
+        }],
+    });
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    handle.spawn(async move {
+        let res = export_client()
+            .post(&trace.endpoint)
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = res {
+            tracing::debug!(endpoint = %trace.endpoint, err = %e, "export otlp spans failed");
+        }
+    });
+}
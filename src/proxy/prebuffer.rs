@@ -0,0 +1,167 @@
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt as _;
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::storage::Protocol;
+
+use super::UpstreamBody;
+use super::translate::{SseEvent, SseParser};
+
+// 超过这个大小仍没有有效内容时不再等待，直接开始转发
+const MAX_PREBUFFER_BYTES: usize = 1024 * 1024;
+
+pub(super) enum Prebuffered {
+    // 已收到首个有效内容（或上游正常结束），可以开始向客户端转发
+    Ready(UpstreamBody),
+    // 首个有效内容之前出错或超时；replay 是已读内容加剩余部分，最后一个渠道仍原样转发
+    Failed {
+        reason: String,
+        replay: UpstreamBody,
+    },
+}
+
+enum EventKind {
+    Content,
+    Error(String),
+    Other,
+}
+
+// 在 SSE 响应头交给客户端之前，先读到第一个有效内容事件
+pub(super) async fn until_first_content(
+    protocol: Protocol,
+    mut body: UpstreamBody,
+    timeout: Duration,
+) -> Prebuffered {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut buf = BytesMut::new();
+    let mut parser = SseParser::default();
+    let replay = |buf: BytesMut, rest: UpstreamBody| -> UpstreamBody {
+        futures_util::stream::once(async move { Ok::<Bytes, std::io::Error>(buf.freeze()) })
+            .chain(rest)
+            .boxed()
+    };
+
+    loop {
+        let next = match tokio::time::timeout_at(deadline, body.next()).await {
+            Ok(next) => next,
+            Err(_) => {
+                return Prebuffered::Failed {
+                    reason: format!("stream_stalled:{}ms 内未收到有效内容", timeout.as_millis()),
+                    replay: replay(buf, body),
+                };
+            }
+        };
+        let chunk = match next {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(e)) => {
+                let reason = match e.to_string() {
+                    msg if msg.starts_with("timeout:") => msg,
+                    msg => format!("stream_error:{msg}"),
+                };
+                let rest = futures_util::stream::once(async move { Err(e) }).boxed();
+                return Prebuffered::Failed {
+                    reason,
+                    replay: replay(buf, rest),
+                };
+            }
+            None => return Prebuffered::Ready(replay(buf, futures_util::stream::empty().boxed())),
+        };
+        buf.extend_from_slice(&chunk);
+
+        let mut events = Vec::new();
+        parser.push(&chunk, &mut events);
+        for ev in &events {
+            match classify(protocol, ev) {
+                EventKind::Content => return Prebuffered::Ready(replay(buf, body)),
+                EventKind::Error(msg) => {
+                    return Prebuffered::Failed {
+                        reason: format!("stream_error:{msg}"),
+                        replay: replay(buf, body),
+                    };
+                }
+                EventKind::Other => {}
+            }
+        }
+        if buf.len() >= MAX_PREBUFFER_BYTES {
+            return Prebuffered::Ready(replay(buf, body));
+        }
+    }
+}
+
+fn classify(protocol: Protocol, ev: &SseEvent) -> EventKind {
+    let data = ev.data.trim();
+    if data.is_empty() {
+        return EventKind::Other;
+    }
+    if data == "[DONE]" {
+        return EventKind::Content;
+    }
+    // 无法识别的格式不做拦截
+    let Ok(v) = serde_json::from_str::<Value>(data) else {
+        return EventKind::Content;
+    };
+    let ty = v.get("type").and_then(Value::as_str);
+    if ev.event.as_deref() == Some("error")
+        || ty == Some("error")
+        || v.get("error").is_some_and(|e| !e.is_null())
+    {
+        let msg = super::parse_error_message(protocol, data.as_bytes())
+            .unwrap_or_else(|| super::truncate(data, 240));
+        return EventKind::Error(msg);
+    }
+
+    let has_content = match protocol {
+        Protocol::Openai => match ty {
+            // Responses API：created / in_progress 只是状态通知
+            Some(t) if t.starts_with("response.") => !matches!(
+                t,
+                "response.created" | "response.in_progress" | "response.queued"
+            ),
+            _ => openai_chunk_has_content(&v),
+        },
+        Protocol::Anthropic => matches!(
+            ty,
+            Some("content_block_delta" | "message_delta" | "message_stop")
+        ),
+        Protocol::Gemini => gemini_chunk_has_content(&v),
+    };
+    if has_content {
+        EventKind::Content
+    } else {
+        EventKind::Other
+    }
+}
+
+fn openai_chunk_has_content(v: &Value) -> bool {
+    let Some(choices) = v.get("choices").and_then(Value::as_array) else {
+        return false;
+    };
+    choices.iter().any(|c| {
+        if c.get("finish_reason").is_some_and(|r| !r.is_null()) {
+            return true;
+        }
+        let Some(delta) = c.get("delta") else {
+            return false;
+        };
+        ["content", "reasoning_content", "refusal"].iter().any(|k| {
+            delta
+                .get(k)
+                .and_then(Value::as_str)
+                .is_some_and(|s| !s.is_empty())
+        }) || delta.get("tool_calls").is_some_and(|t| !t.is_null())
+            || delta.get("function_call").is_some_and(|t| !t.is_null())
+    })
+}
+
+fn gemini_chunk_has_content(v: &Value) -> bool {
+    let Some(candidates) = v.get("candidates").and_then(Value::as_array) else {
+        return false;
+    };
+    candidates.iter().any(|c| {
+        c.get("finishReason").is_some_and(|r| !r.is_null())
+            || c.pointer("/content/parts")
+                .and_then(Value::as_array)
+                .is_some_and(|p| !p.is_empty())
+    })
+}
@@ -0,0 +1,75 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use crate::storage::{self, ClientToken};
+
+use super::ProxyError;
+
+const MINUTE_MS: i64 = 60_000;
+
+fn recent_requests() -> &'static Mutex<HashMap<String, VecDeque<i64>>> {
+    static RECENT: OnceLock<Mutex<HashMap<String, VecDeque<i64>>>> = OnceLock::new();
+    RECENT.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// 先查用量类配额，全部通过后才占用本分钟的请求次数
+pub(super) async fn check(
+    db_path: &Path,
+    token: &ClientToken,
+    now_ms: i64,
+) -> Result<(), ProxyError> {
+    if !token.has_quota() {
+        return Ok(());
+    }
+
+    if token.tokens_per_day.is_some() || token.usd_per_month.is_some() {
+        let usage = storage::client_token_usage(
+            db_path.to_path_buf(),
+            Some(token.id.clone()),
+            storage::local_day_start_ms(),
+            storage::local_month_start_ms(),
+        )
+        .await?
+        .remove(&token.id)
+        .unwrap_or_default();
+
+        if let Some(limit) = token.tokens_per_day
+            && usage.tokens_today >= limit
+        {
+            return Err(ProxyError::QuotaExceeded(format!(
+                "客户端令牌 {} 今日 token 用量已达上限（{}/{limit}）",
+                token.name, usage.tokens_today
+            )));
+        }
+        if let Some(limit) = token.usd_per_month
+            && usage.cost_usd_month >= limit
+        {
+            return Err(ProxyError::QuotaExceeded(format!(
+                "客户端令牌 {} 本月费用已达上限（${:.4}/${limit}）",
+                token.name, usage.cost_usd_month
+            )));
+        }
+    }
+
+    if let Some(limit) = token.requests_per_minute {
+        let mut recent = recent_requests().lock().unwrap_or_else(|e| e.into_inner());
+        // 顺带清掉所有令牌一分钟以前的记录，空窗口直接移除
+        recent.retain(|_, window| {
+            while window.front().is_some_and(|&t| t <= now_ms - MINUTE_MS) {
+                window.pop_front();
+            }
+            !window.is_empty()
+        });
+        let window = recent.entry(token.id.clone()).or_default();
+        if window.len() as i64 >= limit {
+            return Err(ProxyError::QuotaExceeded(format!(
+                "客户端令牌 {} 每分钟请求数已达上限（{limit}）",
+                token.name
+            )));
+        }
+        window.push_back(now_ms);
+    }
+
+    Ok(())
+}
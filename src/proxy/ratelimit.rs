@@ -0,0 +1,422 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use crate::storage::{self, Channel, RateLimitPolicy};
+
+use super::ProxyError;
+
+const MINUTE_MS: i64 = 60_000;
+// 上游只说额度已耗尽、没给出重置时间时，按一分钟处理
+const DEFAULT_RESET_MS: i64 = MINUTE_MS;
+
+// 上游限速头报告的一类额度
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct RateLimitWindow {
+    pub limit: Option<i64>,
+    pub remaining: Option<i64>,
+    pub reset_at_ms: Option<i64>,
+}
+
+impl RateLimitWindow {
+    fn exhausted_until(&self, now_ms: i64) -> Option<i64> {
+        match (self.remaining, self.reset_at_ms) {
+            (Some(r), Some(reset)) if r <= 0 && reset > now_ms => Some(reset),
+            _ => None,
+        }
+    }
+
+    fn consume(&mut self, n: i64) {
+        if let Some(r) = self.remaining.as_mut() {
+            *r = r.saturating_sub(n);
+        }
+    }
+}
+
+// 令牌桶：容量为每分钟额度，按 capacity / 60s 匀速回填；token 在响应结束后按实际用量扣减，可以扣成负数
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TokenBucket {
+    pub capacity: f64,
+    pub available: f64,
+    #[serde(skip)]
+    refilled_at_ms: i64,
+}
+
+impl TokenBucket {
+    fn full(capacity: f64, now_ms: i64) -> Self {
+        Self {
+            capacity,
+            available: capacity,
+            refilled_at_ms: now_ms,
+        }
+    }
+
+    fn refill(&mut self, capacity: f64, now_ms: i64) {
+        let elapsed = now_ms.saturating_sub(self.refilled_at_ms).max(0) as f64;
+        self.capacity = capacity;
+        self.available = (self.available + elapsed * capacity / MINUTE_MS as f64).min(capacity);
+        self.refilled_at_ms = now_ms;
+    }
+
+    fn ready_at(&self, now_ms: i64) -> Option<i64> {
+        if self.available >= 1.0 {
+            return None;
+        }
+        let wait_ms = (1.0 - self.available) * MINUTE_MS as f64 / self.capacity.max(1.0);
+        Some(now_ms.saturating_add(wait_ms.ceil() as i64))
+    }
+}
+
+// 上游限速头按渠道内的 key 分别记录，同一渠道的其他 key 仍可使用
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KeyRateLimit {
+    pub key_fp: String,
+    pub requests: Option<RateLimitWindow>,
+    pub tokens: Option<RateLimitWindow>,
+    pub observed_at_ms: Option<i64>,
+}
+
+impl KeyRateLimit {
+    fn limited_until(&self, now_ms: i64) -> Option<i64> {
+        [
+            self.requests.and_then(|w| w.exhausted_until(now_ms)),
+            self.tokens.and_then(|w| w.exhausted_until(now_ms)),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
+}
+
+// 只保存在进程内，重启后从上游下一次响应重新获取
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RateLimitState {
+    // 最近一次上报限速头的 key
+    pub requests: Option<RateLimitWindow>,
+    pub tokens: Option<RateLimitWindow>,
+    pub observed_at_ms: Option<i64>,
+    pub keys: Vec<KeyRateLimit>,
+    pub request_bucket: Option<TokenBucket>,
+    pub token_bucket: Option<TokenBucket>,
+    // 在此之前路由会跳过该渠道：本地令牌桶耗尽，或所有 key 都被上游限速
+    pub limited_until_ms: Option<i64>,
+}
+
+// 渠道自己配置的令牌桶
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelBuckets {
+    request_bucket: Option<TokenBucket>,
+    token_bucket: Option<TokenBucket>,
+}
+
+impl ChannelBuckets {
+    // 按渠道当前的限速配置调整令牌桶；配置被清空时丢弃对应的桶
+    fn sync(&mut self, policy: Option<RateLimitPolicy>, now_ms: i64) {
+        let policy = policy.unwrap_or_default();
+        sync_bucket(&mut self.request_bucket, policy.requests_per_minute, now_ms);
+        sync_bucket(&mut self.token_bucket, policy.tokens_per_minute, now_ms);
+    }
+
+    fn limited_until(&self, now_ms: i64) -> Option<i64> {
+        [
+            self.request_bucket.and_then(|b| b.ready_at(now_ms)),
+            self.token_bucket.and_then(|b| b.ready_at(now_ms)),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
+}
+
+fn sync_bucket(bucket: &mut Option<TokenBucket>, per_minute: Option<i64>, now_ms: i64) {
+    match (bucket.as_mut(), per_minute) {
+        (_, None) => *bucket = None,
+        (Some(b), Some(cap)) => b.refill(cap as f64, now_ms),
+        (None, Some(cap)) => *bucket = Some(TokenBucket::full(cap as f64, now_ms)),
+    }
+}
+
+fn buckets() -> &'static Mutex<HashMap<String, ChannelBuckets>> {
+    static BUCKETS: OnceLock<Mutex<HashMap<String, ChannelBuckets>>> = OnceLock::new();
+    BUCKETS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// (channel_id, key 指纹)；没有 key 的渠道指纹为空串
+fn key_limits() -> &'static Mutex<HashMap<(String, String), KeyRateLimit>> {
+    static KEYS: OnceLock<Mutex<HashMap<(String, String), KeyRateLimit>>> = OnceLock::new();
+    KEYS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn limit_key(channel_id: &str, key_fp: Option<&str>) -> (String, String) {
+    (
+        channel_id.to_string(),
+        key_fp.unwrap_or_default().to_string(),
+    )
+}
+
+fn channel_key_fps(channel: &Channel) -> Vec<String> {
+    let fps: Vec<String> = channel
+        .keys()
+        .iter()
+        .filter(|k| !k.is_empty())
+        .map(|k| storage::key_fingerprint(k))
+        .collect();
+    if fps.is_empty() {
+        vec![String::new()]
+    } else {
+        fps
+    }
+}
+
+// 记录上游响应中的限速头（OpenAI 的 x-ratelimit-*、Anthropic 的 anthropic-ratelimit-*）
+pub(super) fn observe(
+    channel_id: &str,
+    key_fp: Option<&str>,
+    headers: &reqwest::header::HeaderMap,
+    now_ms: i64,
+) {
+    let retry_after_ms = super::retry_after_secs(headers).map(|s| s.saturating_mul(1000));
+    let window = |kinds: &[&str]| {
+        kinds
+            .iter()
+            .find_map(|kind| parse_window(headers, kind, now_ms, retry_after_ms))
+    };
+    let requests = window(&["requests"]);
+    let tokens = window(&["tokens", "input-tokens"]);
+    if requests.is_none() && tokens.is_none() {
+        return;
+    }
+
+    let mut map = key_limits().lock().unwrap_or_else(|e| e.into_inner());
+    let key = limit_key(channel_id, key_fp);
+    let state = map.entry(key.clone()).or_insert_with(|| KeyRateLimit {
+        key_fp: key.1,
+        ..Default::default()
+    });
+    if requests.is_some() {
+        state.requests = requests;
+    }
+    if tokens.is_some() {
+        state.tokens = tokens;
+    }
+    state.observed_at_ms = Some(now_ms);
+}
+
+// 该 key 被上游限速时返回恢复时间，供选 key 时跳过
+pub(super) fn key_limited_until(channel_id: &str, key_fp: &str, now_ms: i64) -> Option<i64> {
+    let map = key_limits().lock().unwrap_or_else(|e| e.into_inner());
+    map.get(&(channel_id.to_string(), key_fp.to_string()))
+        .and_then(|k| k.limited_until(now_ms))
+}
+
+fn header<'h>(headers: &'h reqwest::header::HeaderMap, name: &str) -> Option<&'h str> {
+    headers.get(name)?.to_str().ok().map(str::trim)
+}
+
+fn parse_window(
+    headers: &reqwest::header::HeaderMap,
+    kind: &str,
+    now_ms: i64,
+    retry_after_ms: Option<i64>,
+) -> Option<RateLimitWindow> {
+    let value = |openai: String, anthropic: String| {
+        header(headers, &openai).or_else(|| header(headers, &anthropic))
+    };
+    let limit = value(
+        format!("x-ratelimit-limit-{kind}"),
+        format!("anthropic-ratelimit-{kind}-limit"),
+    )
+    .and_then(|v| v.parse::<i64>().ok());
+    let remaining = value(
+        format!("x-ratelimit-remaining-{kind}"),
+        format!("anthropic-ratelimit-{kind}-remaining"),
+    )
+    .and_then(|v| v.parse::<i64>().ok());
+    if limit.is_none() && remaining.is_none() {
+        return None;
+    }
+    let reset_at_ms = value(
+        format!("x-ratelimit-reset-{kind}"),
+        format!("anthropic-ratelimit-{kind}-reset"),
+    )
+    .and_then(|v| parse_reset(v, now_ms))
+    .or_else(|| {
+        remaining
+            .is_some_and(|r| r <= 0)
+            .then(|| now_ms.saturating_add(retry_after_ms.unwrap_or(DEFAULT_RESET_MS)))
+    });
+    Some(RateLimitWindow {
+        limit,
+        remaining,
+        reset_at_ms,
+    })
+}
+
+// 支持 OpenAI 的时长（"6m0s"、"20ms"、"1.5s"）、Anthropic 的 RFC 3339 时间和纯秒数
+fn parse_reset(v: &str, now_ms: i64) -> Option<i64> {
+    if let Ok(secs) = v.parse::<f64>() {
+        return Some(now_ms.saturating_add((secs.max(0.0) * 1000.0) as i64));
+    }
+    if let Some(ms) = parse_duration_ms(v) {
+        return Some(now_ms.saturating_add(ms));
+    }
+    let at = time::OffsetDateTime::parse(v, &time::format_description::well_known::Rfc3339).ok()?;
+    Some((at.unix_timestamp_nanos() / 1_000_000) as i64)
+}
+
+fn parse_duration_ms(v: &str) -> Option<i64> {
+    let mut total = 0.0;
+    let mut rest = v;
+    while !rest.is_empty() {
+        let num_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let n: f64 = rest[..num_len].parse().ok()?;
+        rest = &rest[num_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let factor = match &rest[..unit_len] {
+            "h" => 3_600_000.0,
+            "m" => 60_000.0,
+            "s" => 1000.0,
+            "ms" => 1.0,
+            _ => return None,
+        };
+        total += n * factor;
+        rest = &rest[unit_len..];
+    }
+    Some(total as i64)
+}
+
+// 渠道的令牌桶耗尽，或池中每个 key 都被上游限速时返回最早的恢复时间
+fn channel_limited_until(channel: &Channel, now_ms: i64) -> Option<i64> {
+    let bucket = {
+        let mut map = buckets().lock().unwrap_or_else(|e| e.into_inner());
+        match map.get_mut(&channel.id) {
+            Some(b) => {
+                b.sync(channel.rate_limit, now_ms);
+                b.limited_until(now_ms)
+            }
+            None => None,
+        }
+    };
+    let keys = channel_key_fps(channel)
+        .iter()
+        .map(|fp| key_limited_until(&channel.id, fp, now_ms))
+        .collect::<Option<Vec<_>>>()
+        .and_then(|v| v.into_iter().min());
+    bucket.max(keys)
+}
+
+// 剔除限速中的渠道；全部被限速时返回 429，而不是把请求打到必然失败的上游
+pub(super) fn filter_rate_limited(
+    channels: Vec<Channel>,
+    now_ms: i64,
+) -> Result<Vec<Channel>, ProxyError> {
+    let mut earliest: Option<i64> = None;
+    let mut out = Vec::with_capacity(channels.len());
+    for channel in channels {
+        match channel_limited_until(&channel, now_ms) {
+            Some(until) => {
+                tracing::debug!(
+                    channel_id = %channel.id,
+                    limited_until_ms = until,
+                    "skip rate limited channel"
+                );
+                earliest = Some(earliest.map_or(until, |e| e.min(until)));
+            }
+            None => out.push(channel),
+        }
+    }
+    match earliest {
+        Some(until) if out.is_empty() => {
+            let secs = ((until - now_ms).max(0) + 999) / 1000;
+            Err(ProxyError::QuotaExceeded(format!(
+                "可用渠道均已达到速率限制，约 {secs} 秒后恢复"
+            )))
+        }
+        _ => Ok(out),
+    }
+}
+
+// 发出请求前占用一次请求额度
+pub(super) fn acquire(channel: &Channel, key_fp: Option<&str>, now_ms: i64) {
+    if channel.rate_limit.is_some() {
+        let mut map = buckets().lock().unwrap_or_else(|e| e.into_inner());
+        let b = map.entry(channel.id.clone()).or_default();
+        b.sync(channel.rate_limit, now_ms);
+        if let Some(b) = b.request_bucket.as_mut() {
+            b.available -= 1.0;
+        }
+    }
+    let mut map = key_limits().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(w) = map
+        .get_mut(&limit_key(&channel.id, key_fp))
+        .and_then(|k| k.requests.as_mut())
+    {
+        w.consume(1);
+    }
+}
+
+// 用量记录后按实际 token 数扣减
+pub(super) fn consume_tokens(channel_id: &str, key_fp: Option<&str>, tokens: i64, now_ms: i64) {
+    if tokens <= 0 {
+        return;
+    }
+    {
+        let mut map = buckets().lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(b) = map
+            .get_mut(channel_id)
+            .and_then(|b| b.token_bucket.as_mut())
+        {
+            let capacity = b.capacity;
+            b.refill(capacity, now_ms);
+            b.available -= tokens as f64;
+        }
+    }
+    let mut map = key_limits().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(w) = map
+        .get_mut(&limit_key(channel_id, key_fp))
+        .and_then(|k| k.tokens.as_mut())
+    {
+        w.consume(tokens);
+    }
+}
+
+// 供 /api/channels 展示；既没有配置限速也没收到过限速头时为 None。
+// 顺带清掉已经不在池中的 key 和已删除配置的令牌桶
+pub(crate) fn rate_limit_state(channel: &Channel, now_ms: i64) -> Option<RateLimitState> {
+    let fps = channel_key_fps(channel);
+    let keys: Vec<KeyRateLimit> = {
+        let mut map = key_limits().lock().unwrap_or_else(|e| e.into_inner());
+        map.retain(|(channel_id, fp), _| channel_id != &channel.id || fps.contains(fp));
+        fps.iter()
+            .filter_map(|fp| map.get(&(channel.id.clone(), fp.clone())).cloned())
+            .collect()
+    };
+    let b = {
+        let mut map = buckets().lock().unwrap_or_else(|e| e.into_inner());
+        let mut b = map.get(&channel.id).copied().unwrap_or_default();
+        b.sync(channel.rate_limit, now_ms);
+        if b.request_bucket.is_none() && b.token_bucket.is_none() {
+            map.remove(&channel.id);
+        } else {
+            map.insert(channel.id.clone(), b);
+        }
+        b
+    };
+    if keys.is_empty() && b.request_bucket.is_none() && b.token_bucket.is_none() {
+        return None;
+    }
+    let latest = keys.iter().max_by_key(|k| k.observed_at_ms);
+    Some(RateLimitState {
+        requests: latest.and_then(|k| k.requests),
+        tokens: latest.and_then(|k| k.tokens),
+        observed_at_ms: latest.and_then(|k| k.observed_at_ms),
+        request_bucket: b.request_bucket,
+        token_bucket: b.token_bucket,
+        limited_until_ms: channel_limited_until(channel, now_ms),
+        keys,
+    })
+}
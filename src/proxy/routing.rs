@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use crate::storage::{
+    self, Channel, LoadBalanceMode, ModelMatchKind, ModelPattern, Protocol, Route,
+};
+
+use super::ProxyError;
+use super::budget::filter_over_budget;
+use super::ratelimit::filter_rate_limited;
+
+pub struct ResolvedChannels {
+    pub route: Option<Route>,
+    pub match_kind: Option<ModelMatchKind>,
+    pub channels: Vec<Channel>,
+}
+
+impl ResolvedChannels {
+    pub(super) fn route_id(&self) -> Option<String> {
+        self.route.as_ref().map(|r| r.id.clone())
+    }
+}
+
+pub(super) async fn resolve_channels(
+    db_path: PathBuf,
+    protocol: Protocol,
+    model: Option<&str>,
+    now_ms: i64,
+    settings: &storage::AppSettings,
+) -> Result<ResolvedChannels, ProxyError> {
+    let routes: Vec<Route> = storage::list_routes(db_path.clone())
+        .await?
+        .into_iter()
+        .filter(|r| r.enabled && r.protocol == protocol)
+        .collect();
+    let all_channels = storage::list_channels(db_path.clone()).await?;
+
+    // 配置了路由时只走匹配的路由；需要兜底可添加不填匹配模型的路由
+    let matched = match_routes(&routes, model);
+    if !routes.is_empty() && matched.is_empty() {
+        return Err(ProxyError::NoMatchingRoute(
+            protocol,
+            model.unwrap_or("（未指定）").to_string(),
+        ));
+    }
+    for (route, match_kind) in matched {
+        let members = storage::list_route_channels(db_path.clone(), route.id.clone()).await?;
+        if members.is_empty() {
+            continue;
+        }
+
+        let by_id: HashMap<&str, &Channel> =
+            all_channels.iter().map(|c| (c.id.as_str(), c)).collect();
+        let enabled: Vec<Channel> = members
+            .iter()
+            .filter(|m| m.cooldown_until_ms.is_none_or(|until| until <= now_ms))
+            .filter_map(|m| by_id.get(m.channel_id.as_str()).copied())
+            .filter(|c| c.enabled && protocol.accepts_channel(c.protocol))
+            .cloned()
+            .collect();
+        let channels = filter_auto_disabled(enabled, now_ms, settings);
+        let channels = filter_over_budget(db_path.clone(), channels, settings).await;
+        if channels.is_empty() {
+            return Err(ProxyError::NoAvailableChannel(protocol));
+        }
+        let channels = filter_rate_limited(channels, now_ms)?;
+        // 命中路由时按路由内的成员顺序分组，渠道自身的 priority 不参与
+        let member_priority: HashMap<&str, i64> = members
+            .iter()
+            .map(|m| (m.channel_id.as_str(), m.priority))
+            .collect();
+        let channels = balance_groups(
+            db_path,
+            channels,
+            |c| {
+                member_priority
+                    .get(c.id.as_str())
+                    .copied()
+                    .unwrap_or(c.priority)
+            },
+            now_ms,
+            settings,
+        )
+        .await;
+        let channels = defer_retry_after(channels, now_ms);
+        return Ok(ResolvedChannels {
+            route: Some(route.clone()),
+            match_kind: Some(match_kind),
+            channels,
+        });
+    }
+
+    let enabled: Vec<Channel> = all_channels
+        .into_iter()
+        .filter(|c| c.enabled && c.protocol == protocol)
+        .collect();
+    if enabled.is_empty() {
+        return Err(ProxyError::NoEnabledChannel(protocol));
+    }
+    let channels = filter_auto_disabled(enabled, now_ms, settings);
+    let channels = filter_over_budget(db_path.clone(), channels, settings).await;
+    if channels.is_empty() {
+        return Err(ProxyError::NoAvailableChannel(protocol));
+    }
+    let channels = filter_rate_limited(channels, now_ms)?;
+    let channels = balance_groups(db_path, channels, |c| c.priority, now_ms, settings).await;
+    let channels = defer_retry_after(channels, now_ms);
+    Ok(ResolvedChannels {
+        route: None,
+        match_kind: None,
+        channels,
+    })
+}
+
+// 匹配优先级：精确 > 通配（越具体越优先）> 正则 > 兜底；同级按路由名称排序
+fn match_routes<'a>(routes: &'a [Route], model: Option<&str>) -> Vec<(&'a Route, ModelMatchKind)> {
+    let mut matched: Vec<(&Route, ModelPattern)> = routes
+        .iter()
+        .filter_map(|r| match ModelPattern::parse(r.match_model.as_deref()) {
+            Ok(p) => Some((r, p)),
+            Err(e) => {
+                tracing::warn!(route_id = %r.id, err = %e, "skip route with invalid match_model");
+                None
+            }
+        })
+        .filter(|(_, p)| p.matches(model))
+        .collect();
+    matched.sort_by(|(ra, pa), (rb, pb)| {
+        pa.kind()
+            .cmp(&pb.kind())
+            .then_with(|| pb.specificity().cmp(&pa.specificity()))
+            .then_with(|| ra.name.cmp(&rb.name))
+    });
+    matched.into_iter().map(|(r, p)| (r, p.kind())).collect()
+}
+
+fn filter_auto_disabled(
+    channels: Vec<Channel>,
+    now_ms: i64,
+    settings: &storage::AppSettings,
+) -> Vec<Channel> {
+    if !settings.auto_disable_enabled {
+        return channels;
+    }
+    channels
+        .into_iter()
+        .filter(|c| !storage::channel_is_auto_disabled(c, now_ms))
+        .collect()
+}
+
+// 上游 Retry-After 只在进程内生效，重启后即失效
+fn retry_after_until() -> &'static Mutex<HashMap<String, i64>> {
+    static UNTIL: OnceLock<Mutex<HashMap<String, i64>>> = OnceLock::new();
+    UNTIL.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub(super) fn note_retry_after(channel_id: &str, until_ms: i64) {
+    let mut map = retry_after_until()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let entry = map.entry(channel_id.to_string()).or_insert(0);
+    *entry = (*entry).max(until_ms);
+}
+
+// 仍在 Retry-After 期内的渠道排到最后而不是剔除：其它渠道都失败时仍可一试
+fn defer_retry_after(channels: Vec<Channel>, now_ms: i64) -> Vec<Channel> {
+    let mut map = retry_after_until()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    map.retain(|_, until| *until > now_ms);
+    if map.is_empty() {
+        return channels;
+    }
+    let (waiting, ready): (Vec<Channel>, Vec<Channel>) =
+        channels.into_iter().partition(|c| map.contains_key(&c.id));
+    ready.into_iter().chain(waiting).collect()
+}
+
+const ADAPTIVE_WINDOW_MS: i64 = 15 * 60_000;
+
+// 相邻且 priority 相同的渠道视为一组，组内按权重随机排序，组间保持原有顺序用于故障转移
+async fn balance_groups(
+    db_path: PathBuf,
+    channels: Vec<Channel>,
+    priority: impl Fn(&Channel) -> i64,
+    now_ms: i64,
+    settings: &storage::AppSettings,
+) -> Vec<Channel> {
+    let mode = settings.load_balance_mode;
+    let has_group = channels
+        .windows(2)
+        .any(|w| priority(&w[0]) == priority(&w[1]));
+    if mode == LoadBalanceMode::Priority || !has_group {
+        return channels;
+    }
+
+    let health: HashMap<String, storage::ChannelHealth> = if mode == LoadBalanceMode::Adaptive {
+        match storage::channel_health_since(db_path, now_ms.saturating_sub(ADAPTIVE_WINDOW_MS))
+            .await
+        {
+            Ok(v) => v.into_iter().map(|h| (h.channel_id.clone(), h)).collect(),
+            Err(e) => {
+                tracing::warn!(err = %e, "load channel health failed");
+                HashMap::new()
+            }
+        }
+    } else {
+        HashMap::new()
+    };
+
+    let mut out = Vec::with_capacity(channels.len());
+    let mut group: Vec<Channel> = Vec::new();
+    for channel in channels {
+        if group
+            .last()
+            .is_some_and(|c| priority(c) != priority(&channel))
+        {
+            out.extend(shuffle_weighted(std::mem::take(&mut group), mode, &health));
+        }
+        group.push(channel);
+    }
+    out.extend(shuffle_weighted(group, mode, &health));
+    out
+}
+
+fn effective_weight(
+    channel: &Channel,
+    mode: LoadBalanceMode,
+    health: &HashMap<String, storage::ChannelHealth>,
+) -> f64 {
+    let weight = channel.weight.max(1) as f64;
+    if mode != LoadBalanceMode::Adaptive {
+        return weight;
+    }
+    let Some(h) = health.get(&channel.id) else {
+        return weight;
+    };
+    // 成功率做平滑，避免少量样本把渠道打到 0；耗时按秒衰减
+    let success_rate = (h.requests - h.failed + 1) as f64 / (h.requests + 2) as f64;
+    let latency_s = h.avg_latency_ms.unwrap_or(1000.0).max(0.0) / 1000.0;
+    weight * success_rate * success_rate / (1.0 + latency_s)
+}
+
+// 加权随机排列（Efraimidis–Spirakis）：key = u^(1/w)，按 key 从大到小
+fn shuffle_weighted(
+    group: Vec<Channel>,
+    mode: LoadBalanceMode,
+    health: &HashMap<String, storage::ChannelHealth>,
+) -> Vec<Channel> {
+    if group.len() < 2 {
+        return group;
+    }
+    let mut keyed: Vec<(f64, Channel)> = group
+        .into_iter()
+        .map(|c| {
+            let w = effective_weight(&c, mode, health).max(f64::MIN_POSITIVE);
+            (random_unit().powf(1.0 / w), c)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().map(|(_, c)| c).collect()
+}
+
+fn random_unit() -> f64 {
+    // v4 UUID 的低 62 位都是随机位
+    let bits = (uuid::Uuid::new_v4().as_u128() as u64) & ((1u64 << 53) - 1);
+    (bits as f64 + 0.5) / (1u64 << 53) as f64
+}
@@ -4,24 +4,34 @@ use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Instant;
 
+use crate::metrics;
 use crate::storage::Protocol;
 
 #[derive(Clone)]
 pub(super) struct StreamRecordContext {
     pub(super) db_path: std::path::PathBuf,
     pub(super) protocol: Protocol,
+    // 渠道协议，跨协议转换时与 protocol 不同，用于解析上游用量与错误
+    pub(super) upstream_protocol: Protocol,
+    pub(super) route_id: Option<String>,
+    pub(super) client_token_id: Option<String>,
     pub(super) channel_id: String,
     pub(super) model: Option<String>,
+    pub(super) upstream_model: Option<String>,
+    pub(super) key_fingerprint: Option<String>,
     pub(super) request_id: Arc<str>,
+    // 本次请求内第几条用量记录，从 0 开始
+    pub(super) attempt_index: usize,
     pub(super) http_status: i64,
     pub(super) status_is_success: bool,
     pub(super) started: Instant,
     pub(super) parse_sse: bool,
     pub(super) record_usage: bool,
+    pub(super) capture: bool,
 }
 
 pub(super) struct InstrumentedStream {
-    inner: futures_util::stream::BoxStream<'static, Result<Bytes, reqwest::Error>>,
+    inner: super::UpstreamBody,
     ctx: StreamRecordContext,
     finalized: bool,
     ttft_ms: Option<i64>,
@@ -31,13 +41,18 @@ pub(super) struct InstrumentedStream {
     sse_log_truncated: bool,
     err_body_buf: Vec<u8>,
     stream_error: Option<String>,
+    capture: Option<super::capture::CaptureBuf>,
+    // 已计入 cliswitch_streams_in_flight，结束或被丢弃时减回
+    in_flight: bool,
 }
 
 impl InstrumentedStream {
-    pub(super) fn new(
-        inner: futures_util::stream::BoxStream<'static, Result<Bytes, reqwest::Error>>,
-        ctx: StreamRecordContext,
-    ) -> Self {
+    pub(super) fn new(inner: super::UpstreamBody, ctx: StreamRecordContext) -> Self {
+        let capture = ctx.capture.then(Default::default);
+        let in_flight = ctx.record_usage;
+        if in_flight {
+            metrics::stream_started(ctx.protocol, &ctx.channel_id);
+        }
         Self {
             inner,
             ctx,
@@ -49,6 +64,14 @@ impl InstrumentedStream {
             sse_log_truncated: false,
             err_body_buf: Vec::new(),
             stream_error: None,
+            capture,
+            in_flight,
+        }
+    }
+
+    fn leave_in_flight(&mut self) {
+        if std::mem::take(&mut self.in_flight) {
+            metrics::stream_finished(self.ctx.protocol, &self.ctx.channel_id);
         }
     }
 
@@ -58,6 +81,9 @@ impl InstrumentedStream {
         if self.ttft_ms.is_none() {
             self.ttft_ms = Some(self.ctx.started.elapsed().as_millis() as i64);
         }
+        if let Some(c) = self.capture.as_mut() {
+            c.push(bytes);
+        }
         if !self.ctx.status_is_success && self.err_body_buf.len() < MAX_ERR_BODY_BUF {
             let remain = MAX_ERR_BODY_BUF - self.err_body_buf.len();
             self.err_body_buf
@@ -102,8 +128,10 @@ impl InstrumentedStream {
             let Ok(v) = serde_json::from_str::<serde_json::Value>(data) else {
                 continue;
             };
-            self.usage
-                .merge(super::extract_usage_from_value(self.ctx.protocol, &v));
+            self.usage.merge(super::extract_usage_from_value(
+                self.ctx.upstream_protocol,
+                &v,
+            ));
         }
     }
 
@@ -112,6 +140,7 @@ impl InstrumentedStream {
             return;
         }
         self.finalized = true;
+        self.leave_in_flight();
         if !self.ctx.record_usage {
             return;
         }
@@ -126,7 +155,12 @@ impl InstrumentedStream {
         } else if !self.ctx.status_is_success {
             Some(format!("upstream_http:{}", self.ctx.http_status))
         } else if let Some(err) = self.stream_error.as_deref() {
-            Some(format!("stream_error:{}", super::truncate(err, 240)))
+            // 读取超时已经是 timeout:idle / timeout:total 形式
+            if err.starts_with("timeout:") {
+                Some(super::truncate(err, 240))
+            } else {
+                Some(format!("stream_error:{}", super::truncate(err, 240)))
+            }
         } else {
             Some("upstream_error".to_string())
         };
@@ -135,7 +169,7 @@ impl InstrumentedStream {
         } else if let Some(err) = self.stream_error.as_deref() {
             Some(super::truncate(err, 2000))
         } else if !self.ctx.status_is_success && !self.err_body_buf.is_empty() {
-            let msg = super::parse_error_message(self.ctx.protocol, &self.err_body_buf)
+            let msg = super::parse_error_message(self.ctx.upstream_protocol, &self.err_body_buf)
                 .unwrap_or_else(|| String::from_utf8_lossy(&self.err_body_buf).to_string());
             Some(super::truncate(&msg, 2000))
         } else {
@@ -181,10 +215,16 @@ impl InstrumentedStream {
 
         let event = super::build_usage_event(super::UsageEventParams {
             request_id: Some(self.ctx.request_id.clone()),
+            attempt_index: self.ctx.attempt_index,
             protocol: self.ctx.protocol,
+            route_id: self.ctx.route_id.clone(),
+            client_token_id: self.ctx.client_token_id.clone(),
             channel_id: self.ctx.channel_id.clone(),
             model: self.ctx.model.clone(),
+            upstream_model: self.ctx.upstream_model.clone(),
+            key_fingerprint: self.ctx.key_fingerprint.clone(),
             success,
+            cancelled: false,
             http_status: Some(self.ctx.http_status),
             error_kind,
             error_detail,
@@ -199,6 +239,16 @@ impl InstrumentedStream {
             ),
         });
         super::spawn_usage_event(event, self.ctx.db_path.clone());
+
+        if let Some(c) = self.capture.take() {
+            c.spawn_store(
+                self.ctx.db_path.clone(),
+                &self.ctx.request_id,
+                &self.ctx.channel_id,
+                self.ctx.http_status,
+                self.ctx.parse_sse,
+            );
+        }
     }
 }
 
@@ -214,7 +264,7 @@ impl futures_util::Stream for InstrumentedStream {
             }
             Poll::Ready(Some(Err(e))) => {
                 self.stream_error = Some(e.to_string());
-                Poll::Ready(Some(Err(std::io::Error::other(e))))
+                Poll::Ready(Some(Err(e)))
             }
             Poll::Ready(None) => {
                 self.finalize();
@@ -224,3 +274,10 @@ impl futures_util::Stream for InstrumentedStream {
         }
     }
 }
+
+// 客户端中途断开时流不会读到结尾，只需把在途计数减回
+impl Drop for InstrumentedStream {
+    fn drop(&mut self) {
+        self.leave_in_flight();
+    }
+}
@@ -0,0 +1,75 @@
+use futures_util::StreamExt as _;
+use std::time::{Duration, Instant};
+
+use crate::storage::TimeoutPolicy;
+
+use super::{SendError, UpstreamBody};
+
+fn ms(v: i64) -> Option<Duration> {
+    (v > 0).then(|| Duration::from_millis(v as u64))
+}
+
+// 取更早到期的那个限制
+fn earliest(
+    a: Option<(Duration, &'static str)>,
+    b: Option<(Duration, &'static str)>,
+) -> Option<(Duration, &'static str)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b.0 < a.0 { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn total_remaining(timeouts: &TimeoutPolicy, started: Instant) -> Option<(Duration, &'static str)> {
+    ms(timeouts.total_ms).map(|t| (t.saturating_sub(started.elapsed()), "total"))
+}
+
+pub(super) async fn send(
+    request: reqwest::RequestBuilder,
+    timeouts: TimeoutPolicy,
+    started: Instant,
+) -> Result<reqwest::Response, SendError> {
+    let limit = earliest(
+        ms(timeouts.ttfb_ms).map(|d| (d, "ttfb")),
+        total_remaining(&timeouts, started),
+    );
+    let Some((wait, kind)) = limit else {
+        return request.send().await.map_err(SendError::from);
+    };
+    match tokio::time::timeout(wait, request.send()).await {
+        Ok(r) => r.map_err(SendError::from),
+        Err(_) => Err(SendError::timeout(kind, wait)),
+    }
+}
+
+// 响应体按 idle / total 限制读取，超时后以 TimedOut 错误结束
+pub(super) fn limit_body(
+    body: UpstreamBody,
+    timeouts: TimeoutPolicy,
+    started: Instant,
+) -> UpstreamBody {
+    if timeouts.idle_ms <= 0 && timeouts.total_ms <= 0 {
+        return body;
+    }
+    futures_util::stream::unfold(Some(body), move |state| async move {
+        let mut body = state?;
+        let limit = earliest(
+            ms(timeouts.idle_ms).map(|d| (d, "idle")),
+            total_remaining(&timeouts, started),
+        );
+        let Some((wait, kind)) = limit else {
+            return body.next().await.map(|item| (item, Some(body)));
+        };
+        match tokio::time::timeout(wait, body.next()).await {
+            Ok(item) => item.map(|item| (item, Some(body))),
+            Err(_) => Some((
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("timeout:{kind}"),
+                )),
+                None,
+            )),
+        }
+    })
+    .boxed()
+}
@@ -0,0 +1,481 @@
+use axum::http::{StatusCode, Uri};
+use bytes::Bytes;
+use serde_json::{Value, json};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::storage::Protocol;
+
+use super::ProxyError;
+
+mod anthropic;
+mod gemini;
+
+// 跨协议转换：以 OpenAI chat completions 为中间格式，入站协议 -> chat -> 渠道协议
+#[derive(Debug, Clone)]
+pub(super) struct Translation {
+    inbound: Protocol,
+    upstream: Protocol,
+    include_usage: bool,
+}
+
+pub(super) struct TranslatedRequest {
+    pub(super) translation: Translation,
+    pub(super) uri: Uri,
+    pub(super) body: Bytes,
+}
+
+pub(super) fn translate_request(
+    inbound: Protocol,
+    upstream: Protocol,
+    uri: &Uri,
+    body: &[u8],
+) -> Result<TranslatedRequest, ProxyError> {
+    if inbound == upstream || !inbound.accepts_channel(upstream) {
+        return Err(ProxyError::Translate(format!(
+            "不支持 {inbound} -> {upstream} 的协议转换"
+        )));
+    }
+    let path = uri.path().trim_end_matches('/');
+    let unsupported =
+        || ProxyError::Translate(format!("{path} 不支持 {inbound} -> {upstream} 的协议转换"));
+
+    let req = parse_json_body(body)?;
+    let chat = match inbound {
+        Protocol::Openai if path == "/v1/chat/completions" => req,
+        Protocol::Anthropic if path == "/v1/messages" => {
+            anthropic::messages_request_to_chat(&req).map_err(ProxyError::Translate)?
+        }
+        Protocol::Gemini => {
+            let stream = match path.rsplit_once(':').map(|(_, m)| m) {
+                Some("generateContent") => false,
+                Some("streamGenerateContent") => true,
+                _ => return Err(unsupported()),
+            };
+            // 非 SSE 的流式响应是 JSON 数组，转换时不支持
+            if stream
+                && !uri
+                    .query()
+                    .is_some_and(|q| q.split('&').any(|kv| kv == "alt=sse"))
+            {
+                return Err(ProxyError::Translate(
+                    "streamGenerateContent 跨协议转换仅支持 alt=sse".to_string(),
+                ));
+            }
+            let model = super::extract_gemini_model_from_uri(uri).ok_or_else(unsupported)?;
+            gemini::generate_request_to_chat(&req, &model, stream).map_err(ProxyError::Translate)?
+        }
+        _ => return Err(unsupported()),
+    };
+    let include_usage = chat
+        .get("stream_options")
+        .and_then(|o| o.get("include_usage"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let (uri, out) = match upstream {
+        Protocol::Openai => (Uri::from_static("/v1/chat/completions"), chat),
+        Protocol::Anthropic => (
+            Uri::from_static("/v1/messages"),
+            anthropic::chat_request_to_messages(&chat).map_err(ProxyError::Translate)?,
+        ),
+        Protocol::Gemini => {
+            let (path, out) =
+                gemini::chat_request_to_generate(&chat).map_err(ProxyError::Translate)?;
+            let uri = path
+                .parse::<Uri>()
+                .map_err(|e| ProxyError::Translate(e.to_string()))?;
+            (uri, out)
+        }
+    };
+
+    Ok(TranslatedRequest {
+        translation: Translation {
+            inbound,
+            upstream,
+            // 非 OpenAI 入站时 usage 只作中间结果，始终需要
+            include_usage: include_usage || inbound != Protocol::Openai,
+        },
+        uri,
+        body: to_body(&out)?,
+    })
+}
+
+// 上游没有对应接口、由本地直接应答的请求（count_tokens / countTokens 落到其他协议渠道）
+pub(super) fn local_response(
+    inbound: Protocol,
+    upstream: Protocol,
+    uri: &Uri,
+    body: &[u8],
+) -> Option<Bytes> {
+    if inbound == upstream {
+        return None;
+    }
+    let path = uri.path().trim_end_matches('/');
+    let req = || serde_json::from_slice::<Value>(body).unwrap_or(Value::Null);
+    let out = match inbound {
+        Protocol::Anthropic if path == "/v1/messages/count_tokens" => {
+            json!({ "input_tokens": estimate_tokens(&req()) })
+        }
+        Protocol::Gemini if path.ends_with(":countTokens") => {
+            json!({ "totalTokens": estimate_tokens(&req()) })
+        }
+        _ => return None,
+    };
+    serde_json::to_vec(&out).ok().map(Bytes::from)
+}
+
+impl Translation {
+    pub(super) fn upstream(&self) -> Protocol {
+        self.upstream
+    }
+
+    // 上游响应不是 JSON 时返回 None，由调用方改为入站协议的错误，不能原样透传
+    pub(super) fn response_json(&self, bytes: &[u8]) -> Option<Bytes> {
+        let v = serde_json::from_slice::<Value>(bytes).ok()?;
+        let chat = match self.upstream {
+            Protocol::Openai => v,
+            Protocol::Anthropic => anthropic::messages_response_to_chat(&v),
+            Protocol::Gemini => gemini::generate_response_to_chat(&v),
+        };
+        let out = match self.inbound {
+            Protocol::Openai => chat,
+            Protocol::Anthropic => anthropic::chat_response_to_messages(&chat),
+            Protocol::Gemini => gemini::chat_response_to_generate(&chat),
+        };
+        serde_json::to_vec(&out).ok().map(Bytes::from)
+    }
+
+    pub(super) fn error_json(&self, status: StatusCode, bytes: &[u8]) -> Bytes {
+        let message = super::parse_error_message(self.upstream, bytes)
+            .unwrap_or_else(|| String::from_utf8_lossy(bytes).to_string());
+        let out = error_body(self.inbound, status, &message);
+        Bytes::from(serde_json::to_vec(&out).unwrap_or_default())
+    }
+
+    pub(super) fn stream_translator(&self) -> Box<dyn StreamTranslator> {
+        let to_chat: Option<Box<dyn StreamTranslator>> = match self.upstream {
+            Protocol::Openai => None,
+            Protocol::Anthropic => Some(Box::new(anthropic::MessagesToChatStream::new(
+                self.include_usage,
+            ))),
+            Protocol::Gemini => Some(Box::new(gemini::GenerateToChatStream::new(
+                self.include_usage,
+            ))),
+        };
+        let from_chat: Option<Box<dyn StreamTranslator>> = match self.inbound {
+            Protocol::Openai => None,
+            Protocol::Anthropic => Some(Box::new(anthropic::ChatToMessagesStream::new())),
+            Protocol::Gemini => Some(Box::new(gemini::ChatToGenerateStream::new())),
+        };
+        match (to_chat, from_chat) {
+            (Some(first), Some(second)) => Box::new(Chained {
+                first,
+                parser: SseParser::default(),
+                second,
+            }),
+            (Some(t), None) | (None, Some(t)) => t,
+            (None, None) => Box::new(Passthrough),
+        }
+    }
+}
+
+// 按入站协议构造错误响应体，便于客户端按原协议解析
+pub(super) fn error_body(protocol: Protocol, status: StatusCode, message: &str) -> Value {
+    let code = status.as_u16();
+    match protocol {
+        Protocol::Openai => {
+            let kind = match code {
+                400 | 413 | 422 => "invalid_request_error",
+                401 => "authentication_error",
+                403 => "permission_error",
+                404 => "not_found_error",
+                429 => "rate_limit_error",
+                _ => "server_error",
+            };
+            json!({ "error": { "message": message, "type": kind, "param": null, "code": null } })
+        }
+        Protocol::Anthropic => {
+            let kind = match code {
+                400 | 422 => "invalid_request_error",
+                401 => "authentication_error",
+                403 => "permission_error",
+                404 => "not_found_error",
+                413 => "request_too_large",
+                429 => "rate_limit_error",
+                529 => "overloaded_error",
+                _ => "api_error",
+            };
+            json!({ "type": "error", "error": { "type": kind, "message": message } })
+        }
+        Protocol::Gemini => {
+            let kind = match code {
+                400 | 413 | 422 => "INVALID_ARGUMENT",
+                401 => "UNAUTHENTICATED",
+                403 => "PERMISSION_DENIED",
+                404 => "NOT_FOUND",
+                429 => "RESOURCE_EXHAUSTED",
+                503 => "UNAVAILABLE",
+                504 => "DEADLINE_EXCEEDED",
+                _ => "INTERNAL",
+            };
+            json!({ "error": { "code": code, "message": message, "status": kind } })
+        }
+    }
+}
+
+fn parse_json_body(body: &[u8]) -> Result<Value, ProxyError> {
+    let v: Value = serde_json::from_slice(body)
+        .map_err(|e| ProxyError::Translate(format!("请求体不是合法 JSON：{e}")))?;
+    if !v.is_object() {
+        return Err(ProxyError::Translate("请求体必须是 JSON 对象".to_string()));
+    }
+    Ok(v)
+}
+
+fn to_body(v: &Value) -> Result<Bytes, ProxyError> {
+    serde_json::to_vec(v)
+        .map(Bytes::from)
+        .map_err(|e| ProxyError::Translate(e.to_string()))
+}
+
+fn now_secs() -> i64 {
+    crate::storage::now_ms() / 1000
+}
+
+fn content_texts(content: Option<&Value>) -> Vec<String> {
+    match content {
+        Some(Value::String(s)) if !s.is_empty() => vec![s.clone()],
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(Value::as_str))
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn parse_arguments(arguments: Option<&Value>) -> Value {
+    match arguments {
+        Some(Value::String(s)) if !s.trim().is_empty() => {
+            serde_json::from_str(s).unwrap_or_else(|_| json!({}))
+        }
+        Some(v @ Value::Object(_)) => v.clone(),
+        _ => json!({}),
+    }
+}
+
+// 没有对应接口时按字符粗略估算 token：ASCII 约 4 字符/token，其余 1 字符/token
+fn estimate_tokens(req: &Value) -> i64 {
+    let mut text = String::new();
+    collect_text(req, &mut text);
+    let (ascii, other) = text.chars().fold((0i64, 0i64), |(a, o), c| {
+        if c.is_ascii() { (a + 1, o) } else { (a, o + 1) }
+    });
+    (ascii + 3) / 4 + other
+}
+
+fn collect_text(v: &Value, out: &mut String) {
+    match v {
+        Value::String(s) => {
+            out.push_str(s);
+            out.push(' ');
+        }
+        Value::Array(a) => a.iter().for_each(|x| collect_text(x, out)),
+        Value::Object(o) => {
+            for (k, x) in o {
+                // 模型名与图片等二进制数据不计入文本
+                if matches!(
+                    k.as_str(),
+                    "model" | "type" | "source" | "inlineData" | "fileData"
+                ) {
+                    continue;
+                }
+                collect_text(x, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct SseEvent {
+    pub(super) event: Option<String>,
+    pub(super) data: String,
+}
+
+#[derive(Default)]
+pub(super) struct SseParser {
+    buf: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub(super) fn push(&mut self, bytes: &[u8], out: &mut Vec<SseEvent>) {
+        self.buf.extend_from_slice(bytes);
+        while let Some(nl) = self.buf.iter().position(|b| *b == b'\n') {
+            let line = self.buf.drain(..=nl).collect::<Vec<u8>>();
+            let line = String::from_utf8_lossy(&line);
+            self.on_line(line.trim_end_matches(['\r', '\n']), out);
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<SseEvent>) {
+        if !self.buf.is_empty() {
+            let line = std::mem::take(&mut self.buf);
+            let line = String::from_utf8_lossy(&line);
+            self.on_line(line.trim_end_matches('\r'), out);
+        }
+        self.on_line("", out);
+    }
+
+    fn on_line(&mut self, line: &str, out: &mut Vec<SseEvent>) {
+        if line.is_empty() {
+            if !self.data.is_empty() {
+                out.push(SseEvent {
+                    event: self.event.take(),
+                    data: std::mem::take(&mut self.data).join("\n"),
+                });
+            }
+            self.event = None;
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((f, v)) => (f, v.strip_prefix(' ').unwrap_or(v)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => self.data.push(value.to_string()),
+            _ => {}
+        }
+    }
+}
+
+pub(super) fn write_sse(out: &mut Vec<u8>, event: Option<&str>, data: &str) {
+    if let Some(event) = event {
+        out.extend_from_slice(b"event: ");
+        out.extend_from_slice(event.as_bytes());
+        out.push(b'\n');
+    }
+    for line in data.split('\n') {
+        out.extend_from_slice(b"data: ");
+        out.extend_from_slice(line.as_bytes());
+        out.push(b'\n');
+    }
+    out.push(b'\n');
+}
+
+pub(super) fn write_sse_json(out: &mut Vec<u8>, event: Option<&str>, v: &Value) {
+    write_sse(out, event, &v.to_string());
+}
+
+pub(super) trait StreamTranslator: Send {
+    fn on_event(&mut self, ev: SseEvent, out: &mut Vec<u8>);
+    fn finish(&mut self, _out: &mut Vec<u8>) {}
+}
+
+struct Passthrough;
+
+impl StreamTranslator for Passthrough {
+    fn on_event(&mut self, ev: SseEvent, out: &mut Vec<u8>) {
+        write_sse(out, ev.event.as_deref(), &ev.data);
+    }
+}
+
+// 两段转换串联：上游 -> chat -> 入站
+struct Chained {
+    first: Box<dyn StreamTranslator>,
+    parser: SseParser,
+    second: Box<dyn StreamTranslator>,
+}
+
+impl Chained {
+    fn forward(&mut self, mid: Vec<u8>, out: &mut Vec<u8>) {
+        let mut events = Vec::new();
+        self.parser.push(&mid, &mut events);
+        for ev in events {
+            self.second.on_event(ev, out);
+        }
+    }
+}
+
+impl StreamTranslator for Chained {
+    fn on_event(&mut self, ev: SseEvent, out: &mut Vec<u8>) {
+        let mut mid = Vec::new();
+        self.first.on_event(ev, &mut mid);
+        self.forward(mid, out);
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        let mut mid = Vec::new();
+        self.first.finish(&mut mid);
+        self.forward(mid, out);
+        let mut events = Vec::new();
+        self.parser.finish(&mut events);
+        for ev in events {
+            self.second.on_event(ev, out);
+        }
+        self.second.finish(out);
+    }
+}
+
+pub(super) struct TranslatedStream {
+    inner: futures_util::stream::BoxStream<'static, Result<Bytes, std::io::Error>>,
+    parser: SseParser,
+    translator: Box<dyn StreamTranslator>,
+    done: bool,
+}
+
+impl TranslatedStream {
+    pub(super) fn new(
+        inner: futures_util::stream::BoxStream<'static, Result<Bytes, std::io::Error>>,
+        translator: Box<dyn StreamTranslator>,
+    ) -> Self {
+        Self {
+            inner,
+            parser: SseParser::default(),
+            translator,
+            done: false,
+        }
+    }
+}
+
+impl futures_util::Stream for TranslatedStream {
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let mut events = Vec::new();
+            let mut out = Vec::new();
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(bytes))) => this.parser.push(&bytes, &mut events),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    this.done = true;
+                    this.parser.finish(&mut events);
+                    for ev in events.drain(..) {
+                        this.translator.on_event(ev, &mut out);
+                    }
+                    this.translator.finish(&mut out);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+            for ev in events {
+                this.translator.on_event(ev, &mut out);
+            }
+            if !out.is_empty() {
+                return Poll::Ready(Some(Ok(Bytes::from(out))));
+            }
+        }
+    }
+}
@@ -0,0 +1,1209 @@
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+
+use super::{
+    SseEvent, StreamTranslator, content_texts, now_secs, parse_arguments, write_sse, write_sse_json,
+};
+
+// Anthropic 要求 max_tokens 必填
+const DEFAULT_MAX_TOKENS: i64 = 4096;
+
+pub(super) fn chat_request_to_messages(req: &Value) -> Result<Value, String> {
+    let mut system: Vec<Value> = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+
+    let input = req
+        .get("messages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "缺少 messages".to_string())?;
+    for m in input {
+        let role = m.get("role").and_then(Value::as_str).unwrap_or("user");
+        match role {
+            "system" | "developer" => {
+                for text in content_texts(m.get("content")) {
+                    system.push(json!({ "type": "text", "text": text }));
+                }
+            }
+            "user" => push_message(&mut messages, "user", user_blocks(m.get("content"))?),
+            "assistant" => {
+                let mut blocks: Vec<Value> = content_texts(m.get("content"))
+                    .into_iter()
+                    .map(|text| json!({ "type": "text", "text": text }))
+                    .collect();
+                for call in m
+                    .get("tool_calls")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                {
+                    let function = call.get("function").unwrap_or(&Value::Null);
+                    let arguments = function
+                        .get("arguments")
+                        .and_then(Value::as_str)
+                        .unwrap_or("");
+                    let input = if arguments.trim().is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str::<Value>(arguments)
+                            .map_err(|e| format!("tool_calls.arguments 不是合法 JSON：{e}"))?
+                    };
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": call.get("id").cloned().unwrap_or(Value::Null),
+                        "name": function.get("name").cloned().unwrap_or(Value::Null),
+                        "input": input,
+                    }));
+                }
+                push_message(&mut messages, "assistant", blocks);
+            }
+            "tool" => {
+                let block = json!({
+                    "type": "tool_result",
+                    "tool_use_id": m.get("tool_call_id").cloned().unwrap_or(Value::Null),
+                    "content": content_texts(m.get("content")).join("\n"),
+                });
+                push_message(&mut messages, "user", vec![block]);
+            }
+            other => return Err(format!("不支持的消息角色：{other}")),
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert(
+        "model".to_string(),
+        req.get("model").cloned().unwrap_or(Value::Null),
+    );
+    let max_tokens = req
+        .get("max_completion_tokens")
+        .or_else(|| req.get("max_tokens"))
+        .and_then(Value::as_i64)
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+    out.insert("max_tokens".to_string(), json!(max_tokens));
+    if !system.is_empty() {
+        out.insert("system".to_string(), Value::Array(system));
+    }
+    out.insert("messages".to_string(), Value::Array(messages));
+
+    // OpenAI temperature 取值 0~2，Anthropic 为 0~1
+    if let Some(t) = req.get("temperature").and_then(Value::as_f64) {
+        out.insert("temperature".to_string(), json!(t.clamp(0.0, 1.0)));
+    }
+    if let Some(p) = req.get("top_p").filter(|v| v.is_number()) {
+        out.insert("top_p".to_string(), p.clone());
+    }
+    match req.get("stop") {
+        Some(Value::String(s)) => {
+            out.insert("stop_sequences".to_string(), json!([s]));
+        }
+        Some(Value::Array(a)) if !a.is_empty() => {
+            out.insert("stop_sequences".to_string(), Value::Array(a.clone()));
+        }
+        _ => {}
+    }
+    if let Some(stream) = req.get("stream").and_then(Value::as_bool) {
+        out.insert("stream".to_string(), json!(stream));
+    }
+    if let Some(user) = req.get("user").and_then(Value::as_str) {
+        out.insert("metadata".to_string(), json!({ "user_id": user }));
+    }
+
+    if let Some(tools) = req.get("tools").and_then(Value::as_array) {
+        let tools: Vec<Value> = tools
+            .iter()
+            .filter_map(|t| t.get("function"))
+            .map(|f| {
+                let mut tool = Map::new();
+                tool.insert(
+                    "name".to_string(),
+                    f.get("name").cloned().unwrap_or(Value::Null),
+                );
+                if let Some(d) = f.get("description") {
+                    tool.insert("description".to_string(), d.clone());
+                }
+                tool.insert(
+                    "input_schema".to_string(),
+                    f.get("parameters")
+                        .cloned()
+                        .unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+                );
+                Value::Object(tool)
+            })
+            .collect();
+        if !tools.is_empty() {
+            out.insert("tools".to_string(), Value::Array(tools));
+        }
+    }
+    let mut tool_choice = match req.get("tool_choice") {
+        Some(Value::String(s)) if s == "none" => Some(json!({ "type": "none" })),
+        Some(Value::String(s)) if s == "auto" => Some(json!({ "type": "auto" })),
+        Some(Value::String(s)) if s == "required" => Some(json!({ "type": "any" })),
+        Some(Value::Object(o)) => o
+            .get("function")
+            .and_then(|f| f.get("name"))
+            .map(|name| json!({ "type": "tool", "name": name })),
+        _ => None,
+    };
+    if req.get("parallel_tool_calls").and_then(Value::as_bool) == Some(false)
+        && out.contains_key("tools")
+    {
+        let choice = tool_choice.get_or_insert_with(|| json!({ "type": "auto" }));
+        if choice.get("type").and_then(Value::as_str) != Some("none") {
+            choice["disable_parallel_tool_use"] = json!(true);
+        }
+    }
+    if let Some(choice) = tool_choice {
+        out.insert("tool_choice".to_string(), choice);
+    }
+
+    Ok(Value::Object(out))
+}
+
+// 连续同角色消息合并，满足 Anthropic 的 user/assistant 交替要求
+fn push_message(messages: &mut Vec<Value>, role: &str, blocks: Vec<Value>) {
+    if blocks.is_empty() {
+        return;
+    }
+    if let Some(last) = messages.last_mut()
+        && last.get("role").and_then(Value::as_str) == Some(role)
+        && let Some(content) = last.get_mut("content").and_then(Value::as_array_mut)
+    {
+        content.extend(blocks);
+        return;
+    }
+    messages.push(json!({ "role": role, "content": blocks }));
+}
+
+fn user_blocks(content: Option<&Value>) -> Result<Vec<Value>, String> {
+    let Some(Value::Array(parts)) = content else {
+        return Ok(content_texts(content)
+            .into_iter()
+            .map(|text| json!({ "type": "text", "text": text }))
+            .collect());
+    };
+    let mut blocks = Vec::new();
+    for part in parts {
+        match part.get("type").and_then(Value::as_str) {
+            Some("text") => {
+                if let Some(text) = part.get("text").and_then(Value::as_str)
+                    && !text.is_empty()
+                {
+                    blocks.push(json!({ "type": "text", "text": text }));
+                }
+            }
+            Some("image_url") => {
+                let url = part
+                    .get("image_url")
+                    .and_then(|u| u.get("url").or(Some(u)))
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| "image_url 缺少 url".to_string())?;
+                blocks.push(image_block(url));
+            }
+            other => {
+                return Err(format!(
+                    "不支持的消息内容类型：{}",
+                    other.unwrap_or("unknown")
+                ));
+            }
+        }
+    }
+    Ok(blocks)
+}
+
+fn image_block(url: &str) -> Value {
+    if let Some(rest) = url.strip_prefix("data:")
+        && let Some((meta, data)) = rest.split_once(',')
+        && let Some(media_type) = meta.strip_suffix(";base64")
+    {
+        return json!({
+            "type": "image",
+            "source": { "type": "base64", "media_type": media_type, "data": data },
+        });
+    }
+    json!({ "type": "image", "source": { "type": "url", "url": url } })
+}
+
+pub(super) fn messages_response_to_chat(v: &Value) -> Value {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in v
+        .get("content")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        match block.get("type").and_then(Value::as_str) {
+            Some("text") => text.push_str(block.get("text").and_then(Value::as_str).unwrap_or("")),
+            Some("thinking") => reasoning.push_str(
+                block
+                    .get("thinking")
+                    .and_then(Value::as_str)
+                    .unwrap_or(""),
+            ),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block.get("id").cloned().unwrap_or(Value::Null),
+                "type": "function",
+                "function": {
+                    "name": block.get("name").cloned().unwrap_or(Value::Null),
+                    "arguments": block.get("input").map(Value::to_string).unwrap_or_else(|| "{}".to_string()),
+                },
+            })),
+            _ => {}
+        }
+    }
+
+    let mut message = Map::new();
+    message.insert("role".to_string(), json!("assistant"));
+    message.insert(
+        "content".to_string(),
+        if text.is_empty() && !tool_calls.is_empty() {
+            Value::Null
+        } else {
+            json!(text)
+        },
+    );
+    if !reasoning.is_empty() {
+        message.insert("reasoning_content".to_string(), json!(reasoning));
+    }
+    if !tool_calls.is_empty() {
+        message.insert("tool_calls".to_string(), Value::Array(tool_calls));
+    }
+
+    let finish_reason = finish_reason(v.get("stop_reason").and_then(Value::as_str));
+    let mut out = json!({
+        "id": v.get("id").cloned().unwrap_or(Value::Null),
+        "object": "chat.completion",
+        "created": now_secs(),
+        "model": v.get("model").cloned().unwrap_or(Value::Null),
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason,
+            "logprobs": null,
+        }],
+    });
+    if let Some(u) = v.get("usage") {
+        out["usage"] = chat_usage(&UsageCounter::from_value(u));
+    }
+    out
+}
+
+fn finish_reason(stop_reason: Option<&str>) -> Option<&'static str> {
+    stop_reason.map(|r| match r {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        _ => "stop",
+    })
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct UsageCounter {
+    input: i64,
+    output: i64,
+    cache_read: i64,
+    cache_write: i64,
+}
+
+impl UsageCounter {
+    fn from_value(u: &Value) -> Self {
+        let mut c = Self::default();
+        c.merge(u);
+        c
+    }
+
+    fn merge(&mut self, u: &Value) {
+        let get = |k: &str| u.get(k).and_then(Value::as_i64);
+        if let Some(n) = get("input_tokens") {
+            self.input = n;
+        }
+        if let Some(n) = get("output_tokens") {
+            self.output = n;
+        }
+        if let Some(n) = get("cache_read_input_tokens") {
+            self.cache_read = n;
+        }
+        if let Some(n) = get("cache_creation_input_tokens") {
+            self.cache_write = n;
+        }
+    }
+}
+
+// Anthropic 的 input_tokens 不含缓存部分，OpenAI 的 prompt_tokens 含
+fn chat_usage(u: &UsageCounter) -> Value {
+    let prompt = u.input + u.cache_read + u.cache_write;
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": u.output,
+        "total_tokens": prompt + u.output,
+        "prompt_tokens_details": { "cached_tokens": u.cache_read },
+    })
+}
+
+pub(super) struct MessagesToChatStream {
+    include_usage: bool,
+    id: String,
+    model: String,
+    created: i64,
+    tool_index: HashMap<i64, usize>,
+    usage: UsageCounter,
+}
+
+impl MessagesToChatStream {
+    pub(super) fn new(include_usage: bool) -> Self {
+        Self {
+            include_usage,
+            id: String::new(),
+            model: String::new(),
+            created: now_secs(),
+            tool_index: HashMap::new(),
+            usage: UsageCounter::default(),
+        }
+    }
+
+    fn chunk(&self, out: &mut Vec<u8>, delta: Value, finish_reason: Option<&str>) {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        });
+        write_sse_json(out, None, &chunk);
+    }
+}
+
+impl StreamTranslator for MessagesToChatStream {
+    fn on_event(&mut self, ev: SseEvent, out: &mut Vec<u8>) {
+        let Ok(v) = serde_json::from_str::<Value>(&ev.data) else {
+            return;
+        };
+        match v.get("type").and_then(Value::as_str) {
+            Some("message_start") => {
+                let message = v.get("message").unwrap_or(&Value::Null);
+                if let Some(id) = message.get("id").and_then(Value::as_str) {
+                    self.id = id.to_string();
+                }
+                if let Some(model) = message.get("model").and_then(Value::as_str) {
+                    self.model = model.to_string();
+                }
+                if let Some(u) = message.get("usage") {
+                    self.usage.merge(u);
+                }
+                self.chunk(out, json!({ "role": "assistant", "content": "" }), None);
+            }
+            Some("content_block_start") => {
+                let block = v.get("content_block").unwrap_or(&Value::Null);
+                let index = v.get("index").and_then(Value::as_i64).unwrap_or(0);
+                match block.get("type").and_then(Value::as_str) {
+                    Some("tool_use") => {
+                        let tool_index = self.tool_index.len();
+                        self.tool_index.insert(index, tool_index);
+                        let delta = json!({ "tool_calls": [{
+                            "index": tool_index,
+                            "id": block.get("id").cloned().unwrap_or(Value::Null),
+                            "type": "function",
+                            "function": {
+                                "name": block.get("name").cloned().unwrap_or(Value::Null),
+                                "arguments": "",
+                            },
+                        }] });
+                        self.chunk(out, delta, None);
+                    }
+                    Some("text") => {
+                        if let Some(text) = block.get("text").and_then(Value::as_str)
+                            && !text.is_empty()
+                        {
+                            self.chunk(out, json!({ "content": text }), None);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Some("content_block_delta") => {
+                let delta = v.get("delta").unwrap_or(&Value::Null);
+                let index = v.get("index").and_then(Value::as_i64).unwrap_or(0);
+                match delta.get("type").and_then(Value::as_str) {
+                    Some("text_delta") => {
+                        let text = delta.get("text").cloned().unwrap_or(json!(""));
+                        self.chunk(out, json!({ "content": text }), None);
+                    }
+                    Some("thinking_delta") => {
+                        let text = delta.get("thinking").cloned().unwrap_or(json!(""));
+                        self.chunk(out, json!({ "reasoning_content": text }), None);
+                    }
+                    Some("input_json_delta") => {
+                        let Some(tool_index) = self.tool_index.get(&index).copied() else {
+                            return;
+                        };
+                        let partial = delta.get("partial_json").cloned().unwrap_or(json!(""));
+                        let delta = json!({ "tool_calls": [{
+                            "index": tool_index,
+                            "function": { "arguments": partial },
+                        }] });
+                        self.chunk(out, delta, None);
+                    }
+                    _ => {}
+                }
+            }
+            Some("message_delta") => {
+                if let Some(u) = v.get("usage") {
+                    self.usage.merge(u);
+                }
+                let reason = finish_reason(
+                    v.get("delta")
+                        .and_then(|d| d.get("stop_reason"))
+                        .and_then(Value::as_str),
+                )
+                .unwrap_or("stop");
+                self.chunk(out, json!({}), Some(reason));
+            }
+            Some("message_stop") => {
+                if self.include_usage {
+                    let chunk = json!({
+                        "id": self.id,
+                        "object": "chat.completion.chunk",
+                        "created": self.created,
+                        "model": self.model,
+                        "choices": [],
+                        "usage": chat_usage(&self.usage),
+                    });
+                    write_sse_json(out, None, &chunk);
+                }
+                write_sse(out, None, "[DONE]");
+            }
+            Some("error") => {
+                let error = v.get("error").cloned().unwrap_or(Value::Null);
+                write_sse_json(out, None, &json!({ "error": error }));
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(super) fn messages_request_to_chat(req: &Value) -> Result<Value, String> {
+    let mut messages: Vec<Value> = Vec::new();
+
+    let system = match req.get("system") {
+        Some(Value::String(s)) => s.clone(),
+        Some(v @ Value::Array(_)) => content_texts(Some(v)).join("\n"),
+        _ => String::new(),
+    };
+    if !system.is_empty() {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+
+    let input = req
+        .get("messages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "缺少 messages".to_string())?;
+    for m in input {
+        let role = m.get("role").and_then(Value::as_str).unwrap_or("user");
+        let blocks = match m.get("content") {
+            Some(Value::String(s)) => vec![json!({ "type": "text", "text": s })],
+            Some(Value::Array(a)) => a.clone(),
+            _ => Vec::new(),
+        };
+        match role {
+            "user" => push_user_blocks(&mut messages, &blocks)?,
+            "assistant" => {
+                let mut text = String::new();
+                let mut tool_calls = Vec::new();
+                for block in &blocks {
+                    match block.get("type").and_then(Value::as_str) {
+                        Some("text") => {
+                            text.push_str(block.get("text").and_then(Value::as_str).unwrap_or(""))
+                        }
+                        Some("tool_use") => tool_calls.push(json!({
+                            "id": block.get("id").cloned().unwrap_or(Value::Null),
+                            "type": "function",
+                            "function": {
+                                "name": block.get("name").cloned().unwrap_or(Value::Null),
+                                "arguments": block
+                                    .get("input")
+                                    .map(Value::to_string)
+                                    .unwrap_or_else(|| "{}".to_string()),
+                            },
+                        })),
+                        // thinking 块依赖 Anthropic 签名，无法在其他上游复用
+                        Some("thinking" | "redacted_thinking") => {}
+                        other => {
+                            return Err(format!(
+                                "不支持的消息内容类型：{}",
+                                other.unwrap_or("unknown")
+                            ));
+                        }
+                    }
+                }
+                let mut message = json!({ "role": "assistant", "content": text });
+                if !tool_calls.is_empty() {
+                    if text.is_empty() {
+                        message["content"] = Value::Null;
+                    }
+                    message["tool_calls"] = Value::Array(tool_calls);
+                }
+                messages.push(message);
+            }
+            other => return Err(format!("不支持的消息角色：{other}")),
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert(
+        "model".to_string(),
+        req.get("model").cloned().unwrap_or(Value::Null),
+    );
+    out.insert("messages".to_string(), Value::Array(messages));
+    if let Some(n) = req.get("max_tokens").filter(|v| v.is_number()) {
+        out.insert("max_tokens".to_string(), n.clone());
+    }
+    for key in ["temperature", "top_p"] {
+        if let Some(v) = req.get(key).filter(|v| v.is_number()) {
+            out.insert(key.to_string(), v.clone());
+        }
+    }
+    if let Some(stop) = req
+        .get("stop_sequences")
+        .and_then(Value::as_array)
+        .filter(|a| !a.is_empty())
+    {
+        out.insert("stop".to_string(), Value::Array(stop.clone()));
+    }
+    if req.get("stream").and_then(Value::as_bool) == Some(true) {
+        out.insert("stream".to_string(), json!(true));
+        // 需要末尾的 usage chunk 才能统计流式用量
+        out.insert(
+            "stream_options".to_string(),
+            json!({ "include_usage": true }),
+        );
+    }
+    if let Some(user) = req
+        .get("metadata")
+        .and_then(|m| m.get("user_id"))
+        .and_then(Value::as_str)
+    {
+        out.insert("user".to_string(), json!(user));
+    }
+
+    // 只转换自定义工具，服务端工具（web_search 等）无法在其他上游执行
+    let tools: Vec<Value> = req
+        .get("tools")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|t| t.get("input_schema").is_some())
+        .map(|t| {
+            let mut function = Map::new();
+            function.insert(
+                "name".to_string(),
+                t.get("name").cloned().unwrap_or(Value::Null),
+            );
+            if let Some(d) = t.get("description") {
+                function.insert("description".to_string(), d.clone());
+            }
+            function.insert(
+                "parameters".to_string(),
+                t.get("input_schema").cloned().unwrap_or(Value::Null),
+            );
+            json!({ "type": "function", "function": function })
+        })
+        .collect();
+    let has_tools = !tools.is_empty();
+    if has_tools {
+        out.insert("tools".to_string(), Value::Array(tools));
+    }
+    if let Some(choice) = req.get("tool_choice").filter(|_| has_tools) {
+        let mapped = match choice.get("type").and_then(Value::as_str) {
+            Some("auto") => Some(json!("auto")),
+            Some("any") => Some(json!("required")),
+            Some("none") => Some(json!("none")),
+            Some("tool") => Some(json!({
+                "type": "function",
+                "function": { "name": choice.get("name").cloned().unwrap_or(Value::Null) },
+            })),
+            _ => None,
+        };
+        if let Some(mapped) = mapped {
+            out.insert("tool_choice".to_string(), mapped);
+        }
+        if choice
+            .get("disable_parallel_tool_use")
+            .and_then(Value::as_bool)
+            == Some(true)
+        {
+            out.insert("parallel_tool_calls".to_string(), json!(false));
+        }
+    }
+
+    Ok(Value::Object(out))
+}
+
+// tool_result 转为紧随 assistant 的 tool 消息，其余内容合为一条 user 消息
+fn push_user_blocks(messages: &mut Vec<Value>, blocks: &[Value]) -> Result<(), String> {
+    let mut parts: Vec<Value> = Vec::new();
+    for block in blocks {
+        match block.get("type").and_then(Value::as_str) {
+            Some("text") => {
+                if let Some(text) = block.get("text").and_then(Value::as_str)
+                    && !text.is_empty()
+                {
+                    parts.push(json!({ "type": "text", "text": text }));
+                }
+            }
+            Some("image") => {
+                let source = block.get("source").unwrap_or(&Value::Null);
+                let url = match source.get("type").and_then(Value::as_str) {
+                    Some("base64") => format!(
+                        "data:{};base64,{}",
+                        source
+                            .get("media_type")
+                            .and_then(Value::as_str)
+                            .unwrap_or("image/png"),
+                        source.get("data").and_then(Value::as_str).unwrap_or("")
+                    ),
+                    Some("url") => source
+                        .get("url")
+                        .and_then(Value::as_str)
+                        .unwrap_or("")
+                        .to_string(),
+                    _ => return Err("不支持的图片来源".to_string()),
+                };
+                parts.push(json!({ "type": "image_url", "image_url": { "url": url } }));
+            }
+            Some("tool_result") => {
+                let mut content = match block.get("content") {
+                    Some(Value::String(s)) => s.clone(),
+                    v @ Some(Value::Array(_)) => content_texts(v).join("\n"),
+                    _ => String::new(),
+                };
+                if block.get("is_error").and_then(Value::as_bool) == Some(true) {
+                    content = format!("[error] {content}");
+                }
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": block.get("tool_use_id").cloned().unwrap_or(Value::Null),
+                    "content": content,
+                }));
+            }
+            other => {
+                return Err(format!(
+                    "不支持的消息内容类型：{}",
+                    other.unwrap_or("unknown")
+                ));
+            }
+        }
+    }
+    if parts.is_empty() {
+        return Ok(());
+    }
+    // 纯文本时用字符串，兼容不支持多段 content 的 OpenAI 兼容服务
+    let content = if parts.iter().all(|p| p.get("type") == Some(&json!("text"))) {
+        json!(content_texts(Some(&Value::Array(parts))).join("\n"))
+    } else {
+        Value::Array(parts)
+    };
+    messages.push(json!({ "role": "user", "content": content }));
+    Ok(())
+}
+
+pub(super) fn chat_response_to_messages(v: &Value) -> Value {
+    let choice = v
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .unwrap_or(&Value::Null);
+    let message = choice.get("message").unwrap_or(&Value::Null);
+
+    let mut content = Vec::new();
+    if let Some(text) = message.get("content").and_then(Value::as_str)
+        && !text.is_empty()
+    {
+        content.push(json!({ "type": "text", "text": text }));
+    }
+    for call in message
+        .get("tool_calls")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let function = call.get("function").unwrap_or(&Value::Null);
+        content.push(json!({
+            "type": "tool_use",
+            "id": call.get("id").cloned().unwrap_or(Value::Null),
+            "name": function.get("name").cloned().unwrap_or(Value::Null),
+            "input": parse_arguments(function.get("arguments")),
+        }));
+    }
+
+    let usage = v
+        .get("usage")
+        .map(|u| messages_usage(u, &mut UsageCounter::default()))
+        .unwrap_or_else(|| json!({ "input_tokens": 0, "output_tokens": 0 }));
+    json!({
+        "id": v.get("id").cloned().unwrap_or(Value::Null),
+        "type": "message",
+        "role": "assistant",
+        "model": v.get("model").cloned().unwrap_or(Value::Null),
+        "content": content,
+        "stop_reason": stop_reason(choice.get("finish_reason").and_then(Value::as_str)),
+        "stop_sequence": null,
+        "usage": usage,
+    })
+}
+
+fn stop_reason(finish_reason: Option<&str>) -> Value {
+    match finish_reason {
+        Some("length") => json!("max_tokens"),
+        Some("tool_calls" | "function_call") => json!("tool_use"),
+        Some("content_filter") => json!("refusal"),
+        Some(_) => json!("end_turn"),
+        None => Value::Null,
+    }
+}
+
+// OpenAI 的 prompt_tokens 含缓存命中部分，Anthropic 的 input_tokens 不含
+fn messages_usage(u: &Value, counter: &mut UsageCounter) -> Value {
+    let get = |k: &str| u.get(k).and_then(Value::as_i64);
+    let cached = u
+        .get("prompt_tokens_details")
+        .and_then(|d| d.get("cached_tokens"))
+        .and_then(Value::as_i64);
+    if let Some(n) = cached {
+        counter.cache_read = n;
+    }
+    if let Some(n) = get("prompt_tokens") {
+        counter.input = (n - counter.cache_read).max(0);
+    }
+    if let Some(n) = get("completion_tokens") {
+        counter.output = n;
+    }
+    json!({
+        "input_tokens": counter.input,
+        "output_tokens": counter.output,
+        "cache_read_input_tokens": counter.cache_read,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenBlock {
+    Text,
+    Tool(i64),
+}
+
+pub(super) struct ChatToMessagesStream {
+    started: bool,
+    finished: bool,
+    next_index: i64,
+    open: Option<OpenBlock>,
+    stop_reason: Value,
+    usage: UsageCounter,
+}
+
+impl ChatToMessagesStream {
+    pub(super) fn new() -> Self {
+        Self {
+            started: false,
+            finished: false,
+            next_index: 0,
+            open: None,
+            stop_reason: Value::Null,
+            usage: UsageCounter::default(),
+        }
+    }
+
+    fn start(&mut self, chunk: &Value, out: &mut Vec<u8>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let event = json!({
+            "type": "message_start",
+            "message": {
+                "id": chunk.get("id").cloned().unwrap_or(Value::Null),
+                "type": "message",
+                "role": "assistant",
+                "model": chunk.get("model").cloned().unwrap_or(Value::Null),
+                "content": [],
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": { "input_tokens": 0, "output_tokens": 0 },
+            },
+        });
+        write_sse_json(out, Some("message_start"), &event);
+    }
+
+    fn open_block(&mut self, block: OpenBlock, content_block: Value, out: &mut Vec<u8>) {
+        self.close_block(out);
+        let event = json!({
+            "type": "content_block_start",
+            "index": self.next_index,
+            "content_block": content_block,
+        });
+        write_sse_json(out, Some("content_block_start"), &event);
+        self.open = Some(block);
+    }
+
+    fn close_block(&mut self, out: &mut Vec<u8>) {
+        if self.open.take().is_some() {
+            let event = json!({ "type": "content_block_stop", "index": self.next_index });
+            write_sse_json(out, Some("content_block_stop"), &event);
+            self.next_index += 1;
+        }
+    }
+
+    fn block_delta(&self, delta: Value, out: &mut Vec<u8>) {
+        let event = json!({
+            "type": "content_block_delta",
+            "index": self.next_index,
+            "delta": delta,
+        });
+        write_sse_json(out, Some("content_block_delta"), &event);
+    }
+
+    fn stop(&mut self, out: &mut Vec<u8>) {
+        if !self.started || self.finished {
+            return;
+        }
+        self.finished = true;
+        self.close_block(out);
+        let event = json!({
+            "type": "message_delta",
+            "delta": { "stop_reason": self.stop_reason, "stop_sequence": null },
+            "usage": {
+                "input_tokens": self.usage.input,
+                "output_tokens": self.usage.output,
+                "cache_read_input_tokens": self.usage.cache_read,
+            },
+        });
+        write_sse_json(out, Some("message_delta"), &event);
+        write_sse_json(
+            out,
+            Some("message_stop"),
+            &json!({ "type": "message_stop" }),
+        );
+    }
+}
+
+impl StreamTranslator for ChatToMessagesStream {
+    fn on_event(&mut self, ev: SseEvent, out: &mut Vec<u8>) {
+        if ev.data.trim() == "[DONE]" {
+            self.stop(out);
+            return;
+        }
+        let Ok(v) = serde_json::from_str::<Value>(&ev.data) else {
+            return;
+        };
+        if let Some(error) = v.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| error.to_string());
+            let event = json!({
+                "type": "error",
+                "error": { "type": "api_error", "message": message },
+            });
+            write_sse_json(out, Some("error"), &event);
+            return;
+        }
+
+        self.start(&v, out);
+        if let Some(u) = v.get("usage").filter(|u| u.is_object()) {
+            messages_usage(u, &mut self.usage);
+        }
+        let Some(choice) = v.get("choices").and_then(|c| c.get(0)) else {
+            return;
+        };
+        let delta = choice.get("delta").unwrap_or(&Value::Null);
+
+        if let Some(text) = delta.get("content").and_then(Value::as_str)
+            && !text.is_empty()
+        {
+            if self.open != Some(OpenBlock::Text) {
+                self.open_block(OpenBlock::Text, json!({ "type": "text", "text": "" }), out);
+            }
+            self.block_delta(json!({ "type": "text_delta", "text": text }), out);
+        }
+
+        for call in delta
+            .get("tool_calls")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let index = call.get("index").and_then(Value::as_i64).unwrap_or(0);
+            let function = call.get("function").unwrap_or(&Value::Null);
+            if self.open != Some(OpenBlock::Tool(index)) {
+                let block = json!({
+                    "type": "tool_use",
+                    "id": call.get("id").cloned().unwrap_or(Value::Null),
+                    "name": function.get("name").cloned().unwrap_or(Value::Null),
+                    "input": {},
+                });
+                self.open_block(OpenBlock::Tool(index), block, out);
+            }
+            if let Some(args) = function.get("arguments").and_then(Value::as_str)
+                && !args.is_empty()
+            {
+                self.block_delta(
+                    json!({ "type": "input_json_delta", "partial_json": args }),
+                    out,
+                );
+            }
+        }
+
+        if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+            self.stop_reason = stop_reason(Some(reason));
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        // 部分 OpenAI 兼容服务不发送 [DONE]
+        if !self.stop_reason.is_null() {
+            self.stop(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_request_maps_system_tools_images_and_stop() {
+        let req = json!({
+            "model": "claude-x",
+            "temperature": 1.5,
+            "stop": "END",
+            "messages": [
+                { "role": "system", "content": "be brief" },
+                { "role": "developer", "content": [{ "type": "text", "text": "no emoji" }] },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "what is this" },
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } },
+                    { "type": "image_url", "image_url": { "url": "https://x.test/a.png" } },
+                ] },
+                { "role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "read", "arguments": "{\"path\":\"a.txt\"}" },
+                }] },
+                { "role": "tool", "tool_call_id": "call_1", "content": "hello" },
+            ],
+            "tools": [{ "type": "function", "function": { "name": "read", "parameters": { "type": "object" } } }],
+            "tool_choice": "required",
+            "parallel_tool_calls": false,
+        });
+        let out = chat_request_to_messages(&req).expect("translate");
+
+        assert_eq!(
+            out["system"],
+            json!([
+                { "type": "text", "text": "be brief" },
+                { "type": "text", "text": "no emoji" },
+            ])
+        );
+        assert_eq!(out["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(out["temperature"], 1.0);
+        assert_eq!(out["stop_sequences"], json!(["END"]));
+
+        let messages = out["messages"].as_array().expect("messages");
+        assert_eq!(messages.len(), 3);
+        let user = &messages[0]["content"];
+        assert_eq!(
+            user[1]["source"],
+            json!({ "type": "base64", "media_type": "image/png", "data": "AAAA" })
+        );
+        assert_eq!(
+            user[2]["source"],
+            json!({ "type": "url", "url": "https://x.test/a.png" })
+        );
+        assert_eq!(
+            messages[1]["content"][0],
+            json!({ "type": "tool_use", "id": "call_1", "name": "read", "input": { "path": "a.txt" } })
+        );
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "call_1");
+
+        assert_eq!(out["tools"][0]["input_schema"], json!({ "type": "object" }));
+        assert_eq!(
+            out["tool_choice"],
+            json!({ "type": "any", "disable_parallel_tool_use": true })
+        );
+    }
+
+    #[test]
+    fn chat_request_rejects_invalid_tool_arguments() {
+        let req = json!({
+            "messages": [{ "role": "assistant", "tool_calls": [{
+                "id": "call_1",
+                "function": { "name": "read", "arguments": "{not json" },
+            }] }],
+        });
+        assert!(chat_request_to_messages(&req).is_err());
+    }
+
+    #[test]
+    fn messages_response_maps_tool_use_stop_reason_and_usage() {
+        let v = json!({
+            "id": "msg_1",
+            "model": "claude-x",
+            "content": [
+                { "type": "thinking", "thinking": "hmm" },
+                { "type": "tool_use", "id": "toolu_1", "name": "read", "input": { "path": "a.txt" } },
+            ],
+            "stop_reason": "tool_use",
+            "usage": {
+                "input_tokens": 10,
+                "output_tokens": 3,
+                "cache_read_input_tokens": 4,
+                "cache_creation_input_tokens": 2,
+            },
+        });
+        let out = messages_response_to_chat(&v);
+        let message = &out["choices"][0]["message"];
+        assert_eq!(message["content"], Value::Null);
+        assert_eq!(message["reasoning_content"], "hmm");
+        assert_eq!(message["tool_calls"][0]["id"], "toolu_1");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            r#"{"path":"a.txt"}"#
+        );
+        assert_eq!(out["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(
+            out["usage"],
+            json!({
+                "prompt_tokens": 16,
+                "completion_tokens": 3,
+                "total_tokens": 19,
+                "prompt_tokens_details": { "cached_tokens": 4 },
+            })
+        );
+    }
+
+    #[test]
+    fn stop_reasons_map_both_ways() {
+        for (anthropic, openai) in [
+            ("end_turn", "stop"),
+            ("stop_sequence", "stop"),
+            ("max_tokens", "length"),
+            ("tool_use", "tool_calls"),
+            ("refusal", "content_filter"),
+        ] {
+            assert_eq!(finish_reason(Some(anthropic)), Some(openai));
+        }
+        assert_eq!(finish_reason(None), None);
+
+        for (openai, anthropic) in [
+            ("stop", "end_turn"),
+            ("length", "max_tokens"),
+            ("tool_calls", "tool_use"),
+            ("function_call", "tool_use"),
+            ("content_filter", "refusal"),
+        ] {
+            assert_eq!(stop_reason(Some(openai)), json!(anthropic));
+        }
+        assert_eq!(stop_reason(None), Value::Null);
+    }
+
+    #[test]
+    fn messages_request_maps_system_images_tool_results_and_stop() {
+        let req = json!({
+            "model": "gpt-x",
+            "max_tokens": 64,
+            "system": [{ "type": "text", "text": "be brief" }, { "type": "text", "text": "no emoji" }],
+            "stop_sequences": ["END"],
+            "stream": true,
+            "messages": [
+                { "role": "user", "content": [
+                    { "type": "text", "text": "look" },
+                    { "type": "image", "source": { "type": "base64", "media_type": "image/jpeg", "data": "BBBB" } },
+                ] },
+                { "role": "assistant", "content": [
+                    { "type": "thinking", "thinking": "…", "signature": "sig" },
+                    { "type": "tool_use", "id": "toolu_1", "name": "read", "input": { "path": "a.txt" } },
+                ] },
+                { "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": "toolu_1", "content": "gone", "is_error": true },
+                    { "type": "text", "text": "try again" },
+                ] },
+            ],
+            "tools": [
+                { "name": "read", "input_schema": { "type": "object" } },
+                { "type": "web_search_20250305", "name": "web_search" },
+            ],
+            "tool_choice": { "type": "tool", "name": "read", "disable_parallel_tool_use": true },
+        });
+        let out = messages_request_to_chat(&req).expect("translate");
+
+        let messages = out["messages"].as_array().expect("messages");
+        let roles: Vec<&str> = messages
+            .iter()
+            .map(|m| m["role"].as_str().unwrap_or(""))
+            .collect();
+        assert_eq!(roles, ["system", "user", "assistant", "tool", "user"]);
+        assert_eq!(messages[0]["content"], "be brief\nno emoji");
+        assert_eq!(
+            messages[1]["content"][1],
+            json!({ "type": "image_url", "image_url": { "url": "data:image/jpeg;base64,BBBB" } })
+        );
+        assert_eq!(messages[2]["content"], Value::Null);
+        assert_eq!(
+            messages[2]["tool_calls"][0]["function"]["arguments"],
+            r#"{"path":"a.txt"}"#
+        );
+        assert_eq!(messages[3]["tool_call_id"], "toolu_1");
+        assert_eq!(messages[3]["content"], "[error] gone");
+        assert_eq!(messages[4]["content"], "try again");
+
+        assert_eq!(out["stop"], json!(["END"]));
+        assert_eq!(out["stream_options"], json!({ "include_usage": true }));
+        assert_eq!(out["tools"].as_array().map(Vec::len), Some(1));
+        assert_eq!(
+            out["tool_choice"],
+            json!({ "type": "function", "function": { "name": "read" } })
+        );
+        assert_eq!(out["parallel_tool_calls"], false);
+    }
+
+    #[test]
+    fn chat_response_maps_tool_calls_finish_reason_and_usage() {
+        let v = json!({
+            "id": "chatcmpl-1",
+            "model": "gpt-x",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "reading",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "read", "arguments": "{\"path\":\"a.txt\"}" },
+                    }],
+                },
+                "finish_reason": "tool_calls",
+            }],
+            "usage": {
+                "prompt_tokens": 20,
+                "completion_tokens": 4,
+                "prompt_tokens_details": { "cached_tokens": 5 },
+            },
+        });
+        let out = chat_response_to_messages(&v);
+        assert_eq!(out["type"], "message");
+        assert_eq!(
+            out["content"][0],
+            json!({ "type": "text", "text": "reading" })
+        );
+        assert_eq!(
+            out["content"][1],
+            json!({ "type": "tool_use", "id": "call_1", "name": "read", "input": { "path": "a.txt" } })
+        );
+        assert_eq!(out["stop_reason"], "tool_use");
+        assert_eq!(
+            out["usage"],
+            json!({ "input_tokens": 15, "output_tokens": 4, "cache_read_input_tokens": 5 })
+        );
+    }
+}
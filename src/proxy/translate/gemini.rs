@@ -0,0 +1,1188 @@
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use super::{
+    SseEvent, StreamTranslator, content_texts, now_secs, parse_arguments, write_sse, write_sse_json,
+};
+
+pub(super) fn generate_request_to_chat(
+    req: &Value,
+    model: &str,
+    stream: bool,
+) -> Result<Value, String> {
+    let mut messages: Vec<Value> = Vec::new();
+
+    let system = req
+        .get("systemInstruction")
+        .or_else(|| req.get("system_instruction"))
+        .map(|s| content_texts(s.get("parts")).join("\n"))
+        .unwrap_or_default();
+    if !system.is_empty() {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+
+    // Gemini 的 functionCall/functionResponse 通常没有 id，按名称顺序配对
+    let mut pending: HashMap<String, VecDeque<String>> = HashMap::new();
+    let mut call_seq = 0usize;
+    let contents = req
+        .get("contents")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "缺少 contents".to_string())?;
+    for c in contents {
+        let role = c.get("role").and_then(Value::as_str).unwrap_or("user");
+        let parts = c
+            .get("parts")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if role == "model" {
+            let mut text = String::new();
+            let mut tool_calls = Vec::new();
+            for part in parts {
+                if part.get("thought").and_then(Value::as_bool) == Some(true) {
+                    continue;
+                }
+                if let Some(t) = part.get("text").and_then(Value::as_str) {
+                    text.push_str(t);
+                } else if let Some(fc) = part.get("functionCall") {
+                    let name = fc.get("name").and_then(Value::as_str).unwrap_or("");
+                    let id = fc
+                        .get("id")
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("call_{call_seq}"));
+                    call_seq += 1;
+                    pending
+                        .entry(name.to_string())
+                        .or_default()
+                        .push_back(id.clone());
+                    tool_calls.push(json!({
+                        "id": id,
+                        "type": "function",
+                        "function": {
+                            "name": name,
+                            "arguments": fc.get("args").cloned().unwrap_or_else(|| json!({})).to_string(),
+                        },
+                    }));
+                }
+            }
+            let mut message = json!({ "role": "assistant", "content": text });
+            if !tool_calls.is_empty() {
+                if text.is_empty() {
+                    message["content"] = Value::Null;
+                }
+                message["tool_calls"] = Value::Array(tool_calls);
+            }
+            messages.push(message);
+            continue;
+        }
+
+        let mut user_parts = Vec::new();
+        for part in parts {
+            if let Some(t) = part.get("text").and_then(Value::as_str) {
+                if !t.is_empty() {
+                    user_parts.push(json!({ "type": "text", "text": t }));
+                }
+            } else if let Some(data) = part.get("inlineData").or_else(|| part.get("inline_data")) {
+                let mime = data
+                    .get("mimeType")
+                    .or_else(|| data.get("mime_type"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                if !mime.starts_with("image/") {
+                    return Err(format!("不支持的 inlineData 类型：{mime}"));
+                }
+                let url = format!(
+                    "data:{mime};base64,{}",
+                    data.get("data").and_then(Value::as_str).unwrap_or("")
+                );
+                user_parts.push(json!({ "type": "image_url", "image_url": { "url": url } }));
+            } else if let Some(file) = part.get("fileData").or_else(|| part.get("file_data")) {
+                let uri = file
+                    .get("fileUri")
+                    .or_else(|| file.get("file_uri"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                user_parts.push(json!({ "type": "image_url", "image_url": { "url": uri } }));
+            } else if let Some(fr) = part.get("functionResponse") {
+                let name = fr.get("name").and_then(Value::as_str).unwrap_or("");
+                let id = fr
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .or_else(|| pending.get_mut(name).and_then(VecDeque::pop_front))
+                    .unwrap_or_else(|| format!("call_{name}"));
+                let response = fr.get("response").cloned().unwrap_or(Value::Null);
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": id,
+                    "content": response.to_string(),
+                }));
+            } else {
+                return Err("不支持的 parts 内容".to_string());
+            }
+        }
+        if !user_parts.is_empty() {
+            let content = if user_parts.iter().all(|p| p["type"] == "text") {
+                json!(content_texts(Some(&Value::Array(user_parts))).join("\n"))
+            } else {
+                Value::Array(user_parts)
+            };
+            messages.push(json!({ "role": "user", "content": content }));
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert("model".to_string(), json!(model));
+    out.insert("messages".to_string(), Value::Array(messages));
+
+    let config = req
+        .get("generationConfig")
+        .or_else(|| req.get("generation_config"))
+        .unwrap_or(&Value::Null);
+    for (from, to) in [
+        ("temperature", "temperature"),
+        ("topP", "top_p"),
+        ("maxOutputTokens", "max_tokens"),
+        ("presencePenalty", "presence_penalty"),
+        ("frequencyPenalty", "frequency_penalty"),
+        ("seed", "seed"),
+    ] {
+        if let Some(v) = config.get(from).filter(|v| v.is_number()) {
+            out.insert(to.to_string(), v.clone());
+        }
+    }
+    if let Some(stop) = config
+        .get("stopSequences")
+        .and_then(Value::as_array)
+        .filter(|a| !a.is_empty())
+    {
+        out.insert("stop".to_string(), Value::Array(stop.clone()));
+    }
+    if config.get("responseMimeType").and_then(Value::as_str) == Some("application/json") {
+        out.insert(
+            "response_format".to_string(),
+            json!({ "type": "json_object" }),
+        );
+    }
+    if stream {
+        out.insert("stream".to_string(), json!(true));
+        out.insert(
+            "stream_options".to_string(),
+            json!({ "include_usage": true }),
+        );
+    }
+
+    let tools: Vec<Value> = req
+        .get("tools")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|t| {
+            t.get("functionDeclarations")
+                .or_else(|| t.get("function_declarations"))
+                .and_then(Value::as_array)
+        })
+        .flatten()
+        .map(|d| {
+            let mut function = Map::new();
+            function.insert(
+                "name".to_string(),
+                d.get("name").cloned().unwrap_or(Value::Null),
+            );
+            if let Some(desc) = d.get("description") {
+                function.insert("description".to_string(), desc.clone());
+            }
+            let parameters = d
+                .get("parametersJsonSchema")
+                .cloned()
+                .or_else(|| d.get("parameters").map(lowercase_schema_types))
+                .unwrap_or_else(|| json!({ "type": "object", "properties": {} }));
+            function.insert("parameters".to_string(), parameters);
+            json!({ "type": "function", "function": function })
+        })
+        .collect();
+    if !tools.is_empty() {
+        out.insert("tools".to_string(), Value::Array(tools));
+        let calling = req
+            .get("toolConfig")
+            .and_then(|c| c.get("functionCallingConfig"))
+            .unwrap_or(&Value::Null);
+        let allowed = calling
+            .get("allowedFunctionNames")
+            .and_then(Value::as_array)
+            .filter(|a| a.len() == 1)
+            .and_then(|a| a[0].as_str());
+        let choice = match calling.get("mode").and_then(Value::as_str) {
+            Some("NONE") => Some(json!("none")),
+            Some("AUTO") => Some(json!("auto")),
+            Some("ANY") => Some(match allowed {
+                Some(name) => json!({ "type": "function", "function": { "name": name } }),
+                None => json!("required"),
+            }),
+            _ => None,
+        };
+        if let Some(choice) = choice {
+            out.insert("tool_choice".to_string(), choice);
+        }
+    }
+
+    Ok(Value::Object(out))
+}
+
+// Gemini 的 Schema 使用大写类型名（OBJECT/STRING），JSON Schema 需要小写
+fn lowercase_schema_types(v: &Value) -> Value {
+    match v {
+        Value::Object(o) => Value::Object(
+            o.iter()
+                .map(|(k, x)| {
+                    let x = match (k.as_str(), x) {
+                        ("type", Value::String(t)) => json!(t.to_ascii_lowercase()),
+                        _ => lowercase_schema_types(x),
+                    };
+                    (k.clone(), x)
+                })
+                .collect(),
+        ),
+        Value::Array(a) => Value::Array(a.iter().map(lowercase_schema_types).collect()),
+        _ => v.clone(),
+    }
+}
+
+// 返回上游路径（含 query）与请求体
+pub(super) fn chat_request_to_generate(req: &Value) -> Result<(String, Value), String> {
+    let model = req
+        .get("model")
+        .and_then(Value::as_str)
+        .map(|m| m.strip_prefix("models/").unwrap_or(m))
+        .filter(|m| !m.is_empty())
+        .ok_or_else(|| "缺少 model".to_string())?;
+    let stream = req.get("stream").and_then(Value::as_bool) == Some(true);
+
+    let mut system: Vec<Value> = Vec::new();
+    let mut contents: Vec<Value> = Vec::new();
+    let mut call_names: HashMap<String, String> = HashMap::new();
+    let input = req
+        .get("messages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "缺少 messages".to_string())?;
+    for m in input {
+        let role = m.get("role").and_then(Value::as_str).unwrap_or("user");
+        match role {
+            "system" | "developer" => {
+                for text in content_texts(m.get("content")) {
+                    system.push(json!({ "text": text }));
+                }
+            }
+            "user" => {
+                let parts = match m.get("content") {
+                    Some(Value::Array(items)) => {
+                        let mut parts = Vec::new();
+                        for item in items {
+                            match item.get("type").and_then(Value::as_str) {
+                                Some("text") => {
+                                    if let Some(t) = item.get("text").and_then(Value::as_str)
+                                        && !t.is_empty()
+                                    {
+                                        parts.push(json!({ "text": t }));
+                                    }
+                                }
+                                Some("image_url") => {
+                                    let url = item
+                                        .get("image_url")
+                                        .and_then(|u| u.get("url").or(Some(u)))
+                                        .and_then(Value::as_str)
+                                        .ok_or_else(|| "image_url 缺少 url".to_string())?;
+                                    parts.push(image_part(url));
+                                }
+                                other => {
+                                    return Err(format!(
+                                        "不支持的消息内容类型：{}",
+                                        other.unwrap_or("unknown")
+                                    ));
+                                }
+                            }
+                        }
+                        parts
+                    }
+                    content => content_texts(content)
+                        .into_iter()
+                        .map(|t| json!({ "text": t }))
+                        .collect(),
+                };
+                push_content(&mut contents, "user", parts);
+            }
+            "assistant" => {
+                let mut parts: Vec<Value> = content_texts(m.get("content"))
+                    .into_iter()
+                    .map(|t| json!({ "text": t }))
+                    .collect();
+                for call in m
+                    .get("tool_calls")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                {
+                    let function = call.get("function").unwrap_or(&Value::Null);
+                    let name = function.get("name").and_then(Value::as_str).unwrap_or("");
+                    if let Some(id) = call.get("id").and_then(Value::as_str) {
+                        call_names.insert(id.to_string(), name.to_string());
+                    }
+                    parts.push(json!({
+                        "functionCall": {
+                            "name": name,
+                            "args": parse_arguments(function.get("arguments")),
+                        },
+                    }));
+                }
+                push_content(&mut contents, "model", parts);
+            }
+            "tool" => {
+                let name = m
+                    .get("tool_call_id")
+                    .and_then(Value::as_str)
+                    .and_then(|id| call_names.get(id))
+                    .cloned()
+                    .unwrap_or_default();
+                let text = content_texts(m.get("content")).join("\n");
+                // functionResponse.response 必须是对象
+                let response = match serde_json::from_str::<Value>(&text) {
+                    Ok(v @ Value::Object(_)) => v,
+                    _ => json!({ "content": text }),
+                };
+                push_content(
+                    &mut contents,
+                    "user",
+                    vec![json!({ "functionResponse": { "name": name, "response": response } })],
+                );
+            }
+            other => return Err(format!("不支持的消息角色：{other}")),
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert("contents".to_string(), Value::Array(contents));
+    if !system.is_empty() {
+        out.insert("systemInstruction".to_string(), json!({ "parts": system }));
+    }
+
+    let mut config = Map::new();
+    for (from, to) in [
+        ("temperature", "temperature"),
+        ("top_p", "topP"),
+        ("presence_penalty", "presencePenalty"),
+        ("frequency_penalty", "frequencyPenalty"),
+        ("seed", "seed"),
+    ] {
+        if let Some(v) = req.get(from).filter(|v| v.is_number()) {
+            config.insert(to.to_string(), v.clone());
+        }
+    }
+    if let Some(v) = req
+        .get("max_completion_tokens")
+        .or_else(|| req.get("max_tokens"))
+        .filter(|v| v.is_number())
+    {
+        config.insert("maxOutputTokens".to_string(), v.clone());
+    }
+    match req.get("stop") {
+        Some(Value::String(s)) => {
+            config.insert("stopSequences".to_string(), json!([s]));
+        }
+        Some(Value::Array(a)) if !a.is_empty() => {
+            config.insert("stopSequences".to_string(), Value::Array(a.clone()));
+        }
+        _ => {}
+    }
+    if matches!(
+        req.get("response_format")
+            .and_then(|f| f.get("type"))
+            .and_then(Value::as_str),
+        Some("json_object" | "json_schema")
+    ) {
+        config.insert("responseMimeType".to_string(), json!("application/json"));
+    }
+    if !config.is_empty() {
+        out.insert("generationConfig".to_string(), Value::Object(config));
+    }
+
+    let declarations: Vec<Value> = req
+        .get("tools")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t.get("function"))
+        .map(|f| {
+            let mut d = Map::new();
+            d.insert(
+                "name".to_string(),
+                f.get("name").cloned().unwrap_or(Value::Null),
+            );
+            if let Some(desc) = f.get("description") {
+                d.insert("description".to_string(), desc.clone());
+            }
+            if let Some(p) = f.get("parameters") {
+                d.insert("parametersJsonSchema".to_string(), p.clone());
+            }
+            Value::Object(d)
+        })
+        .collect();
+    if !declarations.is_empty() {
+        out.insert(
+            "tools".to_string(),
+            json!([{ "functionDeclarations": declarations }]),
+        );
+        let calling = match req.get("tool_choice") {
+            Some(Value::String(s)) if s == "none" => Some(json!({ "mode": "NONE" })),
+            Some(Value::String(s)) if s == "auto" => Some(json!({ "mode": "AUTO" })),
+            Some(Value::String(s)) if s == "required" => Some(json!({ "mode": "ANY" })),
+            Some(Value::Object(o)) => o
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .map(|name| json!({ "mode": "ANY", "allowedFunctionNames": [name] })),
+            _ => None,
+        };
+        if let Some(calling) = calling {
+            out.insert(
+                "toolConfig".to_string(),
+                json!({ "functionCallingConfig": calling }),
+            );
+        }
+    }
+
+    let path = if stream {
+        format!("/v1beta/models/{model}:streamGenerateContent?alt=sse")
+    } else {
+        format!("/v1beta/models/{model}:generateContent")
+    };
+    Ok((path, Value::Object(out)))
+}
+
+fn push_content(contents: &mut Vec<Value>, role: &str, parts: Vec<Value>) {
+    if parts.is_empty() {
+        return;
+    }
+    if let Some(last) = contents.last_mut()
+        && last.get("role").and_then(Value::as_str) == Some(role)
+        && let Some(existing) = last.get_mut("parts").and_then(Value::as_array_mut)
+    {
+        existing.extend(parts);
+        return;
+    }
+    contents.push(json!({ "role": role, "parts": parts }));
+}
+
+fn image_part(url: &str) -> Value {
+    if let Some(rest) = url.strip_prefix("data:")
+        && let Some((meta, data)) = rest.split_once(',')
+        && let Some(mime) = meta.strip_suffix(";base64")
+    {
+        return json!({ "inlineData": { "mimeType": mime, "data": data } });
+    }
+    let ext = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|p| p.rsplit_once('.'))
+        .map(|(_, e)| e.to_ascii_lowercase());
+    let mime = match ext.as_deref() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    };
+    json!({ "fileData": { "mimeType": mime, "fileUri": url } })
+}
+
+pub(super) fn generate_response_to_chat(v: &Value) -> Value {
+    let candidate = v
+        .get("candidates")
+        .and_then(|c| c.get(0))
+        .unwrap_or(&Value::Null);
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for part in candidate
+        .get("content")
+        .and_then(|c| c.get("parts"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(t) = part.get("text").and_then(Value::as_str) {
+            if part.get("thought").and_then(Value::as_bool) == Some(true) {
+                reasoning.push_str(t);
+            } else {
+                text.push_str(t);
+            }
+        } else if let Some(fc) = part.get("functionCall") {
+            tool_calls.push(tool_call(tool_calls.len(), fc));
+        }
+    }
+
+    let mut message = Map::new();
+    message.insert("role".to_string(), json!("assistant"));
+    message.insert(
+        "content".to_string(),
+        if text.is_empty() && !tool_calls.is_empty() {
+            Value::Null
+        } else {
+            json!(text)
+        },
+    );
+    if !reasoning.is_empty() {
+        message.insert("reasoning_content".to_string(), json!(reasoning));
+    }
+    let has_tool_calls = !tool_calls.is_empty();
+    if has_tool_calls {
+        message.insert("tool_calls".to_string(), Value::Array(tool_calls));
+    }
+
+    let finish_reason = chat_finish_reason(
+        candidate.get("finishReason").and_then(Value::as_str),
+        has_tool_calls,
+    );
+    let mut out = json!({
+        "id": v.get("responseId").cloned().unwrap_or_else(|| json!(format!("chatcmpl-{}", uuid::Uuid::new_v4()))),
+        "object": "chat.completion",
+        "created": now_secs(),
+        "model": v.get("modelVersion").cloned().unwrap_or(Value::Null),
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason,
+            "logprobs": null,
+        }],
+    });
+    if let Some(u) = v.get("usageMetadata") {
+        out["usage"] = chat_usage(u);
+    }
+    out
+}
+
+fn tool_call(index: usize, fc: &Value) -> Value {
+    json!({
+        "id": fc.get("id").cloned().unwrap_or_else(|| json!(format!("call_{index}"))),
+        "type": "function",
+        "function": {
+            "name": fc.get("name").cloned().unwrap_or(Value::Null),
+            "arguments": fc.get("args").cloned().unwrap_or_else(|| json!({})).to_string(),
+        },
+    })
+}
+
+fn chat_finish_reason(reason: Option<&str>, has_tool_calls: bool) -> Option<&'static str> {
+    let reason = reason?;
+    Some(match reason {
+        _ if has_tool_calls => "tool_calls",
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => "content_filter",
+        _ => "stop",
+    })
+}
+
+// Gemini 的 candidatesTokenCount 不含思考部分
+fn chat_usage(u: &Value) -> Value {
+    let get = |k: &str| u.get(k).and_then(Value::as_i64).unwrap_or(0);
+    let prompt = get("promptTokenCount");
+    let completion = get("candidatesTokenCount") + get("thoughtsTokenCount");
+    let total = u
+        .get("totalTokenCount")
+        .and_then(Value::as_i64)
+        .unwrap_or(prompt + completion);
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": total,
+        "prompt_tokens_details": { "cached_tokens": get("cachedContentTokenCount") },
+    })
+}
+
+pub(super) fn chat_response_to_generate(v: &Value) -> Value {
+    let choice = v
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .unwrap_or(&Value::Null);
+    let message = choice.get("message").unwrap_or(&Value::Null);
+
+    let mut parts = Vec::new();
+    if let Some(r) = message.get("reasoning_content").and_then(Value::as_str)
+        && !r.is_empty()
+    {
+        parts.push(json!({ "text": r, "thought": true }));
+    }
+    if let Some(t) = message.get("content").and_then(Value::as_str)
+        && !t.is_empty()
+    {
+        parts.push(json!({ "text": t }));
+    }
+    for call in message
+        .get("tool_calls")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let function = call.get("function").unwrap_or(&Value::Null);
+        parts.push(json!({
+            "functionCall": {
+                "name": function.get("name").cloned().unwrap_or(Value::Null),
+                "args": parse_arguments(function.get("arguments")),
+            },
+        }));
+    }
+
+    let mut candidate = json!({
+        "content": { "role": "model", "parts": parts },
+        "index": 0,
+    });
+    if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+        candidate["finishReason"] = json!(gemini_finish_reason(reason));
+    }
+    let mut out = json!({
+        "candidates": [candidate],
+        "modelVersion": v.get("model").cloned().unwrap_or(Value::Null),
+        "responseId": v.get("id").cloned().unwrap_or(Value::Null),
+    });
+    if let Some(u) = v.get("usage").filter(|u| u.is_object()) {
+        out["usageMetadata"] = usage_metadata(u);
+    }
+    out
+}
+
+fn gemini_finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "length" => "MAX_TOKENS",
+        "content_filter" => "SAFETY",
+        _ => "STOP",
+    }
+}
+
+fn usage_metadata(u: &Value) -> Value {
+    let get = |k: &str| u.get(k).and_then(Value::as_i64).unwrap_or(0);
+    let prompt = get("prompt_tokens");
+    let completion = get("completion_tokens");
+    let mut out = json!({
+        "promptTokenCount": prompt,
+        "candidatesTokenCount": completion,
+        "totalTokenCount": u.get("total_tokens").and_then(Value::as_i64).unwrap_or(prompt + completion),
+    });
+    if let Some(cached) = u
+        .get("prompt_tokens_details")
+        .and_then(|d| d.get("cached_tokens"))
+        .and_then(Value::as_i64)
+        .filter(|n| *n > 0)
+    {
+        out["cachedContentTokenCount"] = json!(cached);
+    }
+    out
+}
+
+// Gemini SSE -> chat chunk；Gemini 流不发送 [DONE]，在流结束时补齐收尾 chunk
+pub(super) struct GenerateToChatStream {
+    include_usage: bool,
+    id: String,
+    model: Value,
+    created: i64,
+    started: bool,
+    done: bool,
+    tool_count: usize,
+    finish_reason: Option<String>,
+    usage: Option<Value>,
+}
+
+impl GenerateToChatStream {
+    pub(super) fn new(include_usage: bool) -> Self {
+        Self {
+            include_usage,
+            id: format!("chatcmpl-{}", uuid::Uuid::new_v4()),
+            model: Value::Null,
+            created: now_secs(),
+            started: false,
+            done: false,
+            tool_count: 0,
+            finish_reason: None,
+            usage: None,
+        }
+    }
+
+    fn chunk(&self, out: &mut Vec<u8>, delta: Value, finish_reason: Option<&str>) {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        });
+        write_sse_json(out, None, &chunk);
+    }
+}
+
+impl StreamTranslator for GenerateToChatStream {
+    fn on_event(&mut self, ev: SseEvent, out: &mut Vec<u8>) {
+        let Ok(v) = serde_json::from_str::<Value>(&ev.data) else {
+            return;
+        };
+        if let Some(error) = v.get("error") {
+            write_sse_json(out, None, &json!({ "error": error }));
+            return;
+        }
+        if let Some(model) = v.get("modelVersion") {
+            self.model = model.clone();
+        }
+        if let Some(u) = v.get("usageMetadata") {
+            self.usage = Some(chat_usage(u));
+        }
+        if !self.started {
+            self.started = true;
+            self.chunk(out, json!({ "role": "assistant", "content": "" }), None);
+        }
+
+        let candidate = v
+            .get("candidates")
+            .and_then(|c| c.get(0))
+            .unwrap_or(&Value::Null);
+        for part in candidate
+            .get("content")
+            .and_then(|c| c.get("parts"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(t) = part.get("text").and_then(Value::as_str) {
+                let key = if part.get("thought").and_then(Value::as_bool) == Some(true) {
+                    "reasoning_content"
+                } else {
+                    "content"
+                };
+                self.chunk(out, json!({ key: t }), None);
+            } else if let Some(fc) = part.get("functionCall") {
+                let mut call = tool_call(self.tool_count, fc);
+                call["index"] = json!(self.tool_count);
+                self.tool_count += 1;
+                self.chunk(out, json!({ "tool_calls": [call] }), None);
+            }
+        }
+        if let Some(reason) = candidate.get("finishReason").and_then(Value::as_str) {
+            self.finish_reason = Some(reason.to_string());
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        if !self.started || self.done {
+            return;
+        }
+        self.done = true;
+        let reason = chat_finish_reason(
+            Some(self.finish_reason.as_deref().unwrap_or("STOP")),
+            self.tool_count > 0,
+        );
+        self.chunk(out, json!({}), reason);
+        if self.include_usage
+            && let Some(usage) = &self.usage
+        {
+            let chunk = json!({
+                "id": self.id,
+                "object": "chat.completion.chunk",
+                "created": self.created,
+                "model": self.model,
+                "choices": [],
+                "usage": usage,
+            });
+            write_sse_json(out, None, &chunk);
+        }
+        write_sse(out, None, "[DONE]");
+    }
+}
+
+// chat chunk -> Gemini SSE；工具调用参数是增量下发的，需累积到结束时一次性输出
+pub(super) struct ChatToGenerateStream {
+    id: Value,
+    model: Value,
+    tool_calls: BTreeMap<i64, (String, String)>,
+    finish_reason: Option<String>,
+    usage: Option<Value>,
+    started: bool,
+    done: bool,
+}
+
+impl ChatToGenerateStream {
+    pub(super) fn new() -> Self {
+        Self {
+            id: Value::Null,
+            model: Value::Null,
+            tool_calls: BTreeMap::new(),
+            finish_reason: None,
+            usage: None,
+            started: false,
+            done: false,
+        }
+    }
+
+    fn emit(&self, out: &mut Vec<u8>, parts: Vec<Value>, finish: bool) {
+        let mut candidate = json!({
+            "content": { "role": "model", "parts": parts },
+            "index": 0,
+        });
+        let mut chunk = json!({
+            "modelVersion": self.model,
+            "responseId": self.id,
+        });
+        if finish {
+            candidate["finishReason"] = json!(gemini_finish_reason(
+                self.finish_reason.as_deref().unwrap_or("stop")
+            ));
+            if let Some(usage) = &self.usage {
+                chunk["usageMetadata"] = usage_metadata(usage);
+            }
+        }
+        chunk["candidates"] = json!([candidate]);
+        write_sse_json(out, None, &chunk);
+    }
+
+    fn flush(&mut self, out: &mut Vec<u8>) {
+        if !self.started || self.done {
+            return;
+        }
+        self.done = true;
+        let parts = self
+            .tool_calls
+            .values()
+            .map(|(name, args)| {
+                json!({
+                    "functionCall": {
+                        "name": name,
+                        "args": parse_arguments(Some(&json!(args))),
+                    },
+                })
+            })
+            .collect();
+        self.emit(out, parts, true);
+    }
+}
+
+impl StreamTranslator for ChatToGenerateStream {
+    fn on_event(&mut self, ev: SseEvent, out: &mut Vec<u8>) {
+        if ev.data.trim() == "[DONE]" {
+            self.flush(out);
+            return;
+        }
+        let Ok(v) = serde_json::from_str::<Value>(&ev.data) else {
+            return;
+        };
+        if let Some(error) = v.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| error.to_string());
+            let body =
+                json!({ "error": { "code": 500, "message": message, "status": "INTERNAL" } });
+            write_sse_json(out, None, &body);
+            return;
+        }
+        self.started = true;
+        if let Some(id) = v.get("id") {
+            self.id = id.clone();
+        }
+        if let Some(model) = v.get("model") {
+            self.model = model.clone();
+        }
+        if let Some(u) = v.get("usage").filter(|u| u.is_object()) {
+            self.usage = Some(u.clone());
+        }
+        let Some(choice) = v.get("choices").and_then(|c| c.get(0)) else {
+            return;
+        };
+        let delta = choice.get("delta").unwrap_or(&Value::Null);
+
+        let mut parts = Vec::new();
+        if let Some(r) = delta.get("reasoning_content").and_then(Value::as_str)
+            && !r.is_empty()
+        {
+            parts.push(json!({ "text": r, "thought": true }));
+        }
+        if let Some(t) = delta.get("content").and_then(Value::as_str)
+            && !t.is_empty()
+        {
+            parts.push(json!({ "text": t }));
+        }
+        if !parts.is_empty() {
+            self.emit(out, parts, false);
+        }
+
+        for call in delta
+            .get("tool_calls")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let index = call.get("index").and_then(Value::as_i64).unwrap_or(0);
+            let entry = self.tool_calls.entry(index).or_default();
+            let function = call.get("function").unwrap_or(&Value::Null);
+            if let Some(name) = function.get("name").and_then(Value::as_str) {
+                entry.0.push_str(name);
+            }
+            if let Some(args) = function.get("arguments").and_then(Value::as_str) {
+                entry.1.push_str(args);
+            }
+        }
+        if let Some(reason) = choice.get("finish_reason").and_then(Value::as_str) {
+            self.finish_reason = Some(reason.to_string());
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        if self.finish_reason.is_some() {
+            self.flush(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_request_maps_system_images_function_calls_and_stop() {
+        let req = json!({
+            "systemInstruction": { "parts": [{ "text": "be brief" }] },
+            "contents": [
+                { "role": "user", "parts": [
+                    { "text": "look" },
+                    { "inlineData": { "mimeType": "image/png", "data": "AAAA" } },
+                ] },
+                { "role": "model", "parts": [
+                    { "text": "thinking", "thought": true },
+                    { "functionCall": { "name": "read", "args": { "path": "a.txt" } } },
+                ] },
+                { "role": "user", "parts": [
+                    { "functionResponse": { "name": "read", "response": { "text": "hello" } } },
+                ] },
+            ],
+            "generationConfig": { "maxOutputTokens": 64, "stopSequences": ["END"], "topP": 0.5 },
+            "tools": [{ "functionDeclarations": [{
+                "name": "read",
+                "parameters": { "type": "OBJECT", "properties": { "path": { "type": "STRING" } } },
+            }] }],
+            "toolConfig": { "functionCallingConfig": { "mode": "ANY", "allowedFunctionNames": ["read"] } },
+        });
+        let out = generate_request_to_chat(&req, "gemini-x", true).expect("translate");
+
+        let messages = out["messages"].as_array().expect("messages");
+        let roles: Vec<&str> = messages
+            .iter()
+            .map(|m| m["role"].as_str().unwrap_or(""))
+            .collect();
+        assert_eq!(roles, ["system", "user", "assistant", "tool"]);
+        assert_eq!(messages[0]["content"], "be brief");
+        assert_eq!(
+            messages[1]["content"][1],
+            json!({ "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } })
+        );
+        assert_eq!(messages[2]["content"], Value::Null);
+        let call = &messages[2]["tool_calls"][0];
+        assert_eq!(call["function"]["arguments"], r#"{"path":"a.txt"}"#);
+        // 没有 id 的 functionResponse 按名称配对到前面的 functionCall
+        assert_eq!(messages[3]["tool_call_id"], call["id"]);
+        assert_eq!(messages[3]["content"], r#"{"text":"hello"}"#);
+
+        assert_eq!(out["model"], "gemini-x");
+        assert_eq!(out["max_tokens"], 64);
+        assert_eq!(out["top_p"], 0.5);
+        assert_eq!(out["stop"], json!(["END"]));
+        assert_eq!(out["stream_options"], json!({ "include_usage": true }));
+        assert_eq!(
+            out["tools"][0]["function"]["parameters"],
+            json!({ "type": "object", "properties": { "path": { "type": "string" } } })
+        );
+        assert_eq!(
+            out["tool_choice"],
+            json!({ "type": "function", "function": { "name": "read" } })
+        );
+    }
+
+    #[test]
+    fn generate_request_rejects_non_image_inline_data() {
+        let req = json!({
+            "contents": [{ "role": "user", "parts": [
+                { "inlineData": { "mimeType": "application/pdf", "data": "AAAA" } },
+            ] }],
+        });
+        assert!(generate_request_to_chat(&req, "gemini-x", false).is_err());
+    }
+
+    #[test]
+    fn chat_request_maps_system_images_tool_calls_and_stop() {
+        let req = json!({
+            "model": "models/gemini-x",
+            "stop": "END",
+            "max_tokens": 32,
+            "messages": [
+                { "role": "system", "content": "be brief" },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "look" },
+                    { "type": "image_url", "image_url": { "url": "data:image/webp;base64,BBBB" } },
+                    { "type": "image_url", "image_url": { "url": "https://x.test/a.PNG?x=1" } },
+                ] },
+                { "role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "read", "arguments": "{\"path\":\"a.txt\"}" },
+                }] },
+                { "role": "tool", "tool_call_id": "call_1", "content": "plain text" },
+            ],
+            "tools": [{ "type": "function", "function": { "name": "read", "parameters": { "type": "object" } } }],
+            "tool_choice": "required",
+        });
+        let (path, out) = chat_request_to_generate(&req).expect("translate");
+        assert_eq!(path, "/v1beta/models/gemini-x:generateContent");
+
+        assert_eq!(
+            out["systemInstruction"],
+            json!({ "parts": [{ "text": "be brief" }] })
+        );
+        let contents = out["contents"].as_array().expect("contents");
+        assert_eq!(contents.len(), 3);
+        assert_eq!(
+            contents[0]["parts"][1],
+            json!({ "inlineData": { "mimeType": "image/webp", "data": "BBBB" } })
+        );
+        assert_eq!(
+            contents[0]["parts"][2],
+            json!({ "fileData": { "mimeType": "image/png", "fileUri": "https://x.test/a.PNG?x=1" } })
+        );
+        assert_eq!(
+            contents[1],
+            json!({ "role": "model", "parts": [{ "functionCall": { "name": "read", "args": { "path": "a.txt" } } }] })
+        );
+        assert_eq!(
+            contents[2]["parts"][0],
+            json!({ "functionResponse": { "name": "read", "response": { "content": "plain text" } } })
+        );
+
+        assert_eq!(
+            out["generationConfig"],
+            json!({ "maxOutputTokens": 32, "stopSequences": ["END"] })
+        );
+        assert_eq!(
+            out["tools"][0]["functionDeclarations"][0]["parametersJsonSchema"],
+            json!({ "type": "object" })
+        );
+        assert_eq!(
+            out["toolConfig"],
+            json!({ "functionCallingConfig": { "mode": "ANY" } })
+        );
+    }
+
+    #[test]
+    fn generate_response_maps_function_calls_finish_reason_and_usage() {
+        let v = json!({
+            "responseId": "resp-1",
+            "modelVersion": "gemini-x",
+            "candidates": [{
+                "content": { "role": "model", "parts": [
+                    { "text": "plan", "thought": true },
+                    { "functionCall": { "name": "read", "args": { "path": "a.txt" } } },
+                ] },
+                "finishReason": "STOP",
+            }],
+            "usageMetadata": {
+                "promptTokenCount": 10,
+                "candidatesTokenCount": 3,
+                "thoughtsTokenCount": 2,
+                "totalTokenCount": 15,
+                "cachedContentTokenCount": 4,
+            },
+        });
+        let out = generate_response_to_chat(&v);
+        let message = &out["choices"][0]["message"];
+        assert_eq!(message["content"], Value::Null);
+        assert_eq!(message["reasoning_content"], "plan");
+        assert_eq!(message["tool_calls"][0]["id"], "call_0");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            r#"{"path":"a.txt"}"#
+        );
+        // 有函数调用时 Gemini 仍返回 STOP
+        assert_eq!(out["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(
+            out["usage"],
+            json!({
+                "prompt_tokens": 10,
+                "completion_tokens": 5,
+                "total_tokens": 15,
+                "prompt_tokens_details": { "cached_tokens": 4 },
+            })
+        );
+    }
+
+    #[test]
+    fn finish_reasons_map_both_ways() {
+        for (gemini, openai) in [
+            ("STOP", "stop"),
+            ("MAX_TOKENS", "length"),
+            ("SAFETY", "content_filter"),
+            ("RECITATION", "content_filter"),
+            ("OTHER", "stop"),
+        ] {
+            assert_eq!(chat_finish_reason(Some(gemini), false), Some(openai));
+        }
+        assert_eq!(chat_finish_reason(None, true), None);
+
+        for (openai, gemini) in [
+            ("stop", "STOP"),
+            ("tool_calls", "STOP"),
+            ("length", "MAX_TOKENS"),
+            ("content_filter", "SAFETY"),
+        ] {
+            assert_eq!(gemini_finish_reason(openai), gemini);
+        }
+    }
+
+    #[test]
+    fn chat_response_maps_tool_calls_finish_reason_and_usage() {
+        let v = json!({
+            "id": "chatcmpl-1",
+            "model": "gpt-x",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "reading",
+                    "reasoning_content": "plan",
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "read", "arguments": "{\"path\":\"a.txt\"}" },
+                    }],
+                },
+                "finish_reason": "length",
+            }],
+            "usage": {
+                "prompt_tokens": 20,
+                "completion_tokens": 4,
+                "total_tokens": 24,
+                "prompt_tokens_details": { "cached_tokens": 5 },
+            },
+        });
+        let out = chat_response_to_generate(&v);
+        let candidate = &out["candidates"][0];
+        assert_eq!(
+            candidate["content"]["parts"],
+            json!([
+                { "text": "plan", "thought": true },
+                { "text": "reading" },
+                { "functionCall": { "name": "read", "args": { "path": "a.txt" } } },
+            ])
+        );
+        assert_eq!(candidate["finishReason"], "MAX_TOKENS");
+        assert_eq!(
+            out["usageMetadata"],
+            json!({
+                "promptTokenCount": 20,
+                "candidatesTokenCount": 4,
+                "totalTokenCount": 24,
+                "cachedContentTokenCount": 5,
+            })
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use axum::Router;
+use axum::middleware;
 use axum::routing::{any, get, post, put};
 use http::Method;
 use std::net::SocketAddr;
@@ -15,6 +16,7 @@ use crate::events::AppEvent;
 use crate::update;
 use crate::{events, storage};
 
+mod auth;
 mod error;
 mod handlers;
 mod state;
@@ -26,12 +28,16 @@ pub use state::AppState;
 fn request_endpoint_template(method: &Method, path: &str) -> Option<&'static str> {
     match (method.as_str(), path) {
         ("GET", "/api/health") => Some("/api/health"),
+        ("GET", "/metrics") => Some("/metrics"),
         ("GET", "/api/settings") => Some("/api/settings"),
         ("PUT", "/api/settings") => Some("/api/settings"),
         ("POST", "/api/maintenance/records/clear") => Some("/api/maintenance/records/clear"),
         ("POST", "/api/maintenance/logs/clear") => Some("/api/maintenance/logs/clear"),
         ("GET", "/api/maintenance/logs/size") => Some("/api/maintenance/logs/size"),
         ("GET", "/api/maintenance/db_size") => Some("/api/maintenance/db_size"),
+        ("POST", "/api/maintenance/db_compact") => Some("/api/maintenance/db_compact"),
+        ("GET", "/api/maintenance/cache") => Some("/api/maintenance/cache"),
+        ("POST", "/api/maintenance/cache/clear") => Some("/api/maintenance/cache/clear"),
         ("POST", "/api/logs/ingest") => Some("/api/logs/ingest"),
         ("GET", "/api/update/status") => Some("/api/update/status"),
         ("POST", "/api/update/check") => Some("/api/update/check"),
@@ -39,15 +45,20 @@ fn request_endpoint_template(method: &Method, path: &str) -> Option<&'static str
         ("GET", "/api/channels") => Some("/api/channels"),
         ("POST", "/api/channels") => Some("/api/channels"),
         ("POST", "/api/channels/reorder") => Some("/api/channels/reorder"),
+        ("GET", "/api/channels/spend") => Some("/api/channels/spend"),
         ("GET", "/api/routes") => Some("/api/routes"),
         ("POST", "/api/routes") => Some("/api/routes"),
+        ("GET", "/api/routes/resolve") => Some("/api/routes/resolve"),
         ("GET", "/api/pricing/status") => Some("/api/pricing/status"),
         ("GET", "/api/pricing/models") => Some("/api/pricing/models"),
         ("POST", "/api/pricing/sync") => Some("/api/pricing/sync"),
         ("GET", "/api/stats/summary") => Some("/api/stats/summary"),
         ("GET", "/api/stats/channels") => Some("/api/stats/channels"),
         ("GET", "/api/stats/trend") => Some("/api/stats/trend"),
+        ("GET", "/api/stats/breakdown") => Some("/api/stats/breakdown"),
         ("GET", "/api/usage/list") => Some("/api/usage/list"),
+        ("GET", "/api/client_tokens") => Some("/api/client_tokens"),
+        ("POST", "/api/client_tokens") => Some("/api/client_tokens"),
         _ => {
             let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
             match segments.as_slice() {
@@ -60,8 +71,20 @@ fn request_endpoint_template(method: &Method, path: &str) -> Option<&'static str
                 ["api", "channels", _, "test"] if method == Method::POST => {
                     Some("/api/channels/{id}/test")
                 }
+                ["api", "channels", _, "keys"] if method == Method::GET => {
+                    Some("/api/channels/{id}/keys")
+                }
+                ["api", "channels", _, "reveal"] if method == Method::POST => {
+                    Some("/api/channels/{id}/reveal")
+                }
                 ["api", "channels", _] if method == Method::PUT => Some("/api/channels/{id}"),
                 ["api", "channels", _] if method == Method::DELETE => Some("/api/channels/{id}"),
+                ["api", "client_tokens", _] if method == Method::PUT => {
+                    Some("/api/client_tokens/{id}")
+                }
+                ["api", "client_tokens", _] if method == Method::DELETE => {
+                    Some("/api/client_tokens/{id}")
+                }
                 ["api", "routes", _] if method == Method::PUT => Some("/api/routes/{id}"),
                 ["api", "routes", _] if method == Method::DELETE => Some("/api/routes/{id}"),
                 ["api", "routes", _, "channels"] if method == Method::GET => {
@@ -70,6 +93,12 @@ fn request_endpoint_template(method: &Method, path: &str) -> Option<&'static str
                 ["api", "routes", _, "channels", "reorder"] if method == Method::POST => {
                     Some("/api/routes/{id}/channels/reorder")
                 }
+                ["api", "usage", _, "capture"] if method == Method::GET => {
+                    Some("/api/usage/{request_id}/capture")
+                }
+                ["api", "usage", _, "replay"] if method == Method::POST => {
+                    Some("/api/usage/{request_id}/replay")
+                }
                 ["v1", "messages", ..] => Some("/v1/messages/{*path}"),
                 ["v1beta", ..] => Some("/v1beta/{*path}"),
                 ["v1", ..] => Some("/v1/{*path}"),
@@ -82,12 +111,16 @@ fn request_endpoint_template(method: &Method, path: &str) -> Option<&'static str
 fn request_purpose(method: &Method, path: &str) -> &'static str {
     match (method.as_str(), path) {
         ("GET", "/api/health") => "handlers::health",
+        ("GET", "/metrics") => "handlers::metrics",
         ("GET", "/api/settings") => "handlers::get_settings",
         ("PUT", "/api/settings") => "handlers::update_settings",
         ("POST", "/api/maintenance/records/clear") => "handlers::records_clear",
         ("POST", "/api/maintenance/logs/clear") => "handlers::logs_clear",
         ("GET", "/api/maintenance/logs/size") => "handlers::logs_size",
         ("GET", "/api/maintenance/db_size") => "handlers::db_size",
+        ("POST", "/api/maintenance/db_compact") => "handlers::db_compact",
+        ("GET", "/api/maintenance/cache") => "handlers::cache_stats",
+        ("POST", "/api/maintenance/cache/clear") => "handlers::cache_clear",
         ("POST", "/api/logs/ingest") => "handlers::frontend_log_ingest",
         ("GET", "/api/update/status") => "handlers::update_status",
         ("POST", "/api/update/check") => "handlers::update_check",
@@ -95,15 +128,20 @@ fn request_purpose(method: &Method, path: &str) -> &'static str {
         ("GET", "/api/channels") => "handlers::list_channels",
         ("POST", "/api/channels") => "handlers::create_channel",
         ("POST", "/api/channels/reorder") => "handlers::reorder_channels",
+        ("GET", "/api/channels/spend") => "handlers::list_channel_spend",
         ("GET", "/api/routes") => "handlers::list_routes",
         ("POST", "/api/routes") => "handlers::create_route",
+        ("GET", "/api/routes/resolve") => "handlers::resolve_route",
         ("GET", "/api/pricing/status") => "handlers::pricing_status",
         ("GET", "/api/pricing/models") => "handlers::pricing_models",
         ("POST", "/api/pricing/sync") => "handlers::pricing_sync",
         ("GET", "/api/stats/summary") => "handlers::stats_summary",
         ("GET", "/api/stats/channels") => "handlers::stats_channels",
         ("GET", "/api/stats/trend") => "handlers::stats_trend",
+        ("GET", "/api/stats/breakdown") => "handlers::stats_breakdown",
         ("GET", "/api/usage/list") => "handlers::usage_list",
+        ("GET", "/api/client_tokens") => "handlers::list_client_tokens",
+        ("POST", "/api/client_tokens") => "handlers::create_client_token",
         _ => {
             let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
             match segments.as_slice() {
@@ -116,8 +154,20 @@ fn request_purpose(method: &Method, path: &str) -> &'static str {
                 ["api", "channels", _, "test"] if method == Method::POST => {
                     "handlers::test_channel"
                 }
+                ["api", "channels", _, "keys"] if method == Method::GET => {
+                    "handlers::list_channel_keys"
+                }
+                ["api", "channels", _, "reveal"] if method == Method::POST => {
+                    "handlers::reveal_channel_secret"
+                }
                 ["api", "channels", _] if method == Method::PUT => "handlers::update_channel",
                 ["api", "channels", _] if method == Method::DELETE => "handlers::delete_channel",
+                ["api", "client_tokens", _] if method == Method::PUT => {
+                    "handlers::update_client_token"
+                }
+                ["api", "client_tokens", _] if method == Method::DELETE => {
+                    "handlers::delete_client_token"
+                }
                 ["api", "routes", _] if method == Method::PUT => "handlers::update_route",
                 ["api", "routes", _] if method == Method::DELETE => "handlers::delete_route",
                 ["api", "routes", _, "channels"] if method == Method::GET => {
@@ -126,6 +176,10 @@ fn request_purpose(method: &Method, path: &str) -> &'static str {
                 ["api", "routes", _, "channels", "reorder"] if method == Method::POST => {
                     "handlers::reorder_route_channels"
                 }
+                ["api", "usage", _, "capture"] if method == Method::GET => {
+                    "handlers::usage_capture"
+                }
+                ["api", "usage", _, "replay"] if method == Method::POST => "handlers::usage_replay",
                 ["v1", "messages", ..] => "handlers::proxy_anthropic",
                 ["v1beta", ..] => "handlers::proxy_gemini",
                 ["v1", ..] => "handlers::proxy_openai",
@@ -161,6 +215,7 @@ fn build_app(state: AppState) -> Router {
 
     let traced_api = Router::new()
         .route("/api/health", get(handlers::health))
+        .route("/metrics", get(handlers::metrics))
         .route(
             "/api/settings",
             get(handlers::get_settings).put(handlers::update_settings),
@@ -172,6 +227,9 @@ fn build_app(state: AppState) -> Router {
         .route("/api/maintenance/logs/clear", post(handlers::logs_clear))
         .route("/api/maintenance/logs/size", get(handlers::logs_size))
         .route("/api/maintenance/db_size", get(handlers::db_size))
+        .route("/api/maintenance/db_compact", post(handlers::db_compact))
+        .route("/api/maintenance/cache", get(handlers::cache_stats))
+        .route("/api/maintenance/cache/clear", post(handlers::cache_clear))
         .route("/api/logs/ingest", post(handlers::frontend_log_ingest))
         .route("/api/update/status", get(handlers::update_status))
         .route("/api/update/check", post(handlers::update_check))
@@ -181,6 +239,7 @@ fn build_app(state: AppState) -> Router {
             get(handlers::list_channels).post(handlers::create_channel),
         )
         .route("/api/channels/reorder", post(handlers::reorder_channels))
+        .route("/api/channels/spend", get(handlers::list_channel_spend))
         .route(
             "/api/channels/{id}",
             put(handlers::update_channel).delete(handlers::delete_channel),
@@ -191,10 +250,16 @@ fn build_app(state: AppState) -> Router {
             post(handlers::disable_channel),
         )
         .route("/api/channels/{id}/test", post(handlers::test_channel))
+        .route("/api/channels/{id}/keys", get(handlers::list_channel_keys))
+        .route(
+            "/api/channels/{id}/reveal",
+            post(handlers::reveal_channel_secret),
+        )
         .route(
             "/api/routes",
             get(handlers::list_routes).post(handlers::create_route),
         )
+        .route("/api/routes/resolve", get(handlers::resolve_route))
         .route(
             "/api/routes/{id}",
             put(handlers::update_route).delete(handlers::delete_route),
@@ -213,11 +278,32 @@ fn build_app(state: AppState) -> Router {
         .route("/api/stats/summary", get(handlers::stats_summary))
         .route("/api/stats/channels", get(handlers::stats_channels))
         .route("/api/stats/trend", get(handlers::stats_trend))
+        .route("/api/stats/breakdown", get(handlers::stats_breakdown))
         .route("/api/usage/list", get(handlers::usage_list))
+        .route(
+            "/api/usage/{request_id}/capture",
+            get(handlers::usage_capture),
+        )
+        .route(
+            "/api/usage/{request_id}/replay",
+            post(handlers::usage_replay),
+        )
+        .route(
+            "/api/client_tokens",
+            get(handlers::list_client_tokens).post(handlers::create_client_token),
+        )
+        .route(
+            "/api/client_tokens/{id}",
+            put(handlers::update_client_token).delete(handlers::delete_client_token),
+        )
         .route("/v1/messages", any(handlers::proxy_anthropic))
         .route("/v1/messages/{*path}", any(handlers::proxy_anthropic))
         .route("/v1beta/{*path}", any(handlers::proxy_gemini))
         .route("/v1/{*path}", any(handlers::proxy_openai))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_client_token,
+        ))
         .layer(trace_layer);
 
     let app = Router::new().merge(traced_api).with_state(state);
@@ -280,6 +366,7 @@ pub async fn serve_with_listener(
 
     let settings_rx2 = settings_rx.clone();
     let settings_rx3 = settings_rx.clone();
+    let settings_rx4 = settings_rx.clone();
     tokio::spawn(tasks::pricing_auto_update_loop(
         (*db_path).clone(),
         http_client.clone(),
@@ -298,6 +385,11 @@ pub async fn serve_with_listener(
         settings_rx3,
     ));
 
+    tokio::spawn(tasks::records_retention_cleanup_loop(
+        (*db_path).clone(),
+        settings_rx4,
+    ));
+
     tokio::spawn(tasks::apply_autostart_setting((*db_path).clone()));
 
     if open_browser {
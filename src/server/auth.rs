@@ -0,0 +1,56 @@
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Method, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::proxy;
+use crate::server::AppState;
+use crate::server::error::ApiError;
+use crate::storage::{self, ClientTokenRole};
+
+// 管理接口的客户端令牌校验；代理接口在 proxy::forward 内按协议与路由校验
+pub(super) async fn require_client_token(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let path = req.uri().path();
+    // 桌面端与健康检查依赖 /api/health，始终放行；/metrics 与管理接口同样需要令牌
+    let guarded = (path.starts_with("/api/") && path != "/api/health") || path == "/metrics";
+    if !guarded {
+        return Ok(next.run(req).await);
+    }
+    let settings = storage::get_app_settings(state.db_path()).await?;
+    if !settings.client_auth_enabled {
+        return Ok(next.run(req).await);
+    }
+
+    let Some(presented) = proxy::presented_token(req.headers(), req.uri()) else {
+        return Err(ApiError::Unauthorized("缺少客户端令牌".to_string()));
+    };
+    let now_ms = storage::now_ms();
+    let Some(token) = storage::find_client_token(state.db_path(), presented)
+        .await?
+        .filter(|t| t.is_active(now_ms))
+    else {
+        return Err(ApiError::Unauthorized(
+            "客户端令牌无效、已停用或已过期".to_string(),
+        ));
+    };
+
+    let read_only =
+        matches!(*req.method(), Method::GET | Method::HEAD) || path == "/api/logs/ingest";
+    let allowed = match token.role {
+        ClientTokenRole::Admin => true,
+        ClientTokenRole::ReadOnly => read_only,
+        ClientTokenRole::Proxy => false,
+    };
+    if !allowed {
+        return Err(ApiError::Forbidden(format!(
+            "客户端令牌 {} 无权执行该操作",
+            token.name
+        )));
+    }
+    Ok(next.run(req).await)
+}
@@ -15,8 +15,14 @@ pub(crate) enum ApiError {
     #[error("{0}")]
     BadRequest(String),
     #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
     NotFound(String),
     #[error("{0}")]
+    TooManyRequests(String),
+    #[error("{0}")]
     BadGateway(String),
     #[error("{0}")]
     Unavailable(String),
@@ -28,7 +34,10 @@ impl IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
         let (status, msg) = match &self {
             ApiError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+            ApiError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg.clone()),
+            ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg.clone()),
             ApiError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
+            ApiError::TooManyRequests(msg) => (StatusCode::TOO_MANY_REQUESTS, msg.clone()),
             ApiError::BadGateway(msg) => (StatusCode::BAD_GATEWAY, msg.clone()),
             ApiError::Unavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg.clone()),
             ApiError::Internal(err) => {
@@ -51,9 +60,16 @@ pub(crate) fn map_proxy_error(e: ProxyError) -> ApiError {
         ProxyError::NoAvailableChannel(p) => {
             ApiError::Unavailable(format!("无可用的 {} 渠道（可能被自动禁用）", p.as_str()))
         }
+        ProxyError::NoMatchingRoute(p, model) => {
+            ApiError::NotFound(format!("没有与模型 {model} 匹配的 {} 路由", p.as_str()))
+        }
         ProxyError::InvalidBaseUrl(msg) => ApiError::Internal(anyhow::anyhow!(msg)),
         ProxyError::ReadBody(msg) => ApiError::BadRequest(msg),
+        ProxyError::Translate(msg) => ApiError::BadRequest(msg),
         ProxyError::Upstream(msg) => ApiError::BadGateway(msg),
+        ProxyError::Unauthorized(msg) => ApiError::Unauthorized(msg),
+        ProxyError::Forbidden(msg) => ApiError::Forbidden(msg),
+        ProxyError::QuotaExceeded(msg) => ApiError::TooManyRequests(msg),
         ProxyError::Storage(e) => ApiError::Internal(e),
     }
 }
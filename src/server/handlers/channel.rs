@@ -9,19 +9,137 @@ use crate::server::AppState;
 use crate::server::error::{ApiError, map_storage_unit_no_content};
 use crate::storage;
 
-fn real_multiplier_is_valid(v: f64) -> bool {
-    if !v.is_finite() || v < 0.0 {
-        return false;
-    }
+const WEIGHT_RANGE: std::ops::RangeInclusive<i64> = 1..=10_000;
+
+fn validate_real_multiplier(v: &f64) -> anyhow::Result<()> {
     let scaled = v * 100.0;
-    (scaled - scaled.round()).abs() < 1e-9
+    if !v.is_finite() || *v < 0.0 || (scaled - scaled.round()).abs() >= 1e-9 {
+        anyhow::bail!("real_multiplier 必须是 >= 0 的有限数字，且最多 2 位小数");
+    }
+    Ok(())
+}
+
+fn require_non_empty(field: &str, v: &str) -> Result<(), ApiError> {
+    if v.trim().is_empty() {
+        return Err(ApiError::BadRequest(format!("{field} 不能为空")));
+    }
+    Ok(())
+}
+
+fn require_in_range(
+    field: &str,
+    v: i64,
+    range: std::ops::RangeInclusive<i64>,
+) -> Result<(), ApiError> {
+    if !range.contains(&v) {
+        return Err(ApiError::BadRequest(format!(
+            "{field} 必须在 {}..={} 之间",
+            range.start(),
+            range.end()
+        )));
+    }
+    Ok(())
+}
+
+// 可选的复合配置交给各自的校验函数，未填写时跳过
+fn validate_opt<T: ?Sized>(
+    v: Option<&T>,
+    validate: impl FnOnce(&T) -> anyhow::Result<()>,
+) -> Result<(), ApiError> {
+    v.map_or(Ok(()), validate)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+fn validate_model_map(map: &std::collections::BTreeMap<String, String>) -> Result<(), ApiError> {
+    for (k, v) in map {
+        require_non_empty("model_map 的模型名", k)?;
+        require_non_empty("model_map 的模型名", v)?;
+    }
+    Ok(())
+}
+
+fn validate_proxy_url(v: &str) -> anyhow::Result<()> {
+    proxy::validate_proxy_url(v).map_err(anyhow::Error::msg)
+}
+
+// 接口只返回打码后的 key，明文需通过 reveal 单独获取
+fn masked_auth_ref(auth_ref: &str) -> String {
+    let keys: Vec<String> = auth_ref
+        .lines()
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(storage::mask_key)
+        .collect();
+    keys.join("\n")
+}
+
+// 编辑时提交回来的打码行换回对应的原 key，其余行按提交内容保存；
+// 打码相同的多个 key 按顺序依次匹配
+fn unmask_auth_ref(submitted: &str, current: &str) -> String {
+    let mut stored: Vec<&str> = current
+        .lines()
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .collect();
+    let lines: Vec<String> = submitted
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            match stored
+                .iter()
+                .position(|k| *k != trimmed && storage::mask_key(k) == trimmed)
+            {
+                Some(i) => stored.remove(i).to_string(),
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    lines.join("\n")
+}
+
+fn masked(mut channel: storage::Channel) -> storage::Channel {
+    channel.auth_ref = masked_auth_ref(&channel.auth_ref);
+    channel
+}
+
+#[derive(Serialize)]
+struct ChannelView {
+    #[serde(flatten)]
+    channel: storage::Channel,
+    // 进程内的限速状态：上游限速头与本地令牌桶
+    rate_limit_state: Option<proxy::RateLimitState>,
 }
 
 pub(in crate::server) async fn list_channels(
     State(state): State<AppState>,
 ) -> Result<impl IntoResponse, ApiError> {
     let channels = storage::list_channels(state.db_path()).await?;
-    Ok(Json(channels))
+    let now_ms = storage::now_ms();
+    let items: Vec<ChannelView> = channels
+        .into_iter()
+        .map(|c| ChannelView {
+            rate_limit_state: proxy::rate_limit_state(&c, now_ms),
+            channel: masked(c),
+        })
+        .collect();
+    Ok(Json(items))
+}
+
+#[derive(Serialize)]
+struct ChannelSecret {
+    auth_ref: String,
+}
+
+pub(in crate::server) async fn reveal_channel_secret(
+    State(state): State<AppState>,
+    axum::extract::Path(channel_id): axum::extract::Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Some(channel) = storage::get_channel(state.db_path(), channel_id).await? else {
+        return Err(ApiError::NotFound("channel not found".to_string()));
+    };
+    Ok(Json(ChannelSecret {
+        auth_ref: channel.auth_ref,
+    }))
 }
 
 #[derive(Debug, Deserialize)]
@@ -59,36 +177,65 @@ pub(in crate::server) async fn create_channel(
     State(state): State<AppState>,
     Json(input): Json<storage::CreateChannel>,
 ) -> Result<impl IntoResponse, ApiError> {
-    if input.name.trim().is_empty() {
-        return Err(ApiError::BadRequest("name 不能为空".to_string()));
-    }
-    if input.base_url.trim().is_empty() {
-        return Err(ApiError::BadRequest("base_url 不能为空".to_string()));
-    }
-    if let Some(v) = input.real_multiplier
-        && !real_multiplier_is_valid(v)
-    {
-        return Err(ApiError::BadRequest(
-            "real_multiplier 必须是 >= 0 的有限数字，且最多 2 位小数".to_string(),
-        ));
-    }
+    require_non_empty("name", &input.name)?;
+    require_non_empty("base_url", &input.base_url)?;
+    validate_opt(input.real_multiplier.as_ref(), validate_real_multiplier)?;
+    validate_model_map(&input.model_map)?;
+    require_in_range("weight", input.weight, WEIGHT_RANGE)?;
+    validate_opt(
+        input.failover_policy.as_ref(),
+        storage::FailoverPolicy::validate,
+    )?;
+    validate_opt(input.timeouts.as_ref(), storage::TimeoutPolicy::validate)?;
+    validate_opt(input.proxy_url.as_deref(), validate_proxy_url)?;
+    validate_opt(input.spend_limit.as_ref(), storage::SpendLimit::validate)?;
+    validate_opt(
+        input.rate_limit.as_ref(),
+        storage::RateLimitPolicy::validate,
+    )?;
 
     let channel = storage::create_channel(state.db_path(), input).await?;
-    Ok((StatusCode::CREATED, Json(channel)))
+    Ok((StatusCode::CREATED, Json(masked(channel))))
 }
 
 pub(in crate::server) async fn update_channel(
     State(state): State<AppState>,
     axum::extract::Path(channel_id): axum::extract::Path<String>,
-    Json(input): Json<storage::UpdateChannel>,
+    Json(mut input): Json<storage::UpdateChannel>,
 ) -> Result<impl IntoResponse, ApiError> {
-    if let Some(v) = input.real_multiplier
-        && !real_multiplier_is_valid(v)
+    if let Some(auth_ref) = &input.auth_ref
+        && let Some(current) = storage::get_channel(state.db_path(), channel_id.clone()).await?
     {
-        return Err(ApiError::BadRequest(
-            "real_multiplier 必须是 >= 0 的有限数字，且最多 2 位小数".to_string(),
-        ));
+        let merged = unmask_auth_ref(auth_ref, &current.auth_ref);
+        input.auth_ref = (merged != current.auth_ref).then_some(merged);
+    }
+    validate_opt(input.real_multiplier.as_ref(), validate_real_multiplier)?;
+    if let Some(map) = &input.model_map {
+        validate_model_map(map)?;
+    }
+    if let Some(v) = input.weight {
+        require_in_range("weight", v, WEIGHT_RANGE)?;
     }
+    validate_opt(
+        input.failover_policy.as_ref().and_then(Option::as_ref),
+        storage::FailoverPolicy::validate,
+    )?;
+    validate_opt(
+        input.timeouts.as_ref().and_then(Option::as_ref),
+        storage::TimeoutPolicy::validate,
+    )?;
+    validate_opt(
+        input.proxy_url.as_ref().and_then(Option::as_deref),
+        validate_proxy_url,
+    )?;
+    validate_opt(
+        input.spend_limit.as_ref().and_then(Option::as_ref),
+        storage::SpendLimit::validate,
+    )?;
+    validate_opt(
+        input.rate_limit.as_ref().and_then(Option::as_ref),
+        storage::RateLimitPolicy::validate,
+    )?;
     let res = storage::update_channel(state.db_path(), channel_id, input).await;
     map_storage_unit_no_content(res, |msg| {
         msg.starts_with("channel not found")
@@ -123,6 +270,62 @@ pub(in crate::server) async fn delete_channel(
     })
 }
 
+#[derive(Serialize)]
+struct ChannelKeyHealth {
+    key_fp: String,
+    masked: String,
+    use_count: i64,
+    last_used_ms: Option<i64>,
+    auto_disabled_until_ms: i64,
+}
+
+// 设置了余额或限额的渠道当前的花费（充值货币）
+pub(in crate::server) async fn list_channel_spend(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let settings = storage::get_app_settings(state.db_path()).await?;
+    let spend = storage::channel_spend(
+        state.db_path(),
+        storage::local_day_start_ms(),
+        storage::local_month_start_ms(),
+        settings.usd_cny_rate,
+    )
+    .await?;
+    Ok(Json(spend))
+}
+
+pub(in crate::server) async fn list_channel_keys(
+    State(state): State<AppState>,
+    axum::extract::Path(channel_id): axum::extract::Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Some(channel) = storage::get_channel(state.db_path(), channel_id.clone()).await? else {
+        return Err(ApiError::NotFound("channel not found".to_string()));
+    };
+    let mut states: std::collections::HashMap<String, storage::ChannelKeyState> =
+        storage::list_channel_key_states(state.db_path(), channel_id)
+            .await?
+            .into_iter()
+            .map(|s| (s.key_fp.clone(), s))
+            .collect();
+
+    let keys: Vec<ChannelKeyHealth> = channel
+        .keys()
+        .into_iter()
+        .map(|key| {
+            let key_fp = storage::key_fingerprint(key);
+            let st = states.remove(&key_fp);
+            ChannelKeyHealth {
+                masked: storage::mask_key(key),
+                use_count: st.as_ref().map(|s| s.use_count).unwrap_or(0),
+                last_used_ms: st.as_ref().and_then(|s| s.last_used_ms),
+                auto_disabled_until_ms: st.map(|s| s.auto_disabled_until_ms).unwrap_or(0),
+                key_fp,
+            }
+        })
+        .collect();
+    Ok(Json(keys))
+}
+
 #[derive(Serialize)]
 struct ChannelTestResponse {
     reachable: bool,
@@ -164,13 +367,21 @@ pub(in crate::server) async fn test_channel(
 
     let mut url = build_models_url(base_url, channel.protocol);
     let mut headers = axum::http::HeaderMap::new();
-    proxy::apply_auth(&channel, channel.protocol, &mut url, &mut headers)
+    let token = channel.keys().first().copied().unwrap_or_default();
+    proxy::apply_auth(token, channel.protocol, &mut url, &mut headers)
         .map_err(|e| ApiError::BadGateway(e.to_string()))?;
 
+    // 与实际转发走同一出口和连接超时
+    let settings = storage::get_app_settings(state.db_path()).await?;
+    let timeouts = channel.timeouts.unwrap_or(settings.timeouts);
+    let egress = proxy::channel_egress(&channel, &settings);
+    let client = proxy::client_for(&state.http_client, timeouts.connect_ms, &egress)
+        .map_err(|e| ApiError::BadRequest(format!("代理配置无效：{e}")))?;
+
     let started = std::time::Instant::now();
     let resp = tokio::time::timeout(
         std::time::Duration::from_secs(8),
-        state.http_client.get(url).headers(headers).send(),
+        client.get(url).headers(headers).send(),
     )
     .await;
 
@@ -0,0 +1,158 @@
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use serde::Serialize;
+
+use crate::server::AppState;
+use crate::server::error::{ApiError, map_storage_unit_no_content};
+use crate::storage;
+
+// 除 excluding 以外是否还有可用的管理员令牌
+pub(in crate::server) async fn has_active_admin(
+    state: &AppState,
+    excluding: Option<&str>,
+) -> Result<bool, ApiError> {
+    let now_ms = storage::now_ms();
+    let tokens = storage::list_client_tokens(state.db_path()).await?;
+    Ok(tokens.iter().any(|t| {
+        t.role == storage::ClientTokenRole::Admin
+            && t.is_active(now_ms)
+            && Some(t.id.as_str()) != excluding
+    }))
+}
+
+// 鉴权开启时不允许停用、降级或删除最后一个管理员令牌
+async fn ensure_admin_remains(state: &AppState, token_id: &str) -> Result<(), ApiError> {
+    let settings = storage::get_app_settings(state.db_path()).await?;
+    if settings.client_auth_enabled && !has_active_admin(state, Some(token_id)).await? {
+        return Err(ApiError::BadRequest(
+            "客户端鉴权已开启，至少需要保留一个启用中的管理员令牌".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn validate_route_ids(state: &AppState, route_ids: &[String]) -> Result<(), ApiError> {
+    for id in route_ids {
+        if storage::get_route(state.db_path(), id.clone())
+            .await?
+            .is_none()
+        {
+            return Err(ApiError::BadRequest(format!("route 不存在：{id}")));
+        }
+    }
+    Ok(())
+}
+
+fn validate_quota(
+    requests_per_minute: Option<i64>,
+    tokens_per_day: Option<i64>,
+    usd_per_month: Option<f64>,
+) -> Result<(), ApiError> {
+    if requests_per_minute.is_some_and(|v| v < 1) {
+        return Err(ApiError::BadRequest(
+            "requests_per_minute 必须 >= 1".to_string(),
+        ));
+    }
+    if tokens_per_day.is_some_and(|v| v < 1) {
+        return Err(ApiError::BadRequest("tokens_per_day 必须 >= 1".to_string()));
+    }
+    if usd_per_month.is_some_and(|v| !v.is_finite() || v <= 0.0) {
+        return Err(ApiError::BadRequest("usd_per_month 必须大于 0".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ClientTokenView {
+    #[serde(flatten)]
+    token: storage::ClientToken,
+    usage: storage::ClientTokenUsage,
+}
+
+pub(in crate::server) async fn list_client_tokens(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let tokens = storage::list_client_tokens(state.db_path()).await?;
+    let mut usage = storage::client_token_usage(
+        state.db_path(),
+        None,
+        storage::local_day_start_ms(),
+        storage::local_month_start_ms(),
+    )
+    .await?;
+    let items: Vec<ClientTokenView> = tokens
+        .into_iter()
+        .map(|token| ClientTokenView {
+            usage: usage.remove(&token.id).unwrap_or_default(),
+            token,
+        })
+        .collect();
+    Ok(Json(items))
+}
+
+pub(in crate::server) async fn create_client_token(
+    State(state): State<AppState>,
+    Json(input): Json<storage::CreateClientToken>,
+) -> Result<impl IntoResponse, ApiError> {
+    if input.name.trim().is_empty() {
+        return Err(ApiError::BadRequest("name 不能为空".to_string()));
+    }
+    if input.expires_at_ms.is_some_and(|t| t <= storage::now_ms()) {
+        return Err(ApiError::BadRequest(
+            "expires_at_ms 必须晚于当前时间".to_string(),
+        ));
+    }
+    validate_route_ids(&state, &input.route_ids).await?;
+    validate_quota(
+        input.requests_per_minute,
+        input.tokens_per_day,
+        input.usd_per_month,
+    )?;
+    let created = storage::create_client_token(state.db_path(), input).await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+pub(in crate::server) async fn update_client_token(
+    State(state): State<AppState>,
+    axum::extract::Path(token_id): axum::extract::Path<String>,
+    Json(input): Json<storage::UpdateClientToken>,
+) -> Result<impl IntoResponse, ApiError> {
+    if input.name.as_deref().is_some_and(|n| n.trim().is_empty()) {
+        return Err(ApiError::BadRequest("name 不能为空".to_string()));
+    }
+    if let Some(route_ids) = &input.route_ids {
+        validate_route_ids(&state, route_ids).await?;
+    }
+    validate_quota(
+        input.requests_per_minute.flatten(),
+        input.tokens_per_day.flatten(),
+        input.usd_per_month.flatten(),
+    )?;
+    let demoted = input.enabled == Some(false)
+        || input
+            .role
+            .is_some_and(|r| r != storage::ClientTokenRole::Admin)
+        || matches!(input.expires_at_ms, Some(Some(t)) if t <= storage::now_ms());
+    if demoted {
+        ensure_admin_remains(&state, &token_id).await?;
+    }
+    let res = storage::update_client_token(state.db_path(), token_id, input).await;
+    map_storage_unit_no_content(res, |msg| {
+        msg.starts_with("client token not found")
+            .then(|| ApiError::NotFound("client token not found".to_string()))
+    })
+}
+
+pub(in crate::server) async fn delete_client_token(
+    State(state): State<AppState>,
+    axum::extract::Path(token_id): axum::extract::Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    ensure_admin_remains(&state, &token_id).await?;
+    let res = storage::delete_client_token(state.db_path(), token_id).await;
+    map_storage_unit_no_content(res, |msg| {
+        msg.starts_with("client token not found")
+            .then(|| ApiError::NotFound("client token not found".to_string()))
+    })
+}
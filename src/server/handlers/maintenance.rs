@@ -241,6 +241,8 @@ struct DbSizeResponse {
     wal_bytes: u64,
     shm_bytes: u64,
     total_bytes: u64,
+    #[serde(flatten)]
+    tables: storage::DbTableSizes,
 }
 
 pub(in crate::server) async fn db_size(
@@ -258,6 +260,7 @@ pub(in crate::server) async fn db_size(
     let wal_bytes = file_len(&wal_path);
     let shm_bytes = file_len(&shm_path);
     let total_bytes = db_bytes.saturating_add(wal_bytes).saturating_add(shm_bytes);
+    let tables = storage::db_table_sizes(state.db_path()).await?;
 
     Ok(Json(DbSizeResponse {
         path: db_path.display().to_string(),
@@ -265,5 +268,37 @@ pub(in crate::server) async fn db_size(
         wal_bytes,
         shm_bytes,
         total_bytes,
+        tables,
     }))
 }
+
+pub(in crate::server) async fn db_compact(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let res = storage::compact_database(state.db_path()).await?;
+    tracing::info!(
+        before_bytes = res.before_bytes,
+        after_bytes = res.after_bytes,
+        "database compacted"
+    );
+    Ok(Json(res))
+}
+
+pub(in crate::server) async fn cache_stats(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(storage::response_cache_stats(state.db_path()).await?))
+}
+
+#[derive(Debug, Serialize)]
+struct CacheClearResponse {
+    deleted: i64,
+}
+
+pub(in crate::server) async fn cache_clear(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let deleted = storage::clear_response_cache(state.db_path()).await?;
+    tracing::info!(deleted, "response cache cleared");
+    Ok(Json(CacheClearResponse { deleted }))
+}
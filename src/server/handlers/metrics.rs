@@ -0,0 +1,17 @@
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+
+use crate::server::AppState;
+use crate::server::error::ApiError;
+use crate::{metrics, storage};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub(in crate::server) async fn metrics(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let channels = storage::list_channels(state.db_path()).await?;
+    let body = metrics::render(&channels, storage::now_ms());
+    Ok(([(header::CONTENT_TYPE, CONTENT_TYPE)], body))
+}
@@ -1,6 +1,8 @@
 pub(super) mod channel;
+pub(super) mod client_token;
 pub(super) mod health;
 pub(super) mod maintenance;
+pub(super) mod metrics;
 pub(super) mod pricing;
 pub(super) mod proxy;
 pub(super) mod route;
@@ -10,18 +12,26 @@ pub(super) mod update;
 pub(super) mod usage;
 
 pub(super) use channel::{
-    create_channel, delete_channel, disable_channel, enable_channel, list_channels,
-    reorder_channels, test_channel, update_channel,
+    create_channel, delete_channel, disable_channel, enable_channel, list_channel_keys,
+    list_channel_spend, list_channels, reorder_channels, reveal_channel_secret, test_channel,
+    update_channel,
+};
+pub(super) use client_token::{
+    create_client_token, delete_client_token, list_client_tokens, update_client_token,
 };
 pub(super) use health::health;
-pub(super) use maintenance::{db_size, frontend_log_ingest, logs_clear, logs_size, records_clear};
+pub(super) use maintenance::{
+    cache_clear, cache_stats, db_compact, db_size, frontend_log_ingest, logs_clear, logs_size,
+    records_clear,
+};
+pub(super) use metrics::metrics;
 pub(super) use pricing::{pricing_models, pricing_status, pricing_sync};
 pub(super) use proxy::{proxy_anthropic, proxy_gemini, proxy_openai};
 pub(super) use route::{
     create_route, delete_route, list_route_channels, list_routes, reorder_route_channels,
-    update_route,
+    resolve_route, update_route,
 };
 pub(super) use settings::{get_settings, update_settings};
-pub(super) use stats::{stats_channels, stats_summary, stats_trend};
+pub(super) use stats::{stats_breakdown, stats_channels, stats_summary, stats_trend};
 pub(super) use update::{update_check, update_download, update_status};
-pub(super) use usage::usage_list;
+pub(super) use usage::{usage_capture, usage_list, usage_replay};
@@ -1,53 +1,64 @@
 use axum::body::Body;
 use axum::extract::State;
-use axum::http::Request;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
 
-use crate::proxy;
+use crate::proxy::{self, ProxyError};
 use crate::server::AppState;
-use crate::server::error::{ApiError, map_proxy_error};
-use crate::storage;
+use crate::server::error::map_proxy_error;
+use crate::storage::{self, Protocol};
 
-pub(in crate::server) async fn proxy_openai(
-    State(state): State<AppState>,
+// 鉴权、配额和路由不匹配的错误按入站协议的错误格式返回，便于客户端 SDK 给出可读的提示
+fn proxy_error_response(protocol: Protocol, e: ProxyError) -> Response {
+    match e {
+        ProxyError::Unauthorized(msg) => {
+            proxy::error_response(protocol, StatusCode::UNAUTHORIZED, &msg)
+        }
+        ProxyError::Forbidden(msg) => proxy::error_response(protocol, StatusCode::FORBIDDEN, &msg),
+        ProxyError::QuotaExceeded(msg) => {
+            proxy::error_response(protocol, StatusCode::TOO_MANY_REQUESTS, &msg)
+        }
+        e @ ProxyError::NoMatchingRoute(..) => {
+            proxy::error_response(protocol, StatusCode::NOT_FOUND, &e.to_string())
+        }
+        e => map_proxy_error(e).into_response(),
+    }
+}
+
+async fn forward(
+    state: AppState,
+    protocol: Protocol,
+    protocol_root: &'static str,
     req: Request<Body>,
-) -> Result<axum::response::Response, ApiError> {
+) -> Response {
     proxy::forward(
         &state.http_client,
         state.db_path(),
-        storage::Protocol::Openai,
-        "/v1",
+        protocol,
+        protocol_root,
         req,
     )
     .await
-    .map_err(map_proxy_error)
+    .unwrap_or_else(|e| proxy_error_response(protocol, e))
+}
+
+pub(in crate::server) async fn proxy_openai(
+    State(state): State<AppState>,
+    req: Request<Body>,
+) -> Response {
+    forward(state, storage::Protocol::Openai, "/v1", req).await
 }
 
 pub(in crate::server) async fn proxy_anthropic(
     State(state): State<AppState>,
     req: Request<Body>,
-) -> Result<axum::response::Response, ApiError> {
-    proxy::forward(
-        &state.http_client,
-        state.db_path(),
-        storage::Protocol::Anthropic,
-        "/v1",
-        req,
-    )
-    .await
-    .map_err(map_proxy_error)
+) -> Response {
+    forward(state, storage::Protocol::Anthropic, "/v1", req).await
 }
 
 pub(in crate::server) async fn proxy_gemini(
     State(state): State<AppState>,
     req: Request<Body>,
-) -> Result<axum::response::Response, ApiError> {
-    proxy::forward(
-        &state.http_client,
-        state.db_path(),
-        storage::Protocol::Gemini,
-        "/v1beta",
-        req,
-    )
-    .await
-    .map_err(map_proxy_error)
+) -> Response {
+    forward(state, storage::Protocol::Gemini, "/v1beta", req).await
 }
@@ -1,13 +1,20 @@
 use axum::Json;
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use crate::proxy;
 use crate::server::AppState;
-use crate::server::error::{ApiError, map_storage_unit_no_content};
+use crate::server::error::{ApiError, map_proxy_error, map_storage_unit_no_content};
 use crate::storage;
 
+fn validate_match_model(match_model: Option<&str>) -> Result<(), ApiError> {
+    storage::ModelPattern::parse(match_model)
+        .map(|_| ())
+        .map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
 pub(in crate::server) async fn list_routes(
     State(state): State<AppState>,
 ) -> Result<impl IntoResponse, ApiError> {
@@ -22,6 +29,7 @@ pub(in crate::server) async fn create_route(
     if input.name.trim().is_empty() {
         return Err(ApiError::BadRequest("name 不能为空".to_string()));
     }
+    validate_match_model(input.match_model.as_deref())?;
     let route = storage::create_route(state.db_path(), input).await?;
     Ok((StatusCode::CREATED, Json(route)))
 }
@@ -31,6 +39,9 @@ pub(in crate::server) async fn update_route(
     axum::extract::Path(route_id): axum::extract::Path<String>,
     Json(input): Json<storage::UpdateRoute>,
 ) -> Result<impl IntoResponse, ApiError> {
+    if let Some(match_model) = &input.match_model {
+        validate_match_model(match_model.as_deref())?;
+    }
     let res = storage::update_route(state.db_path(), route_id, input).await;
     map_storage_unit_no_content(res, |msg| {
         msg.starts_with("route not found")
@@ -91,3 +102,55 @@ pub(in crate::server) async fn reorder_route_channels(
         }
     })
 }
+
+#[derive(Debug, Deserialize)]
+pub(in crate::server) struct ResolveRouteQuery {
+    protocol: storage::Protocol,
+    model: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ResolvedChannelItem {
+    id: String,
+    name: String,
+    protocol: storage::Protocol,
+    priority: i64,
+}
+
+#[derive(Serialize)]
+struct ResolveRouteResponse {
+    protocol: storage::Protocol,
+    model: Option<String>,
+    route: Option<storage::Route>,
+    match_kind: Option<storage::ModelMatchKind>,
+    channels: Vec<ResolvedChannelItem>,
+}
+
+pub(in crate::server) async fn resolve_route(
+    State(state): State<AppState>,
+    Query(q): Query<ResolveRouteQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let model = q
+        .model
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty());
+    let resolved = proxy::resolve_route(state.db_path(), q.protocol, model.as_deref())
+        .await
+        .map_err(map_proxy_error)?;
+    Ok(Json(ResolveRouteResponse {
+        protocol: q.protocol,
+        model,
+        route: resolved.route,
+        match_kind: resolved.match_kind,
+        channels: resolved
+            .channels
+            .into_iter()
+            .map(|c| ResolvedChannelItem {
+                id: c.id,
+                name: c.name,
+                protocol: c.protocol,
+                priority: c.priority,
+            })
+            .collect(),
+    }))
+}
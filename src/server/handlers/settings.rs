@@ -5,7 +5,7 @@ use serde::Deserialize;
 
 use crate::server::AppState;
 use crate::server::error::ApiError;
-use crate::{autostart, logging, storage};
+use crate::{autostart, logging, proxy, storage};
 
 pub(in crate::server) async fn get_settings(
     State(state): State<AppState>,
@@ -28,6 +28,26 @@ pub(in crate::server) struct UpdateSettingsInput {
     auto_disable_disable_minutes: Option<i64>,
     log_level: Option<logging::LogLevel>,
     log_retention_days: Option<i64>,
+    usage_retention_days: Option<i64>,
+    error_detail_retention_days: Option<i64>,
+    channel_failure_retention_days: Option<i64>,
+    load_balance_mode: Option<storage::LoadBalanceMode>,
+    hedge_enabled: Option<bool>,
+    hedge_delay_ms: Option<i64>,
+    failover_policy: Option<storage::FailoverPolicy>,
+    stream_failover_enabled: Option<bool>,
+    stream_first_content_timeout_ms: Option<i64>,
+    timeouts: Option<storage::TimeoutPolicy>,
+    proxy_url: Option<String>,
+    client_auth_enabled: Option<bool>,
+    usd_cny_rate: Option<f64>,
+    spend_warning_percent: Option<i64>,
+    response_cache_enabled: Option<bool>,
+    response_cache_ttl_secs: Option<i64>,
+    response_cache_max_mb: Option<i64>,
+    capture_enabled: Option<bool>,
+    otlp_enabled: Option<bool>,
+    otlp_endpoint: Option<String>,
 }
 
 pub(in crate::server) async fn update_settings(
@@ -71,6 +91,50 @@ pub(in crate::server) async fn update_settings(
         ),
         ("log_level", input.log_level.is_some()),
         ("log_retention_days", input.log_retention_days.is_some()),
+        ("usage_retention_days", input.usage_retention_days.is_some()),
+        (
+            "error_detail_retention_days",
+            input.error_detail_retention_days.is_some(),
+        ),
+        (
+            "channel_failure_retention_days",
+            input.channel_failure_retention_days.is_some(),
+        ),
+        ("load_balance_mode", input.load_balance_mode.is_some()),
+        ("hedge_enabled", input.hedge_enabled.is_some()),
+        ("hedge_delay_ms", input.hedge_delay_ms.is_some()),
+        ("failover_policy", input.failover_policy.is_some()),
+        (
+            "stream_failover_enabled",
+            input.stream_failover_enabled.is_some(),
+        ),
+        (
+            "stream_first_content_timeout_ms",
+            input.stream_first_content_timeout_ms.is_some(),
+        ),
+        ("timeouts", input.timeouts.is_some()),
+        ("proxy_url", input.proxy_url.is_some()),
+        ("client_auth_enabled", input.client_auth_enabled.is_some()),
+        ("usd_cny_rate", input.usd_cny_rate.is_some()),
+        (
+            "spend_warning_percent",
+            input.spend_warning_percent.is_some(),
+        ),
+        (
+            "response_cache_enabled",
+            input.response_cache_enabled.is_some(),
+        ),
+        (
+            "response_cache_ttl_secs",
+            input.response_cache_ttl_secs.is_some(),
+        ),
+        (
+            "response_cache_max_mb",
+            input.response_cache_max_mb.is_some(),
+        ),
+        ("capture_enabled", input.capture_enabled.is_some()),
+        ("otlp_enabled", input.otlp_enabled.is_some()),
+        ("otlp_endpoint", input.otlp_endpoint.is_some()),
     ]
     .into_iter()
     .filter_map(|(name, is_changed)| is_changed.then_some(name))
@@ -111,6 +175,96 @@ pub(in crate::server) async fn update_settings(
             "log_retention_days 必须在 1..=3650 之间".to_string(),
         ));
     }
+    if let Some(v) = input.usage_retention_days
+        && v != 0
+        && !(storage::USAGE_RETENTION_MIN_DAYS..=3650).contains(&v)
+    {
+        return Err(ApiError::BadRequest(format!(
+            "usage_retention_days 必须为 0（不清理）或在 {}..=3650 之间",
+            storage::USAGE_RETENTION_MIN_DAYS
+        )));
+    }
+    for (name, v) in [
+        (
+            "error_detail_retention_days",
+            input.error_detail_retention_days,
+        ),
+        (
+            "channel_failure_retention_days",
+            input.channel_failure_retention_days,
+        ),
+    ] {
+        if let Some(v) = v
+            && !(0..=3650).contains(&v)
+        {
+            return Err(ApiError::BadRequest(format!(
+                "{name} 必须在 0..=3650 之间（0 表示不清理）"
+            )));
+        }
+    }
+
+    if let Some(v) = input.hedge_delay_ms
+        && !(0..=600_000).contains(&v)
+    {
+        return Err(ApiError::BadRequest(
+            "hedge_delay_ms 必须在 0..=600000 之间".to_string(),
+        ));
+    }
+    if let Some(v) = input.stream_first_content_timeout_ms
+        && !(1_000..=600_000).contains(&v)
+    {
+        return Err(ApiError::BadRequest(
+            "stream_first_content_timeout_ms 必须在 1000..=600000 之间".to_string(),
+        ));
+    }
+    if let Some(v) = input.usd_cny_rate
+        && !(v.is_finite() && v > 0.0)
+    {
+        return Err(ApiError::BadRequest("usd_cny_rate 必须大于 0".to_string()));
+    }
+    if let Some(v) = input.spend_warning_percent
+        && !(1..=100).contains(&v)
+    {
+        return Err(ApiError::BadRequest(
+            "spend_warning_percent 必须在 1..=100 之间".to_string(),
+        ));
+    }
+    if let Some(v) = input.response_cache_ttl_secs
+        && !(1..=2_592_000).contains(&v)
+    {
+        return Err(ApiError::BadRequest(
+            "response_cache_ttl_secs 必须在 1..=2592000 之间".to_string(),
+        ));
+    }
+    if let Some(v) = input.response_cache_max_mb
+        && !(1..=10_240).contains(&v)
+    {
+        return Err(ApiError::BadRequest(
+            "response_cache_max_mb 必须在 1..=10240 之间".to_string(),
+        ));
+    }
+    if let Some(v) = &input.proxy_url {
+        proxy::validate_proxy_url(v).map_err(ApiError::BadRequest)?;
+    }
+    if let Some(v) = &input.otlp_endpoint {
+        proxy::validate_otlp_endpoint(v).map_err(ApiError::BadRequest)?;
+    }
+    if let Some(v) = &input.timeouts {
+        v.validate()
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    }
+    if let Some(v) = &input.failover_policy {
+        v.validate()
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    }
+    // 没有可用的管理员令牌时开启鉴权会把自己锁在外面
+    if input.client_auth_enabled == Some(true)
+        && !super::client_token::has_active_admin(&state, None).await?
+    {
+        return Err(ApiError::BadRequest(
+            "开启客户端鉴权前请先创建一个启用中的管理员令牌".to_string(),
+        ));
+    }
 
     let auto_start_enabled = input.auto_start_enabled;
     if let Some(enabled) = auto_start_enabled {
@@ -143,6 +297,26 @@ pub(in crate::server) async fn update_settings(
             auto_disable_disable_minutes: input.auto_disable_disable_minutes,
             log_level: input.log_level,
             log_retention_days: input.log_retention_days,
+            usage_retention_days: input.usage_retention_days,
+            error_detail_retention_days: input.error_detail_retention_days,
+            channel_failure_retention_days: input.channel_failure_retention_days,
+            load_balance_mode: input.load_balance_mode,
+            hedge_enabled: input.hedge_enabled,
+            hedge_delay_ms: input.hedge_delay_ms,
+            failover_policy: input.failover_policy,
+            stream_failover_enabled: input.stream_failover_enabled,
+            stream_first_content_timeout_ms: input.stream_first_content_timeout_ms,
+            timeouts: input.timeouts,
+            proxy_url: input.proxy_url,
+            client_auth_enabled: input.client_auth_enabled,
+            usd_cny_rate: input.usd_cny_rate,
+            spend_warning_percent: input.spend_warning_percent,
+            response_cache_enabled: input.response_cache_enabled,
+            response_cache_ttl_secs: input.response_cache_ttl_secs,
+            response_cache_max_mb: input.response_cache_max_mb,
+            capture_enabled: input.capture_enabled,
+            otlp_enabled: input.otlp_enabled,
+            otlp_endpoint: input.otlp_endpoint,
         },
     )
     .await?;
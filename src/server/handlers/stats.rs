@@ -5,12 +5,17 @@ use serde::{Deserialize, Serialize};
 
 use crate::server::AppState;
 use crate::server::error::ApiError;
-use crate::storage;
+use crate::storage::{self, StatsBucket, StatsGroupBy, StatsWindow};
+
+// 趋势图最多返回的桶数，防止按小时查询一整年
+const MAX_TREND_BUCKETS: i64 = 2000;
 
 #[derive(Debug, Clone, Copy)]
 enum StatsRange {
     Today,
     Month,
+    // 由 start_ms / end_ms 指定
+    Custom,
 }
 
 impl StatsRange {
@@ -18,6 +23,7 @@ impl StatsRange {
         match self {
             StatsRange::Today => "today",
             StatsRange::Month => "month",
+            StatsRange::Custom => "custom",
         }
     }
 }
@@ -33,34 +39,74 @@ impl std::str::FromStr for StatsRange {
     }
 }
 
-fn start_ms_for_range(range: StatsRange) -> i64 {
-    let now = time::OffsetDateTime::now_utc();
-    let offset = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
-    let local = now.to_offset(offset);
-
-    let start_local = match range {
-        StatsRange::Today => local.replace_time(time::Time::MIDNIGHT),
-        StatsRange::Month => {
-            let d = local.date();
-            let first = time::Date::from_calendar_date(d.year(), d.month(), 1).unwrap_or(d);
-            local.replace_date(first).replace_time(time::Time::MIDNIGHT)
-        }
-    };
-
-    (start_local
-        .to_offset(time::UtcOffset::UTC)
-        .unix_timestamp_nanos()
-        / 1_000_000) as i64
+#[derive(Debug, Deserialize)]
+pub(in crate::server) struct StatsQuery {
+    range: Option<String>,
+    start_ms: Option<i64>,
+    end_ms: Option<i64>,
+    bucket: Option<String>,
+    group_by: Option<String>,
+    // 相对 UTC 向东的分钟数（UTC+8 为 480），缺省使用服务端本地时区
+    tz_offset_minutes: Option<i64>,
 }
 
-fn current_local_offset_ms() -> i64 {
-    let offset = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
-    (offset.whole_seconds() as i64) * 1000
+struct ResolvedQuery {
+    range: StatsRange,
+    window: StatsWindow,
+    offset_ms: i64,
 }
 
-#[derive(Debug, Deserialize)]
-pub(in crate::server) struct StatsQuery {
-    range: Option<String>,
+impl StatsQuery {
+    fn resolve(&self) -> Result<ResolvedQuery, ApiError> {
+        let offset_ms = match self.tz_offset_minutes {
+            Some(m) if !(-840..=840).contains(&m) => {
+                return Err(ApiError::BadRequest(
+                    "tz_offset_minutes 必须在 -840..=840 之间".to_string(),
+                ));
+            }
+            Some(m) => m * 60_000,
+            None => storage::current_local_offset_ms(),
+        };
+
+        let range = match (self.range.as_deref(), self.start_ms) {
+            (Some(_), Some(_)) => {
+                return Err(ApiError::BadRequest(
+                    "range 与 start_ms 不能同时指定".to_string(),
+                ));
+            }
+            (_, Some(_)) => StatsRange::Custom,
+            (range, None) => range
+                .unwrap_or("today")
+                .parse::<StatsRange>()
+                .map_err(ApiError::BadRequest)?,
+        };
+        let now_ms = storage::now_ms();
+        let start_ms = match range {
+            StatsRange::Today => storage::day_start_ms(now_ms, offset_ms),
+            StatsRange::Month => storage::month_start_ms(now_ms, offset_ms),
+            StatsRange::Custom => self.start_ms.unwrap_or_default(),
+        };
+        if let Some(end_ms) = self.end_ms
+            && end_ms <= start_ms
+        {
+            return Err(ApiError::BadRequest("end_ms 必须大于 start_ms".to_string()));
+        }
+        Ok(ResolvedQuery {
+            range,
+            window: StatsWindow {
+                start_ms,
+                end_ms: self.end_ms,
+            },
+            offset_ms,
+        })
+    }
+
+    fn group_by(&self, default: StatsGroupBy) -> Result<StatsGroupBy, ApiError> {
+        match self.group_by.as_deref() {
+            Some(v) => v.parse().map_err(ApiError::BadRequest),
+            None => Ok(default),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -74,16 +120,10 @@ pub(in crate::server) async fn stats_summary(
     State(state): State<AppState>,
     Query(q): Query<StatsQuery>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let range = q
-        .range
-        .as_deref()
-        .unwrap_or("today")
-        .parse::<StatsRange>()
-        .map_err(ApiError::BadRequest)?;
-    let start_ms = start_ms_for_range(range);
-    let summary = storage::stats_summary(state.db_path(), start_ms).await?;
+    let resolved = q.resolve()?;
+    let summary = storage::stats_summary(state.db_path(), resolved.window).await?;
     Ok(Json(StatsSummaryResponse {
-        range: range.as_str().to_string(),
+        range: resolved.range.as_str().to_string(),
         summary,
     }))
 }
@@ -92,6 +132,7 @@ pub(in crate::server) async fn stats_summary(
 struct StatsChannelsResponse {
     range: String,
     start_ms: i64,
+    end_ms: Option<i64>,
     items: Vec<storage::ChannelStats>,
 }
 
@@ -99,17 +140,37 @@ pub(in crate::server) async fn stats_channels(
     State(state): State<AppState>,
     Query(q): Query<StatsQuery>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let range = q
-        .range
-        .as_deref()
-        .unwrap_or("today")
-        .parse::<StatsRange>()
-        .map_err(ApiError::BadRequest)?;
-    let start_ms = start_ms_for_range(range);
-    let items = storage::stats_channels(state.db_path(), start_ms).await?;
+    let resolved = q.resolve()?;
+    let items = storage::stats_channels(state.db_path(), resolved.window).await?;
     Ok(Json(StatsChannelsResponse {
-        range: range.as_str().to_string(),
-        start_ms,
+        range: resolved.range.as_str().to_string(),
+        start_ms: resolved.window.start_ms,
+        end_ms: resolved.window.end_ms,
+        items,
+    }))
+}
+
+#[derive(Serialize)]
+struct StatsBreakdownResponse {
+    range: String,
+    start_ms: i64,
+    end_ms: Option<i64>,
+    group_by: &'static str,
+    items: Vec<storage::GroupStats>,
+}
+
+pub(in crate::server) async fn stats_breakdown(
+    State(state): State<AppState>,
+    Query(q): Query<StatsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let resolved = q.resolve()?;
+    let group_by = q.group_by(StatsGroupBy::Model)?;
+    let items = storage::stats_breakdown(state.db_path(), resolved.window, group_by).await?;
+    Ok(Json(StatsBreakdownResponse {
+        range: resolved.range.as_str().to_string(),
+        start_ms: resolved.window.start_ms,
+        end_ms: resolved.window.end_ms,
+        group_by: group_by.as_str(),
         items,
     }))
 }
@@ -118,7 +179,9 @@ pub(in crate::server) async fn stats_channels(
 struct StatsTrendResponse {
     range: String,
     start_ms: i64,
-    unit: String,
+    end_ms: Option<i64>,
+    unit: &'static str,
+    group_by: &'static str,
     items: Vec<storage::TrendPoint>,
 }
 
@@ -126,29 +189,39 @@ pub(in crate::server) async fn stats_trend(
     State(state): State<AppState>,
     Query(q): Query<StatsQuery>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let range = q
-        .range
-        .as_deref()
-        .unwrap_or("today")
-        .parse::<StatsRange>()
-        .map_err(ApiError::BadRequest)?;
-
-    match range {
-        StatsRange::Month => {
-            let start_ms = start_ms_for_range(range);
-            let items = storage::stats_trend_by_day_channel(
-                state.db_path(),
-                start_ms,
-                current_local_offset_ms(),
-            )
-            .await?;
-            Ok(Json(StatsTrendResponse {
-                range: range.as_str().to_string(),
-                start_ms,
-                unit: "day".to_string(),
-                items,
-            }))
-        }
-        StatsRange::Today => Err(ApiError::BadRequest("trend 仅支持 range=month".to_string())),
+    let resolved = q.resolve()?;
+    let bucket = match q.bucket.as_deref() {
+        Some(v) => v.parse::<StatsBucket>().map_err(ApiError::BadRequest)?,
+        None => StatsBucket::Day,
+    };
+    let group_by = q.group_by(StatsGroupBy::Channel)?;
+
+    let span_ms = resolved
+        .window
+        .end_ms
+        .unwrap_or_else(storage::now_ms)
+        .saturating_sub(resolved.window.start_ms);
+    if span_ms / bucket.approx_ms() > MAX_TREND_BUCKETS {
+        return Err(ApiError::BadRequest(format!(
+            "时间范围过大，bucket={} 时最多 {MAX_TREND_BUCKETS} 个桶",
+            bucket.as_str()
+        )));
     }
+
+    let items = storage::stats_trend(
+        state.db_path(),
+        resolved.window,
+        bucket,
+        group_by,
+        resolved.offset_ms,
+    )
+    .await?;
+    Ok(Json(StatsTrendResponse {
+        range: resolved.range.as_str().to_string(),
+        start_ms: resolved.window.start_ms,
+        end_ms: resolved.window.end_ms,
+        unit: bucket.as_str(),
+        group_by: group_by.as_str(),
+        items,
+    }))
 }
@@ -1,10 +1,11 @@
 use axum::Json;
-use axum::extract::{Query, State};
-use axum::response::IntoResponse;
-use serde::Deserialize;
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
 
+use crate::proxy;
 use crate::server::AppState;
-use crate::server::error::ApiError;
+use crate::server::error::{ApiError, map_proxy_error};
 use crate::storage;
 
 #[derive(Debug, Deserialize)]
@@ -82,3 +83,88 @@ pub(in crate::server) async fn usage_list(
 
     Ok(Json(res))
 }
+
+#[derive(Debug, Serialize)]
+struct CaptureView {
+    request_id: String,
+    ts_ms: i64,
+    protocol: storage::Protocol,
+    method: String,
+    path: String,
+    request_headers: Vec<(String, String)>,
+    request_body: String,
+    request_truncated: bool,
+    channel_id: Option<String>,
+    response_status: Option<i64>,
+    response_sse: bool,
+    response_body: Option<String>,
+    response_truncated: bool,
+    completed_at_ms: Option<i64>,
+}
+
+impl From<storage::RequestCapture> for CaptureView {
+    fn from(c: storage::RequestCapture) -> Self {
+        Self {
+            request_id: c.request_id,
+            ts_ms: c.ts_ms,
+            protocol: c.protocol,
+            method: c.method,
+            path: c.path,
+            request_headers: c.request_headers,
+            request_body: String::from_utf8_lossy(&c.request_body).to_string(),
+            request_truncated: c.request_truncated,
+            channel_id: c.channel_id,
+            response_status: c.response_status,
+            response_sse: c.response_sse,
+            response_body: c
+                .response_body
+                .map(|b| String::from_utf8_lossy(&b).to_string()),
+            response_truncated: c.response_truncated,
+            completed_at_ms: c.completed_at_ms,
+        }
+    }
+}
+
+async fn load_capture(
+    state: &AppState,
+    request_id: String,
+) -> Result<storage::RequestCapture, ApiError> {
+    storage::get_request_capture(state.db_path(), request_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("未找到该请求的抓包记录".to_string()))
+}
+
+pub(in crate::server) async fn usage_capture(
+    State(state): State<AppState>,
+    Path(request_id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let capture = load_capture(&state, request_id).await?;
+    Ok(Json(CaptureView::from(capture)))
+}
+
+#[derive(Debug, Deserialize)]
+pub(in crate::server) struct ReplayInput {
+    channel_id: String,
+}
+
+// 直接返回上游响应；新请求的 ID 在 x-cliswitch-request-id 响应头中
+pub(in crate::server) async fn usage_replay(
+    State(state): State<AppState>,
+    Path(request_id): Path<String>,
+    Json(input): Json<ReplayInput>,
+) -> Result<Response, ApiError> {
+    let capture = load_capture(&state, request_id).await?;
+    let Some(channel) = storage::get_channel(state.db_path(), input.channel_id).await? else {
+        return Err(ApiError::NotFound("channel not found".to_string()));
+    };
+    if !capture.protocol.accepts_channel(channel.protocol) {
+        return Err(ApiError::BadRequest(format!(
+            "{} 请求无法经由 {} 渠道重放",
+            capture.protocol.as_str(),
+            channel.protocol.as_str()
+        )));
+    }
+    proxy::replay(&state.http_client, state.db_path(), capture, channel)
+        .await
+        .map_err(map_proxy_error)
+}
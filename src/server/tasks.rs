@@ -166,3 +166,77 @@ pub(crate) async fn logs_retention_cleanup_loop(
         }
     }
 }
+
+async fn load_app_settings(db_path: &std::path::Path) -> storage::AppSettings {
+    match storage::get_app_settings(db_path.to_path_buf()).await {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!(err = %e, "load app settings failed");
+            storage::AppSettings::default()
+        }
+    }
+}
+
+fn retention_days(settings: &storage::AppSettings) -> (i64, i64, i64) {
+    (
+        settings.usage_retention_days,
+        settings.error_detail_retention_days,
+        settings.channel_failure_retention_days,
+    )
+}
+
+pub(crate) async fn records_retention_cleanup_loop(
+    db_path: PathBuf,
+    mut notify: watch::Receiver<u64>,
+) {
+    let interval = Duration::from_secs(3600);
+
+    loop {
+        let settings = load_app_settings(&db_path).await;
+
+        let now_ms = storage::now_ms();
+        let cutoff = |days: i64| (days > 0).then(|| now_ms - days.min(3650) * 86_400_000);
+        let retention = storage::RecordsRetention {
+            usage_events_before_ms: cutoff(settings.usage_retention_days),
+            error_detail_before_ms: cutoff(settings.error_detail_retention_days),
+            // 自动禁用按窗口内的失败次数判断，窗口内的记录始终保留
+            channel_failures_before_ms: cutoff(settings.channel_failure_retention_days)
+                .map(|c| c.min(now_ms - settings.auto_disable_window_minutes.max(0) * 60_000)),
+        };
+
+        match storage::prune_records(db_path.clone(), retention).await {
+            Ok(r) => {
+                if r.usage_events_deleted > 0
+                    || r.request_captures_deleted > 0
+                    || r.error_details_cleared > 0
+                    || r.channel_failures_deleted > 0
+                {
+                    tracing::info!(
+                        usage_events_deleted = r.usage_events_deleted,
+                        request_captures_deleted = r.request_captures_deleted,
+                        error_details_cleared = r.error_details_cleared,
+                        channel_failures_deleted = r.channel_failures_deleted,
+                        freed_bytes = r.freed_bytes,
+                        "records retention cleanup done"
+                    );
+                }
+            }
+            Err(e) => tracing::warn!(err = %e, "records retention cleanup failed"),
+        }
+
+        // 其他设置变更不触发清理，只有保留天数改变时才提前执行
+        let applied = retention_days(&settings);
+        let deadline = tokio::time::Instant::now() + interval;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => break,
+                changed = notify.changed() => {
+                    if changed.is_err() { return; }
+                }
+            }
+            if retention_days(&load_app_settings(&db_path).await) != applied {
+                break;
+            }
+        }
+    }
+}
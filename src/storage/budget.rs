@@ -0,0 +1,201 @@
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::channel::RechargeCurrency;
+use super::rollup;
+use super::stats::StatsWindow;
+use super::with_conn;
+
+// 金额均以渠道的充值货币计，留空表示不限制
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpendLimit {
+    // 预付余额，自 balance_set_at_ms 起的花费从中扣减（累计值保存在 channels.balance_spent_usd）
+    pub balance: Option<f64>,
+    pub balance_set_at_ms: Option<i64>,
+    pub daily_cap: Option<f64>,
+    pub monthly_cap: Option<f64>,
+}
+
+impl SpendLimit {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, v) in [
+            ("balance", self.balance),
+            ("daily_cap", self.daily_cap),
+            ("monthly_cap", self.monthly_cap),
+        ] {
+            if v.is_some_and(|v| !v.is_finite() || v < 0.0) {
+                anyhow::bail!("spend_limit.{name} 必须是 >= 0 的数字");
+            }
+        }
+        Ok(())
+    }
+
+    // 余额被修改时重新开始计算扣减，未修改则沿用原来的起点
+    pub(super) fn stamped(mut self, prev: Option<&SpendLimit>, now_ms: i64) -> Option<SpendLimit> {
+        self.balance_set_at_ms = match (self.balance, prev) {
+            (None, _) => None,
+            (Some(b), Some(p)) if p.balance == Some(b) && p.balance_set_at_ms.is_some() => {
+                p.balance_set_at_ms
+            }
+            (Some(_), _) => Some(now_ms),
+        };
+        let empty =
+            self.balance.is_none() && self.daily_cap.is_none() && self.monthly_cap.is_none();
+        (!empty).then_some(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendLimitKind {
+    Balance,
+    Daily,
+    Monthly,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelSpend {
+    pub channel_id: String,
+    pub currency: RechargeCurrency,
+    pub spent_today: f64,
+    pub spent_month: f64,
+    pub spent_since_balance: f64,
+    pub limit: SpendLimit,
+}
+
+impl ChannelSpend {
+    pub fn balance_remaining(&self) -> Option<f64> {
+        self.limit.balance.map(|b| b - self.spent_since_balance)
+    }
+
+    // (类型, 已花费, 限额)，按占用比例从高到低
+    pub fn usage(&self) -> Vec<(SpendLimitKind, f64, f64)> {
+        let mut out: Vec<(SpendLimitKind, f64, f64)> = [
+            (
+                SpendLimitKind::Balance,
+                self.spent_since_balance,
+                self.limit.balance,
+            ),
+            (
+                SpendLimitKind::Daily,
+                self.spent_today,
+                self.limit.daily_cap,
+            ),
+            (
+                SpendLimitKind::Monthly,
+                self.spent_month,
+                self.limit.monthly_cap,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(kind, spent, limit)| limit.map(|l| (kind, spent, l)))
+        .collect();
+        let ratio = |spent: f64, limit: f64| {
+            if limit > 0.0 {
+                spent / limit
+            } else {
+                f64::INFINITY
+            }
+        };
+        out.sort_by(|a, b| ratio(b.1, b.2).total_cmp(&ratio(a.1, a.2)));
+        out
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.usage().iter().any(|(_, spent, limit)| spent >= limit)
+    }
+}
+
+// 写入用量记录（或回填费用）的同一事务中调用：累加设置余额以来的美元费用
+pub(super) fn add_balance_spend(
+    conn: &Connection,
+    channel_id: &str,
+    ts_ms: i64,
+    cost_usd: f64,
+) -> rusqlite::Result<()> {
+    if !cost_usd.is_finite() || cost_usd <= 0.0 {
+        return Ok(());
+    }
+    conn.execute(
+        r#"
+        UPDATE channels
+        SET balance_spent_usd = balance_spent_usd + ?2
+        WHERE id = ?1
+          AND CAST(json_extract(spend_limit, '$.balance_set_at_ms') AS INTEGER) <= ?3
+        "#,
+        params![channel_id, cost_usd, ts_ms],
+    )?;
+    Ok(())
+}
+
+// 实际花费 = 估算美元费用 × real_multiplier，充值货币为 CNY 时再按汇率折算；
+// 今日和本月从用量汇总表读取，余额扣减读累计值
+pub async fn channel_spend(
+    db_path: PathBuf,
+    day_start_ms: i64,
+    month_start_ms: i64,
+    usd_cny_rate: f64,
+) -> anyhow::Result<Vec<ChannelSpend>> {
+    with_conn(db_path, move |conn| {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT id, recharge_currency, real_multiplier, spend_limit, balance_spent_usd
+            FROM channels
+            WHERE spend_limit IS NOT NULL
+            "#,
+        )?;
+        let channels = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<RechargeCurrency>>(1)?
+                        .unwrap_or(RechargeCurrency::Cny),
+                    row.get::<_, Option<f64>>(2)?.unwrap_or(1.0),
+                    row.get::<_, String>(3)?,
+                    row.get::<_, f64>(4)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if channels.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let spent_since = |start_ms: i64| -> rusqlite::Result<HashMap<String, f64>> {
+            let window = StatsWindow {
+                start_ms,
+                end_ms: None,
+            };
+            Ok(rollup::load(conn, window, None, 0, "u.channel_id", "''")?
+                .into_iter()
+                .map(|(_, channel_id, _, r)| (channel_id, r.cost_usd))
+                .collect())
+        };
+        let today = spent_since(day_start_ms)?;
+        let month = spent_since(month_start_ms)?;
+
+        let mut out = Vec::with_capacity(channels.len());
+        for (channel_id, currency, real_multiplier, limit, since_balance) in channels {
+            let Ok(limit) = serde_json::from_str::<SpendLimit>(&limit) else {
+                continue;
+            };
+            let factor = real_multiplier
+                * match currency {
+                    RechargeCurrency::Cny => usd_cny_rate,
+                    RechargeCurrency::Usd => 1.0,
+                };
+            out.push(ChannelSpend {
+                spent_today: today.get(&channel_id).copied().unwrap_or(0.0) * factor,
+                spent_month: month.get(&channel_id).copied().unwrap_or(0.0) * factor,
+                spent_since_balance: since_balance * factor,
+                channel_id,
+                currency,
+                limit,
+            });
+        }
+        Ok(out)
+    })
+    .await
+}
@@ -0,0 +1,152 @@
+use rusqlite::{OptionalExtension as _, params};
+use std::path::PathBuf;
+
+use super::{Protocol, with_conn};
+
+// 请求头按 (名称, 值) 保存，敏感值在写入前已替换为 REDACTED
+#[derive(Debug, Clone)]
+pub struct CreateRequestCapture {
+    pub request_id: String,
+    pub ts_ms: i64,
+    pub protocol: Protocol,
+    pub method: String,
+    pub path: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: Vec<u8>,
+    pub request_truncated: bool,
+}
+
+// 返回给客户端的那次上游响应；SSE 时 body 为完整事件流
+#[derive(Debug, Clone)]
+pub struct CaptureResponse {
+    pub request_id: String,
+    pub channel_id: String,
+    pub status: i64,
+    pub sse: bool,
+    pub body: Vec<u8>,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestCapture {
+    pub request_id: String,
+    pub ts_ms: i64,
+    pub protocol: Protocol,
+    pub method: String,
+    pub path: String,
+    pub request_headers: Vec<(String, String)>,
+    pub request_body: Vec<u8>,
+    pub request_truncated: bool,
+    pub channel_id: Option<String>,
+    pub response_status: Option<i64>,
+    pub response_sse: bool,
+    pub response_body: Option<Vec<u8>>,
+    pub response_truncated: bool,
+    pub completed_at_ms: Option<i64>,
+}
+
+pub async fn insert_request_capture(
+    db_path: PathBuf,
+    input: CreateRequestCapture,
+) -> anyhow::Result<()> {
+    with_conn(db_path, move |conn| {
+        conn.execute(
+            r#"
+            INSERT OR REPLACE INTO request_captures (
+              request_id, ts_ms, protocol, method, path, request_headers, request_body, request_truncated
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+            params![
+                input.request_id,
+                input.ts_ms,
+                input.protocol.as_str(),
+                input.method,
+                input.path,
+                serde_json::to_string(&input.request_headers)?,
+                input.request_body,
+                if input.request_truncated { 1 } else { 0 },
+            ],
+        )?;
+        Ok(())
+    })
+    .await
+}
+
+pub async fn update_capture_response(
+    db_path: PathBuf,
+    input: CaptureResponse,
+) -> anyhow::Result<()> {
+    with_conn(db_path, move |conn| {
+        conn.execute(
+            r#"
+            UPDATE request_captures
+            SET channel_id = ?2,
+                response_status = ?3,
+                response_sse = ?4,
+                response_body = ?5,
+                response_truncated = ?6,
+                completed_at_ms = ?7
+            WHERE request_id = ?1
+            "#,
+            params![
+                input.request_id,
+                input.channel_id,
+                input.status,
+                if input.sse { 1 } else { 0 },
+                input.body,
+                if input.truncated { 1 } else { 0 },
+                super::now_ms(),
+            ],
+        )?;
+        Ok(())
+    })
+    .await
+}
+
+pub async fn get_request_capture(
+    db_path: PathBuf,
+    request_id: String,
+) -> anyhow::Result<Option<RequestCapture>> {
+    with_conn(db_path, move |conn| {
+        let row = conn
+            .query_row(
+                r#"
+                SELECT request_id, ts_ms, protocol, method, path, request_headers, request_body,
+                       request_truncated, channel_id, response_status, response_sse, response_body,
+                       response_truncated, completed_at_ms
+                FROM request_captures
+                WHERE request_id = ?1
+                "#,
+                params![request_id],
+                |row| {
+                    Ok((
+                        RequestCapture {
+                            request_id: row.get(0)?,
+                            ts_ms: row.get(1)?,
+                            protocol: row.get(2)?,
+                            method: row.get(3)?,
+                            path: row.get(4)?,
+                            request_headers: Vec::new(),
+                            request_body: row.get(6)?,
+                            request_truncated: row.get::<_, i64>(7)? != 0,
+                            channel_id: row.get(8)?,
+                            response_status: row.get(9)?,
+                            response_sse: row.get::<_, i64>(10)? != 0,
+                            response_body: row.get(11)?,
+                            response_truncated: row.get::<_, i64>(12)? != 0,
+                            completed_at_ms: row.get(13)?,
+                        },
+                        row.get::<_, String>(5)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((mut capture, headers)) = row else {
+            return Ok(None);
+        };
+        capture.request_headers = serde_json::from_str(&headers).unwrap_or_default();
+        Ok(Some(capture))
+    })
+    .await
+}
@@ -1,12 +1,20 @@
+use anyhow::Context as _;
 use rusqlite::types::{FromSql, FromSqlError, ValueRef};
 use rusqlite::{OptionalExtension as _, params};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use uuid::Uuid;
 
+use super::budget::SpendLimit;
+use super::failover::{FailoverPolicy, RateLimitPolicy, TimeoutPolicy};
 use super::protocol::normalize_base_url;
+use super::route::glob_match;
+use super::secret::{self, SecretKey};
 use super::{Protocol, now_ms, with_conn};
 
+const CHANNEL_COLUMNS: &str = "id, name, protocol, base_url, auth_type, auth_ref, priority, recharge_currency, real_multiplier, enabled, auto_disabled_until_ms, model_map, key_strategy, weight, failover_policy, timeouts, proxy_url, spend_limit, rate_limit, created_at_ms, updated_at_ms";
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum RechargeCurrency {
     #[serde(rename = "CNY")]
@@ -50,6 +58,47 @@ impl FromSql for RechargeCurrency {
     }
 }
 
+// auth_ref 中有多个 key 时的选取方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStrategy {
+    #[default]
+    RoundRobin,
+    LeastUsed,
+    Random,
+}
+
+impl KeyStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyStrategy::RoundRobin => "round_robin",
+            KeyStrategy::LeastUsed => "least_used",
+            KeyStrategy::Random => "random",
+        }
+    }
+}
+
+impl std::str::FromStr for KeyStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round_robin" => Ok(KeyStrategy::RoundRobin),
+            "least_used" => Ok(KeyStrategy::LeastUsed),
+            "random" => Ok(KeyStrategy::Random),
+            other => Err(anyhow::anyhow!("未知 key_strategy：{other}")),
+        }
+    }
+}
+
+impl FromSql for KeyStrategy {
+    fn column_result(value: ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        let s = value.as_str()?;
+        s.parse::<KeyStrategy>()
+            .map_err(|e| FromSqlError::Other(e.into_boxed_dyn_error()))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Channel {
     pub id: String,
@@ -63,10 +112,165 @@ pub struct Channel {
     pub real_multiplier: f64,
     pub enabled: bool,
     pub auto_disabled_until_ms: i64,
+    #[serde(default)]
+    pub model_map: BTreeMap<String, String>,
+    #[serde(default)]
+    pub key_strategy: KeyStrategy,
+    // 同优先级渠道之间的流量权重
+    #[serde(default = "default_weight")]
+    pub weight: i64,
+    // 为空时使用全局故障转移策略
+    #[serde(default)]
+    pub failover_policy: Option<FailoverPolicy>,
+    // 为空时使用全局超时设置
+    #[serde(default)]
+    pub timeouts: Option<TimeoutPolicy>,
+    // 为空时沿用全局代理；"direct" 表示不走代理
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+    // 余额与每日、每月限额，达到后该渠道不再参与路由
+    #[serde(default)]
+    pub spend_limit: Option<SpendLimit>,
+    // 客户端侧限速，为空时只按上游返回的限速头处理
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitPolicy>,
     pub created_at_ms: i64,
     pub updated_at_ms: i64,
 }
 
+impl Channel {
+    // auth_ref 每行一个 key，空行忽略
+    pub fn keys(&self) -> Vec<&str> {
+        self.auth_ref
+            .lines()
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .collect()
+    }
+
+    // model_map 的键可以是精确模型名或通配（`*` / `?`），精确匹配优先，通配按非通配字符数从长到短
+    pub fn upstream_model_for(&self, model: &str) -> Option<&str> {
+        if let Some(v) = self.model_map.get(model) {
+            return Some(v.as_str());
+        }
+        self.model_map
+            .iter()
+            .filter(|(k, _)| k.contains(['*', '?']))
+            .filter(|(k, _)| glob_match(k.as_bytes(), model.as_bytes()))
+            .max_by_key(|(k, _)| k.chars().filter(|c| !matches!(c, '*' | '?')).count())
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn normalize_proxy_url(v: Option<String>) -> Option<String> {
+    v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+fn default_weight() -> i64 {
+    1
+}
+
+fn channel_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Channel> {
+    let protocol: Protocol = row.get(2)?;
+    let base_url: String = row.get(3)?;
+    let model_map: Option<String> = row.get(11)?;
+    let failover_policy: Option<String> = row.get(14)?;
+    let timeouts: Option<String> = row.get(15)?;
+    let spend_limit: Option<String> = row.get(17)?;
+    let rate_limit: Option<String> = row.get(18)?;
+    Ok(Channel {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        protocol,
+        base_url: normalize_base_url(protocol, &base_url),
+        auth_type: row.get(4)?,
+        auth_ref: row.get(5)?,
+        priority: row.get(6)?,
+        recharge_currency: row
+            .get::<_, Option<RechargeCurrency>>(7)?
+            .unwrap_or(RechargeCurrency::Cny),
+        real_multiplier: row.get::<_, Option<f64>>(8)?.unwrap_or(1.0),
+        enabled: row.get::<_, i64>(9)? != 0,
+        auto_disabled_until_ms: row.get::<_, Option<i64>>(10)?.unwrap_or(0),
+        model_map: model_map
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default(),
+        key_strategy: row.get::<_, Option<KeyStrategy>>(12)?.unwrap_or_default(),
+        weight: row.get::<_, Option<i64>>(13)?.unwrap_or(1),
+        failover_policy: failover_policy.and_then(|s| serde_json::from_str(&s).ok()),
+        timeouts: timeouts.and_then(|s| serde_json::from_str(&s).ok()),
+        proxy_url: row.get(16)?,
+        spend_limit: spend_limit.and_then(|s| serde_json::from_str(&s).ok()),
+        rate_limit: rate_limit.and_then(|s| serde_json::from_str(&s).ok()),
+        created_at_ms: row.get(19)?,
+        updated_at_ms: row.get(20)?,
+    })
+}
+
+fn decrypt_auth_ref(key: Option<&SecretKey>, mut channel: Channel) -> anyhow::Result<Channel> {
+    channel.auth_ref = secret::unseal(key, &channel.auth_ref)
+        .with_context(|| format!("渠道 {} 的密钥无法解密", channel.name))?;
+    Ok(channel)
+}
+
+fn encrypted_auth_refs(
+    conn: &rusqlite::Connection,
+) -> anyhow::Result<Vec<(String, String, String)>> {
+    let mut stmt = conn.prepare(r#"SELECT id, name, auth_ref FROM channels"#)?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<Vec<(String, String, String)>>>()?;
+    Ok(rows
+        .into_iter()
+        .filter(|(_, _, auth_ref)| secret::is_encrypted(auth_ref))
+        .collect())
+}
+
+pub(super) fn has_encrypted_auth_refs(conn: &rusqlite::Connection) -> anyhow::Result<bool> {
+    Ok(!encrypted_auth_refs(conn)?.is_empty())
+}
+
+// 启动时确认所有密文都能解开，密钥丢失或不匹配时直接报错而不是在使用时才失败
+pub(super) fn check_auth_refs(conn: &rusqlite::Connection, key: &SecretKey) -> anyhow::Result<()> {
+    let broken: Vec<String> = encrypted_auth_refs(conn)?
+        .into_iter()
+        .filter(|(_, _, auth_ref)| secret::decrypt(key, auth_ref).is_err())
+        .map(|(_, name, _)| name)
+        .collect();
+    if !broken.is_empty() {
+        anyhow::bail!(
+            "以下渠道的密钥无法用当前密钥解密：{}。请恢复原来的系统钥匙串条目或口令（{}）后再启动",
+            broken.join("、"),
+            secret::SECRET_PASSPHRASE_ENV
+        );
+    }
+    Ok(())
+}
+
+// 把旧版明文保存的 auth_ref 加密；已加密的跳过
+pub(super) fn encrypt_plaintext_auth_refs(
+    conn: &rusqlite::Connection,
+    key: &SecretKey,
+) -> anyhow::Result<usize> {
+    let plain: Vec<(String, String)> = {
+        let mut stmt = conn.prepare(r#"SELECT id, auth_ref FROM channels"#)?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<(String, String)>>>()?
+            .into_iter()
+            .filter(|(_, auth_ref)| !secret::is_encrypted(auth_ref))
+            .collect()
+    };
+    let tx = conn.unchecked_transaction()?;
+    for (id, auth_ref) in &plain {
+        tx.execute(
+            r#"UPDATE channels SET auth_ref = ?2 WHERE id = ?1"#,
+            params![id, secret::encrypt(key, auth_ref)?],
+        )?;
+    }
+    tx.commit()?;
+    Ok(plain.len())
+}
+
 pub fn channel_is_auto_disabled(channel: &Channel, now_ms: i64) -> bool {
     channel.auto_disabled_until_ms > now_ms
 }
@@ -89,22 +293,7 @@ pub async fn record_channel_failure_and_maybe_disable(
 
     with_conn(db_path, move |conn| {
         let tx = conn.unchecked_transaction()?;
-        let cutoff_ms = now_ms.saturating_sub(window_ms);
-
-        tx.execute(
-            r#"DELETE FROM channel_failures WHERE channel_id = ?1 AND at_ms < ?2"#,
-            params![channel_id, cutoff_ms],
-        )?;
-        tx.execute(
-            r#"INSERT INTO channel_failures (channel_id, at_ms) VALUES (?1, ?2)"#,
-            params![channel_id, now_ms],
-        )?;
-
-        let cnt: i64 = tx.query_row(
-            r#"SELECT COUNT(*) FROM channel_failures WHERE channel_id = ?1 AND at_ms >= ?2"#,
-            params![channel_id, cutoff_ms],
-            |row| row.get(0),
-        )?;
+        let cnt = record_failure_in_window(&tx, &channel_id, None, now_ms, window_ms)?;
 
         if cnt < failure_times {
             tx.commit()?;
@@ -130,6 +319,32 @@ pub async fn record_channel_failure_and_maybe_disable(
     .await
 }
 
+// 写入一次失败并返回窗口内的失败次数；key_fp 为空表示渠道级别的失败
+pub(super) fn record_failure_in_window(
+    conn: &rusqlite::Connection,
+    channel_id: &str,
+    key_fp: Option<&str>,
+    now_ms: i64,
+    window_ms: i64,
+) -> rusqlite::Result<i64> {
+    let cutoff_ms = now_ms.saturating_sub(window_ms);
+
+    conn.execute(
+        r#"DELETE FROM channel_failures WHERE channel_id = ?1 AND key_fp IS ?2 AND at_ms < ?3"#,
+        params![channel_id, key_fp, cutoff_ms],
+    )?;
+    conn.execute(
+        r#"INSERT INTO channel_failures (channel_id, key_fp, at_ms) VALUES (?1, ?2, ?3)"#,
+        params![channel_id, key_fp, now_ms],
+    )?;
+
+    conn.query_row(
+        r#"SELECT COUNT(*) FROM channel_failures WHERE channel_id = ?1 AND key_fp IS ?2 AND at_ms >= ?3"#,
+        params![channel_id, key_fp, cutoff_ms],
+        |row| row.get(0),
+    )
+}
+
 pub async fn clear_channel_failures(db_path: PathBuf, channel_id: String) -> anyhow::Result<()> {
     with_conn(db_path, move |conn| {
         conn.execute(
@@ -142,10 +357,11 @@ pub async fn clear_channel_failures(db_path: PathBuf, channel_id: String) -> any
 }
 
 pub async fn list_channels(db_path: PathBuf) -> anyhow::Result<Vec<Channel>> {
-    with_conn(db_path, |conn| {
-        let mut stmt = conn.prepare(
+    let key = secret::secret_key(&db_path)?;
+    with_conn(db_path, move |conn| {
+        let mut stmt = conn.prepare(&format!(
             r#"
-            SELECT id, name, protocol, base_url, auth_type, auth_ref, priority, recharge_currency, real_multiplier, enabled, auto_disabled_until_ms, created_at_ms, updated_at_ms
+            SELECT {CHANNEL_COLUMNS}
             FROM channels
             ORDER BY CASE protocol
               WHEN 'openai' THEN 0
@@ -153,32 +369,12 @@ pub async fn list_channels(db_path: PathBuf) -> anyhow::Result<Vec<Channel>> {
               WHEN 'gemini' THEN 2
               ELSE 9
             END, priority DESC, name ASC
-            "#,
-        )?;
-        let rows = stmt.query_map([], |row| {
-            let protocol: Protocol = row.get(2)?;
-            let base_url: String = row.get(3)?;
-            Ok(Channel {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                protocol,
-                base_url: normalize_base_url(protocol, &base_url),
-                auth_type: row.get(4)?,
-                auth_ref: row.get(5)?,
-                priority: row.get(6)?,
-                recharge_currency: row
-                    .get::<_, Option<RechargeCurrency>>(7)?
-                    .unwrap_or(RechargeCurrency::Cny),
-                real_multiplier: row.get::<_, Option<f64>>(8)?.unwrap_or(1.0),
-                enabled: row.get::<_, i64>(9)? != 0,
-                auto_disabled_until_ms: row.get::<_, Option<i64>>(10)?.unwrap_or(0),
-                created_at_ms: row.get(11)?,
-                updated_at_ms: row.get(12)?,
-            })
-        })?;
-
-        rows.collect::<rusqlite::Result<Vec<_>>>()
-            .map_err(Into::into)
+            "#
+        ))?;
+        let rows = stmt.query_map([], channel_from_row)?;
+
+        rows.map(|row| decrypt_auth_ref(key.as_ref(), row?))
+            .collect::<anyhow::Result<Vec<_>>>()
     })
     .await
 }
@@ -195,9 +391,51 @@ pub struct CreateChannel {
     pub recharge_currency: Option<RechargeCurrency>,
     pub real_multiplier: Option<f64>,
     pub enabled: bool,
+    #[serde(default)]
+    pub model_map: BTreeMap<String, String>,
+    #[serde(default)]
+    pub key_strategy: KeyStrategy,
+    #[serde(default = "default_weight")]
+    pub weight: i64,
+    #[serde(default)]
+    pub failover_policy: Option<FailoverPolicy>,
+    #[serde(default)]
+    pub timeouts: Option<TimeoutPolicy>,
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+    #[serde(default)]
+    pub spend_limit: Option<SpendLimit>,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitPolicy>,
+}
+
+// 只需写出基本信息，其余字段与接口未传时的默认值一致
+impl Default for CreateChannel {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            protocol: Protocol::Openai,
+            base_url: String::new(),
+            auth_type: None,
+            auth_ref: String::new(),
+            priority: 0,
+            recharge_currency: None,
+            real_multiplier: None,
+            enabled: true,
+            model_map: BTreeMap::new(),
+            key_strategy: KeyStrategy::default(),
+            weight: default_weight(),
+            failover_policy: None,
+            timeouts: None,
+            proxy_url: None,
+            spend_limit: None,
+            rate_limit: None,
+        }
+    }
 }
 
 pub async fn create_channel(db_path: PathBuf, input: CreateChannel) -> anyhow::Result<Channel> {
+    let key = secret::secret_key(&db_path)?;
     with_conn(db_path, move |conn| {
         let ts = now_ms();
         let id = Uuid::new_v4().to_string();
@@ -207,12 +445,34 @@ pub async fn create_channel(db_path: PathBuf, input: CreateChannel) -> anyhow::R
             .trim()
             .to_string();
         let base_url = normalize_base_url(input.protocol, &input.base_url);
+        let proxy_url = normalize_proxy_url(input.proxy_url);
         let recharge_currency = input.recharge_currency.unwrap_or(RechargeCurrency::Cny);
         let real_multiplier = input.real_multiplier.unwrap_or(1.0);
+        let model_map_json = serde_json::to_string(&input.model_map)?;
+        let failover_policy_json = input
+            .failover_policy
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let timeouts_json = input
+            .timeouts
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let spend_limit = input.spend_limit.and_then(|l| l.stamped(None, ts));
+        let spend_limit_json = spend_limit
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let rate_limit = input.rate_limit.filter(|l| !l.is_empty());
+        let rate_limit_json = rate_limit
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
         conn.execute(
             r#"
-            INSERT INTO channels (id, name, protocol, base_url, auth_type, auth_ref, priority, recharge_currency, real_multiplier, enabled, created_at_ms, updated_at_ms)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            INSERT INTO channels (id, name, protocol, base_url, auth_type, auth_ref, priority, recharge_currency, real_multiplier, enabled, model_map, key_strategy, weight, failover_policy, timeouts, proxy_url, spend_limit, rate_limit, created_at_ms, updated_at_ms)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)
             "#,
             params![
                 id,
@@ -220,11 +480,19 @@ pub async fn create_channel(db_path: PathBuf, input: CreateChannel) -> anyhow::R
                 input.protocol.as_str(),
                 base_url,
                 auth_type,
-                input.auth_ref,
+                secret::seal(key.as_ref(), &input.auth_ref)?,
                 input.priority,
                 recharge_currency.as_str(),
                 real_multiplier,
                 if input.enabled { 1 } else { 0 },
+                model_map_json,
+                input.key_strategy.as_str(),
+                input.weight,
+                failover_policy_json,
+                timeouts_json,
+                proxy_url,
+                spend_limit_json,
+                rate_limit_json,
                 ts,
                 ts,
             ],
@@ -242,6 +510,14 @@ pub async fn create_channel(db_path: PathBuf, input: CreateChannel) -> anyhow::R
             real_multiplier,
             enabled: input.enabled,
             auto_disabled_until_ms: 0,
+            model_map: input.model_map,
+            key_strategy: input.key_strategy,
+            weight: input.weight,
+            failover_policy: input.failover_policy,
+            timeouts: input.timeouts,
+            proxy_url,
+            spend_limit,
+            rate_limit,
             created_at_ms: ts,
             updated_at_ms: ts,
         })
@@ -249,7 +525,7 @@ pub async fn create_channel(db_path: PathBuf, input: CreateChannel) -> anyhow::R
     .await
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct UpdateChannel {
     pub name: Option<String>,
     pub base_url: Option<String>,
@@ -259,6 +535,28 @@ pub struct UpdateChannel {
     pub recharge_currency: Option<RechargeCurrency>,
     pub real_multiplier: Option<f64>,
     pub enabled: Option<bool>,
+    pub model_map: Option<BTreeMap<String, String>>,
+    pub key_strategy: Option<KeyStrategy>,
+    pub weight: Option<i64>,
+    // 缺省表示不修改，显式 null 表示恢复使用全局策略
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub failover_policy: Option<Option<FailoverPolicy>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub timeouts: Option<Option<TimeoutPolicy>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub proxy_url: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub spend_limit: Option<Option<SpendLimit>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub rate_limit: Option<Option<RateLimitPolicy>>,
+}
+
+pub(super) fn deserialize_some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
 }
 
 pub async fn update_channel(
@@ -266,42 +564,23 @@ pub async fn update_channel(
     channel_id: String,
     input: UpdateChannel,
 ) -> anyhow::Result<()> {
+    let key = secret::secret_key(&db_path)?;
     with_conn(db_path, move |conn| {
         let ts = now_ms();
         let clear_failures = input.enabled == Some(true);
 
         let mut channel: Channel = {
-            let mut stmt = conn.prepare(
+            let mut stmt = conn.prepare(&format!(
                 r#"
-                SELECT id, name, protocol, base_url, auth_type, auth_ref, priority, recharge_currency, real_multiplier, enabled, auto_disabled_until_ms, created_at_ms, updated_at_ms
+                SELECT {CHANNEL_COLUMNS}
                 FROM channels
                 WHERE id = ?1
-                "#,
-            )?;
-            let row = stmt.query_row([&channel_id], |row| {
-                let protocol: Protocol = row.get(2)?;
-                let base_url: String = row.get(3)?;
-                Ok(Channel {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    protocol,
-                    base_url: normalize_base_url(protocol, &base_url),
-                    auth_type: row.get(4)?,
-                    auth_ref: row.get(5)?,
-                    priority: row.get(6)?,
-                    recharge_currency: row
-                        .get::<_, Option<RechargeCurrency>>(7)?
-                        .unwrap_or(RechargeCurrency::Cny),
-                    real_multiplier: row.get::<_, Option<f64>>(8)?.unwrap_or(1.0),
-                    enabled: row.get::<_, i64>(9)? != 0,
-                    auto_disabled_until_ms: row.get::<_, Option<i64>>(10)?.unwrap_or(0),
-                    created_at_ms: row.get(11)?,
-                    updated_at_ms: row.get(12)?,
-                })
-            });
+                "#
+            ))?;
+            let row = stmt.query_row([&channel_id], channel_from_row);
 
             match row {
-                Ok(v) => v,
+                Ok(v) => decrypt_auth_ref(key.as_ref(), v)?,
                 Err(rusqlite::Error::QueryReturnedNoRows) => {
                     return Err(anyhow::anyhow!("channel not found: {channel_id}"));
                 }
@@ -336,13 +615,41 @@ pub async fn update_channel(
                 channel.auto_disabled_until_ms = 0;
             }
         }
+        if let Some(v) = input.model_map {
+            channel.model_map = v;
+        }
+        if let Some(v) = input.key_strategy {
+            channel.key_strategy = v;
+        }
+        if let Some(v) = input.weight {
+            channel.weight = v;
+        }
+        if let Some(v) = input.failover_policy {
+            channel.failover_policy = v;
+        }
+        if let Some(v) = input.timeouts {
+            channel.timeouts = v;
+        }
+        if let Some(v) = input.proxy_url {
+            channel.proxy_url = normalize_proxy_url(v);
+        }
+        let balance_set_at_ms = channel.spend_limit.as_ref().and_then(|l| l.balance_set_at_ms);
+        if let Some(v) = input.spend_limit {
+            channel.spend_limit = v.and_then(|l| l.stamped(channel.spend_limit.as_ref(), ts));
+        }
+        // 余额重新设置后从零开始累计
+        let reset_balance_spent =
+            channel.spend_limit.as_ref().and_then(|l| l.balance_set_at_ms) != balance_set_at_ms;
+        if let Some(v) = input.rate_limit {
+            channel.rate_limit = v.filter(|l| !l.is_empty());
+        }
         channel.updated_at_ms = ts;
 
         let tx = conn.unchecked_transaction()?;
         tx.execute(
             r#"
             UPDATE channels
-            SET name = ?2, base_url = ?3, auth_type = ?4, auth_ref = ?5, priority = ?6, recharge_currency = ?7, real_multiplier = ?8, enabled = ?9, auto_disabled_until_ms = ?10, updated_at_ms = ?11
+            SET name = ?2, base_url = ?3, auth_type = ?4, auth_ref = ?5, priority = ?6, recharge_currency = ?7, real_multiplier = ?8, enabled = ?9, auto_disabled_until_ms = ?10, model_map = ?11, key_strategy = ?12, weight = ?13, failover_policy = ?14, timeouts = ?15, proxy_url = ?16, spend_limit = ?17, rate_limit = ?18, updated_at_ms = ?19
             WHERE id = ?1
             "#,
             params![
@@ -350,20 +657,54 @@ pub async fn update_channel(
                 channel.name,
                 channel.base_url,
                 channel.auth_type,
-                channel.auth_ref,
+                secret::seal(key.as_ref(), &channel.auth_ref)?,
                 channel.priority,
                 channel.recharge_currency.as_str(),
                 channel.real_multiplier,
                 if channel.enabled { 1 } else { 0 },
                 channel.auto_disabled_until_ms,
+                serde_json::to_string(&channel.model_map)?,
+                channel.key_strategy.as_str(),
+                channel.weight,
+                channel
+                    .failover_policy
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                channel
+                    .timeouts
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                channel.proxy_url,
+                channel
+                    .spend_limit
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                channel
+                    .rate_limit
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
                 channel.updated_at_ms,
             ],
         )?;
+        if reset_balance_spent {
+            tx.execute(
+                r#"UPDATE channels SET balance_spent_usd = 0 WHERE id = ?1"#,
+                params![channel.id],
+            )?;
+        }
         if clear_failures {
             tx.execute(
                 r#"DELETE FROM channel_failures WHERE channel_id = ?1"#,
                 params![channel.id],
             )?;
+            tx.execute(
+                r#"UPDATE channel_keys SET auto_disabled_until_ms = 0 WHERE channel_id = ?1"#,
+                params![channel.id],
+            )?;
         }
         tx.commit()?;
 
@@ -404,6 +745,10 @@ pub async fn set_channel_enabled(
                 r#"DELETE FROM channel_failures WHERE channel_id = ?1"#,
                 params![channel_id],
             )?;
+            tx.execute(
+                r#"UPDATE channel_keys SET auto_disabled_until_ms = 0 WHERE channel_id = ?1"#,
+                params![channel_id],
+            )?;
         }
         tx.commit()?;
 
@@ -416,38 +761,20 @@ pub async fn set_channel_enabled(
 }
 
 pub async fn get_channel(db_path: PathBuf, channel_id: String) -> anyhow::Result<Option<Channel>> {
+    let key = secret::secret_key(&db_path)?;
     with_conn(db_path, move |conn| {
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare(&format!(
             r#"
-            SELECT id, name, protocol, base_url, auth_type, auth_ref, priority, recharge_currency, real_multiplier, enabled, auto_disabled_until_ms, created_at_ms, updated_at_ms
+            SELECT {CHANNEL_COLUMNS}
             FROM channels
             WHERE id = ?1
-            "#,
-        )?;
+            "#
+        ))?;
 
-        stmt.query_row([channel_id], |row| {
-            let protocol: Protocol = row.get(2)?;
-            let base_url: String = row.get(3)?;
-            Ok(Channel {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                protocol,
-                base_url: normalize_base_url(protocol, &base_url),
-                auth_type: row.get(4)?,
-                auth_ref: row.get(5)?,
-                priority: row.get(6)?,
-                recharge_currency: row
-                    .get::<_, Option<RechargeCurrency>>(7)?
-                    .unwrap_or(RechargeCurrency::Cny),
-                real_multiplier: row.get::<_, Option<f64>>(8)?.unwrap_or(1.0),
-                enabled: row.get::<_, i64>(9)? != 0,
-                auto_disabled_until_ms: row.get::<_, Option<i64>>(10)?.unwrap_or(0),
-                created_at_ms: row.get(11)?,
-                updated_at_ms: row.get(12)?,
-            })
-        })
-        .optional()
-        .map_err(Into::into)
+        stmt.query_row([channel_id], channel_from_row)
+            .optional()?
+            .map(|c| decrypt_auth_ref(key.as_ref(), c))
+            .transpose()
     })
     .await
 }
@@ -518,6 +845,10 @@ pub async fn delete_channel(db_path: PathBuf, channel_id: String) -> anyhow::Res
             r#"DELETE FROM route_channels WHERE channel_id = ?1"#,
             params![channel_id],
         )?;
+        tx.execute(
+            r#"DELETE FROM channel_keys WHERE channel_id = ?1"#,
+            params![channel_id],
+        )?;
         let deleted = tx.execute(r#"DELETE FROM channels WHERE id = ?1"#, params![channel_id])?;
         tx.commit()?;
 
@@ -0,0 +1,184 @@
+use rusqlite::{OptionalExtension as _, params};
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+use std::path::PathBuf;
+
+use super::channel::record_failure_in_window;
+use super::with_conn;
+
+// 指纹只用于区分与展示，不可逆推出 key
+pub fn key_fingerprint(key: &str) -> String {
+    let digest = Sha256::digest(key.trim().as_bytes());
+    hex::encode(&digest[..8])
+}
+
+pub fn mask_key(key: &str) -> String {
+    let key = key.trim();
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{head}…{tail}")
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelKeyState {
+    pub key_fp: String,
+    pub use_count: i64,
+    pub last_used_ms: Option<i64>,
+    pub auto_disabled_until_ms: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeyDisabled {
+    pub key_until_ms: i64,
+    // 池中所有 key 都被禁用时，渠道也会被禁用到最早恢复的那个 key
+    pub channel_until_ms: Option<i64>,
+}
+
+pub async fn list_channel_key_states(
+    db_path: PathBuf,
+    channel_id: String,
+) -> anyhow::Result<Vec<ChannelKeyState>> {
+    with_conn(db_path, move |conn| {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT key_fp, use_count, last_used_ms, auto_disabled_until_ms
+            FROM channel_keys
+            WHERE channel_id = ?1
+            "#,
+        )?;
+        let rows = stmt.query_map(params![channel_id], |row| {
+            Ok(ChannelKeyState {
+                key_fp: row.get(0)?,
+                use_count: row.get(1)?,
+                last_used_ms: row.get(2)?,
+                auto_disabled_until_ms: row.get(3)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(Into::into)
+    })
+    .await
+}
+
+pub async fn touch_channel_key(
+    db_path: PathBuf,
+    channel_id: String,
+    key_fp: String,
+    now_ms: i64,
+) -> anyhow::Result<()> {
+    with_conn(db_path, move |conn| {
+        conn.execute(
+            r#"
+            INSERT INTO channel_keys (channel_id, key_fp, use_count, last_used_ms, auto_disabled_until_ms)
+            VALUES (?1, ?2, 1, ?3, 0)
+            ON CONFLICT(channel_id, key_fp) DO UPDATE SET
+              use_count = use_count + 1,
+              last_used_ms = excluded.last_used_ms
+            "#,
+            params![channel_id, key_fp, now_ms],
+        )?;
+        Ok(())
+    })
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn record_key_failure_and_maybe_disable(
+    db_path: PathBuf,
+    channel_id: String,
+    key_fp: String,
+    pool: Vec<String>,
+    now_ms: i64,
+    window_minutes: i64,
+    failure_times: i64,
+    disable_minutes: i64,
+) -> anyhow::Result<Option<KeyDisabled>> {
+    if window_minutes < 1 || disable_minutes < 1 || failure_times < 1 {
+        anyhow::bail!(
+            "auto_disable 配置非法：window_minutes={window_minutes}, failure_times={failure_times}, disable_minutes={disable_minutes}"
+        );
+    }
+    let window_ms = window_minutes.saturating_mul(60_000);
+    let disable_ms = disable_minutes.saturating_mul(60_000);
+
+    with_conn(db_path, move |conn| {
+        let tx = conn.unchecked_transaction()?;
+        let cnt = record_failure_in_window(&tx, &channel_id, Some(&key_fp), now_ms, window_ms)?;
+
+        if cnt < failure_times {
+            tx.commit()?;
+            return Ok(None);
+        }
+
+        let key_until_ms = now_ms.saturating_add(disable_ms);
+        tx.execute(
+            r#"
+            INSERT INTO channel_keys (channel_id, key_fp, use_count, last_used_ms, auto_disabled_until_ms)
+            VALUES (?1, ?2, 0, NULL, ?3)
+            ON CONFLICT(channel_id, key_fp) DO UPDATE SET
+              auto_disabled_until_ms = excluded.auto_disabled_until_ms
+            "#,
+            params![channel_id, key_fp, key_until_ms],
+        )?;
+        tx.execute(
+            r#"DELETE FROM channel_failures WHERE channel_id = ?1 AND key_fp = ?2"#,
+            params![channel_id, key_fp],
+        )?;
+
+        let mut earliest_until_ms = Some(key_until_ms);
+        for fp in &pool {
+            let until_ms: i64 = tx
+                .query_row(
+                    r#"SELECT auto_disabled_until_ms FROM channel_keys WHERE channel_id = ?1 AND key_fp = ?2"#,
+                    params![channel_id, fp],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .unwrap_or(0);
+            earliest_until_ms = if until_ms > now_ms {
+                earliest_until_ms.map(|v| v.min(until_ms))
+            } else {
+                None
+            };
+            if earliest_until_ms.is_none() {
+                break;
+            }
+        }
+
+        if let Some(until_ms) = earliest_until_ms {
+            tx.execute(
+                r#"
+                UPDATE channels
+                SET auto_disabled_until_ms = ?2, updated_at_ms = ?3
+                WHERE id = ?1
+                "#,
+                params![channel_id, until_ms, now_ms],
+            )?;
+        }
+        tx.commit()?;
+        Ok(Some(KeyDisabled {
+            key_until_ms,
+            channel_until_ms: earliest_until_ms,
+        }))
+    })
+    .await
+}
+
+pub async fn clear_key_failures(
+    db_path: PathBuf,
+    channel_id: String,
+    key_fp: String,
+) -> anyhow::Result<()> {
+    with_conn(db_path, move |conn| {
+        conn.execute(
+            r#"DELETE FROM channel_failures WHERE channel_id = ?1 AND key_fp = ?2"#,
+            params![channel_id, key_fp],
+        )?;
+        Ok(())
+    })
+    .await
+}
@@ -0,0 +1,392 @@
+use rusqlite::types::{FromSql, FromSqlError, ValueRef};
+use rusqlite::{OptionalExtension as _, params};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use super::channel::deserialize_some;
+use super::channel_key::mask_key;
+use super::{Protocol, now_ms, with_conn};
+
+const CLIENT_TOKEN_COLUMNS: &str = "id, name, masked, role, protocols, route_ids, enabled, expires_at_ms, last_used_ms, created_at_ms, updated_at_ms, requests_per_minute, tokens_per_day, usd_per_month";
+
+// 本地客户端令牌的管理接口权限；所有角色都可以调用代理接口
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientTokenRole {
+    // 只能调用 /v1 等代理接口
+    #[default]
+    Proxy,
+    // 额外可以读取管理接口
+    ReadOnly,
+    Admin,
+}
+
+impl ClientTokenRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ClientTokenRole::Proxy => "proxy",
+            ClientTokenRole::ReadOnly => "read_only",
+            ClientTokenRole::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for ClientTokenRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "proxy" => Ok(ClientTokenRole::Proxy),
+            "read_only" => Ok(ClientTokenRole::ReadOnly),
+            "admin" => Ok(ClientTokenRole::Admin),
+            other => Err(anyhow::anyhow!("未知 role：{other}")),
+        }
+    }
+}
+
+impl FromSql for ClientTokenRole {
+    fn column_result(value: ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        let s = value.as_str()?;
+        s.parse::<ClientTokenRole>()
+            .map_err(|e| FromSqlError::Other(e.into_boxed_dyn_error()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientToken {
+    pub id: String,
+    pub name: String,
+    // 明文只在创建时返回一次，之后只保留哈希与掩码
+    pub masked: String,
+    pub role: ClientTokenRole,
+    // 为空表示不限制
+    pub protocols: Vec<Protocol>,
+    pub route_ids: Vec<String>,
+    pub enabled: bool,
+    pub expires_at_ms: Option<i64>,
+    pub last_used_ms: Option<i64>,
+    pub created_at_ms: i64,
+    pub updated_at_ms: i64,
+    // 配额，为空表示不限制；天、月按本地时区的自然日、自然月计算
+    pub requests_per_minute: Option<i64>,
+    pub tokens_per_day: Option<i64>,
+    pub usd_per_month: Option<f64>,
+}
+
+impl ClientToken {
+    pub fn is_active(&self, now_ms: i64) -> bool {
+        self.enabled && self.expires_at_ms.is_none_or(|t| t > now_ms)
+    }
+
+    pub fn allows_protocol(&self, protocol: Protocol) -> bool {
+        self.protocols.is_empty() || self.protocols.contains(&protocol)
+    }
+
+    // 限定了路由时，未命中任何路由的请求也不放行
+    pub fn allows_route(&self, route_id: Option<&str>) -> bool {
+        self.route_ids.is_empty()
+            || route_id.is_some_and(|id| self.route_ids.iter().any(|r| r == id))
+    }
+
+    pub fn has_quota(&self) -> bool {
+        self.requests_per_minute.is_some()
+            || self.tokens_per_day.is_some()
+            || self.usd_per_month.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedClientToken {
+    #[serde(flatten)]
+    pub info: ClientToken,
+    pub token: String,
+}
+
+fn client_token_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ClientToken> {
+    let protocols: String = row.get(4)?;
+    let route_ids: String = row.get(5)?;
+    Ok(ClientToken {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        masked: row.get(2)?,
+        role: row.get(3)?,
+        protocols: serde_json::from_str(&protocols).unwrap_or_default(),
+        route_ids: serde_json::from_str(&route_ids).unwrap_or_default(),
+        enabled: row.get::<_, i64>(6)? != 0,
+        expires_at_ms: row.get(7)?,
+        last_used_ms: row.get(8)?,
+        created_at_ms: row.get(9)?,
+        updated_at_ms: row.get(10)?,
+        requests_per_minute: row.get(11)?,
+        tokens_per_day: row.get(12)?,
+        usd_per_month: row.get(13)?,
+    })
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.trim().as_bytes()))
+}
+
+fn generate_token() -> String {
+    format!("csk-{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub async fn list_client_tokens(db_path: PathBuf) -> anyhow::Result<Vec<ClientToken>> {
+    with_conn(db_path, |conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CLIENT_TOKEN_COLUMNS} FROM client_tokens ORDER BY created_at_ms ASC"
+        ))?;
+        let rows = stmt.query_map([], client_token_from_row)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(Into::into)
+    })
+    .await
+}
+
+// 按明文查找令牌，不检查是否启用或过期
+pub async fn find_client_token(
+    db_path: PathBuf,
+    token: String,
+) -> anyhow::Result<Option<ClientToken>> {
+    with_conn(db_path, move |conn| {
+        conn.query_row(
+            &format!("SELECT {CLIENT_TOKEN_COLUMNS} FROM client_tokens WHERE token_hash = ?1"),
+            params![token_hash(&token)],
+            client_token_from_row,
+        )
+        .optional()
+        .map_err(Into::into)
+    })
+    .await
+}
+
+pub async fn touch_client_token(db_path: PathBuf, id: String, now_ms: i64) -> anyhow::Result<()> {
+    with_conn(db_path, move |conn| {
+        conn.execute(
+            r#"UPDATE client_tokens SET last_used_ms = ?2 WHERE id = ?1"#,
+            params![id, now_ms],
+        )?;
+        Ok(())
+    })
+    .await
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateClientToken {
+    pub name: String,
+    #[serde(default)]
+    pub role: ClientTokenRole,
+    #[serde(default)]
+    pub protocols: Vec<Protocol>,
+    #[serde(default)]
+    pub route_ids: Vec<String>,
+    #[serde(default)]
+    pub expires_at_ms: Option<i64>,
+    #[serde(default)]
+    pub requests_per_minute: Option<i64>,
+    #[serde(default)]
+    pub tokens_per_day: Option<i64>,
+    #[serde(default)]
+    pub usd_per_month: Option<f64>,
+}
+
+pub async fn create_client_token(
+    db_path: PathBuf,
+    input: CreateClientToken,
+) -> anyhow::Result<CreatedClientToken> {
+    with_conn(db_path, move |conn| {
+        let ts = now_ms();
+        let id = Uuid::new_v4().to_string();
+        let token = generate_token();
+        let masked = mask_key(&token);
+        conn.execute(
+            r#"
+            INSERT INTO client_tokens (id, name, token_hash, masked, role, protocols, route_ids, enabled, expires_at_ms, requests_per_minute, tokens_per_day, usd_per_month, created_at_ms, updated_at_ms)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 1, ?8, ?9, ?10, ?11, ?12, ?13)
+            "#,
+            params![
+                id,
+                input.name,
+                token_hash(&token),
+                masked,
+                input.role.as_str(),
+                serde_json::to_string(&input.protocols)?,
+                serde_json::to_string(&input.route_ids)?,
+                input.expires_at_ms,
+                input.requests_per_minute,
+                input.tokens_per_day,
+                input.usd_per_month,
+                ts,
+                ts,
+            ],
+        )?;
+
+        Ok(CreatedClientToken {
+            info: ClientToken {
+                id,
+                name: input.name,
+                masked,
+                role: input.role,
+                protocols: input.protocols,
+                route_ids: input.route_ids,
+                enabled: true,
+                expires_at_ms: input.expires_at_ms,
+                last_used_ms: None,
+                created_at_ms: ts,
+                updated_at_ms: ts,
+                requests_per_minute: input.requests_per_minute,
+                tokens_per_day: input.tokens_per_day,
+                usd_per_month: input.usd_per_month,
+            },
+            token,
+        })
+    })
+    .await
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateClientToken {
+    pub name: Option<String>,
+    pub role: Option<ClientTokenRole>,
+    pub protocols: Option<Vec<Protocol>>,
+    pub route_ids: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+    // 缺省表示不修改，显式 null 表示永不过期
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expires_at_ms: Option<Option<i64>>,
+    // 显式 null 表示取消该项配额
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub requests_per_minute: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub tokens_per_day: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub usd_per_month: Option<Option<f64>>,
+}
+
+pub async fn update_client_token(
+    db_path: PathBuf,
+    id: String,
+    input: UpdateClientToken,
+) -> anyhow::Result<()> {
+    with_conn(db_path, move |conn| {
+        let Some(mut token) = conn
+            .query_row(
+                &format!("SELECT {CLIENT_TOKEN_COLUMNS} FROM client_tokens WHERE id = ?1"),
+                params![id],
+                client_token_from_row,
+            )
+            .optional()?
+        else {
+            return Err(anyhow::anyhow!("client token not found: {id}"));
+        };
+
+        if let Some(v) = input.name {
+            token.name = v;
+        }
+        if let Some(v) = input.role {
+            token.role = v;
+        }
+        if let Some(v) = input.protocols {
+            token.protocols = v;
+        }
+        if let Some(v) = input.route_ids {
+            token.route_ids = v;
+        }
+        if let Some(v) = input.enabled {
+            token.enabled = v;
+        }
+        if let Some(v) = input.expires_at_ms {
+            token.expires_at_ms = v;
+        }
+        if let Some(v) = input.requests_per_minute {
+            token.requests_per_minute = v;
+        }
+        if let Some(v) = input.tokens_per_day {
+            token.tokens_per_day = v;
+        }
+        if let Some(v) = input.usd_per_month {
+            token.usd_per_month = v;
+        }
+
+        conn.execute(
+            r#"
+            UPDATE client_tokens
+            SET name = ?2, role = ?3, protocols = ?4, route_ids = ?5, enabled = ?6, expires_at_ms = ?7,
+                requests_per_minute = ?8, tokens_per_day = ?9, usd_per_month = ?10, updated_at_ms = ?11
+            WHERE id = ?1
+            "#,
+            params![
+                token.id,
+                token.name,
+                token.role.as_str(),
+                serde_json::to_string(&token.protocols)?,
+                serde_json::to_string(&token.route_ids)?,
+                if token.enabled { 1 } else { 0 },
+                token.expires_at_ms,
+                token.requests_per_minute,
+                token.tokens_per_day,
+                token.usd_per_month,
+                now_ms(),
+            ],
+        )?;
+        Ok(())
+    })
+    .await
+}
+
+pub async fn delete_client_token(db_path: PathBuf, id: String) -> anyhow::Result<()> {
+    with_conn(db_path, move |conn| {
+        let deleted = conn.execute(r#"DELETE FROM client_tokens WHERE id = ?1"#, params![id])?;
+        if deleted == 0 {
+            return Err(anyhow::anyhow!("client token not found"));
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ClientTokenUsage {
+    pub tokens_today: i64,
+    pub cost_usd_month: f64,
+}
+
+// 按已落库的用量统计；进行中的请求尚未计入，并发时可能略微超出配额
+pub async fn client_token_usage(
+    db_path: PathBuf,
+    token_id: Option<String>,
+    day_start_ms: i64,
+    month_start_ms: i64,
+) -> anyhow::Result<HashMap<String, ClientTokenUsage>> {
+    with_conn(db_path, move |conn| {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT client_token_id,
+                   COALESCE(SUM(CASE WHEN ts_ms >= ?2
+                     THEN COALESCE(total_tokens, COALESCE(prompt_tokens, 0) + COALESCE(completion_tokens, 0))
+                     ELSE 0 END), 0),
+                   COALESCE(SUM(CAST(estimated_cost_usd AS REAL)), 0)
+            FROM usage_events
+            WHERE client_token_id IS NOT NULL
+              AND (?1 IS NULL OR client_token_id = ?1)
+              AND ts_ms >= ?3
+            GROUP BY client_token_id
+            "#,
+        )?;
+        let rows = stmt.query_map(params![token_id, day_start_ms, month_start_ms], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                ClientTokenUsage {
+                    tokens_today: row.get(1)?,
+                    cost_usd_month: row.get(2)?,
+                },
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<HashMap<_, _>>>()
+            .map_err(Into::into)
+    })
+    .await
+}
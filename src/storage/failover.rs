@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+// 状态码规则：精确值（如 "429"）或整段（如 "5xx"）
+fn status_matches(rule: &str, status: u16) -> bool {
+    let rule = rule.trim();
+    if let Some(class) = rule.strip_suffix("xx").or_else(|| rule.strip_suffix("XX")) {
+        return class.parse::<u16>().is_ok_and(|c| status / 100 == c);
+    }
+    rule.parse::<u16>().is_ok_and(|v| v == status)
+}
+
+fn rule_is_valid(rule: &str) -> bool {
+    let rule = rule.trim();
+    match rule.strip_suffix("xx").or_else(|| rule.strip_suffix("XX")) {
+        Some(class) => class.len() == 1 && class.parse::<u16>().is_ok_and(|c| (1..=5).contains(&c)),
+        None => rule.len() == 3 && rule.parse::<u16>().is_ok_and(|v| (100..=599).contains(&v)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailoverPolicy {
+    // 命中时切换到下一个渠道；未命中的非 2xx 直接返回给客户端
+    pub retry_statuses: Vec<String>,
+    // 命中时计入渠道失败（自动禁用）
+    pub failure_statuses: Vec<String>,
+    // 连接失败、超时等传输层错误
+    pub retry_transport_errors: bool,
+    pub count_transport_errors: bool,
+    // 上游带 Retry-After 时，在该时长内把渠道排到最后
+    pub honor_retry_after: bool,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        let statuses = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        Self {
+            retry_statuses: statuses(&["401", "402", "403", "404", "408", "429", "5xx"]),
+            failure_statuses: statuses(&["401", "402", "403", "408", "429", "5xx"]),
+            retry_transport_errors: true,
+            count_transport_errors: true,
+            honor_retry_after: true,
+        }
+    }
+}
+
+impl FailoverPolicy {
+    pub fn should_retry(&self, status: u16) -> bool {
+        self.retry_statuses
+            .iter()
+            .any(|r| status_matches(r, status))
+    }
+
+    pub fn counts_as_failure(&self, status: u16) -> bool {
+        self.failure_statuses
+            .iter()
+            .any(|r| status_matches(r, status))
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(bad) = self
+            .retry_statuses
+            .iter()
+            .chain(self.failure_statuses.iter())
+            .find(|r| !rule_is_valid(r))
+        {
+            anyhow::bail!("failover 状态码规则非法：{bad}（支持如 429 或 5xx）");
+        }
+        Ok(())
+    }
+}
+
+// 各项超时，单位毫秒，0 表示不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutPolicy {
+    pub connect_ms: i64,
+    // 发出请求到收到响应头
+    pub ttfb_ms: i64,
+    // 响应体相邻两段数据之间
+    pub idle_ms: i64,
+    // 单次尝试从发出到响应体读完
+    pub total_ms: i64,
+}
+
+impl Default for TimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            connect_ms: 10_000,
+            ttfb_ms: 300_000,
+            idle_ms: 120_000,
+            total_ms: 0,
+        }
+    }
+}
+
+impl TimeoutPolicy {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, v) in [
+            ("connect_ms", self.connect_ms),
+            ("ttfb_ms", self.ttfb_ms),
+            ("idle_ms", self.idle_ms),
+            ("total_ms", self.total_ms),
+        ] {
+            if !(0..=3_600_000).contains(&v) {
+                anyhow::bail!("timeouts.{name} 必须在 0..=3600000 之间");
+            }
+        }
+        Ok(())
+    }
+}
+
+// 客户端侧的令牌桶限速，按上游套餐的 RPM / TPM 填写；留空表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitPolicy {
+    pub requests_per_minute: Option<i64>,
+    pub tokens_per_minute: Option<i64>,
+}
+
+impl RateLimitPolicy {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, v) in [
+            ("requests_per_minute", self.requests_per_minute),
+            ("tokens_per_minute", self.tokens_per_minute),
+        ] {
+            if v.is_some_and(|v| v < 1) {
+                anyhow::bail!("rate_limit.{name} 必须 >= 1");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests_per_minute.is_none() && self.tokens_per_minute.is_none()
+    }
+}
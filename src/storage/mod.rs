@@ -3,36 +3,66 @@ use rusqlite::{Connection, params};
 use serde::Serialize;
 use std::path::{Path, PathBuf};
 
+mod budget;
+mod capture;
 mod channel;
+mod channel_key;
+mod client_token;
+mod failover;
 mod pricing;
 mod protocol;
+mod response_cache;
+mod rollup;
 mod route;
+mod secret;
 mod settings;
 mod stats;
 mod usage;
 
+pub use budget::{ChannelSpend, SpendLimit, SpendLimitKind, channel_spend};
+pub use capture::{
+    CaptureResponse, CreateRequestCapture, RequestCapture, get_request_capture,
+    insert_request_capture, update_capture_response,
+};
 pub use channel::{
-    Channel, CreateChannel, RechargeCurrency, UpdateChannel, channel_is_auto_disabled,
+    Channel, CreateChannel, KeyStrategy, RechargeCurrency, UpdateChannel, channel_is_auto_disabled,
     clear_channel_failures, create_channel, delete_channel, get_channel, list_channels,
     record_channel_failure_and_maybe_disable, reorder_channels, set_channel_enabled,
     update_channel,
 };
+pub use channel_key::{
+    ChannelKeyState, KeyDisabled, clear_key_failures, key_fingerprint, list_channel_key_states,
+    mask_key, record_key_failure_and_maybe_disable, touch_channel_key,
+};
+pub use client_token::{
+    ClientToken, ClientTokenRole, ClientTokenUsage, CreateClientToken, CreatedClientToken,
+    UpdateClientToken, client_token_usage, create_client_token, delete_client_token,
+    find_client_token, list_client_tokens, touch_client_token, update_client_token,
+};
+pub use failover::{FailoverPolicy, RateLimitPolicy, TimeoutPolicy};
 pub use pricing::{
     PricingModel, PricingStatus, UpsertPricingModel, pricing_status, search_pricing_models,
     upsert_pricing_models,
 };
 pub use protocol::Protocol;
+pub use response_cache::{
+    CachedResponse, CreateCachedResponse, ResponseCacheStats, clear_response_cache,
+    get_cached_response, put_cached_response, response_cache_stats,
+};
 pub use route::{
-    CreateRoute, Route, RouteChannel, UpdateRoute, create_route, delete_route, get_route,
-    list_route_channels, list_routes, set_route_channels, update_route,
+    CreateRoute, ModelMatchKind, ModelPattern, Route, RouteChannel, UpdateRoute, create_route,
+    delete_route, get_route, list_route_channels, list_routes, set_route_channels, update_route,
 };
+pub use secret::{SECRET_PASSPHRASE_ENV, channel_secrets_encrypted, set_secret_passphrase};
 pub use settings::{
-    AppSettings, AppSettingsPatch, AutoStartLaunchMode, CloseBehavior, get_app_settings,
-    update_app_settings,
+    AppSettings, AppSettingsPatch, AutoStartLaunchMode, CloseBehavior, LoadBalanceMode,
+    USAGE_RETENTION_MIN_DAYS, get_app_settings, update_app_settings,
 };
 pub use stats::{
-    ChannelStats, StatsSummary, TrendPoint, stats_channels, stats_summary,
-    stats_trend_by_day_channel,
+    ChannelHealth, ChannelStats, GroupStats, Percentiles, StatsBucket, StatsGroupBy, StatsSummary,
+    StatsWindow, TrendPoint, channel_health_since, channel_latency_p95, current_local_offset_ms,
+    day_start_ms, local_day_start_ms, local_month_start_ms, month_start_ms, stats_breakdown,
+    stats_channels, stats_summary, stats_trend,
 };
 pub use usage::{
     CreateUsageEvent, UsageEvent, UsageListQuery, UsageListResult, backfill_usage_event_costs,
@@ -42,6 +72,9 @@ pub use usage::{
 pub fn init_db(db_path: &Path) -> anyhow::Result<()> {
     let conn = Connection::open(db_path).with_context(|| "打开 SQLite 文件失败")?;
 
+    // 只对新建的数据库立即生效，已有数据库需手动整理一次才会转换
+    conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL;")?;
+
     let migration = include_str!("../../migrations/001_init.sql");
     conn.execute_batch(migration)
         .with_context(|| "执行 migrations/001_init.sql 失败")?;
@@ -51,6 +84,23 @@ pub fn init_db(db_path: &Path) -> anyhow::Result<()> {
     ensure_app_settings_schema(&conn)?;
     ensure_pricing_models_schema(&conn)?;
     ensure_usage_events_schema(&conn)?;
+    ensure_client_tokens_schema(&conn)?;
+
+    let backfilled = rollup::backfill(&conn).with_context(|| "回填用量汇总表失败")?;
+    if backfilled > 0 {
+        tracing::info!(count = backfilled, "backfilled usage rollups");
+    }
+
+    let has_encrypted = channel::has_encrypted_auth_refs(&conn)?;
+    let key = secret::open(db_path, has_encrypted).with_context(|| "读取渠道密钥的加密密钥失败")?;
+    if let Some(key) = key {
+        channel::check_auth_refs(&conn, &key)?;
+        let migrated = channel::encrypt_plaintext_auth_refs(&conn, &key)
+            .with_context(|| "加密渠道密钥失败")?;
+        if migrated > 0 {
+            tracing::info!(count = migrated, "encrypted plaintext channel secrets");
+        }
+    }
 
     Ok(())
 }
@@ -75,6 +125,25 @@ fn ensure_channels_schema(conn: &Connection) -> anyhow::Result<()> {
         "auto_disabled_until_ms",
         "INTEGER NOT NULL DEFAULT 0",
     )?;
+    ensure_column(conn, "channels", "model_map", "TEXT NOT NULL DEFAULT '{}'")?;
+    ensure_column(conn, "channels", "weight", "INTEGER NOT NULL DEFAULT 1")?;
+    ensure_column(
+        conn,
+        "channels",
+        "key_strategy",
+        "TEXT NOT NULL DEFAULT 'round_robin'",
+    )?;
+    ensure_column(conn, "channels", "failover_policy", "TEXT NULL")?;
+    ensure_column(conn, "channels", "timeouts", "TEXT NULL")?;
+    ensure_column(conn, "channels", "proxy_url", "TEXT NULL")?;
+    ensure_column(conn, "channels", "spend_limit", "TEXT NULL")?;
+    ensure_column(
+        conn,
+        "channels",
+        "balance_spent_usd",
+        "REAL NOT NULL DEFAULT 0",
+    )?;
+    ensure_column(conn, "channels", "rate_limit", "TEXT NULL")?;
     Ok(())
 }
 
@@ -93,6 +162,7 @@ fn ensure_channel_failures_schema(conn: &Connection) -> anyhow::Result<()> {
         r#"CREATE INDEX IF NOT EXISTS idx_channel_failures_channel_ts ON channel_failures(channel_id, at_ms)"#,
         [],
     )?;
+    ensure_column(conn, "channel_failures", "key_fp", "TEXT NULL")?;
     Ok(())
 }
 
@@ -122,10 +192,41 @@ fn ensure_usage_events_schema(conn: &Connection) -> anyhow::Result<()> {
     ensure_column(conn, "usage_events", "error_detail", "TEXT NULL")?;
     ensure_column(conn, "usage_events", "cache_read_tokens", "INTEGER NULL")?;
     ensure_column(conn, "usage_events", "cache_write_tokens", "INTEGER NULL")?;
+    ensure_column(conn, "usage_events", "upstream_model", "TEXT NULL")?;
+    ensure_column(conn, "usage_events", "key_fingerprint", "TEXT NULL")?;
+    ensure_column(
+        conn,
+        "usage_events",
+        "cancelled",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    ensure_column(conn, "usage_events", "client_token_id", "TEXT NULL")?;
+    ensure_column(
+        conn,
+        "usage_events",
+        "cache_hit",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_usage_request_ts ON usage_events(request_id, ts_ms)",
         [],
     )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_usage_client_token_ts ON usage_events(client_token_id, ts_ms)",
+        [],
+    )?;
+    // 按保留期清空错误详情时只扫描尚未清空的记录
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_usage_error_detail_ts ON usage_events(ts_ms) WHERE error_detail IS NOT NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+fn ensure_client_tokens_schema(conn: &Connection) -> anyhow::Result<()> {
+    ensure_column(conn, "client_tokens", "requests_per_minute", "INTEGER NULL")?;
+    ensure_column(conn, "client_tokens", "tokens_per_day", "INTEGER NULL")?;
+    ensure_column(conn, "client_tokens", "usd_per_month", "REAL NULL")?;
     Ok(())
 }
 
@@ -182,6 +283,31 @@ pub async fn clear_records(
     with_conn(db_path, move |conn| {
         conn.busy_timeout(std::time::Duration::from_secs(5))?;
 
+        // 抓包随请求记录一起清理；故障转移后最终成功的请求保留
+        match kind {
+            RecordsClearKind::DateRange { start_ms, end_ms } => conn.execute(
+                r#"DELETE FROM request_captures WHERE ts_ms >= ?1 AND ts_ms <= ?2"#,
+                params![start_ms, end_ms],
+            )?,
+            RecordsClearKind::Errors => conn.execute(
+                r#"
+                DELETE FROM request_captures
+                WHERE request_id IN (SELECT request_id FROM usage_events WHERE success = 0)
+                  AND request_id NOT IN (
+                    SELECT request_id FROM usage_events WHERE success = 1 AND request_id IS NOT NULL
+                  )
+                "#,
+                [],
+            )?,
+            RecordsClearKind::All => conn.execute(r#"DELETE FROM request_captures"#, [])?,
+        };
+
+        // 汇总表用剩余的原始记录重算；原始记录已不存在的更早时段保持不变
+        let oldest_ms: Option<i64> =
+            conn.query_row(r#"SELECT MIN(ts_ms) FROM usage_events"#, [], |row| {
+                row.get(0)
+            })?;
+
         let usage_events_deleted: i64 = match kind {
             RecordsClearKind::DateRange { start_ms, end_ms } => conn
                 .execute(
@@ -200,6 +326,20 @@ pub async fn clear_records(
                 .unwrap_or(i64::MAX),
         };
 
+        let tx = conn.unchecked_transaction()?;
+        match kind {
+            RecordsClearKind::DateRange { start_ms, end_ms } => {
+                rollup::rebuild(&tx, Some(start_ms), Some(end_ms.saturating_add(1)))?;
+            }
+            RecordsClearKind::Errors => {
+                if let Some(oldest_ms) = oldest_ms {
+                    rollup::rebuild(&tx, Some(oldest_ms), None)?;
+                }
+            }
+            RecordsClearKind::All => rollup::clear(&tx)?,
+        }
+        tx.commit()?;
+
         let channel_failures_deleted: i64 = match kind {
             RecordsClearKind::DateRange { start_ms, end_ms } => conn
                 .execute(
@@ -229,6 +369,212 @@ pub async fn clear_records(
     .await
 }
 
+// 按保留期清理的截止时间，None 表示该类记录不清理
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecordsRetention {
+    pub usage_events_before_ms: Option<i64>,
+    pub error_detail_before_ms: Option<i64>,
+    pub channel_failures_before_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PruneRecordsResult {
+    pub usage_events_deleted: i64,
+    pub request_captures_deleted: i64,
+    pub error_details_cleared: i64,
+    pub channel_failures_deleted: i64,
+    pub freed_bytes: i64,
+}
+
+// 每批处理的行数；批与批之间释放写锁，避免长时间阻塞代理写入用量
+const PRUNE_BATCH_ROWS: i64 = 2000;
+const VACUUM_BATCH_PAGES: i64 = 1000;
+const AUTO_VACUUM_INCREMENTAL: i64 = 2;
+
+fn auto_vacuum_mode(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))
+}
+
+fn free_bytes(conn: &Connection) -> rusqlite::Result<i64> {
+    let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    let free_pages: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+    Ok(free_pages * page_size)
+}
+
+// 分批归还空闲页，返回实际释放的字节数
+fn incremental_vacuum(conn: &Connection) -> rusqlite::Result<i64> {
+    let before = free_bytes(conn)?;
+    let mut remaining = before;
+    while remaining > 0 {
+        let mut stmt = conn.prepare(&format!("PRAGMA incremental_vacuum({VACUUM_BATCH_PAGES})"))?;
+        let mut rows = stmt.query([])?;
+        while rows.next()?.is_some() {}
+        let now = free_bytes(conn)?;
+        if now >= remaining {
+            break;
+        }
+        remaining = now;
+    }
+    Ok(before - remaining)
+}
+
+fn run_in_batches(conn: &Connection, sql: &str, before_ms: i64) -> rusqlite::Result<i64> {
+    let mut total = 0i64;
+    loop {
+        let n = conn.execute(sql, params![before_ms, PRUNE_BATCH_ROWS])? as i64;
+        total += n;
+        if n < PRUNE_BATCH_ROWS {
+            return Ok(total);
+        }
+    }
+}
+
+// 汇总表不受影响，历史统计仍然完整
+pub async fn prune_records(
+    db_path: PathBuf,
+    retention: RecordsRetention,
+) -> anyhow::Result<PruneRecordsResult> {
+    with_conn(db_path, move |conn| {
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        let mut out = PruneRecordsResult::default();
+
+        if let Some(before_ms) = retention.usage_events_before_ms {
+            out.usage_events_deleted = run_in_batches(
+                conn,
+                r#"
+                DELETE FROM usage_events
+                WHERE rowid IN (SELECT rowid FROM usage_events WHERE ts_ms < ?1 LIMIT ?2)
+                "#,
+                before_ms,
+            )?;
+            out.request_captures_deleted = run_in_batches(
+                conn,
+                r#"
+                DELETE FROM request_captures
+                WHERE rowid IN (SELECT rowid FROM request_captures WHERE ts_ms < ?1 LIMIT ?2)
+                "#,
+                before_ms,
+            )?;
+        }
+        if let Some(before_ms) = retention.error_detail_before_ms {
+            out.error_details_cleared = run_in_batches(
+                conn,
+                r#"
+                UPDATE usage_events SET error_detail = NULL
+                WHERE error_detail IS NOT NULL AND rowid IN (
+                  SELECT rowid FROM usage_events
+                  WHERE ts_ms < ?1 AND error_detail IS NOT NULL
+                  LIMIT ?2
+                )
+                "#,
+                before_ms,
+            )?;
+        }
+        if let Some(before_ms) = retention.channel_failures_before_ms {
+            out.channel_failures_deleted = run_in_batches(
+                conn,
+                r#"
+                DELETE FROM channel_failures
+                WHERE id IN (SELECT id FROM channel_failures WHERE at_ms < ?1 LIMIT ?2)
+                "#,
+                before_ms,
+            )?;
+        }
+
+        let touched = out.usage_events_deleted
+            + out.request_captures_deleted
+            + out.error_details_cleared
+            + out.channel_failures_deleted;
+        // 旧版本创建的数据库不是增量模式，需要在维护页手动整理一次
+        if touched > 0 && auto_vacuum_mode(conn)? == AUTO_VACUUM_INCREMENTAL {
+            out.freed_bytes = incremental_vacuum(conn)?;
+        }
+        Ok(out)
+    })
+    .await
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableSize {
+    pub name: String,
+    pub rows: i64,
+    // 含该表所有索引占用的页
+    pub bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DbTableSizes {
+    pub tables: Vec<TableSize>,
+    // 已释放但尚未归还文件系统的页
+    pub free_bytes: i64,
+    // 为 false 时按保留期清理后不会自动归还空间，需要手动整理一次
+    pub incremental_vacuum: bool,
+}
+
+pub async fn db_table_sizes(db_path: PathBuf) -> anyhow::Result<DbTableSizes> {
+    with_conn(db_path, move |conn| {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT m.tbl_name, SUM(d.pgsize) AS bytes
+            FROM dbstat d
+            JOIN sqlite_master m ON m.name = d.name
+            WHERE m.tbl_name NOT LIKE 'sqlite_%'
+            GROUP BY m.tbl_name
+            ORDER BY bytes DESC, m.tbl_name ASC
+            "#,
+        )?;
+        let sizes = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut tables = Vec::with_capacity(sizes.len());
+        for (name, bytes) in sizes {
+            let rows: i64 = conn.query_row(
+                &format!(r#"SELECT COUNT(*) FROM "{}""#, name.replace('"', "\"\"")),
+                [],
+                |row| row.get(0),
+            )?;
+            tables.push(TableSize { name, rows, bytes });
+        }
+
+        Ok(DbTableSizes {
+            tables,
+            free_bytes: free_bytes(conn)?,
+            incremental_vacuum: auto_vacuum_mode(conn)? == AUTO_VACUUM_INCREMENTAL,
+        })
+    })
+    .await
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompactDbResult {
+    pub before_bytes: i64,
+    pub after_bytes: i64,
+}
+
+// 完整 VACUUM 并切换到增量模式；期间持有写锁，只由用户在维护页手动触发
+pub async fn compact_database(db_path: PathBuf) -> anyhow::Result<CompactDbResult> {
+    with_conn(db_path, move |conn| {
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        let size = |conn: &Connection| -> rusqlite::Result<i64> {
+            let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+            let pages: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+            Ok(page_size * pages)
+        };
+        let before_bytes = size(conn)?;
+        conn.execute_batch(
+            "PRAGMA wal_checkpoint(TRUNCATE); PRAGMA auto_vacuum = INCREMENTAL; VACUUM;",
+        )?;
+        Ok(CompactDbResult {
+            before_bytes,
+            after_bytes: size(conn)?,
+        })
+    })
+    .await
+}
+
 async fn with_conn<T, F>(db_path: PathBuf, f: F) -> anyhow::Result<T>
 where
     T: Send + 'static,
@@ -17,6 +17,24 @@ impl Protocol {
             Protocol::Gemini => "gemini",
         }
     }
+
+    pub fn root(self) -> &'static str {
+        match self {
+            Protocol::Openai | Protocol::Anthropic => "/v1",
+            Protocol::Gemini => "/v1beta",
+        }
+    }
+
+    // 该入站协议的路由可挂载哪些协议的渠道（跨协议时由 proxy 负责转换）
+    pub fn accepts_channel(self, channel: Protocol) -> bool {
+        self == channel
+            || matches!(
+                (self, channel),
+                (Protocol::Openai, Protocol::Anthropic | Protocol::Gemini)
+                    | (Protocol::Anthropic, Protocol::Openai)
+                    | (Protocol::Gemini, Protocol::Openai | Protocol::Anthropic)
+            )
+    }
 }
 
 impl std::fmt::Display for Protocol {
@@ -46,13 +64,6 @@ impl FromSql for Protocol {
     }
 }
 
-fn protocol_root(protocol: Protocol) -> &'static str {
-    match protocol {
-        Protocol::Openai | Protocol::Anthropic => "/v1",
-        Protocol::Gemini => "/v1beta",
-    }
-}
-
 pub(crate) fn normalize_base_url(protocol: Protocol, base_url: &str) -> String {
     let base_url = base_url.trim();
     let (without_fragment, fragment) = match base_url.split_once('#') {
@@ -64,7 +75,7 @@ pub(crate) fn normalize_base_url(protocol: Protocol, base_url: &str) -> String {
         None => (without_fragment, None),
     };
 
-    let root = protocol_root(protocol);
+    let root = protocol.root();
     let without_query = without_query.trim_end_matches('/');
     let normalized = if without_query.ends_with(root) {
         without_query[..without_query.len().saturating_sub(root.len())]
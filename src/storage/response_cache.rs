@@ -0,0 +1,149 @@
+use rusqlite::{OptionalExtension as _, params};
+use serde::Serialize;
+use std::path::PathBuf;
+
+use super::{Protocol, with_conn};
+
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    // 产生该响应的渠道，命中时记到它名下
+    pub channel_id: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateCachedResponse {
+    pub cache_key: String,
+    pub protocol: Protocol,
+    pub model: Option<String>,
+    pub channel_id: String,
+    pub body: Vec<u8>,
+    pub ttl_ms: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResponseCacheStats {
+    pub entries: i64,
+    pub size_bytes: i64,
+    pub hits: i64,
+}
+
+pub async fn get_cached_response(
+    db_path: PathBuf,
+    cache_key: String,
+    now_ms: i64,
+) -> anyhow::Result<Option<CachedResponse>> {
+    with_conn(db_path, move |conn| {
+        let found = conn
+            .query_row(
+                r#"
+                SELECT channel_id, body
+                FROM response_cache
+                WHERE cache_key = ?1 AND expires_at_ms > ?2
+                "#,
+                params![cache_key, now_ms],
+                |row| {
+                    Ok(CachedResponse {
+                        channel_id: row.get(0)?,
+                        body: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+        if found.is_some() {
+            conn.execute(
+                r#"UPDATE response_cache SET hits = hits + 1, last_hit_ms = ?2 WHERE cache_key = ?1"#,
+                params![cache_key, now_ms],
+            )?;
+        }
+        Ok(found)
+    })
+    .await
+}
+
+// 写入后清理过期条目，超出容量时按最近使用时间淘汰
+pub async fn put_cached_response(
+    db_path: PathBuf,
+    input: CreateCachedResponse,
+    now_ms: i64,
+    max_bytes: i64,
+) -> anyhow::Result<()> {
+    with_conn(db_path, move |conn| {
+        let size = input.body.len() as i64;
+        if size > max_bytes {
+            return Ok(());
+        }
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            r#"
+            INSERT OR REPLACE INTO response_cache (
+              cache_key, protocol, model, channel_id, body, size_bytes, hits, created_at_ms, expires_at_ms, last_hit_ms
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, ?7, ?8, NULL)
+            "#,
+            params![
+                input.cache_key,
+                input.protocol.as_str(),
+                input.model,
+                input.channel_id,
+                input.body,
+                size,
+                now_ms,
+                now_ms.saturating_add(input.ttl_ms),
+            ],
+        )?;
+        tx.execute(
+            r#"DELETE FROM response_cache WHERE expires_at_ms <= ?1"#,
+            params![now_ms],
+        )?;
+        tx.execute(
+            r#"
+            DELETE FROM response_cache
+            WHERE cache_key IN (
+              SELECT cache_key FROM (
+                SELECT
+                  cache_key,
+                  SUM(size_bytes) OVER (
+                    ORDER BY COALESCE(last_hit_ms, created_at_ms) DESC, created_at_ms DESC
+                  ) AS running
+                FROM response_cache
+              )
+              WHERE running > ?1
+            )
+            "#,
+            params![max_bytes],
+        )?;
+        tx.commit()?;
+        Ok(())
+    })
+    .await
+}
+
+pub async fn response_cache_stats(db_path: PathBuf) -> anyhow::Result<ResponseCacheStats> {
+    with_conn(db_path, move |conn| {
+        conn.query_row(
+            r#"
+            SELECT COUNT(*), COALESCE(SUM(size_bytes), 0), COALESCE(SUM(hits), 0)
+            FROM response_cache
+            "#,
+            [],
+            |row| {
+                Ok(ResponseCacheStats {
+                    entries: row.get(0)?,
+                    size_bytes: row.get(1)?,
+                    hits: row.get(2)?,
+                })
+            },
+        )
+        .map_err(Into::into)
+    })
+    .await
+}
+
+pub async fn clear_response_cache(db_path: PathBuf) -> anyhow::Result<i64> {
+    with_conn(db_path, move |conn| {
+        let deleted = conn.execute(r#"DELETE FROM response_cache"#, [])?;
+        Ok(deleted as i64)
+    })
+    .await
+}
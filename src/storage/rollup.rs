@@ -0,0 +1,602 @@
+use rusqlite::{Connection, OptionalExtension as _, params};
+use std::collections::{BTreeMap, HashMap};
+
+use super::stats::{HOUR_MS, StatsBucket, StatsWindow};
+
+// 耗时直方图的桶上界（毫秒），末尾另有一个溢出桶
+const HIST_BOUNDS_MS: &[i64] = &[
+    10, 20, 30, 40, 50, 60, 80, 100, 150, 200, 300, 400, 500, 600, 800, 1_000, 1_500, 2_000, 3_000,
+    4_000, 5_000, 6_000, 8_000, 10_000, 15_000, 20_000, 30_000, 40_000, 50_000, 60_000, 80_000,
+    100_000, 150_000, 200_000, 300_000, 600_000,
+];
+
+// 按渠道、模型、协议、路由预聚合到整点小时的用量，写入用量记录时同步累加。
+// 只有小时表：按天、按月的统计在查询时由小时行合并，非整点时区的边界用原始记录补齐
+const ROLLUP_TABLE: &str = "usage_rollup_hourly";
+
+fn bucket_start_ms(ts_ms: i64) -> i64 {
+    ts_ms.div_euclid(HOUR_MS) * HOUR_MS
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct Histogram {
+    counts: Vec<i64>,
+    max_ms: i64,
+}
+
+impl Histogram {
+    fn from_db(text: &str, max_ms: i64) -> Self {
+        Self {
+            counts: serde_json::from_str(text).unwrap_or_default(),
+            max_ms,
+        }
+    }
+
+    fn to_db(&self) -> String {
+        serde_json::to_string(&self.counts).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    fn observe(&mut self, v: i64) {
+        let idx = HIST_BOUNDS_MS.partition_point(|b| *b < v);
+        if self.counts.len() <= idx {
+            self.counts.resize(HIST_BOUNDS_MS.len() + 1, 0);
+        }
+        self.counts[idx] += 1;
+        self.max_ms = self.max_ms.max(v);
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            *a += b;
+        }
+        self.max_ms = self.max_ms.max(other.max_ms);
+    }
+
+    // 最近秩所在桶的上界，不超过观测到的最大值
+    pub(super) fn percentile(&self, pct: u64) -> Option<i64> {
+        let total = self.counts.iter().sum::<i64>().max(0) as u64;
+        if total == 0 {
+            return None;
+        }
+        let rank = (total * pct).div_ceil(100).max(1) as i64;
+        let mut seen = 0;
+        for (idx, n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let bound = HIST_BOUNDS_MS.get(idx).copied().unwrap_or(self.max_ms);
+                return Some(bound.min(self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+}
+
+// requests 按请求计（同一 request_id 只算首条记录），attempts 按尝试计且不含被取消的对冲请求
+#[derive(Debug, Clone, Default)]
+pub(super) struct Rollup {
+    pub(super) requests: i64,
+    pub(super) requests_success: i64,
+    pub(super) attempts: i64,
+    pub(super) attempts_success: i64,
+    pub(super) latency_sum_ms: i64,
+    pub(super) latency_count: i64,
+    pub(super) prompt_tokens: i64,
+    pub(super) completion_tokens: i64,
+    pub(super) total_tokens: i64,
+    pub(super) cost_usd: f64,
+    pub(super) latency: Histogram,
+    pub(super) ttft: Histogram,
+}
+
+impl Rollup {
+    pub(super) fn avg_latency_ms(&self) -> Option<f64> {
+        (self.latency_count > 0).then(|| self.latency_sum_ms as f64 / self.latency_count as f64)
+    }
+
+    fn merge(&mut self, o: &Rollup) {
+        self.requests += o.requests;
+        self.requests_success += o.requests_success;
+        self.attempts += o.attempts;
+        self.attempts_success += o.attempts_success;
+        self.latency_sum_ms += o.latency_sum_ms;
+        self.latency_count += o.latency_count;
+        self.prompt_tokens += o.prompt_tokens;
+        self.completion_tokens += o.completion_tokens;
+        self.total_tokens += o.total_tokens;
+        self.cost_usd += o.cost_usd;
+        self.latency.merge(&o.latency);
+        self.ttft.merge(&o.ttft);
+    }
+
+    fn observe(&mut self, ev: &RollupEvent, first_of_request: bool, first_success: bool) {
+        if first_of_request {
+            self.requests += 1;
+        }
+        if first_success {
+            self.requests_success += 1;
+        }
+        if !ev.cancelled {
+            self.attempts += 1;
+            if ev.success {
+                self.attempts_success += 1;
+            }
+            if ev.latency_ms > 0 {
+                self.latency_sum_ms += ev.latency_ms;
+                self.latency_count += 1;
+            }
+        }
+        self.prompt_tokens += ev.prompt_tokens.unwrap_or(0);
+        self.completion_tokens += ev.completion_tokens.unwrap_or(0);
+        self.total_tokens += ev.total_tokens.unwrap_or(0);
+        self.cost_usd += ev.cost_usd.filter(|v| v.is_finite()).unwrap_or(0.0);
+        // 分位数只看成功且真正请求了上游的记录
+        if ev.success && !ev.cancelled && !ev.cache_hit {
+            if ev.latency_ms > 0 {
+                self.latency.observe(ev.latency_ms);
+            }
+            if let Some(t) = ev.ttft_ms.filter(|t| *t >= 0) {
+                self.ttft.observe(t);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct RollupKey {
+    pub(super) channel_id: String,
+    pub(super) model: String,
+    pub(super) protocol: String,
+    pub(super) route_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct RollupEvent {
+    pub(super) request_id: Option<String>,
+    pub(super) ts_ms: i64,
+    pub(super) key: RollupKey,
+    pub(super) success: bool,
+    pub(super) cancelled: bool,
+    pub(super) cache_hit: bool,
+    pub(super) latency_ms: i64,
+    pub(super) ttft_ms: Option<i64>,
+    pub(super) prompt_tokens: Option<i64>,
+    pub(super) completion_tokens: Option<i64>,
+    pub(super) total_tokens: Option<i64>,
+    pub(super) cost_usd: Option<f64>,
+}
+
+fn upsert(
+    conn: &Connection,
+    bucket_start_ms: i64,
+    key: &RollupKey,
+    delta: &Rollup,
+) -> rusqlite::Result<()> {
+    let name = ROLLUP_TABLE;
+    let existing = conn
+        .query_row(
+            &format!(
+                r#"
+                SELECT latency_hist, latency_max_ms, ttft_hist, ttft_max_ms
+                FROM {name}
+                WHERE bucket_start_ms = ?1 AND channel_id = ?2 AND model = ?3 AND protocol = ?4 AND route_id = ?5
+                "#
+            ),
+            params![
+                bucket_start_ms,
+                key.channel_id,
+                key.model,
+                key.protocol,
+                key.route_id
+            ],
+            |row| {
+                Ok((
+                    Histogram::from_db(&row.get::<_, String>(0)?, row.get(1)?),
+                    Histogram::from_db(&row.get::<_, String>(2)?, row.get(3)?),
+                ))
+            },
+        )
+        .optional()?;
+    let (mut latency, mut ttft) = existing.unwrap_or_default();
+    latency.merge(&delta.latency);
+    ttft.merge(&delta.ttft);
+
+    conn.execute(
+        &format!(
+            r#"
+            INSERT INTO {name} (
+              bucket_start_ms, channel_id, model, protocol, route_id,
+              requests, requests_success, attempts, attempts_success,
+              latency_sum_ms, latency_count, prompt_tokens, completion_tokens, total_tokens,
+              cost_usd, latency_hist, latency_max_ms, ttft_hist, ttft_max_ms
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)
+            ON CONFLICT(bucket_start_ms, channel_id, model, protocol, route_id) DO UPDATE SET
+              requests = requests + excluded.requests,
+              requests_success = requests_success + excluded.requests_success,
+              attempts = attempts + excluded.attempts,
+              attempts_success = attempts_success + excluded.attempts_success,
+              latency_sum_ms = latency_sum_ms + excluded.latency_sum_ms,
+              latency_count = latency_count + excluded.latency_count,
+              prompt_tokens = prompt_tokens + excluded.prompt_tokens,
+              completion_tokens = completion_tokens + excluded.completion_tokens,
+              total_tokens = total_tokens + excluded.total_tokens,
+              cost_usd = cost_usd + excluded.cost_usd,
+              latency_hist = excluded.latency_hist,
+              latency_max_ms = excluded.latency_max_ms,
+              ttft_hist = excluded.ttft_hist,
+              ttft_max_ms = excluded.ttft_max_ms
+            "#
+        ),
+        params![
+            bucket_start_ms,
+            key.channel_id,
+            key.model,
+            key.protocol,
+            key.route_id,
+            delta.requests,
+            delta.requests_success,
+            delta.attempts,
+            delta.attempts_success,
+            delta.latency_sum_ms,
+            delta.latency_count,
+            delta.prompt_tokens,
+            delta.completion_tokens,
+            delta.total_tokens,
+            delta.cost_usd,
+            latency.to_db(),
+            latency.max_ms,
+            ttft.to_db(),
+            ttft.max_ms,
+        ],
+    )?;
+    Ok(())
+}
+
+// 在写入用量记录的同一事务中调用；attempt_index 为该记录在请求内的序号，
+// 同一请求最多只有一条成功记录，因此成功即为首条成功
+pub(super) fn record_event(
+    conn: &Connection,
+    ev: &RollupEvent,
+    attempt_index: usize,
+) -> rusqlite::Result<()> {
+    let mut delta = Rollup::default();
+    delta.observe(ev, attempt_index == 0, ev.success);
+    upsert(conn, bucket_start_ms(ev.ts_ms), &ev.key, &delta)
+}
+
+// 费用回填只会把 NULL 改成估算值，直接累加即可
+pub(super) fn add_cost(
+    conn: &Connection,
+    ts_ms: i64,
+    key: &RollupKey,
+    cost_usd: f64,
+) -> rusqlite::Result<()> {
+    if !cost_usd.is_finite() {
+        return Ok(());
+    }
+    conn.execute(
+        &format!(
+            r#"
+            UPDATE {ROLLUP_TABLE}
+            SET cost_usd = cost_usd + ?1
+            WHERE bucket_start_ms = ?2 AND channel_id = ?3 AND model = ?4 AND protocol = ?5 AND route_id = ?6
+            "#
+        ),
+        params![
+            cost_usd,
+            bucket_start_ms(ts_ms),
+            key.channel_id,
+            key.model,
+            key.protocol,
+            key.route_id
+        ],
+    )?;
+    Ok(())
+}
+
+pub(super) fn clear(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(&format!("DELETE FROM {ROLLUP_TABLE}"), [])?;
+    Ok(())
+}
+
+// 与 event_from_row 的列顺序一致
+const EVENT_COLUMNS: &str = r#"
+  request_id, ts_ms, channel_id, COALESCE(model, ''), protocol, COALESCE(route_id, ''),
+  success, cancelled, cache_hit, latency_ms, ttft_ms,
+  prompt_tokens, completion_tokens, total_tokens, CAST(estimated_cost_usd AS REAL)
+"#;
+
+// 与 rollup_from_row 的列顺序一致
+const ROLLUP_COLUMNS: &str = r#"
+  u.requests, u.requests_success, u.attempts, u.attempts_success,
+  u.latency_sum_ms, u.latency_count, u.prompt_tokens, u.completion_tokens, u.total_tokens,
+  u.cost_usd, u.latency_hist, u.latency_max_ms, u.ttft_hist, u.ttft_max_ms
+"#;
+
+fn event_from_row(row: &rusqlite::Row<'_>, i: usize) -> rusqlite::Result<RollupEvent> {
+    Ok(RollupEvent {
+        request_id: row.get(i)?,
+        ts_ms: row.get(i + 1)?,
+        key: RollupKey {
+            channel_id: row.get(i + 2)?,
+            model: row.get(i + 3)?,
+            protocol: row.get(i + 4)?,
+            route_id: row.get(i + 5)?,
+        },
+        success: row.get::<_, i64>(i + 6)? != 0,
+        cancelled: row.get::<_, i64>(i + 7)? != 0,
+        cache_hit: row.get::<_, i64>(i + 8)? != 0,
+        latency_ms: row.get(i + 9)?,
+        ttft_ms: row.get(i + 10)?,
+        prompt_tokens: row.get(i + 11)?,
+        completion_tokens: row.get(i + 12)?,
+        total_tokens: row.get(i + 13)?,
+        cost_usd: row.get(i + 14)?,
+    })
+}
+
+fn rollup_from_row(row: &rusqlite::Row<'_>, i: usize) -> rusqlite::Result<Rollup> {
+    Ok(Rollup {
+        requests: row.get(i)?,
+        requests_success: row.get(i + 1)?,
+        attempts: row.get(i + 2)?,
+        attempts_success: row.get(i + 3)?,
+        latency_sum_ms: row.get(i + 4)?,
+        latency_count: row.get(i + 5)?,
+        prompt_tokens: row.get(i + 6)?,
+        completion_tokens: row.get(i + 7)?,
+        total_tokens: row.get(i + 8)?,
+        cost_usd: row.get(i + 9)?,
+        latency: Histogram::from_db(&row.get::<_, String>(i + 10)?, row.get(i + 11)?),
+        ttft: Histogram::from_db(&row.get::<_, String>(i + 12)?, row.get(i + 13)?),
+    })
+}
+
+// 在 from_ms 之前已出现过、且在 [from_ms, to_ms) 内还有记录的请求：request_id -> 是否已有成功记录
+fn prior_requests(
+    conn: &Connection,
+    from_ms: i64,
+    to_ms: Option<i64>,
+) -> rusqlite::Result<HashMap<String, bool>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT request_id, MAX(success)
+        FROM usage_events
+        WHERE ts_ms < ?1 AND request_id IN (
+          SELECT request_id FROM usage_events
+          WHERE ts_ms >= ?1 AND (?2 IS NULL OR ts_ms < ?2) AND request_id IS NOT NULL
+        )
+        GROUP BY request_id
+        "#,
+    )?;
+    let rows = stmt.query_map(params![from_ms, to_ms], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? != 0))
+    })?;
+    rows.collect()
+}
+
+// 按时间顺序逐条调用，返回 (是否为该请求的首条记录, 是否为该请求的首条成功记录)
+fn first_of_request(seen: &mut HashMap<String, bool>, ev: &RollupEvent) -> (bool, bool) {
+    let Some(rid) = ev.request_id.clone() else {
+        return (true, ev.success);
+    };
+    match seen.get_mut(&rid) {
+        Some(had_success) => {
+            let first_success = ev.success && !*had_success;
+            *had_success |= ev.success;
+            (false, first_success)
+        }
+        None => {
+            seen.insert(rid, ev.success);
+            (true, ev.success)
+        }
+    }
+}
+
+// 用原始记录重算 [from_ms, to_ms) 所覆盖的整点小时；
+// 已被清理的原始记录无法恢复，因此只应在这些小时内原始记录完整时调用
+pub(super) fn rebuild(
+    conn: &Connection,
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+) -> rusqlite::Result<i64> {
+    let from_ms = from_ms.map(bucket_start_ms);
+    let to_ms = to_ms.map(|v| bucket_start_ms(v - 1) + HOUR_MS);
+    conn.execute(
+        &format!(
+            r#"
+            DELETE FROM {ROLLUP_TABLE}
+            WHERE (?1 IS NULL OR bucket_start_ms >= ?1) AND (?2 IS NULL OR bucket_start_ms < ?2)
+            "#
+        ),
+        params![from_ms, to_ms],
+    )?;
+
+    let mut seen = match from_ms {
+        Some(from_ms) => prior_requests(conn, from_ms, to_ms)?,
+        None => HashMap::new(),
+    };
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT {EVENT_COLUMNS}
+        FROM usage_events
+        WHERE (?1 IS NULL OR ts_ms >= ?1) AND (?2 IS NULL OR ts_ms < ?2)
+        ORDER BY ts_ms ASC
+        "#
+    ))?;
+    let mut rows = stmt.query(params![from_ms, to_ms])?;
+    let mut acc: HashMap<(i64, RollupKey), Rollup> = HashMap::new();
+    let mut events = 0i64;
+    while let Some(row) = rows.next()? {
+        let ev = event_from_row(row, 0)?;
+        let (first, first_success) = first_of_request(&mut seen, &ev);
+        acc.entry((bucket_start_ms(ev.ts_ms), ev.key.clone()))
+            .or_default()
+            .observe(&ev, first, first_success);
+        events += 1;
+    }
+    for ((bucket_start_ms, key), delta) in &acc {
+        upsert(conn, *bucket_start_ms, key, delta)?;
+    }
+    Ok(events)
+}
+
+// 启动时执行：汇总表为空而原始记录存在（升级前的数据或汇总表被清空）时全量重算
+pub(super) fn backfill(conn: &Connection) -> rusqlite::Result<i64> {
+    let needed: bool = conn.query_row(
+        r#"
+        SELECT NOT EXISTS (SELECT 1 FROM usage_rollup_hourly)
+           AND EXISTS (SELECT 1 FROM usage_events)
+        "#,
+        [],
+        |row| row.get(0),
+    )?;
+    if !needed {
+        return Ok(0);
+    }
+    let tx = conn.unchecked_transaction()?;
+    let events = rebuild(&tx, None, None)?;
+    tx.commit()?;
+    Ok(events)
+}
+
+type Grouped = BTreeMap<(i64, String), (String, Rollup)>;
+
+// 前三列依次为桶、分组键、展示名
+fn group_entry<'a>(
+    out: &'a mut Grouped,
+    row: &rusqlite::Row<'_>,
+) -> rusqlite::Result<&'a mut Rollup> {
+    let bucket_key: i64 = row.get(0)?;
+    let group_key = row.get::<_, Option<String>>(1)?.unwrap_or_default();
+    let group_name = row.get::<_, Option<String>>(2)?.unwrap_or_default();
+    let entry = out.entry((bucket_key, group_key)).or_default();
+    if group_name > entry.0 {
+        entry.0 = group_name;
+    }
+    Ok(&mut entry.1)
+}
+
+// 原始记录按汇总表的列名展开，分组和分桶的 SQL 表达式可以原样使用
+const RAW_EVENTS: &str = r#"(
+  SELECT request_id, ts_ms AS bucket_start_ms, channel_id, COALESCE(model, '') AS model,
+         protocol, COALESCE(route_id, '') AS route_id, success, cancelled, cache_hit,
+         latency_ms, ttft_ms, prompt_tokens, completion_tokens, total_tokens,
+         CAST(estimated_cost_usd AS REAL) AS cost_usd
+  FROM usage_events
+)"#;
+
+// 读取窗口内的用量并按 (桶, 分组) 合并。整个落在窗口内且不跨统计桶边界的汇总行直接使用；
+// 窗口边界或统计桶边界（非整点时区）落在行内时，该时段改用原始记录精确计算，
+// 原始记录已被清理时退回为包含整行
+pub(super) fn load(
+    conn: &Connection,
+    window: StatsWindow,
+    bucket: Option<StatsBucket>,
+    offset_ms: i64,
+    key_sql: &str,
+    name_sql: &str,
+) -> rusqlite::Result<Vec<(i64, String, String, Rollup)>> {
+    let unit = HOUR_MS;
+    let bucket_at =
+        |col: &str| bucket.map_or_else(|| "0".to_string(), |b| b.sql_key(col, offset_ms));
+    let whole = format!(
+        "u.bucket_start_ms >= ?1 AND (?2 IS NULL OR u.bucket_start_ms + {unit} <= ?2) AND {} = {}",
+        bucket_at("u.bucket_start_ms"),
+        bucket_at(&format!("(u.bucket_start_ms + {unit} - 1)")),
+    );
+
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT u.bucket_start_ms, SUM(u.attempts)
+        FROM {ROLLUP_TABLE} u
+        WHERE u.bucket_start_ms > ?1 - {unit} AND (?2 IS NULL OR u.bucket_start_ms < ?2)
+          AND NOT ({whole})
+        GROUP BY u.bucket_start_ms
+        "#
+    ))?;
+    let partial = stmt
+        .query_map(params![window.start_ms, window.end_ms], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    // 原始记录条数与汇总行一致才说明该时段没有被清理过
+    let mut raw = Vec::new();
+    let mut fallback = Vec::new();
+    for (bucket_start_ms, attempts) in partial {
+        let n: i64 = conn.query_row(
+            r#"
+            SELECT COUNT(*) FROM usage_events
+            WHERE ts_ms >= ?1 AND ts_ms < ?2 AND cancelled = 0
+            "#,
+            params![bucket_start_ms, bucket_start_ms + unit],
+            |row| row.get(0),
+        )?;
+        if n == attempts {
+            raw.push(bucket_start_ms);
+        } else {
+            fallback.push(bucket_start_ms);
+        }
+    }
+
+    let mut out = Grouped::new();
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT
+          {bucket_sql} AS bucket_key,
+          {key_sql} AS group_key,
+          {name_sql} AS group_name,
+          {ROLLUP_COLUMNS}
+        FROM {ROLLUP_TABLE} u
+        LEFT JOIN channels c ON c.id = u.channel_id
+        LEFT JOIN routes r ON r.id = u.route_id
+        WHERE ({whole}) OR u.bucket_start_ms IN (SELECT value FROM json_each(?3))
+        "#,
+        bucket_sql = bucket_at("u.bucket_start_ms"),
+    ))?;
+    let fallback = serde_json::to_string(&fallback).unwrap_or_else(|_| "[]".to_string());
+    let mut rows = stmt.query(params![window.start_ms, window.end_ms, fallback])?;
+    while let Some(row) = rows.next()? {
+        group_entry(&mut out, row)?.merge(&rollup_from_row(row, 3)?);
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT
+          {bucket_sql} AS bucket_key,
+          {key_sql} AS group_key,
+          {name_sql} AS group_name,
+          u.request_id, u.bucket_start_ms, u.channel_id, u.model, u.protocol, u.route_id,
+          u.success, u.cancelled, u.cache_hit, u.latency_ms, u.ttft_ms,
+          u.prompt_tokens, u.completion_tokens, u.total_tokens, u.cost_usd
+        FROM {RAW_EVENTS} u
+        LEFT JOIN channels c ON c.id = u.channel_id
+        LEFT JOIN routes r ON r.id = u.route_id
+        WHERE u.bucket_start_ms >= ?1 AND u.bucket_start_ms < ?2
+        ORDER BY u.bucket_start_ms ASC
+        "#,
+        bucket_sql = bucket_at("u.bucket_start_ms"),
+    ))?;
+    for bucket_start_ms in raw {
+        let end_ms = bucket_start_ms + unit;
+        // 行内窗口之前的记录只用来判断是否为请求的首条记录
+        let mut seen = prior_requests(conn, bucket_start_ms, Some(end_ms))?;
+        let mut rows = stmt.query(params![bucket_start_ms, end_ms])?;
+        while let Some(row) = rows.next()? {
+            let ev = event_from_row(row, 3)?;
+            let (first, first_success) = first_of_request(&mut seen, &ev);
+            if ev.ts_ms < window.start_ms || window.end_ms.is_some_and(|end| ev.ts_ms >= end) {
+                continue;
+            }
+            group_entry(&mut out, row)?.observe(&ev, first, first_success);
+        }
+    }
+
+    Ok(out
+        .into_iter()
+        .map(|((bucket_key, key), (name, r))| (bucket_key, key, name, r))
+        .collect())
+}
@@ -5,6 +5,95 @@ use uuid::Uuid;
 
 use super::{Protocol, now_ms, with_conn};
 
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelMatchKind {
+    Exact,
+    Glob,
+    Regex,
+    CatchAll,
+}
+
+// match_model 语法：空值为兜底；`re:` 前缀或以 `^` 开头为正则；包含 `*` / `?` 为通配；其余为精确匹配
+#[derive(Debug, Clone)]
+pub enum ModelPattern {
+    Exact(String),
+    Glob(String),
+    Regex(regex::Regex),
+    CatchAll,
+}
+
+impl ModelPattern {
+    pub fn parse(raw: Option<&str>) -> anyhow::Result<Self> {
+        let Some(s) = raw.map(str::trim).filter(|s| !s.is_empty()) else {
+            return Ok(ModelPattern::CatchAll);
+        };
+        if let Some(re) = s.strip_prefix("re:") {
+            let re = regex::Regex::new(re.trim())
+                .map_err(|e| anyhow::anyhow!("invalid match_model regex: {e}"))?;
+            return Ok(ModelPattern::Regex(re));
+        }
+        if s.starts_with('^') {
+            let re = regex::Regex::new(s)
+                .map_err(|e| anyhow::anyhow!("invalid match_model regex: {e}"))?;
+            return Ok(ModelPattern::Regex(re));
+        }
+        if s.contains(['*', '?']) {
+            return Ok(ModelPattern::Glob(s.to_string()));
+        }
+        Ok(ModelPattern::Exact(s.to_string()))
+    }
+
+    pub fn kind(&self) -> ModelMatchKind {
+        match self {
+            ModelPattern::Exact(_) => ModelMatchKind::Exact,
+            ModelPattern::Glob(_) => ModelMatchKind::Glob,
+            ModelPattern::Regex(_) => ModelMatchKind::Regex,
+            ModelPattern::CatchAll => ModelMatchKind::CatchAll,
+        }
+    }
+
+    pub fn matches(&self, model: Option<&str>) -> bool {
+        let model = model.map(str::trim).filter(|m| !m.is_empty());
+        match (self, model) {
+            (ModelPattern::CatchAll, _) => true,
+            (_, None) => false,
+            (ModelPattern::Exact(p), Some(m)) => p == m,
+            (ModelPattern::Glob(p), Some(m)) => glob_match(p.as_bytes(), m.as_bytes()),
+            (ModelPattern::Regex(re), Some(m)) => re.is_match(m),
+        }
+    }
+
+    // 同类规则之间的排序键：通配规则按去掉通配符后的长度，越长越具体
+    pub fn specificity(&self) -> usize {
+        match self {
+            ModelPattern::Glob(p) => p.chars().filter(|c| !matches!(c, '*' | '?')).count(),
+            _ => 0,
+        }
+    }
+}
+
+pub(super) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0usize, 0usize);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == b'?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Route {
     pub id: String,
@@ -245,7 +334,7 @@ pub async fn set_route_channels(
     channel_ids_in_priority_order: Vec<String>,
 ) -> anyhow::Result<()> {
     with_conn(db_path, move |conn| {
-        let route_protocol: String = conn
+        let route_protocol: Protocol = conn
             .query_row(
                 r#"SELECT protocol FROM routes WHERE id = ?1"#,
                 [&route_id],
@@ -261,7 +350,7 @@ pub async fn set_route_channels(
         )?;
 
         for (idx, channel_id) in channel_ids_in_priority_order.into_iter().enumerate() {
-            let channel_protocol: String = tx
+            let channel_protocol: Protocol = tx
                 .query_row(
                     r#"SELECT protocol FROM channels WHERE id = ?1"#,
                     [&channel_id],
@@ -270,7 +359,7 @@ pub async fn set_route_channels(
                 .optional()?
                 .ok_or_else(|| anyhow::anyhow!("channel not found"))?;
 
-            if channel_protocol != route_protocol {
+            if !route_protocol.accepts_channel(channel_protocol) {
                 return Err(anyhow::anyhow!(
                     "channel protocol mismatch: route={route_protocol} channel={channel_protocol}"
                 ));
@@ -0,0 +1,281 @@
+use aes_gcm::aead::rand_core::RngCore as _;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+pub const SECRET_PASSPHRASE_ENV: &str = "CLISWITCH_SECRET_PASSPHRASE";
+
+const KEYRING_SERVICE: &str = "cliswitch";
+const KDF_FILE: &str = "secret.json";
+const KDF_NAME: &str = "pbkdf2-sha256";
+const KDF_ITERATIONS: u32 = 600_000;
+const KDF_SALT_LEN: usize = 16;
+const KEY_CHECK: &str = "cliswitch";
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+// 渠道密钥的加密密钥：默认保存在系统钥匙串，设置口令后改由口令派生
+#[derive(Clone)]
+pub(super) struct SecretKey(Key<Aes256Gcm>);
+
+// 口令派生参数；只保存盐和校验密文，不含密钥本身
+#[derive(Serialize, Deserialize)]
+struct KdfFile {
+    kdf: String,
+    iterations: u32,
+    salt: String,
+    check: String,
+}
+
+// None 表示没有可用的密钥且库里没有密文，渠道密钥以明文保存
+fn cache() -> &'static Mutex<HashMap<PathBuf, Option<SecretKey>>> {
+    static KEYS: OnceLock<Mutex<HashMap<PathBuf, Option<SecretKey>>>> = OnceLock::new();
+    KEYS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn passphrase_slot() -> &'static OnceLock<String> {
+    static PASSPHRASE: OnceLock<String> = OnceLock::new();
+    &PASSPHRASE
+}
+
+/// 改用口令派生密钥，不再访问系统钥匙串；需在 `init_db` 之前调用，优先于环境变量。
+pub fn set_secret_passphrase(passphrase: String) {
+    let _ = passphrase_slot().set(passphrase);
+}
+
+fn passphrase() -> Option<String> {
+    passphrase_slot().get().cloned().or_else(|| {
+        std::env::var(SECRET_PASSPHRASE_ENV)
+            .ok()
+            .filter(|s| !s.is_empty())
+    })
+}
+
+fn data_file_path(db_path: &Path, name: &str) -> PathBuf {
+    db_path
+        .parent()
+        .map(|dir| dir.join(name))
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+// 口令模式按派生文件缓存，同一数据目录下的数据库只派生一次
+fn cache_key(db_path: &Path) -> PathBuf {
+    let db_path = std::path::absolute(db_path).unwrap_or_else(|_| db_path.to_path_buf());
+    match passphrase() {
+        Some(_) => data_file_path(&db_path, KDF_FILE),
+        None => db_path,
+    }
+}
+
+pub(super) fn secret_key(db_path: &Path) -> anyhow::Result<Option<SecretKey>> {
+    if let Some(key) = cached(db_path) {
+        return Ok(key);
+    }
+    unlock(db_path, true).map(Some)
+}
+
+/// `init_db` 之后调用：渠道密钥是否已加密保存。系统钥匙串不可用且未设置口令时为 false。
+pub fn channel_secrets_encrypted(db_path: &Path) -> bool {
+    !matches!(cached(db_path), Some(None))
+}
+
+fn cached(db_path: &Path) -> Option<Option<SecretKey>> {
+    let keys = cache().lock().unwrap_or_else(|e| e.into_inner());
+    keys.get(&cache_key(db_path)).cloned()
+}
+
+// 库里已有密文或设置了口令时必须拿到密钥；否则钥匙串不可用时继续以明文保存
+pub(super) fn open(db_path: &Path, has_encrypted: bool) -> anyhow::Result<Option<SecretKey>> {
+    if has_encrypted || passphrase().is_some() {
+        return unlock(db_path, has_encrypted).map(Some);
+    }
+    match unlock(db_path, false) {
+        Ok(key) => Ok(Some(key)),
+        Err(_) => {
+            let mut keys = cache().lock().unwrap_or_else(|e| e.into_inner());
+            keys.insert(cache_key(db_path), None);
+            Ok(None)
+        }
+    }
+}
+
+// 库里已有密文时找不到密钥直接报错，不会悄悄生成新密钥
+fn unlock(db_path: &Path, has_encrypted: bool) -> anyhow::Result<SecretKey> {
+    let path = cache_key(db_path);
+    let mut keys = cache().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(Some(key)) = keys.get(&path) {
+        return Ok(key.clone());
+    }
+    let key = match passphrase() {
+        Some(passphrase) => passphrase_key(&path, &passphrase, !has_encrypted)?,
+        None => keyring_key(&path, !has_encrypted)?,
+    };
+    keys.insert(path, Some(key.clone()));
+    Ok(key)
+}
+
+fn passphrase_key(path: &Path, passphrase: &str, create: bool) -> anyhow::Result<SecretKey> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => {
+            let file: KdfFile = serde_json::from_str(&s)
+                .with_context(|| format!("密钥派生文件格式错误：{}", path.display()))?;
+            if file.kdf != KDF_NAME {
+                anyhow::bail!("不支持的密钥派生算法 {}：{}", file.kdf, path.display());
+            }
+            let salt = hex::decode(&file.salt)
+                .with_context(|| format!("密钥派生文件格式错误：{}", path.display()))?;
+            let key = derive_key(passphrase, &salt, file.iterations);
+            if decrypt(&key, &file.check).ok().as_deref() != Some(KEY_CHECK) {
+                anyhow::bail!("口令错误，无法解锁渠道密钥：{}", path.display());
+            }
+            Ok(key)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {
+            let mut salt = [0u8; KDF_SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(passphrase, &salt, KDF_ITERATIONS);
+            let file = KdfFile {
+                kdf: KDF_NAME.to_string(),
+                iterations: KDF_ITERATIONS,
+                salt: hex::encode(salt),
+                check: encrypt(&key, KEY_CHECK)?,
+            };
+            write_file_atomic(path, &serde_json::to_string_pretty(&file)?)
+                .with_context(|| format!("写入密钥派生文件失败：{}", path.display()))?;
+            Ok(key)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!(
+                "找不到密钥派生文件 {}，已加密的渠道密钥无法解密",
+                path.display()
+            )
+        }
+        Err(e) => Err(e).with_context(|| format!("读取密钥派生文件失败：{}", path.display())),
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> SecretKey {
+    let mut key = Key::<Aes256Gcm>::default();
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    SecretKey(key)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+fn keyring_key(db_path: &Path, create: bool) -> anyhow::Result<SecretKey> {
+    let hint = || format!("无法访问系统钥匙串，可设置 {SECRET_PASSPHRASE_ENV} 改用口令派生密钥");
+    // 以数据库路径区分不同的数据目录
+    let entry =
+        keyring::Entry::new(KEYRING_SERVICE, &db_path.display().to_string()).with_context(hint)?;
+    match entry.get_password() {
+        Ok(s) => parse_key(&s).with_context(|| "系统钥匙串中的渠道密钥格式错误"),
+        Err(keyring::Error::NoEntry) if create => {
+            let key = Aes256Gcm::generate_key(OsRng);
+            entry.set_password(&hex::encode(key)).with_context(hint)?;
+            Ok(SecretKey(key))
+        }
+        Err(keyring::Error::NoEntry) => {
+            anyhow::bail!("系统钥匙串中找不到渠道密钥，已加密的渠道密钥无法解密")
+        }
+        Err(e) => Err(e).with_context(hint),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn keyring_key(_db_path: &Path, _create: bool) -> anyhow::Result<SecretKey> {
+    anyhow::bail!("当前平台不支持系统钥匙串，请设置 {SECRET_PASSPHRASE_ENV} 改用口令派生密钥")
+}
+
+fn parse_key(s: &str) -> anyhow::Result<SecretKey> {
+    let bytes = hex::decode(s.trim())?;
+    if bytes.len() != 32 {
+        anyhow::bail!("密钥长度错误");
+    }
+    Ok(SecretKey(*Key::<Aes256Gcm>::from_slice(&bytes)))
+}
+
+// 先写临时文件再改名，避免留下写了一半的文件
+fn write_file_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write as _;
+    let tmp = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+    let res = (|| {
+        let mut f = open_private(&tmp)?;
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+        std::fs::rename(&tmp, path)
+    })();
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    res
+}
+
+#[cfg(unix)]
+fn open_private(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt as _;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open_private(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+pub(super) fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+pub(super) fn encrypt(key: &SecretKey, plaintext: &str) -> anyhow::Result<String> {
+    let cipher = Aes256Gcm::new(&key.0);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| anyhow::anyhow!("加密渠道密钥失败"))?;
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&ciphertext);
+    Ok(format!("{ENCRYPTED_PREFIX}{}", hex::encode(out)))
+}
+
+// 未带前缀的视为旧版明文，原样返回
+pub(super) fn decrypt(key: &SecretKey, stored: &str) -> anyhow::Result<String> {
+    let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let bytes = hex::decode(encoded).context("渠道密钥密文格式错误")?;
+    if bytes.len() < NONCE_LEN {
+        anyhow::bail!("渠道密钥密文过短");
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let plaintext = Aes256Gcm::new(&key.0)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("解密渠道密钥失败，请确认钥匙串或口令与数据库匹配"))?;
+    String::from_utf8(plaintext).context("渠道密钥不是有效的 UTF-8")
+}
+
+// 未启用加密时原样保存
+pub(super) fn seal(key: Option<&SecretKey>, plaintext: &str) -> anyhow::Result<String> {
+    match key {
+        Some(key) => encrypt(key, plaintext),
+        None => Ok(plaintext.to_string()),
+    }
+}
+
+pub(super) fn unseal(key: Option<&SecretKey>, stored: &str) -> anyhow::Result<String> {
+    match key {
+        Some(key) => decrypt(key, stored),
+        None if is_encrypted(stored) => anyhow::bail!(
+            "渠道密钥已加密但没有可用的密钥，请设置 {SECRET_PASSPHRASE_ENV} 或恢复系统钥匙串"
+        ),
+        None => Ok(stored.to_string()),
+    }
+}
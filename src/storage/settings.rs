@@ -4,6 +4,7 @@ use std::path::PathBuf;
 
 use crate::logging::LogLevel;
 
+use super::failover::{FailoverPolicy, TimeoutPolicy};
 use super::{now_ms, with_conn};
 
 const KEY_PRICING_AUTO_UPDATE_ENABLED: &str = "pricing_auto_update_enabled";
@@ -18,6 +19,29 @@ const KEY_AUTO_DISABLE_FAILURE_TIMES: &str = "auto_disable_failure_times";
 const KEY_AUTO_DISABLE_DISABLE_MINUTES: &str = "auto_disable_disable_minutes";
 const KEY_LOG_LEVEL: &str = "log_level";
 const KEY_LOG_RETENTION_DAYS: &str = "log_retention_days";
+const KEY_USAGE_RETENTION_DAYS: &str = "usage_retention_days";
+const KEY_ERROR_DETAIL_RETENTION_DAYS: &str = "error_detail_retention_days";
+const KEY_CHANNEL_FAILURE_RETENTION_DAYS: &str = "channel_failure_retention_days";
+
+// 覆盖最长的自然月，月度花费与配额统计不会因清理而归零
+pub const USAGE_RETENTION_MIN_DAYS: i64 = 32;
+const KEY_LOAD_BALANCE_MODE: &str = "load_balance_mode";
+const KEY_HEDGE_ENABLED: &str = "hedge_enabled";
+const KEY_HEDGE_DELAY_MS: &str = "hedge_delay_ms";
+const KEY_FAILOVER_POLICY: &str = "failover_policy";
+const KEY_STREAM_FAILOVER_ENABLED: &str = "stream_failover_enabled";
+const KEY_STREAM_FIRST_CONTENT_TIMEOUT_MS: &str = "stream_first_content_timeout_ms";
+const KEY_TIMEOUTS: &str = "timeouts";
+const KEY_PROXY_URL: &str = "proxy_url";
+const KEY_CLIENT_AUTH_ENABLED: &str = "client_auth_enabled";
+const KEY_USD_CNY_RATE: &str = "usd_cny_rate";
+const KEY_SPEND_WARNING_PERCENT: &str = "spend_warning_percent";
+const KEY_RESPONSE_CACHE_ENABLED: &str = "response_cache_enabled";
+const KEY_RESPONSE_CACHE_TTL_SECS: &str = "response_cache_ttl_secs";
+const KEY_RESPONSE_CACHE_MAX_MB: &str = "response_cache_max_mb";
+const KEY_CAPTURE_ENABLED: &str = "capture_enabled";
+const KEY_OTLP_ENABLED: &str = "otlp_enabled";
+const KEY_OTLP_ENDPOINT: &str = "otlp_endpoint";
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -53,6 +77,25 @@ impl AutoStartLaunchMode {
     }
 }
 
+// 同优先级渠道之间如何分配流量；不同优先级之间始终只做故障转移
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalanceMode {
+    Priority,
+    Weighted,
+    Adaptive,
+}
+
+impl LoadBalanceMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            LoadBalanceMode::Priority => "priority",
+            LoadBalanceMode::Weighted => "weighted",
+            LoadBalanceMode::Adaptive => "adaptive",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
     pub pricing_auto_update_enabled: bool,
@@ -67,6 +110,40 @@ pub struct AppSettings {
     pub auto_disable_disable_minutes: i64,
     pub log_level: LogLevel,
     pub log_retention_days: i64,
+    // 原始用量记录（及抓包）保留天数，0 表示不清理；统计数据来自汇总表，不受影响。
+    // 客户端令牌的月度配额仍按原始记录计算，因此最少保留 USAGE_RETENTION_MIN_DAYS 天
+    pub usage_retention_days: i64,
+    // 超过该天数后只清空 error_detail 文本，记录本身保留
+    pub error_detail_retention_days: i64,
+    pub channel_failure_retention_days: i64,
+    pub load_balance_mode: LoadBalanceMode,
+    pub hedge_enabled: bool,
+    // 0 表示按首个渠道近期耗时的 p95 决定何时发出对冲请求
+    pub hedge_delay_ms: i64,
+    // 渠道未单独配置时使用的全局故障转移策略
+    pub failover_policy: FailoverPolicy,
+    // SSE 响应在首个有效内容到达前先缓冲，期间出错或超时则换下一个渠道
+    pub stream_failover_enabled: bool,
+    pub stream_first_content_timeout_ms: i64,
+    // 渠道未单独配置时使用的上游超时
+    pub timeouts: TimeoutPolicy,
+    // 渠道未单独配置时的上游代理，空表示沿用系统代理环境变量
+    pub proxy_url: String,
+    // 开启后代理与管理接口都需要携带客户端令牌（/api/health 除外）
+    pub client_auth_enabled: bool,
+    // 充值货币为 CNY 的渠道按此汇率把美元费用折算成人民币
+    pub usd_cny_rate: f64,
+    // 渠道花费达到余额或限额的该百分比时发出提醒
+    pub spend_warning_percent: i64,
+    // temperature 为 0 的非流式响应缓存在 SQLite 中，相同请求直接返回
+    pub response_cache_enabled: bool,
+    pub response_cache_ttl_secs: i64,
+    pub response_cache_max_mb: i64,
+    // 完整记录每个请求的请求体、请求头与响应，用于排查和重放
+    pub capture_enabled: bool,
+    // 以 OTLP/HTTP JSON 把每个请求及各渠道尝试的 span 发往本地 collector
+    pub otlp_enabled: bool,
+    pub otlp_endpoint: String,
 }
 
 impl Default for AppSettings {
@@ -84,6 +161,26 @@ impl Default for AppSettings {
             auto_disable_disable_minutes: 30,
             log_level: LogLevel::Warning,
             log_retention_days: 30,
+            usage_retention_days: 0,
+            error_detail_retention_days: 0,
+            channel_failure_retention_days: 0,
+            load_balance_mode: LoadBalanceMode::Priority,
+            hedge_enabled: false,
+            hedge_delay_ms: 0,
+            failover_policy: FailoverPolicy::default(),
+            stream_failover_enabled: true,
+            stream_first_content_timeout_ms: 30_000,
+            timeouts: TimeoutPolicy::default(),
+            proxy_url: String::new(),
+            client_auth_enabled: false,
+            usd_cny_rate: 7.2,
+            spend_warning_percent: 80,
+            response_cache_enabled: false,
+            response_cache_ttl_secs: 3600,
+            response_cache_max_mb: 256,
+            capture_enabled: false,
+            otlp_enabled: false,
+            otlp_endpoint: "http://127.0.0.1:4318/v1/traces".to_string(),
         }
     }
 }
@@ -102,6 +199,26 @@ pub struct AppSettingsPatch {
     pub auto_disable_disable_minutes: Option<i64>,
     pub log_level: Option<LogLevel>,
     pub log_retention_days: Option<i64>,
+    pub usage_retention_days: Option<i64>,
+    pub error_detail_retention_days: Option<i64>,
+    pub channel_failure_retention_days: Option<i64>,
+    pub load_balance_mode: Option<LoadBalanceMode>,
+    pub hedge_enabled: Option<bool>,
+    pub hedge_delay_ms: Option<i64>,
+    pub failover_policy: Option<FailoverPolicy>,
+    pub stream_failover_enabled: Option<bool>,
+    pub stream_first_content_timeout_ms: Option<i64>,
+    pub timeouts: Option<TimeoutPolicy>,
+    pub proxy_url: Option<String>,
+    pub client_auth_enabled: Option<bool>,
+    pub usd_cny_rate: Option<f64>,
+    pub spend_warning_percent: Option<i64>,
+    pub response_cache_enabled: Option<bool>,
+    pub response_cache_ttl_secs: Option<i64>,
+    pub response_cache_max_mb: Option<i64>,
+    pub capture_enabled: Option<bool>,
+    pub otlp_enabled: Option<bool>,
+    pub otlp_endpoint: Option<String>,
 }
 
 fn get_setting(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
@@ -205,6 +322,93 @@ pub async fn get_app_settings(db_path: PathBuf) -> anyhow::Result<AppSettings> {
         {
             out.log_retention_days = n;
         }
+        if let Some(v) = get_setting(conn, KEY_USAGE_RETENTION_DAYS)?
+            && let Ok(n) = v.trim().parse::<i64>()
+        {
+            out.usage_retention_days = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_ERROR_DETAIL_RETENTION_DAYS)?
+            && let Ok(n) = v.trim().parse::<i64>()
+        {
+            out.error_detail_retention_days = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_CHANNEL_FAILURE_RETENTION_DAYS)?
+            && let Ok(n) = v.trim().parse::<i64>()
+        {
+            out.channel_failure_retention_days = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_LOAD_BALANCE_MODE)? {
+            match v.trim() {
+                "priority" => out.load_balance_mode = LoadBalanceMode::Priority,
+                "weighted" => out.load_balance_mode = LoadBalanceMode::Weighted,
+                "adaptive" => out.load_balance_mode = LoadBalanceMode::Adaptive,
+                _ => {}
+            }
+        }
+        if let Some(v) = get_setting(conn, KEY_HEDGE_ENABLED)? {
+            out.hedge_enabled = parse_bool(&v);
+        }
+        if let Some(v) = get_setting(conn, KEY_HEDGE_DELAY_MS)?
+            && let Ok(n) = v.trim().parse::<i64>()
+        {
+            out.hedge_delay_ms = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_FAILOVER_POLICY)?
+            && let Ok(policy) = serde_json::from_str::<FailoverPolicy>(&v)
+        {
+            out.failover_policy = policy;
+        }
+        if let Some(v) = get_setting(conn, KEY_STREAM_FAILOVER_ENABLED)? {
+            out.stream_failover_enabled = parse_bool(&v);
+        }
+        if let Some(v) = get_setting(conn, KEY_STREAM_FIRST_CONTENT_TIMEOUT_MS)?
+            && let Ok(n) = v.trim().parse::<i64>()
+        {
+            out.stream_first_content_timeout_ms = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_TIMEOUTS)?
+            && let Ok(timeouts) = serde_json::from_str::<TimeoutPolicy>(&v)
+        {
+            out.timeouts = timeouts;
+        }
+        if let Some(v) = get_setting(conn, KEY_PROXY_URL)? {
+            out.proxy_url = v.trim().to_string();
+        }
+        if let Some(v) = get_setting(conn, KEY_CLIENT_AUTH_ENABLED)? {
+            out.client_auth_enabled = parse_bool(&v);
+        }
+        if let Some(v) = get_setting(conn, KEY_USD_CNY_RATE)?
+            && let Ok(n) = v.trim().parse::<f64>()
+        {
+            out.usd_cny_rate = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_SPEND_WARNING_PERCENT)?
+            && let Ok(n) = v.trim().parse::<i64>()
+        {
+            out.spend_warning_percent = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_RESPONSE_CACHE_ENABLED)? {
+            out.response_cache_enabled = parse_bool(&v);
+        }
+        if let Some(v) = get_setting(conn, KEY_RESPONSE_CACHE_TTL_SECS)?
+            && let Ok(n) = v.trim().parse::<i64>()
+        {
+            out.response_cache_ttl_secs = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_RESPONSE_CACHE_MAX_MB)?
+            && let Ok(n) = v.trim().parse::<i64>()
+        {
+            out.response_cache_max_mb = n;
+        }
+        if let Some(v) = get_setting(conn, KEY_CAPTURE_ENABLED)? {
+            out.capture_enabled = parse_bool(&v);
+        }
+        if let Some(v) = get_setting(conn, KEY_OTLP_ENABLED)? {
+            out.otlp_enabled = parse_bool(&v);
+        }
+        if let Some(v) = get_setting(conn, KEY_OTLP_ENDPOINT)? {
+            out.otlp_endpoint = v.trim().to_string();
+        }
 
         Ok(out)
     })
@@ -294,6 +498,141 @@ pub async fn update_app_settings(
         if let Some(v) = patch.log_retention_days {
             set_setting(conn, KEY_LOG_RETENTION_DAYS, &v.to_string(), updated_at_ms)?;
         }
+        if let Some(v) = patch.usage_retention_days {
+            set_setting(
+                conn,
+                KEY_USAGE_RETENTION_DAYS,
+                &v.to_string(),
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.error_detail_retention_days {
+            set_setting(
+                conn,
+                KEY_ERROR_DETAIL_RETENTION_DAYS,
+                &v.to_string(),
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.channel_failure_retention_days {
+            set_setting(
+                conn,
+                KEY_CHANNEL_FAILURE_RETENTION_DAYS,
+                &v.to_string(),
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.load_balance_mode {
+            set_setting(conn, KEY_LOAD_BALANCE_MODE, v.as_str(), updated_at_ms)?;
+        }
+        if let Some(v) = patch.hedge_enabled {
+            set_setting(
+                conn,
+                KEY_HEDGE_ENABLED,
+                if v { "true" } else { "false" },
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.hedge_delay_ms {
+            set_setting(conn, KEY_HEDGE_DELAY_MS, &v.to_string(), updated_at_ms)?;
+        }
+        if let Some(v) = patch.failover_policy {
+            set_setting(
+                conn,
+                KEY_FAILOVER_POLICY,
+                &serde_json::to_string(&v)?,
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.stream_failover_enabled {
+            set_setting(
+                conn,
+                KEY_STREAM_FAILOVER_ENABLED,
+                if v { "true" } else { "false" },
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.stream_first_content_timeout_ms {
+            set_setting(
+                conn,
+                KEY_STREAM_FIRST_CONTENT_TIMEOUT_MS,
+                &v.to_string(),
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.timeouts {
+            set_setting(
+                conn,
+                KEY_TIMEOUTS,
+                &serde_json::to_string(&v)?,
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.proxy_url {
+            set_setting(conn, KEY_PROXY_URL, v.trim(), updated_at_ms)?;
+        }
+        if let Some(v) = patch.client_auth_enabled {
+            set_setting(
+                conn,
+                KEY_CLIENT_AUTH_ENABLED,
+                if v { "true" } else { "false" },
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.usd_cny_rate {
+            set_setting(conn, KEY_USD_CNY_RATE, &v.to_string(), updated_at_ms)?;
+        }
+        if let Some(v) = patch.spend_warning_percent {
+            set_setting(
+                conn,
+                KEY_SPEND_WARNING_PERCENT,
+                &v.to_string(),
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.response_cache_enabled {
+            set_setting(
+                conn,
+                KEY_RESPONSE_CACHE_ENABLED,
+                if v { "true" } else { "false" },
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.response_cache_ttl_secs {
+            set_setting(
+                conn,
+                KEY_RESPONSE_CACHE_TTL_SECS,
+                &v.to_string(),
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.response_cache_max_mb {
+            set_setting(
+                conn,
+                KEY_RESPONSE_CACHE_MAX_MB,
+                &v.to_string(),
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.capture_enabled {
+            set_setting(
+                conn,
+                KEY_CAPTURE_ENABLED,
+                if v { "true" } else { "false" },
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.otlp_enabled {
+            set_setting(
+                conn,
+                KEY_OTLP_ENABLED,
+                if v { "true" } else { "false" },
+                updated_at_ms,
+            )?;
+        }
+        if let Some(v) = patch.otlp_endpoint {
+            set_setting(conn, KEY_OTLP_ENDPOINT, v.trim(), updated_at_ms)?;
+        }
         Ok(())
     })
     .await?;
@@ -1,12 +1,203 @@
-use rusqlite::params;
+use rusqlite::{Connection, params};
 use serde::Serialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use super::{Protocol, with_conn};
+use super::rollup::{self, Histogram, Rollup};
+use super::{Protocol, now_ms, with_conn};
+
+pub(super) const HOUR_MS: i64 = 3_600_000;
+pub(super) const DAY_MS: i64 = 86_400_000;
+
+pub fn current_local_offset_ms() -> i64 {
+    let offset = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
+    (offset.whole_seconds() as i64) * 1000
+}
+
+// 本地时区的今日零点
+pub fn local_day_start_ms() -> i64 {
+    day_start_ms(now_ms(), current_local_offset_ms())
+}
+
+// 本地时区的本月一日零点
+pub fn local_month_start_ms() -> i64 {
+    month_start_ms(now_ms(), current_local_offset_ms())
+}
+
+// offset_ms 为相对 UTC 向东的偏移
+pub fn day_start_ms(at_ms: i64, offset_ms: i64) -> i64 {
+    (at_ms + offset_ms).div_euclid(DAY_MS) * DAY_MS - offset_ms
+}
+
+pub fn month_start_ms(at_ms: i64, offset_ms: i64) -> i64 {
+    let day = (at_ms + offset_ms).div_euclid(DAY_MS);
+    let date =
+        time::Date::from_julian_day(UNIX_EPOCH_JULIAN_DAY + day as i32).unwrap_or(time::Date::MIN);
+    month_key_start_ms(date.year() as i64 * 12 + date.month() as i64 - 1, offset_ms)
+}
+
+const UNIX_EPOCH_JULIAN_DAY: i32 = 2_440_588;
+
+// year * 12 + (month - 1) 对应的当月一日零点
+fn month_key_start_ms(key: i64, offset_ms: i64) -> i64 {
+    let month =
+        time::Month::try_from((key.rem_euclid(12) + 1) as u8).unwrap_or(time::Month::January);
+    let date = time::Date::from_calendar_date(key.div_euclid(12) as i32, month, 1)
+        .unwrap_or(time::Date::MIN);
+    (date.to_julian_day() - UNIX_EPOCH_JULIAN_DAY) as i64 * DAY_MS - offset_ms
+}
+
+// 统计区间 [start_ms, end_ms)，end_ms 为 None 表示到当前
+#[derive(Debug, Clone, Copy)]
+pub struct StatsWindow {
+    pub start_ms: i64,
+    pub end_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsBucket {
+    Hour,
+    Day,
+    // 以周一为一周的开始
+    Week,
+    Month,
+}
+
+impl StatsBucket {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StatsBucket::Hour => "hour",
+            StatsBucket::Day => "day",
+            StatsBucket::Week => "week",
+            StatsBucket::Month => "month",
+        }
+    }
+
+    // 用于限制桶数量，月按 28 天估算
+    pub fn approx_ms(self) -> i64 {
+        match self {
+            StatsBucket::Hour => HOUR_MS,
+            StatsBucket::Day => DAY_MS,
+            StatsBucket::Week => 7 * DAY_MS,
+            StatsBucket::Month => 28 * DAY_MS,
+        }
+    }
+
+    // 作用于 col 所给的毫秒时间戳；偏移量是整数，直接拼进 SQL
+    pub(super) fn sql_key(self, col: &str, offset_ms: i64) -> String {
+        let local = format!("({col} + {offset_ms})");
+        match self {
+            StatsBucket::Hour => format!("{local} / {HOUR_MS}"),
+            StatsBucket::Day => format!("{local} / {DAY_MS}"),
+            // 1970-01-01 是周四
+            StatsBucket::Week => format!("({local} / {DAY_MS} + 3) / 7"),
+            StatsBucket::Month => format!(
+                "CAST(strftime('%Y', {local} / 1000, 'unixepoch') AS INTEGER) * 12 \
+                 + CAST(strftime('%m', {local} / 1000, 'unixepoch') AS INTEGER) - 1"
+            ),
+        }
+    }
+
+    fn key_start_ms(self, key: i64, offset_ms: i64) -> i64 {
+        match self {
+            StatsBucket::Hour => key * HOUR_MS - offset_ms,
+            StatsBucket::Day => key * DAY_MS - offset_ms,
+            StatsBucket::Week => (key * 7 - 3) * DAY_MS - offset_ms,
+            StatsBucket::Month => month_key_start_ms(key, offset_ms),
+        }
+    }
+}
+
+impl std::str::FromStr for StatsBucket {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hour" => Ok(StatsBucket::Hour),
+            "day" => Ok(StatsBucket::Day),
+            "week" => Ok(StatsBucket::Week),
+            "month" => Ok(StatsBucket::Month),
+            other => Err(format!("未知 bucket：{other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsGroupBy {
+    Channel,
+    Model,
+    Protocol,
+    Route,
+}
+
+impl StatsGroupBy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StatsGroupBy::Channel => "channel",
+            StatsGroupBy::Model => "model",
+            StatsGroupBy::Protocol => "protocol",
+            StatsGroupBy::Route => "route",
+        }
+    }
+
+    // (分组键, 展示名)
+    fn sql_columns(self) -> (&'static str, &'static str) {
+        match self {
+            StatsGroupBy::Channel => ("u.channel_id", "COALESCE(c.name, u.channel_id)"),
+            StatsGroupBy::Model => ("COALESCE(u.model, '')", "COALESCE(u.model, '')"),
+            StatsGroupBy::Protocol => ("u.protocol", "u.protocol"),
+            StatsGroupBy::Route => (
+                "COALESCE(u.route_id, '')",
+                "COALESCE(r.name, u.route_id, '')",
+            ),
+        }
+    }
+}
+
+impl std::str::FromStr for StatsGroupBy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "channel" => Ok(StatsGroupBy::Channel),
+            "model" => Ok(StatsGroupBy::Model),
+            "protocol" => Ok(StatsGroupBy::Protocol),
+            "route" => Ok(StatsGroupBy::Route),
+            other => Err(format!("未知 group_by：{other}")),
+        }
+    }
+}
+
+// 取最近秩，样本为空时全部为 None
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Percentiles {
+    pub p50: Option<i64>,
+    pub p95: Option<i64>,
+    pub p99: Option<i64>,
+}
+
+fn nearest_rank(sorted: &[i64], pct: usize) -> Option<i64> {
+    let idx = (sorted.len() * pct).div_ceil(100).saturating_sub(1);
+    sorted.get(idx).copied()
+}
+
+impl Percentiles {
+    // 汇总表只保存直方图，结果为所在桶的上界
+    fn from_histogram(h: &Histogram) -> Self {
+        Self {
+            p50: h.percentile(50),
+            p95: h.percentile(95),
+            p99: h.percentile(99),
+        }
+    }
+}
+
+fn format_cost(v: f64) -> Option<String> {
+    (v > 0.0).then(|| format!("{v:.6}"))
+}
 
 #[derive(Debug, Clone, Serialize)]
 pub struct StatsSummary {
     pub start_ms: i64,
+    pub end_ms: Option<i64>,
     pub requests: i64,
     pub success: i64,
     pub failed: i64,
@@ -15,113 +206,123 @@ pub struct StatsSummary {
     pub completion_tokens: i64,
     pub total_tokens: i64,
     pub estimated_cost_usd: Option<String>,
+    pub latency_ms: Percentiles,
+    pub ttft_ms: Percentiles,
 }
 
-pub async fn stats_summary(db_path: PathBuf, start_ms: i64) -> anyhow::Result<StatsSummary> {
+pub async fn stats_summary(db_path: PathBuf, window: StatsWindow) -> anyhow::Result<StatsSummary> {
     with_conn(db_path, move |conn| {
-        let mut stmt = conn.prepare(
-            r#"
-            WITH per_req AS (
-              SELECT
-                COALESCE(request_id, id) AS rid,
-                MAX(success) AS any_success
-              FROM usage_events
-              WHERE ts_ms >= ?1
-              GROUP BY rid
-            ),
-            req_agg AS (
-              SELECT
-                COUNT(*) AS requests,
-                SUM(any_success) AS success
-              FROM per_req
-            )
-            SELECT
-              (SELECT requests FROM req_agg) AS requests,
-              (SELECT success FROM req_agg) AS success,
-              (SELECT requests - success FROM req_agg) AS failed,
-              AVG(CASE WHEN latency_ms > 0 THEN latency_ms ELSE NULL END) AS avg_latency_ms,
-              SUM(COALESCE(prompt_tokens, 0)) AS prompt_tokens,
-              SUM(COALESCE(completion_tokens, 0)) AS completion_tokens,
-              SUM(COALESCE(total_tokens, 0)) AS total_tokens,
-              SUM(COALESCE(CAST(estimated_cost_usd AS REAL), 0.0)) AS estimated_cost
-            FROM usage_events
-            WHERE ts_ms >= ?1
-            "#,
-        )?;
-        let row = stmt.query_row(params![start_ms], |row| {
-            let requests: i64 = row.get(0)?;
-            let success: Option<i64> = row.get(1)?;
-            let failed: Option<i64> = row.get(2)?;
-            let avg_latency_ms: Option<f64> = row.get(3)?;
-            let prompt_tokens: Option<i64> = row.get(4)?;
-            let completion_tokens: Option<i64> = row.get(5)?;
-            let total_tokens: Option<i64> = row.get(6)?;
-            let estimated_cost: Option<f64> = row.get(7)?;
-
-            Ok(StatsSummary {
-                start_ms,
-                requests,
-                success: success.unwrap_or(0),
-                failed: failed.unwrap_or(0),
-                avg_latency_ms,
-                prompt_tokens: prompt_tokens.unwrap_or(0),
-                completion_tokens: completion_tokens.unwrap_or(0),
-                total_tokens: total_tokens.unwrap_or(0),
-                estimated_cost_usd: estimated_cost
-                    .filter(|v| *v > 0.0)
-                    .map(|v| format!("{v:.6}")),
-            })
-        })?;
-        Ok(row)
+        let r = rollup::load(conn, window, None, 0, "''", "''")?
+            .into_iter()
+            .next()
+            .map(|(_, _, _, r)| r)
+            .unwrap_or_default();
+        Ok(StatsSummary {
+            start_ms: window.start_ms,
+            end_ms: window.end_ms,
+            requests: r.requests,
+            success: r.requests_success,
+            failed: r.requests - r.requests_success,
+            avg_latency_ms: r.avg_latency_ms(),
+            prompt_tokens: r.prompt_tokens,
+            completion_tokens: r.completion_tokens,
+            total_tokens: r.total_tokens,
+            estimated_cost_usd: format_cost(r.cost_usd),
+            latency_ms: Percentiles::from_histogram(&r.latency),
+            ttft_ms: Percentiles::from_histogram(&r.ttft),
+        })
     })
     .await
 }
 
+// 按任意维度汇总的一组用量；requests 按尝试计，不含对冲中被取消的一方
 #[derive(Debug, Clone, Serialize)]
-pub struct TrendPoint {
-    pub bucket_start_ms: i64,
-    pub channel_id: String,
+pub struct GroupStats {
+    pub key: String,
     pub name: String,
+    pub requests: i64,
     pub success: i64,
+    pub failed: i64,
+    pub avg_latency_ms: Option<f64>,
+    pub latency_ms: Percentiles,
+    pub ttft_ms: Percentiles,
+    pub total_tokens: i64,
+    pub estimated_cost_usd: Option<String>,
 }
 
-pub async fn stats_trend_by_day_channel(
+impl GroupStats {
+    fn from_rollup(key: String, name: String, r: &Rollup) -> Self {
+        Self {
+            key,
+            name,
+            requests: r.attempts,
+            success: r.attempts_success,
+            failed: r.attempts - r.attempts_success,
+            avg_latency_ms: r.avg_latency_ms(),
+            latency_ms: Percentiles::from_histogram(&r.latency),
+            ttft_ms: Percentiles::from_histogram(&r.ttft),
+            total_tokens: r.total_tokens,
+            estimated_cost_usd: format_cost(r.cost_usd),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendPoint {
+    pub bucket_start_ms: i64,
+    #[serde(flatten)]
+    pub stats: GroupStats,
+}
+
+fn grouped_stats(
+    conn: &Connection,
+    window: StatsWindow,
+    bucket: Option<StatsBucket>,
+    group_by: StatsGroupBy,
+    offset_ms: i64,
+) -> rusqlite::Result<Vec<(i64, GroupStats)>> {
+    let (key_sql, name_sql) = group_by.sql_columns();
+    let mut out = rollup::load(conn, window, bucket, offset_ms, key_sql, name_sql)?
+        .into_iter()
+        .filter(|(_, _, _, r)| r.attempts > 0)
+        .map(|(bucket_key, key, name, r)| (bucket_key, GroupStats::from_rollup(key, name, &r)))
+        .collect::<Vec<_>>();
+    out.sort_by(|(ka, a), (kb, b)| {
+        ka.cmp(kb)
+            .then(b.requests.cmp(&a.requests))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(out)
+}
+
+pub async fn stats_breakdown(
     db_path: PathBuf,
-    start_ms: i64,
+    window: StatsWindow,
+    group_by: StatsGroupBy,
+) -> anyhow::Result<Vec<GroupStats>> {
+    with_conn(db_path, move |conn| {
+        let rows = grouped_stats(conn, window, None, group_by, 0)?;
+        Ok(rows.into_iter().map(|(_, stats)| stats).collect())
+    })
+    .await
+}
+
+pub async fn stats_trend(
+    db_path: PathBuf,
+    window: StatsWindow,
+    bucket: StatsBucket,
+    group_by: StatsGroupBy,
     offset_ms: i64,
 ) -> anyhow::Result<Vec<TrendPoint>> {
     with_conn(db_path, move |conn| {
-        let mut stmt = conn.prepare(
-            r#"
-            SELECT
-              ((u.ts_ms + ?2) / 86400000) AS bucket_day,
-              c.id,
-              c.name,
-              SUM(CASE WHEN u.success = 1 THEN 1 ELSE 0 END) AS success
-            FROM usage_events u
-            JOIN channels c ON c.id = u.channel_id
-            WHERE u.ts_ms >= ?1
-            GROUP BY bucket_day, c.id, c.name
-            HAVING SUM(CASE WHEN u.success = 1 THEN 1 ELSE 0 END) > 0
-            ORDER BY bucket_day ASC, c.name ASC
-            "#,
-        )?;
-
-        let rows = stmt.query_map(params![start_ms, offset_ms], |row| {
-            let bucket_day: i64 = row.get(0)?;
-            let bucket_start_ms = bucket_day
-                .saturating_mul(86_400_000)
-                .saturating_sub(offset_ms);
-            Ok(TrendPoint {
-                bucket_start_ms,
-                channel_id: row.get(1)?,
-                name: row.get(2)?,
-                success: row.get::<_, Option<i64>>(3)?.unwrap_or(0),
+        let rows = grouped_stats(conn, window, Some(bucket), group_by, offset_ms)?;
+        Ok(rows
+            .into_iter()
+            .map(|(key, stats)| TrendPoint {
+                bucket_start_ms: bucket.key_start_ms(key, offset_ms),
+                stats,
             })
-        })?;
-
-        rows.collect::<rusqlite::Result<Vec<_>>>()
-            .map_err(Into::into)
+            .collect())
     })
     .await
 }
@@ -135,46 +336,93 @@ pub struct ChannelStats {
     pub success: i64,
     pub failed: i64,
     pub avg_latency_ms: Option<f64>,
+    pub latency_ms: Percentiles,
+    pub ttft_ms: Percentiles,
     pub total_tokens: i64,
     pub estimated_cost_usd: Option<String>,
 }
 
-pub async fn stats_channels(db_path: PathBuf, start_ms: i64) -> anyhow::Result<Vec<ChannelStats>> {
+pub async fn stats_channels(
+    db_path: PathBuf,
+    window: StatsWindow,
+) -> anyhow::Result<Vec<ChannelStats>> {
+    with_conn(db_path, move |conn| {
+        let mut usage = rollup::load(conn, window, None, 0, "u.channel_id", "''")?
+            .into_iter()
+            .map(|(_, key, _, r)| (key, r))
+            .collect::<HashMap<_, _>>();
+
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT id, name, protocol
+            FROM channels
+            ORDER BY name ASC
+            "#,
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Protocol>(2)?,
+            ))
+        })?;
+
+        let mut out = Vec::new();
+        for row in rows {
+            let (channel_id, name, protocol) = row?;
+            let r = usage.remove(&channel_id).unwrap_or_default();
+            let stats = GroupStats::from_rollup(channel_id, name, &r);
+            out.push(ChannelStats {
+                channel_id: stats.key,
+                name: stats.name,
+                protocol,
+                requests: stats.requests,
+                success: stats.success,
+                failed: stats.failed,
+                avg_latency_ms: stats.avg_latency_ms,
+                latency_ms: stats.latency_ms,
+                ttft_ms: stats.ttft_ms,
+                total_tokens: stats.total_tokens,
+                estimated_cost_usd: stats.estimated_cost_usd,
+            });
+        }
+        Ok(out)
+    })
+    .await
+}
+
+// 供自适应负载均衡使用的近期健康度，只看请求结果与成功请求的耗时
+#[derive(Debug, Clone)]
+pub struct ChannelHealth {
+    pub channel_id: String,
+    pub requests: i64,
+    pub failed: i64,
+    pub avg_latency_ms: Option<f64>,
+}
+
+pub async fn channel_health_since(
+    db_path: PathBuf,
+    start_ms: i64,
+) -> anyhow::Result<Vec<ChannelHealth>> {
     with_conn(db_path, move |conn| {
         let mut stmt = conn.prepare(
             r#"
             SELECT
-              c.id,
-              c.name,
-              c.protocol,
-              COUNT(u.id) AS requests,
-              SUM(CASE WHEN u.success = 1 THEN 1 ELSE 0 END) AS success,
-              SUM(CASE WHEN u.success = 0 THEN 1 ELSE 0 END) AS failed,
-              AVG(u.latency_ms) AS avg_latency_ms,
-              SUM(COALESCE(u.total_tokens, 0)) AS total_tokens,
-              SUM(COALESCE(CAST(u.estimated_cost_usd AS REAL), 0.0)) AS estimated_cost
-            FROM channels c
-            LEFT JOIN usage_events u
-              ON u.channel_id = c.id
-             AND u.ts_ms >= ?1
-            GROUP BY c.id, c.name, c.protocol
-            ORDER BY c.name ASC
+              channel_id,
+              COUNT(*) AS requests,
+              SUM(CASE WHEN success = 0 THEN 1 ELSE 0 END) AS failed,
+              AVG(CASE WHEN success = 1 AND latency_ms > 0 THEN latency_ms ELSE NULL END) AS avg_latency_ms
+            FROM usage_events
+            WHERE ts_ms >= ?1 AND cancelled = 0 AND cache_hit = 0
+            GROUP BY channel_id
             "#,
         )?;
         let rows = stmt.query_map(params![start_ms], |row| {
-            Ok(ChannelStats {
+            Ok(ChannelHealth {
                 channel_id: row.get(0)?,
-                name: row.get(1)?,
-                protocol: row.get(2)?,
-                requests: row.get(3)?,
-                success: row.get::<_, Option<i64>>(4)?.unwrap_or(0),
-                failed: row.get::<_, Option<i64>>(5)?.unwrap_or(0),
-                avg_latency_ms: row.get(6)?,
-                total_tokens: row.get::<_, Option<i64>>(7)?.unwrap_or(0),
-                estimated_cost_usd: row
-                    .get::<_, Option<f64>>(8)?
-                    .filter(|v| *v > 0.0)
-                    .map(|v| format!("{v:.6}")),
+                requests: row.get(1)?,
+                failed: row.get::<_, Option<i64>>(2)?.unwrap_or(0),
+                avg_latency_ms: row.get(3)?,
             })
         })?;
 
@@ -183,3 +431,34 @@ pub async fn stats_channels(db_path: PathBuf, start_ms: i64) -> anyhow::Result<V
     })
     .await
 }
+
+// 渠道近期成功请求耗时的 p95，样本不足时返回 None
+pub async fn channel_latency_p95(
+    db_path: PathBuf,
+    channel_id: String,
+    sample_limit: i64,
+    min_samples: usize,
+) -> anyhow::Result<Option<i64>> {
+    with_conn(db_path, move |conn| {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT latency_ms
+            FROM usage_events
+            WHERE channel_id = ?1 AND success = 1 AND latency_ms > 0 AND cache_hit = 0
+            ORDER BY ts_ms DESC
+            LIMIT ?2
+            "#,
+        )?;
+        let mut samples = stmt
+            .query_map(params![channel_id, sample_limit], |row| {
+                row.get::<_, i64>(0)
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if samples.len() < min_samples.max(1) {
+            return Ok(None);
+        }
+        samples.sort_unstable();
+        Ok(nearest_rank(&samples, 95))
+    })
+    .await
+}
@@ -6,6 +6,8 @@ use uuid::Uuid;
 
 use crate::events::{self, AppEvent};
 
+use super::budget;
+use super::rollup::{self, RollupEvent, RollupKey};
 use super::{Protocol, with_conn};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,7 +19,10 @@ pub struct UsageEvent {
     pub route_id: Option<String>,
     pub channel_id: String,
     pub model: Option<String>,
+    pub upstream_model: Option<String>,
     pub success: bool,
+    // 对冲请求中落败后被取消的一方
+    pub cancelled: bool,
     pub http_status: Option<i64>,
     pub error_kind: Option<String>,
     pub error_detail: Option<String>,
@@ -29,17 +34,27 @@ pub struct UsageEvent {
     pub cache_read_tokens: Option<i64>,
     pub cache_write_tokens: Option<i64>,
     pub estimated_cost_usd: Option<String>,
+    pub key_fingerprint: Option<String>,
+    // 发起调用的本地客户端令牌
+    pub client_token_id: Option<String>,
+    // 由响应缓存直接返回，没有请求上游
+    pub cache_hit: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct CreateUsageEvent {
     pub request_id: Option<Arc<str>>,
+    // 同一请求内第几条用量记录，从 0 开始；为 0 时计入请求数
+    pub attempt_index: usize,
     pub ts_ms: i64,
     pub protocol: Protocol,
     pub route_id: Option<String>,
     pub channel_id: String,
     pub model: Option<String>,
+    pub upstream_model: Option<String>,
     pub success: bool,
+    // 对冲请求中落败后被取消的一方
+    pub cancelled: bool,
     pub http_status: Option<i64>,
     pub error_kind: Option<String>,
     pub error_detail: Option<String>,
@@ -51,20 +66,32 @@ pub struct CreateUsageEvent {
     pub cache_read_tokens: Option<i64>,
     pub cache_write_tokens: Option<i64>,
     pub estimated_cost_usd: Option<String>,
+    pub key_fingerprint: Option<String>,
+    // 发起调用的本地客户端令牌
+    pub client_token_id: Option<String>,
+    // 由响应缓存直接返回，没有请求上游
+    pub cache_hit: bool,
 }
 
-pub async fn insert_usage_event(db_path: PathBuf, input: CreateUsageEvent) -> anyhow::Result<()> {
+// 返回写入的估算费用（未显式给出时按价格表计算）
+pub async fn insert_usage_event(
+    db_path: PathBuf,
+    input: CreateUsageEvent,
+) -> anyhow::Result<Option<String>> {
     let at_ms = input.ts_ms;
     let res = with_conn(db_path, move |conn| {
         let id = Uuid::new_v4().to_string();
         let CreateUsageEvent {
             request_id,
+            attempt_index,
             ts_ms,
             protocol,
             route_id,
             channel_id,
             model,
+            upstream_model,
             success,
+            cancelled,
             http_status,
             error_kind,
             error_detail,
@@ -76,10 +103,13 @@ pub async fn insert_usage_event(db_path: PathBuf, input: CreateUsageEvent) -> an
             cache_read_tokens,
             cache_write_tokens,
             estimated_cost_usd,
+            key_fingerprint,
+            client_token_id,
+            cache_hit,
         } = input;
 
         let estimated_cost_usd = estimated_cost_usd.or_else(|| {
-            model.as_deref().and_then(|m| {
+            upstream_model.as_deref().or(model.as_deref()).and_then(|m| {
                 estimate_cost_usd(
                     conn,
                     m,
@@ -92,16 +122,41 @@ pub async fn insert_usage_event(db_path: PathBuf, input: CreateUsageEvent) -> an
             })
         });
 
-        conn.execute(
+        let rollup_event = RollupEvent {
+            request_id: request_id.as_deref().map(str::to_string),
+            ts_ms,
+            key: RollupKey {
+                channel_id: channel_id.clone(),
+                model: model.clone().unwrap_or_default(),
+                protocol: protocol.as_str().to_string(),
+                route_id: route_id.clone().unwrap_or_default(),
+            },
+            success,
+            cancelled,
+            cache_hit,
+            latency_ms,
+            ttft_ms,
+            prompt_tokens,
+            completion_tokens,
+            total_tokens,
+            cost_usd: estimated_cost_usd
+                .as_deref()
+                .and_then(|v| v.trim().parse().ok()),
+        };
+
+        // 原始记录与汇总表在同一事务中写入；先插入以尽早拿到写锁
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
             r#"
             INSERT INTO usage_events (
               id, request_id, ts_ms, protocol, route_id, channel_id, model,
-              success, http_status, error_kind, error_detail, latency_ms,
+              upstream_model, success, http_status, error_kind, error_detail, latency_ms,
               ttft_ms, prompt_tokens, completion_tokens, total_tokens,
               cache_read_tokens, cache_write_tokens,
-              estimated_cost_usd
+              estimated_cost_usd, key_fingerprint, cancelled, client_token_id, cache_hit
             )
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24)
             "#,
             params![
                 id,
@@ -111,6 +166,7 @@ pub async fn insert_usage_event(db_path: PathBuf, input: CreateUsageEvent) -> an
                 route_id,
                 channel_id,
                 model,
+                upstream_model,
                 if success { 1 } else { 0 },
                 http_status,
                 error_kind,
@@ -123,9 +179,18 @@ pub async fn insert_usage_event(db_path: PathBuf, input: CreateUsageEvent) -> an
                 cache_read_tokens,
                 cache_write_tokens,
                 estimated_cost_usd,
+                key_fingerprint,
+                if cancelled { 1 } else { 0 },
+                client_token_id,
+                if cache_hit { 1 } else { 0 },
             ],
         )?;
-        Ok(())
+        rollup::record_event(&tx, &rollup_event, attempt_index)?;
+        if let Some(cost) = rollup_event.cost_usd {
+            budget::add_balance_spend(&tx, &channel_id, ts_ms, cost)?;
+        }
+        tx.commit()?;
+        Ok(estimated_cost_usd)
     })
     .await;
     if res.is_ok() {
@@ -289,7 +354,8 @@ pub async fn backfill_usage_event_costs(db_path: PathBuf) -> anyhow::Result<i64>
     with_conn(db_path, move |conn| {
         let mut stmt = conn.prepare(
             r#"
-            SELECT id, model, success, prompt_tokens, completion_tokens, cache_read_tokens, cache_write_tokens
+            SELECT id, COALESCE(upstream_model, model), success, prompt_tokens, completion_tokens, cache_read_tokens, cache_write_tokens,
+                   ts_ms, channel_id, COALESCE(model, ''), protocol, COALESCE(route_id, '')
             FROM usage_events
             WHERE estimated_cost_usd IS NULL
               AND model IS NOT NULL
@@ -308,13 +374,30 @@ pub async fn backfill_usage_event_costs(db_path: PathBuf) -> anyhow::Result<i64>
                 row.get::<_, Option<i64>>(4)?,
                 row.get::<_, Option<i64>>(5)?,
                 row.get::<_, Option<i64>>(6)?,
+                row.get::<_, i64>(7)?,
+                RollupKey {
+                    channel_id: row.get(8)?,
+                    model: row.get(9)?,
+                    protocol: row.get(10)?,
+                    route_id: row.get(11)?,
+                },
             ))
         })?;
 
+        let tx = conn.unchecked_transaction()?;
         let mut updated = 0i64;
         for row in rows {
-            let (id, model, success, prompt_tokens, completion_tokens, cache_read_tokens, cache_write_tokens) =
-                row?;
+            let (
+                id,
+                model,
+                success,
+                prompt_tokens,
+                completion_tokens,
+                cache_read_tokens,
+                cache_write_tokens,
+                ts_ms,
+                key,
+            ) = row?;
             let Some(cost) = estimate_cost_usd(
                 conn,
                 &model,
@@ -326,12 +409,20 @@ pub async fn backfill_usage_event_costs(db_path: PathBuf) -> anyhow::Result<i64>
             ) else {
                 continue;
             };
-            let n = conn.execute(
+            let n = tx.execute(
                 "UPDATE usage_events SET estimated_cost_usd = ?1 WHERE id = ?2 AND estimated_cost_usd IS NULL",
                 params![cost, id],
             )?;
+            if n > 0
+                && let Ok(v) = cost.trim().parse::<f64>()
+            {
+                rollup::add_cost(&tx, ts_ms, &key, v)?;
+                budget::add_balance_spend(&tx, &key.channel_id, ts_ms, v)?;
+            }
             updated += n as i64;
         }
+        drop(stmt);
+        tx.commit()?;
 
         Ok(updated)
     })
@@ -346,10 +437,10 @@ pub async fn list_usage_events_recent(
         let mut stmt = conn.prepare(
             r#"
             SELECT id, request_id, ts_ms, protocol, route_id, channel_id, model,
-                   success, http_status, error_kind, error_detail, latency_ms,
+                   upstream_model, success, http_status, error_kind, error_detail, latency_ms,
                    ttft_ms, prompt_tokens, completion_tokens, total_tokens,
                    cache_read_tokens, cache_write_tokens,
-                   estimated_cost_usd
+                   estimated_cost_usd, key_fingerprint, cancelled, client_token_id, cache_hit
             FROM usage_events
             ORDER BY ts_ms DESC
             LIMIT ?1
@@ -364,18 +455,23 @@ pub async fn list_usage_events_recent(
                 route_id: row.get(4)?,
                 channel_id: row.get(5)?,
                 model: row.get(6)?,
-                success: row.get::<_, i64>(7)? != 0,
-                http_status: row.get(8)?,
-                error_kind: row.get(9)?,
-                error_detail: row.get(10)?,
-                latency_ms: row.get(11)?,
-                ttft_ms: row.get(12)?,
-                prompt_tokens: row.get(13)?,
-                completion_tokens: row.get(14)?,
-                total_tokens: row.get(15)?,
-                cache_read_tokens: row.get(16)?,
-                cache_write_tokens: row.get(17)?,
-                estimated_cost_usd: row.get(18)?,
+                upstream_model: row.get(7)?,
+                success: row.get::<_, i64>(8)? != 0,
+                http_status: row.get(9)?,
+                error_kind: row.get(10)?,
+                error_detail: row.get(11)?,
+                latency_ms: row.get(12)?,
+                ttft_ms: row.get(13)?,
+                prompt_tokens: row.get(14)?,
+                completion_tokens: row.get(15)?,
+                total_tokens: row.get(16)?,
+                cache_read_tokens: row.get(17)?,
+                cache_write_tokens: row.get(18)?,
+                estimated_cost_usd: row.get(19)?,
+                key_fingerprint: row.get(20)?,
+                cancelled: row.get::<_, i64>(21)? != 0,
+                client_token_id: row.get(22)?,
+                cache_hit: row.get::<_, i64>(23)? != 0,
             })
         })?;
         rows.collect::<rusqlite::Result<Vec<_>>>()
@@ -459,10 +555,10 @@ pub async fn list_usage_events(
         let sql = format!(
             r#"
             SELECT id, request_id, ts_ms, protocol, route_id, channel_id, model,
-                   success, http_status, error_kind, error_detail, latency_ms,
+                   upstream_model, success, http_status, error_kind, error_detail, latency_ms,
                    ttft_ms, prompt_tokens, completion_tokens, total_tokens,
                    cache_read_tokens, cache_write_tokens,
-                   estimated_cost_usd
+                   estimated_cost_usd, key_fingerprint, cancelled, client_token_id, cache_hit
             FROM usage_events
             {where_clause}
             ORDER BY ts_ms DESC
@@ -480,18 +576,23 @@ pub async fn list_usage_events(
                 route_id: row.get(4)?,
                 channel_id: row.get(5)?,
                 model: row.get(6)?,
-                success: row.get::<_, i64>(7)? != 0,
-                http_status: row.get(8)?,
-                error_kind: row.get(9)?,
-                error_detail: row.get(10)?,
-                latency_ms: row.get(11)?,
-                ttft_ms: row.get(12)?,
-                prompt_tokens: row.get(13)?,
-                completion_tokens: row.get(14)?,
-                total_tokens: row.get(15)?,
-                cache_read_tokens: row.get(16)?,
-                cache_write_tokens: row.get(17)?,
-                estimated_cost_usd: row.get(18)?,
+                upstream_model: row.get(7)?,
+                success: row.get::<_, i64>(8)? != 0,
+                http_status: row.get(9)?,
+                error_kind: row.get(10)?,
+                error_detail: row.get(11)?,
+                latency_ms: row.get(12)?,
+                ttft_ms: row.get(13)?,
+                prompt_tokens: row.get(14)?,
+                completion_tokens: row.get(15)?,
+                total_tokens: row.get(16)?,
+                cache_read_tokens: row.get(17)?,
+                cache_write_tokens: row.get(18)?,
+                estimated_cost_usd: row.get(19)?,
+                key_fingerprint: row.get(20)?,
+                cancelled: row.get::<_, i64>(21)? != 0,
+                client_token_id: row.get(22)?,
+                cache_hit: row.get::<_, i64>(23)? != 0,
             })
         })?;
 
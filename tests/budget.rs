@@ -0,0 +1,211 @@
+mod common;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use cliswitch::{proxy, storage};
+use common::{channel_input, spawn_upstream, temp_db_path};
+use tokio::time::Duration;
+
+#[tokio::test]
+async fn channel_over_spend_cap_is_skipped_with_warning() {
+    let capped_base = spawn_upstream(StatusCode::OK, r#"{"from":"capped"}"#).await;
+    let spare_base = spawn_upstream(StatusCode::OK, r#"{"from":"spare"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let mut capped_input = channel_input(
+        "capped",
+        storage::Protocol::Openai,
+        format!("{capped_base}/v1"),
+        "sk-capped",
+        10,
+    );
+    capped_input.spend_limit = Some(storage::SpendLimit {
+        monthly_cap: Some(1.0),
+        ..Default::default()
+    });
+    let capped = storage::create_channel(db_path.clone(), capped_input)
+        .await
+        .expect("create channel");
+    storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "spare",
+            storage::Protocol::Openai,
+            format!("{spare_base}/v1"),
+            "sk-spare",
+            0,
+        ),
+    )
+    .await
+    .expect("create channel");
+
+    // 0.2 USD × 7.2 = 1.44 CNY，超过 1 CNY 的月限额
+    storage::insert_usage_event(
+        db_path.clone(),
+        storage::CreateUsageEvent {
+            request_id: None,
+            attempt_index: 0,
+            ts_ms: storage::now_ms(),
+            protocol: storage::Protocol::Openai,
+            route_id: None,
+            channel_id: capped.id.clone(),
+            model: Some("gpt-test".to_string()),
+            upstream_model: None,
+            success: true,
+            cancelled: false,
+            http_status: Some(200),
+            error_kind: None,
+            error_detail: None,
+            latency_ms: 10,
+            ttft_ms: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            total_tokens: None,
+            cache_read_tokens: None,
+            cache_write_tokens: None,
+            estimated_cost_usd: Some("0.2".to_string()),
+            key_fingerprint: None,
+            client_token_id: None,
+            cache_hit: false,
+        },
+    )
+    .await
+    .expect("insert usage");
+
+    let mut events = cliswitch::events::subscribe();
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-test"}"#))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    let body = to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    assert_eq!(&body[..], br#"{"from":"spare"}"#);
+
+    let warning = loop {
+        let ev = tokio::time::timeout(Duration::from_secs(2), events.recv())
+            .await
+            .expect("spend warning")
+            .expect("event");
+        if let cliswitch::events::AppEvent::ChannelSpendWarning(w) = ev
+            && w.channel_id == capped.id
+        {
+            break w;
+        }
+    };
+    assert!(warning.exhausted);
+    assert_eq!(warning.kind, storage::SpendLimitKind::Monthly);
+    assert!((warning.spent - 1.44).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn channel_balance_spend_survives_retention() {
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let mut input = channel_input(
+        "prepaid",
+        storage::Protocol::Openai,
+        "https://api.example.com/v1".to_string(),
+        "sk-test",
+        10,
+    );
+    input.recharge_currency = Some(storage::RechargeCurrency::Usd);
+    input.spend_limit = Some(storage::SpendLimit {
+        balance: Some(10.0),
+        ..Default::default()
+    });
+    let channel = storage::create_channel(db_path.clone(), input)
+        .await
+        .expect("create channel");
+
+    let day_ms = 86_400_000;
+    let now = storage::now_ms();
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute(
+            "UPDATE channels SET spend_limit = json_set(spend_limit, '$.balance_set_at_ms', ?1) WHERE id = ?2",
+            rusqlite::params![now - 50 * day_ms, channel.id],
+        )
+        .expect("backdate balance");
+    }
+    storage::insert_usage_event(
+        db_path.clone(),
+        storage::CreateUsageEvent {
+            request_id: None,
+            attempt_index: 0,
+            ts_ms: now - 40 * day_ms,
+            protocol: storage::Protocol::Openai,
+            route_id: None,
+            channel_id: channel.id.clone(),
+            model: Some("m1".to_string()),
+            upstream_model: None,
+            success: true,
+            cancelled: false,
+            http_status: Some(200),
+            error_kind: None,
+            error_detail: None,
+            latency_ms: 10,
+            ttft_ms: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            total_tokens: None,
+            cache_read_tokens: None,
+            cache_write_tokens: None,
+            estimated_cost_usd: Some("2".to_string()),
+            key_fingerprint: None,
+            client_token_id: None,
+            cache_hit: false,
+        },
+    )
+    .await
+    .expect("insert usage");
+
+    let res = storage::prune_records(
+        db_path.clone(),
+        storage::RecordsRetention {
+            usage_events_before_ms: Some(now - 30 * day_ms),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("prune");
+    // 余额扣减是累计值，原始记录被清理后不变
+    assert_eq!(res.usage_events_deleted, 1);
+    let spend = storage::channel_spend(db_path.clone(), now, now - 41 * day_ms, 7.0)
+        .await
+        .expect("channel spend");
+    assert_eq!(spend[0].spent_since_balance, 2.0);
+    assert_eq!(spend[0].spent_month, 2.0);
+
+    // 重新设置余额后从零开始扣减
+    storage::update_channel(
+        db_path.clone(),
+        channel.id.clone(),
+        storage::UpdateChannel {
+            spend_limit: Some(Some(storage::SpendLimit {
+                balance: Some(20.0),
+                ..Default::default()
+            })),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update channel");
+    let spend = storage::channel_spend(db_path.clone(), now, now - 41 * day_ms, 7.0)
+        .await
+        .expect("channel spend");
+    assert_eq!(spend[0].spent_since_balance, 0.0);
+}
@@ -0,0 +1,244 @@
+mod common;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use cliswitch::{proxy, storage};
+use common::{channel_input, spawn_upstream_counted, temp_db_path};
+use std::sync::atomic::Ordering;
+use tokio::time::{Duration, sleep};
+
+#[tokio::test]
+async fn deterministic_request_is_served_from_cache() {
+    let (base, calls) = spawn_upstream_counted(
+        StatusCode::OK,
+        r#"{"id":"chatcmpl-1","object":"chat.completion","created":1,"model":"gpt-test","choices":[{"index":0,"message":{"role":"assistant","content":"cached hello"},"finish_reason":"stop"}],"usage":{"prompt_tokens":3,"completion_tokens":2,"total_tokens":5}}"#,
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            response_cache_enabled: Some(true),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+    let channel = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c1",
+            storage::Protocol::Openai,
+            format!("{base}/v1"),
+            "sk-test",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let send = |body: &'static str| {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("req");
+        proxy::forward(
+            &client,
+            db_path.clone(),
+            storage::Protocol::Openai,
+            "/v1",
+            req,
+        )
+    };
+
+    let first =
+        send(r#"{"model":"gpt-test","temperature":0,"messages":[{"role":"user","content":"hi"}]}"#)
+            .await
+            .expect("forward ok");
+    assert_eq!(first.status(), StatusCode::OK);
+    to_bytes(first.into_body(), usize::MAX).await.expect("body");
+    for _ in 0..100 {
+        let stats = storage::response_cache_stats(db_path.clone())
+            .await
+            .expect("cache stats");
+        if stats.entries > 0 {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    // 字段顺序不同、改为流式请求，仍命中同一条缓存并回放为 SSE
+    let second = send(r#"{"messages":[{"role":"user","content":"hi"}],"stream":true,"temperature":0,"model":"gpt-test"}"#)
+        .await
+        .expect("forward ok");
+    assert_eq!(
+        second
+            .headers()
+            .get("x-cliswitch-cache")
+            .map(|v| v.as_bytes()),
+        Some(&b"hit"[..])
+    );
+    let body = to_bytes(second.into_body(), usize::MAX)
+        .await
+        .expect("body");
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("chat.completion.chunk"), "body: {body}");
+    assert!(body.contains("cached hello"), "body: {body}");
+    assert!(body.trim_end().ends_with("data: [DONE]"), "body: {body}");
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    let mut hit = None;
+    for _ in 0..100 {
+        let events = storage::list_usage_events_recent(db_path.clone(), 10)
+            .await
+            .expect("list usage events");
+        hit = events.into_iter().find(|e| e.cache_hit);
+        if hit.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    let hit = hit.expect("cache hit usage event");
+    assert_eq!(hit.channel_id, channel.id);
+    assert_eq!(hit.estimated_cost_usd.as_deref(), Some("0"));
+    assert_eq!(hit.total_tokens, Some(5));
+
+    // 非确定性请求不走缓存
+    let third = send(
+        r#"{"model":"gpt-test","temperature":0.7,"messages":[{"role":"user","content":"hi"}]}"#,
+    )
+    .await
+    .expect("forward ok");
+    assert!(third.headers().get("x-cliswitch-cache").is_none());
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn cache_is_scoped_per_client_token_and_hits_count_toward_quota() {
+    let (base, calls) = spawn_upstream_counted(
+        StatusCode::OK,
+        r#"{"id":"chatcmpl-1","object":"chat.completion","created":1,"model":"gpt-test","choices":[{"index":0,"message":{"role":"assistant","content":"hello"},"finish_reason":"stop"}],"usage":{"prompt_tokens":3,"completion_tokens":2,"total_tokens":5}}"#,
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            response_cache_enabled: Some(true),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+    storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c1",
+            storage::Protocol::Openai,
+            format!("{base}/v1"),
+            "sk-test",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+    let token = |name: &str, tokens_per_day: Option<i64>| {
+        storage::create_client_token(
+            db_path.clone(),
+            storage::CreateClientToken {
+                name: name.to_string(),
+                role: storage::ClientTokenRole::Proxy,
+                protocols: Vec::new(),
+                route_ids: Vec::new(),
+                expires_at_ms: None,
+                requests_per_minute: None,
+                tokens_per_day,
+                usd_per_month: None,
+            },
+        )
+    };
+    let limited = token("limited", Some(8)).await.expect("create token");
+    let other = token("other", None).await.expect("create token");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let send = |token: &str| {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .header("x-cliswitch-token", token)
+            .body(Body::from(
+                r#"{"model":"gpt-test","temperature":0,"messages":[{"role":"user","content":"hi"}]}"#,
+            ))
+            .expect("req");
+        proxy::forward(
+            &client,
+            db_path.clone(),
+            storage::Protocol::Openai,
+            "/v1",
+            req,
+        )
+    };
+    let wait_for_events = |n: usize| {
+        let db_path = db_path.clone();
+        async move {
+            for _ in 0..100 {
+                let events = storage::list_usage_events_recent(db_path.clone(), 10)
+                    .await
+                    .expect("list usage events");
+                let entries = storage::response_cache_stats(db_path.clone())
+                    .await
+                    .expect("cache stats")
+                    .entries;
+                if events.len() >= n && entries > 0 {
+                    return events;
+                }
+                sleep(Duration::from_millis(10)).await;
+            }
+            panic!("timeout waiting for usage events");
+        }
+    };
+
+    let resp = send(&limited.token).await.expect("forward ok");
+    to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    wait_for_events(1).await;
+
+    // 其他令牌的相同请求不会命中这条缓存
+    let resp = send(&other.token).await.expect("forward ok");
+    assert!(resp.headers().get("x-cliswitch-cache").is_none());
+    to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+    let resp = send(&limited.token).await.expect("forward ok");
+    assert_eq!(
+        resp.headers()
+            .get("x-cliswitch-cache")
+            .map(|v| v.as_bytes()),
+        Some(&b"hit"[..])
+    );
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+    let events = wait_for_events(3).await;
+    let hit = events
+        .iter()
+        .find(|e| e.cache_hit)
+        .expect("cache hit event");
+    assert_eq!(
+        hit.client_token_id.as_deref(),
+        Some(limited.info.id.as_str())
+    );
+
+    // 命中的 token 同样计入每日配额
+    let err = send(&limited.token)
+        .await
+        .expect_err("daily tokens exceeded");
+    assert!(matches!(err, proxy::ProxyError::QuotaExceeded(_)));
+}
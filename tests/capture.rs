@@ -0,0 +1,116 @@
+mod common;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use cliswitch::{proxy, storage};
+use common::{channel_input, spawn_upstream_capture, temp_db_path, wait_for_usage_event};
+use tokio::time::{Duration, sleep};
+
+#[tokio::test]
+async fn captured_request_can_be_replayed_through_another_channel() {
+    let (first_base, _) = spawn_upstream_capture(StatusCode::OK, r#"{"from":"first"}"#).await;
+    let (second_base, second_seen) =
+        spawn_upstream_capture(StatusCode::OK, r#"{"from":"second"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            capture_enabled: Some(true),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+    storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "first",
+            storage::Protocol::Openai,
+            format!("{first_base}/v1"),
+            "sk-first",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+    let mut second_input = channel_input(
+        "second",
+        storage::Protocol::Openai,
+        format!("{second_base}/v1"),
+        "sk-second",
+        0,
+    );
+    second_input.enabled = false;
+    let second = storage::create_channel(db_path.clone(), second_input)
+        .await
+        .expect("create channel");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .header(axum::http::header::AUTHORIZATION, "Bearer sk-client-secret")
+        .body(Body::from(r#"{"model":"gpt-test","messages":[]}"#))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    let request_id = wait_for_usage_event(db_path.clone())
+        .await
+        .request_id
+        .expect("request id");
+
+    let mut capture = None;
+    for _ in 0..100 {
+        capture = storage::get_request_capture(db_path.clone(), request_id.clone())
+            .await
+            .expect("get capture")
+            .filter(|c| c.response_body.is_some());
+        if capture.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    let capture = capture.expect("capture with response");
+    assert_eq!(capture.path, "/v1/chat/completions");
+    assert_eq!(
+        capture.request_body,
+        br#"{"model":"gpt-test","messages":[]}"#
+    );
+    assert_eq!(
+        capture.response_body.as_deref(),
+        Some(&br#"{"from":"first"}"#[..])
+    );
+    assert_eq!(capture.response_status, Some(200));
+    let auth = capture
+        .request_headers
+        .iter()
+        .find(|(k, _)| k == "authorization")
+        .map(|(_, v)| v.as_str());
+    assert_eq!(auth, Some("REDACTED"));
+
+    // 重放可以指定未启用的渠道，并使用该渠道自己的密钥
+    let replayed = proxy::replay(&client, db_path.clone(), capture, second.clone())
+        .await
+        .expect("replay");
+    assert!(replayed.headers().contains_key("x-cliswitch-request-id"));
+    let body = to_bytes(replayed.into_body(), usize::MAX)
+        .await
+        .expect("body");
+    assert_eq!(&body[..], br#"{"from":"second"}"#);
+    let (path, seen_body) = second_seen.lock().expect("lock").clone().expect("seen");
+    assert_eq!(path, "/v1/chat/completions");
+    assert_eq!(seen_body, r#"{"model":"gpt-test","messages":[]}"#);
+}
@@ -0,0 +1,234 @@
+mod common;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use cliswitch::{proxy, storage};
+use common::{
+    assert_no_usage_events, channel_input, spawn_upstream, temp_db_path, wait_for_usage_event,
+};
+
+#[tokio::test]
+async fn client_token_is_checked_and_recorded_when_auth_enabled() {
+    let base = spawn_upstream(StatusCode::OK, r#"{"ok":true}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c1",
+            storage::Protocol::Openai,
+            format!("{base}/v1"),
+            "sk-upstream",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+    let anthropic_only = storage::create_client_token(
+        db_path.clone(),
+        storage::CreateClientToken {
+            name: "claude".to_string(),
+            role: storage::ClientTokenRole::Proxy,
+            protocols: vec![storage::Protocol::Anthropic],
+            route_ids: Vec::new(),
+            expires_at_ms: None,
+            requests_per_minute: None,
+            tokens_per_day: None,
+            usd_per_month: None,
+        },
+    )
+    .await
+    .expect("create token");
+    let editor = storage::create_client_token(
+        db_path.clone(),
+        storage::CreateClientToken {
+            name: "editor".to_string(),
+            role: storage::ClientTokenRole::Proxy,
+            protocols: Vec::new(),
+            route_ids: Vec::new(),
+            expires_at_ms: None,
+            requests_per_minute: None,
+            tokens_per_day: None,
+            usd_per_month: None,
+        },
+    )
+    .await
+    .expect("create token");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            client_auth_enabled: Some(true),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("enable client auth");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let call = |key: Option<&str>| {
+        let mut req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(axum::http::header::CONTENT_TYPE, "application/json");
+        if let Some(key) = key {
+            req = req.header(axum::http::header::AUTHORIZATION, format!("Bearer {key}"));
+        }
+        let req = req
+            .body(Body::from(r#"{"model":"gpt-test"}"#))
+            .expect("req");
+        proxy::forward(
+            &client,
+            db_path.clone(),
+            storage::Protocol::Openai,
+            "/v1",
+            req,
+        )
+    };
+
+    let err = call(None).await.expect_err("missing token");
+    assert!(matches!(err, proxy::ProxyError::Unauthorized(_)));
+    let err = call(Some("sk-placeholder"))
+        .await
+        .expect_err("unknown token");
+    assert!(matches!(err, proxy::ProxyError::Unauthorized(_)));
+    let err = call(Some(&anthropic_only.token))
+        .await
+        .expect_err("wrong protocol");
+    assert!(matches!(err, proxy::ProxyError::Forbidden(_)));
+    assert_no_usage_events(db_path.clone()).await;
+
+    let resp = call(Some(&editor.token)).await.expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let ev = wait_for_usage_event(db_path.clone()).await;
+    assert!(ev.success);
+    assert_eq!(ev.client_token_id.as_deref(), Some(editor.info.id.as_str()));
+
+    let resp = proxy::error_response(
+        storage::Protocol::Anthropic,
+        StatusCode::UNAUTHORIZED,
+        "bad token",
+    );
+    let body = to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    let v: serde_json::Value = serde_json::from_slice(&body).expect("json");
+    assert_eq!(v["error"]["type"], "authentication_error");
+}
+
+#[tokio::test]
+async fn client_token_quota_rejects_with_429() {
+    let base = spawn_upstream(
+        StatusCode::OK,
+        r#"{"usage":{"prompt_tokens":20,"completion_tokens":10,"total_tokens":30}}"#,
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c1",
+            storage::Protocol::Openai,
+            format!("{base}/v1"),
+            "sk-upstream",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+    let daily = storage::create_client_token(
+        db_path.clone(),
+        storage::CreateClientToken {
+            name: "daily".to_string(),
+            role: storage::ClientTokenRole::Proxy,
+            protocols: Vec::new(),
+            route_ids: Vec::new(),
+            expires_at_ms: None,
+            requests_per_minute: None,
+            tokens_per_day: Some(10),
+            usd_per_month: None,
+        },
+    )
+    .await
+    .expect("create token");
+    let per_minute = storage::create_client_token(
+        db_path.clone(),
+        storage::CreateClientToken {
+            name: "per-minute".to_string(),
+            role: storage::ClientTokenRole::Proxy,
+            protocols: Vec::new(),
+            route_ids: Vec::new(),
+            expires_at_ms: None,
+            requests_per_minute: Some(1),
+            tokens_per_day: None,
+            usd_per_month: None,
+        },
+    )
+    .await
+    .expect("create token");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let call = |token: &str| {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .header(axum::http::header::AUTHORIZATION, "Bearer sk-anything")
+            .header("x-cliswitch-token", token)
+            .body(Body::from(r#"{"model":"gpt-test"}"#))
+            .expect("req");
+        proxy::forward(
+            &client,
+            db_path.clone(),
+            storage::Protocol::Openai,
+            "/v1",
+            req,
+        )
+    };
+
+    let resp = call(&daily.token).await.expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let _ = to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    let ev = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(ev.client_token_id.as_deref(), Some(daily.info.id.as_str()));
+    let err = call(&daily.token).await.expect_err("daily tokens exceeded");
+    assert!(matches!(err, proxy::ProxyError::QuotaExceeded(_)));
+
+    // 没有可用渠道而被拒绝的请求不占用每分钟次数
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .header("x-cliswitch-token", per_minute.token.as_str())
+        .body(Body::from(r#"{"model":"claude-test"}"#))
+        .expect("req");
+    let err = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Anthropic,
+        "/v1",
+        req,
+    )
+    .await
+    .expect_err("no anthropic channel");
+    assert!(!matches!(err, proxy::ProxyError::QuotaExceeded(_)));
+
+    let resp = call(&per_minute.token).await.expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let err = call(&per_minute.token)
+        .await
+        .expect_err("requests per minute exceeded");
+    assert!(matches!(err, proxy::ProxyError::QuotaExceeded(_)));
+
+    let resp = proxy::error_response(
+        storage::Protocol::Openai,
+        StatusCode::TOO_MANY_REQUESTS,
+        "quota",
+    );
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    let v: serde_json::Value = serde_json::from_slice(&body).expect("json");
+    assert_eq!(v["error"]["type"], "rate_limit_error");
+}
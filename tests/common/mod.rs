@@ -0,0 +1,174 @@
+#![allow(dead_code)]
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode},
+    routing::any,
+};
+use cliswitch::{proxy, storage};
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use tokio::time::{Duration, sleep};
+
+pub async fn spawn_upstream(status: StatusCode, body: &'static str) -> String {
+    let app = Router::new().route(
+        "/{*path}",
+        any(move || async move {
+            (
+                status,
+                [(axum::http::header::CONTENT_TYPE, "application/json")],
+                body,
+            )
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let addr = listener.local_addr().expect("local_addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+
+    format!("http://127.0.0.1:{}", addr.port())
+}
+
+pub async fn spawn_upstream_counted(
+    status: StatusCode,
+    body: &'static str,
+) -> (String, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls2 = calls.clone();
+    let app = Router::new().route(
+        "/{*path}",
+        any(move || {
+            let calls = calls2.clone();
+            async move {
+                calls.fetch_add(1, Ordering::Relaxed);
+                (
+                    status,
+                    [(axum::http::header::CONTENT_TYPE, "application/json")],
+                    body,
+                )
+            }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let addr = listener.local_addr().expect("local_addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+
+    (format!("http://127.0.0.1:{}", addr.port()), calls)
+}
+
+pub type CapturedRequest = Arc<std::sync::Mutex<Option<(String, String)>>>;
+
+pub async fn spawn_upstream_capture(
+    status: StatusCode,
+    body: &'static str,
+) -> (String, CapturedRequest) {
+    spawn_upstream_capture_as(status, "application/json", body).await
+}
+
+pub async fn spawn_upstream_capture_as(
+    status: StatusCode,
+    content_type: &'static str,
+    body: &'static str,
+) -> (String, CapturedRequest) {
+    let captured: CapturedRequest = Arc::new(std::sync::Mutex::new(None));
+    let captured2 = captured.clone();
+    let app = Router::new().route(
+        "/{*path}",
+        any(move |uri: axum::http::Uri, req_body: String| {
+            let captured = captured2.clone();
+            async move {
+                *captured.lock().expect("lock") = Some((uri.path().to_string(), req_body));
+                (
+                    status,
+                    [(axum::http::header::CONTENT_TYPE, content_type)],
+                    body,
+                )
+            }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let addr = listener.local_addr().expect("local_addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+
+    (format!("http://127.0.0.1:{}", addr.port()), captured)
+}
+
+pub fn channel_input(
+    name: &str,
+    protocol: storage::Protocol,
+    base_url: String,
+    auth_ref: &str,
+    priority: i64,
+) -> storage::CreateChannel {
+    storage::CreateChannel {
+        name: name.to_string(),
+        protocol,
+        base_url,
+        auth_ref: auth_ref.to_string(),
+        priority,
+        ..Default::default()
+    }
+}
+
+pub fn temp_db_path() -> std::path::PathBuf {
+    // 测试环境没有系统钥匙串，统一改用口令派生密钥
+    storage::set_secret_passphrase("cliswitch-test".to_string());
+    let mut p = std::env::temp_dir();
+    p.push(format!("cliswitch-test-{}.sqlite", uuid::Uuid::new_v4()));
+    p
+}
+
+pub async fn wait_for_usage_event(db_path: std::path::PathBuf) -> storage::UsageEvent {
+    for _ in 0..100 {
+        let events = storage::list_usage_events_recent(db_path.clone(), 10)
+            .await
+            .expect("list usage events");
+        if let Some(e) = events.into_iter().next() {
+            return e;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timeout waiting for usage event");
+}
+
+pub async fn assert_no_usage_events(db_path: std::path::PathBuf) {
+    sleep(Duration::from_millis(50)).await;
+    let events = storage::list_usage_events_recent(db_path, 10)
+        .await
+        .expect("list usage events");
+    assert!(
+        events.is_empty(),
+        "expected no usage events, got {}",
+        events.len()
+    );
+}
+
+pub async fn forward_openai_chat(
+    db_path: std::path::PathBuf,
+) -> Result<axum::response::Response, proxy::ProxyError> {
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-test"}"#))
+        .expect("req");
+    proxy::forward(&client, db_path, storage::Protocol::Openai, "/v1", req).await
+}
@@ -0,0 +1,692 @@
+mod common;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use cliswitch::{proxy, storage};
+use common::{
+    channel_input, spawn_upstream_capture, spawn_upstream_capture_as, temp_db_path,
+    wait_for_usage_event,
+};
+
+async fn cross_protocol_route(
+    db_path: std::path::PathBuf,
+    route_protocol: storage::Protocol,
+    channel_protocol: storage::Protocol,
+    base: &str,
+) -> String {
+    let channel = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "cross",
+            channel_protocol,
+            format!("{base}{}", channel_protocol.root()),
+            "t",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+    let route = storage::create_route(
+        db_path.clone(),
+        storage::CreateRoute {
+            name: "cross".to_string(),
+            protocol: route_protocol,
+            match_model: None,
+            enabled: true,
+        },
+    )
+    .await
+    .expect("create route");
+    storage::set_route_channels(db_path, route.id, vec![channel.id.clone()])
+        .await
+        .expect("set route channels");
+    channel.id
+}
+
+#[tokio::test]
+async fn openai_chat_served_by_anthropic_channel() {
+    let (base, captured) = spawn_upstream_capture(
+        StatusCode::OK,
+        r#"{"id":"msg_1","type":"message","role":"assistant","model":"claude-sonnet-4","content":[{"type":"text","text":"hi"},{"type":"tool_use","id":"toolu_1","name":"lookup","input":{"q":"x"}}],"stop_reason":"tool_use","usage":{"input_tokens":10,"output_tokens":5,"cache_read_input_tokens":2}}"#,
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let channel_id = cross_protocol_route(
+        db_path.clone(),
+        storage::Protocol::Openai,
+        storage::Protocol::Anthropic,
+        &base,
+    )
+    .await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"model":"claude-sonnet-4","stop":"END","messages":[
+                {"role":"system","content":"be brief"},
+                {"role":"user","content":[{"type":"text","text":"look"},{"type":"image_url","image_url":{"url":"data:image/png;base64,AAAA"}}]},
+                {"role":"assistant","content":null,"tool_calls":[{"id":"call_0","type":"function","function":{"name":"lookup","arguments":"{\"q\":\"y\"}"}}]},
+                {"role":"tool","tool_call_id":"call_0","content":"found"}
+            ],"tools":[{"type":"function","function":{"name":"lookup","parameters":{"type":"object"}}}]}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let (path, body) = captured.lock().expect("lock").clone().expect("captured");
+    assert_eq!(path, "/v1/messages");
+    let sent: serde_json::Value = serde_json::from_str(&body).expect("json body");
+    assert_eq!(sent["system"][0]["text"], "be brief");
+    assert_eq!(sent["stop_sequences"][0], "END");
+    assert_eq!(sent["max_tokens"], 4096);
+    assert_eq!(
+        sent["messages"][0]["content"][1]["source"]["media_type"],
+        "image/png"
+    );
+    assert_eq!(sent["messages"][1]["content"][0]["type"], "tool_use");
+    assert_eq!(sent["messages"][1]["content"][0]["input"]["q"], "y");
+    assert_eq!(sent["messages"][2]["content"][0]["tool_use_id"], "call_0");
+    assert_eq!(sent["tools"][0]["input_schema"]["type"], "object");
+
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    let v: serde_json::Value = serde_json::from_slice(&bytes).expect("json resp");
+    assert_eq!(v["object"], "chat.completion");
+    assert_eq!(v["choices"][0]["message"]["content"], "hi");
+    assert_eq!(v["choices"][0]["finish_reason"], "tool_calls");
+    assert_eq!(
+        v["choices"][0]["message"]["tool_calls"][0]["function"]["arguments"],
+        r#"{"q":"x"}"#
+    );
+    assert_eq!(v["usage"]["prompt_tokens"], 12);
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.protocol, storage::Protocol::Openai);
+    assert_eq!(event.channel_id, channel_id);
+    assert_eq!(event.prompt_tokens, Some(10));
+    assert_eq!(event.completion_tokens, Some(5));
+}
+
+#[tokio::test]
+async fn openai_chat_stream_served_by_anthropic_channel() {
+    let (base, _) = spawn_upstream_capture_as(
+        StatusCode::OK,
+        "text/event-stream",
+        concat!(
+            "event: message_start\n",
+            r#"data: {"type":"message_start","message":{"id":"msg_1","model":"claude-sonnet-4","usage":{"input_tokens":7,"output_tokens":1}}}"#,
+            "\n\n",
+            "event: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+            "\n\n",
+            "event: message_delta\n",
+            r#"data: {"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":3}}"#,
+            "\n\n",
+            "event: message_stop\n",
+            r#"data: {"type":"message_stop"}"#,
+            "\n\n",
+        ),
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    cross_protocol_route(
+        db_path.clone(),
+        storage::Protocol::Openai,
+        storage::Protocol::Anthropic,
+        &base,
+    )
+    .await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"model":"claude-sonnet-4","stream":true,"stream_options":{"include_usage":true},"messages":[{"role":"user","content":"hi"}]}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    let text = std::str::from_utf8(&bytes).expect("utf8");
+    let chunks: Vec<serde_json::Value> = text
+        .lines()
+        .filter_map(|l| l.strip_prefix("data: "))
+        .filter(|d| *d != "[DONE]")
+        .map(|d| serde_json::from_str(d).expect("chunk json"))
+        .collect();
+    let content: String = chunks
+        .iter()
+        .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(content, "Hello");
+    assert!(
+        chunks
+            .iter()
+            .any(|c| c["choices"][0]["finish_reason"] == "stop")
+    );
+    assert_eq!(
+        chunks.last().expect("usage chunk")["usage"]["total_tokens"],
+        10
+    );
+    assert!(text.trim_end().ends_with("data: [DONE]"));
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.prompt_tokens, Some(7));
+    assert_eq!(event.completion_tokens, Some(3));
+}
+
+#[tokio::test]
+async fn cross_protocol_non_json_response_is_not_passed_through() {
+    let (base, _captured) =
+        spawn_upstream_capture_as(StatusCode::OK, "text/plain", "plain upstream text").await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    cross_protocol_route(
+        db_path.clone(),
+        storage::Protocol::Anthropic,
+        storage::Protocol::Openai,
+        &base,
+    )
+    .await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .header("x-api-key", "client-key")
+        .body(Body::from(
+            r#"{"model":"deepseek-chat","max_tokens":16,"messages":[{"role":"user","content":"hi"}]}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Anthropic,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    let v: serde_json::Value = serde_json::from_slice(&bytes).expect("json resp");
+    assert_eq!(v["type"], "error");
+    assert_eq!(v["error"]["type"], "api_error");
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert!(!event.success);
+    assert_eq!(event.error_kind.as_deref(), Some("translate_error"));
+}
+
+#[tokio::test]
+async fn anthropic_messages_served_by_openai_channel() {
+    let (base, captured) = spawn_upstream_capture(
+        StatusCode::OK,
+        r#"{"id":"chatcmpl-1","object":"chat.completion","model":"deepseek-chat","choices":[{"index":0,"message":{"role":"assistant","content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"read","arguments":"{\"path\":\"a.txt\"}"}}]},"finish_reason":"tool_calls"}],"usage":{"prompt_tokens":20,"completion_tokens":4,"total_tokens":24,"prompt_tokens_details":{"cached_tokens":5}}}"#,
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    cross_protocol_route(
+        db_path.clone(),
+        storage::Protocol::Anthropic,
+        storage::Protocol::Openai,
+        &base,
+    )
+    .await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .header("x-api-key", "client-key")
+        .body(Body::from(
+            r#"{"model":"deepseek-chat","max_tokens":256,"system":[{"type":"text","text":"you are an agent"}],"messages":[
+                {"role":"user","content":"open a.txt"},
+                {"role":"assistant","content":[{"type":"tool_use","id":"toolu_0","name":"read","input":{"path":"b.txt"}}]},
+                {"role":"user","content":[{"type":"tool_result","tool_use_id":"toolu_0","content":"missing"},{"type":"text","text":"try a.txt"}]}
+            ],"tools":[{"name":"read","input_schema":{"type":"object"}}],"tool_choice":{"type":"any"}}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Anthropic,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let (path, body) = captured.lock().expect("lock").clone().expect("captured");
+    assert_eq!(path, "/v1/chat/completions");
+    let sent: serde_json::Value = serde_json::from_str(&body).expect("json body");
+    let roles: Vec<&str> = sent["messages"]
+        .as_array()
+        .expect("messages")
+        .iter()
+        .map(|m| m["role"].as_str().unwrap_or(""))
+        .collect();
+    assert_eq!(roles, ["system", "user", "assistant", "tool", "user"]);
+    assert_eq!(
+        sent["messages"][2]["tool_calls"][0]["function"]["arguments"],
+        r#"{"path":"b.txt"}"#
+    );
+    assert_eq!(sent["messages"][3]["tool_call_id"], "toolu_0");
+    assert_eq!(sent["tools"][0]["function"]["name"], "read");
+    assert_eq!(sent["tool_choice"], "required");
+
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    let v: serde_json::Value = serde_json::from_slice(&bytes).expect("json resp");
+    assert_eq!(v["type"], "message");
+    assert_eq!(v["stop_reason"], "tool_use");
+    assert_eq!(v["content"][0]["type"], "tool_use");
+    assert_eq!(v["content"][0]["input"]["path"], "a.txt");
+    assert_eq!(v["usage"]["input_tokens"], 15);
+    assert_eq!(v["usage"]["cache_read_input_tokens"], 5);
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.prompt_tokens, Some(20));
+    assert_eq!(event.completion_tokens, Some(4));
+    assert_eq!(event.cache_read_tokens, Some(5));
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/messages/count_tokens")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"model":"deepseek-chat","messages":[{"role":"user","content":"abcdefgh"}]}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Anthropic,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    let v: serde_json::Value = serde_json::from_slice(&bytes).expect("json resp");
+    assert!(v["input_tokens"].as_i64().expect("input_tokens") > 0);
+}
+
+#[tokio::test]
+async fn anthropic_messages_stream_served_by_openai_channel() {
+    let (base, captured) = spawn_upstream_capture_as(
+        StatusCode::OK,
+        "text/event-stream",
+        concat!(
+            r#"data: {"id":"c1","model":"qwen","choices":[{"index":0,"delta":{"role":"assistant","content":"Hi"}}]}"#,
+            "\n\n",
+            r#"data: {"id":"c1","model":"qwen","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"ls","arguments":"{\"d\":"}}]}}]}"#,
+            "\n\n",
+            r#"data: {"id":"c1","model":"qwen","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"1}"}}]}}]}"#,
+            "\n\n",
+            r#"data: {"id":"c1","model":"qwen","choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#,
+            "\n\n",
+            r#"data: {"id":"c1","model":"qwen","choices":[],"usage":{"prompt_tokens":9,"completion_tokens":6,"total_tokens":15}}"#,
+            "\n\n",
+            "data: [DONE]\n\n",
+        ),
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    cross_protocol_route(
+        db_path.clone(),
+        storage::Protocol::Anthropic,
+        storage::Protocol::Openai,
+        &base,
+    )
+    .await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"model":"qwen","max_tokens":64,"stream":true,"messages":[{"role":"user","content":"hi"}]}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Anthropic,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+
+    let (_, body) = captured.lock().expect("lock").clone().expect("captured");
+    let sent: serde_json::Value = serde_json::from_str(&body).expect("json body");
+    assert_eq!(sent["stream_options"]["include_usage"], true);
+
+    let text = std::str::from_utf8(&bytes).expect("utf8");
+    let events: Vec<serde_json::Value> = text
+        .lines()
+        .filter_map(|l| l.strip_prefix("data: "))
+        .map(|d| serde_json::from_str(d).expect("event json"))
+        .collect();
+    let types: Vec<&str> = events
+        .iter()
+        .map(|e| e["type"].as_str().unwrap_or(""))
+        .collect();
+    assert_eq!(
+        types,
+        [
+            "message_start",
+            "content_block_start",
+            "content_block_delta",
+            "content_block_stop",
+            "content_block_start",
+            "content_block_delta",
+            "content_block_delta",
+            "content_block_stop",
+            "message_delta",
+            "message_stop",
+        ]
+    );
+    assert_eq!(events[4]["content_block"]["name"], "ls");
+    assert_eq!(events[4]["index"], 1);
+    assert_eq!(events[8]["delta"]["stop_reason"], "tool_use");
+    assert_eq!(events[8]["usage"]["output_tokens"], 6);
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.prompt_tokens, Some(9));
+    assert_eq!(event.completion_tokens, Some(6));
+}
+
+#[tokio::test]
+async fn gemini_generate_served_by_openai_channel() {
+    let (base, captured) = spawn_upstream_capture(
+        StatusCode::OK,
+        r#"{"id":"chatcmpl-9","object":"chat.completion","model":"gpt-4o","choices":[{"index":0,"message":{"role":"assistant","content":null,"tool_calls":[{"id":"call_x","type":"function","function":{"name":"weather","arguments":"{\"city\":\"Paris\"}"}}]},"finish_reason":"tool_calls"}],"usage":{"prompt_tokens":30,"completion_tokens":8,"total_tokens":38}}"#,
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    cross_protocol_route(
+        db_path.clone(),
+        storage::Protocol::Gemini,
+        storage::Protocol::Openai,
+        &base,
+    )
+    .await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1beta/models/gpt-4o:generateContent?key=client-key")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"systemInstruction":{"parts":[{"text":"be terse"}]},"contents":[
+                {"role":"user","parts":[{"text":"weather in Rome?"}]},
+                {"role":"model","parts":[{"functionCall":{"name":"weather","args":{"city":"Rome"}}}]},
+                {"role":"user","parts":[{"functionResponse":{"name":"weather","response":{"temp":20}}}]},
+                {"role":"user","parts":[{"text":"and Paris?"}]}
+            ],"tools":[{"functionDeclarations":[{"name":"weather","parameters":{"type":"OBJECT","properties":{"city":{"type":"STRING"}}}}]}],
+            "generationConfig":{"maxOutputTokens":100,"stopSequences":["END"]}}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Gemini,
+        "/v1beta",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let (path, body) = captured.lock().expect("lock").clone().expect("captured");
+    assert_eq!(path, "/v1/chat/completions");
+    let sent: serde_json::Value = serde_json::from_str(&body).expect("json body");
+    assert_eq!(sent["model"], "gpt-4o");
+    assert_eq!(sent["messages"][0]["content"], "be terse");
+    assert_eq!(sent["messages"][2]["tool_calls"][0]["id"], "call_0");
+    assert_eq!(sent["messages"][3]["role"], "tool");
+    assert_eq!(sent["messages"][3]["tool_call_id"], "call_0");
+    assert_eq!(sent["max_tokens"], 100);
+    assert_eq!(sent["stop"][0], "END");
+    assert_eq!(
+        sent["tools"][0]["function"]["parameters"]["properties"]["city"]["type"],
+        "string"
+    );
+
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    let v: serde_json::Value = serde_json::from_slice(&bytes).expect("json resp");
+    let part = &v["candidates"][0]["content"]["parts"][0];
+    assert_eq!(part["functionCall"]["name"], "weather");
+    assert_eq!(part["functionCall"]["args"]["city"], "Paris");
+    assert_eq!(v["candidates"][0]["finishReason"], "STOP");
+    assert_eq!(v["usageMetadata"]["totalTokenCount"], 38);
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.protocol, storage::Protocol::Gemini);
+    assert_eq!(event.prompt_tokens, Some(30));
+    assert_eq!(event.completion_tokens, Some(8));
+}
+
+#[tokio::test]
+async fn openai_chat_stream_served_by_gemini_channel() {
+    let (base, captured) = spawn_upstream_capture_as(
+        StatusCode::OK,
+        "text/event-stream",
+        concat!(
+            r#"data: {"candidates":[{"content":{"role":"model","parts":[{"text":"Sun"}]},"index":0}],"modelVersion":"gemini-2.5-flash"}"#,
+            "\r\n\r\n",
+            r#"data: {"candidates":[{"content":{"role":"model","parts":[{"text":"ny"}]},"finishReason":"STOP","index":0}],"usageMetadata":{"promptTokenCount":11,"candidatesTokenCount":2,"totalTokenCount":13},"modelVersion":"gemini-2.5-flash"}"#,
+            "\r\n\r\n",
+        ),
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    cross_protocol_route(
+        db_path.clone(),
+        storage::Protocol::Openai,
+        storage::Protocol::Gemini,
+        &base,
+    )
+    .await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .header(axum::http::header::AUTHORIZATION, "Bearer client-key")
+        .body(Body::from(
+            r#"{"model":"gemini-2.5-flash","stream":true,"stream_options":{"include_usage":true},"messages":[{"role":"system","content":"sys"},{"role":"user","content":"weather?"}]}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+
+    let (path, body) = captured.lock().expect("lock").clone().expect("captured");
+    assert_eq!(
+        path,
+        "/v1beta/models/gemini-2.5-flash:streamGenerateContent"
+    );
+    let sent: serde_json::Value = serde_json::from_str(&body).expect("json body");
+    assert_eq!(sent["systemInstruction"]["parts"][0]["text"], "sys");
+    assert_eq!(sent["contents"][0]["role"], "user");
+    assert_eq!(sent["contents"][0]["parts"][0]["text"], "weather?");
+
+    let text = std::str::from_utf8(&bytes).expect("utf8");
+    let chunks: Vec<serde_json::Value> = text
+        .lines()
+        .filter_map(|l| l.strip_prefix("data: "))
+        .filter(|d| *d != "[DONE]")
+        .map(|d| serde_json::from_str(d).expect("chunk json"))
+        .collect();
+    let content: String = chunks
+        .iter()
+        .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(content, "Sunny");
+    assert_eq!(
+        chunks.last().expect("usage chunk")["usage"]["total_tokens"],
+        13
+    );
+    assert!(text.trim_end().ends_with("data: [DONE]"));
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.prompt_tokens, Some(11));
+    assert_eq!(event.completion_tokens, Some(2));
+}
+
+#[tokio::test]
+async fn gemini_stream_served_by_anthropic_channel() {
+    let (base, captured) = spawn_upstream_capture_as(
+        StatusCode::OK,
+        "text/event-stream",
+        concat!(
+            "event: message_start\n",
+            r#"data: {"type":"message_start","message":{"id":"msg_1","model":"claude-haiku","usage":{"input_tokens":5,"output_tokens":1}}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"ok"}}"#,
+            "\n\n",
+            "event: message_delta\n",
+            r#"data: {"type":"message_delta","delta":{"stop_reason":"max_tokens"},"usage":{"output_tokens":2}}"#,
+            "\n\n",
+            "event: message_stop\n",
+            r#"data: {"type":"message_stop"}"#,
+            "\n\n",
+        ),
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    cross_protocol_route(
+        db_path.clone(),
+        storage::Protocol::Gemini,
+        storage::Protocol::Anthropic,
+        &base,
+    )
+    .await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1beta/models/claude-haiku:streamGenerateContent?alt=sse")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"contents":[{"role":"user","parts":[{"text":"hi"}]}]}"#,
+        ))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Gemini,
+        "/v1beta",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+
+    let (path, body) = captured.lock().expect("lock").clone().expect("captured");
+    assert_eq!(path, "/v1/messages");
+    let sent: serde_json::Value = serde_json::from_str(&body).expect("json body");
+    assert_eq!(sent["model"], "claude-haiku");
+    assert_eq!(sent["stream"], true);
+
+    let text = std::str::from_utf8(&bytes).expect("utf8");
+    let chunks: Vec<serde_json::Value> = text
+        .lines()
+        .filter_map(|l| l.strip_prefix("data: "))
+        .map(|d| serde_json::from_str(d).expect("chunk json"))
+        .collect();
+    let content: String = chunks
+        .iter()
+        .filter_map(|c| c["candidates"][0]["content"]["parts"][0]["text"].as_str())
+        .collect();
+    assert_eq!(content, "ok");
+    let last = chunks.last().expect("last chunk");
+    assert_eq!(last["candidates"][0]["finishReason"], "MAX_TOKENS");
+    assert_eq!(last["usageMetadata"]["promptTokenCount"], 5);
+    assert_eq!(last["usageMetadata"]["candidatesTokenCount"], 2);
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.prompt_tokens, Some(5));
+    assert_eq!(event.completion_tokens, Some(2));
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use cliswitch::storage;
+use common::temp_db_path;
+
+#[tokio::test]
+async fn init_db_upgrades_usage_events_without_request_id() {
+    let db_path = temp_db_path();
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute_batch(
+            r#"
+            CREATE TABLE usage_events (
+              id TEXT PRIMARY KEY,
+              ts_ms INTEGER NOT NULL,
+              protocol TEXT NOT NULL,
+              route_id TEXT NULL,
+              channel_id TEXT NOT NULL,
+              model TEXT NULL,
+              success INTEGER NOT NULL,
+              http_status INTEGER NULL,
+              error_kind TEXT NULL,
+              latency_ms INTEGER NOT NULL,
+              prompt_tokens INTEGER NULL,
+              completion_tokens INTEGER NULL,
+              total_tokens INTEGER NULL,
+              estimated_cost_usd TEXT NULL
+            );
+            INSERT INTO usage_events (id, ts_ms, protocol, channel_id, model, success, latency_ms, total_tokens)
+            VALUES ('e1', 1706747400000, 'openai', 'c1', 'm1', 1, 120, 9);
+            "#,
+        )
+        .expect("create old schema");
+    }
+    storage::init_db(&db_path).expect("init_db on old schema");
+
+    let summary = storage::stats_summary(
+        db_path.clone(),
+        storage::StatsWindow {
+            start_ms: 1_706_745_600_000,
+            end_ms: Some(1_706_832_000_000),
+        },
+    )
+    .await
+    .expect("summary");
+    assert_eq!((summary.requests, summary.total_tokens), (1, 9));
+}
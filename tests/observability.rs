@@ -0,0 +1,236 @@
+mod common;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+    routing::any,
+};
+use cliswitch::{proxy, storage};
+use common::{
+    channel_input, forward_openai_chat, spawn_upstream, temp_db_path, wait_for_usage_event,
+};
+use std::sync::Arc;
+use tokio::time::{Duration, sleep};
+
+#[tokio::test]
+async fn metrics_track_requests_tokens_and_channel_state() {
+    let upstream = spawn_upstream(
+        StatusCode::OK,
+        r#"{"id":"ok","usage":{"prompt_tokens":7,"completion_tokens":3,"total_tokens":10}}"#,
+    )
+    .await;
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let channel = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "metrics \"c1\"",
+            storage::Protocol::Openai,
+            upstream,
+            "k1",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+
+    let resp = forward_openai_chat(db_path.clone())
+        .await
+        .expect("forward ok");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let _ = to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    wait_for_usage_event(db_path.clone()).await;
+
+    let channels = storage::list_channels(db_path.clone())
+        .await
+        .expect("list channels");
+    let text = cliswitch::metrics::render(&channels, storage::now_ms());
+    let labels = format!(
+        r#"protocol="openai",channel="{}",model="gpt-test""#,
+        channel.id
+    );
+    assert!(
+        text.contains(&format!(
+            "cliswitch_requests_total{{{labels},status=\"200\"}} 1"
+        )),
+        "{text}"
+    );
+    assert!(text.contains(&format!(
+        "cliswitch_tokens_total{{{labels},type=\"prompt\"}} 7"
+    )));
+    assert!(text.contains(&format!(
+        "cliswitch_tokens_total{{{labels},type=\"completion\"}} 3"
+    )));
+    assert!(text.contains(&format!(
+        "cliswitch_request_duration_seconds_count{{{labels}}} 1"
+    )));
+    assert!(text.contains(&format!(
+        r#"cliswitch_channel_info{{channel="{}",name="metrics \"c1\"",protocol="openai"}} 1"#,
+        channel.id
+    )));
+    assert!(text.contains(&format!(
+        "cliswitch_channel_auto_disabled{{channel=\"{}\"}} 0",
+        channel.id
+    )));
+}
+
+#[tokio::test]
+async fn otlp_spans_cover_request_and_attempts_with_traceparent() {
+    type Seen = Arc<std::sync::Mutex<Vec<serde_json::Value>>>;
+    let exported: Seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let exported2 = exported.clone();
+    let collector = Router::new().route(
+        "/v1/traces",
+        any(move |axum::Json(v): axum::Json<serde_json::Value>| {
+            let exported = exported2.clone();
+            async move {
+                exported.lock().expect("lock").push(v);
+                StatusCode::OK
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let collector_addr = listener.local_addr().expect("local_addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, collector).await;
+    });
+
+    let upstream_traceparent = Arc::new(std::sync::Mutex::new(None::<String>));
+    let seen = upstream_traceparent.clone();
+    let upstream = Router::new().route(
+        "/{*path}",
+        any(move |headers: axum::http::HeaderMap| {
+            let seen = seen.clone();
+            async move {
+                *seen.lock().expect("lock") = headers
+                    .get("traceparent")
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string);
+                (
+                    StatusCode::OK,
+                    [(axum::http::header::CONTENT_TYPE, "application/json")],
+                    r#"{"id":"ok","usage":{"prompt_tokens":4,"completion_tokens":2,"total_tokens":6}}"#,
+                )
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let upstream_addr = listener.local_addr().expect("local_addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, upstream).await;
+    });
+
+    let failing = spawn_upstream(StatusCode::INTERNAL_SERVER_ERROR, r#"{"error":"boom"}"#).await;
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            otlp_enabled: Some(true),
+            otlp_endpoint: Some(format!("http://{collector_addr}/v1/traces")),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+    let bad = storage::create_channel(
+        db_path.clone(),
+        channel_input("bad", storage::Protocol::Openai, failing, "k1", 20),
+    )
+    .await
+    .expect("create channel");
+    let good = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "good",
+            storage::Protocol::Openai,
+            format!("http://{upstream_addr}"),
+            "k2",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .header("traceparent", format!("00-{trace_id}-00f067aa0ba902b7-01"))
+        .body(Body::from(r#"{"model":"gpt-test"}"#))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward ok");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let _ = to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+
+    let mut spans = Vec::new();
+    for _ in 0..100 {
+        if let Some(v) = exported.lock().expect("lock").first() {
+            spans = v["resourceSpans"][0]["scopeSpans"][0]["spans"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(spans.len(), 3, "{spans:?}");
+    let root = &spans[0];
+    assert_eq!(root["traceId"], trace_id);
+    assert_eq!(root["parentSpanId"], "00f067aa0ba902b7");
+    let attr = |span: &serde_json::Value, key: &str| {
+        span["attributes"]
+            .as_array()
+            .and_then(|a| a.iter().find(|kv| kv["key"] == key))
+            .map(|kv| kv["value"].clone())
+    };
+    let attempt_for = |channel_id: &str| {
+        spans[1..]
+            .iter()
+            .find(|s| {
+                attr(s, "cliswitch.channel.id").map(|v| v["stringValue"].clone())
+                    == Some(serde_json::json!(channel_id))
+            })
+            .cloned()
+            .expect("attempt span")
+    };
+    let failed = attempt_for(&bad.id);
+    assert_eq!(failed["parentSpanId"], root["spanId"]);
+    assert_eq!(failed["status"]["code"], 2);
+    let ok = attempt_for(&good.id);
+    assert_eq!(ok["parentSpanId"], root["spanId"]);
+    assert_eq!(ok["status"]["code"], 1);
+    assert_eq!(
+        attr(&ok, "gen_ai.usage.input_tokens"),
+        Some(serde_json::json!({ "intValue": "4" }))
+    );
+
+    // 上游收到的 traceparent 沿用客户端的 trace-id，父 span 换成本次尝试
+    let sent = upstream_traceparent
+        .lock()
+        .expect("lock")
+        .clone()
+        .expect("traceparent sent upstream");
+    assert_eq!(
+        sent,
+        format!(
+            "00-{trace_id}-{}-01",
+            ok["spanId"].as_str().expect("span id")
+        )
+    );
+}
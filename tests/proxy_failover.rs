@@ -1,3 +1,5 @@
+mod common;
+
 use axum::{
     Router,
     body::{Body, to_bytes},
@@ -5,99 +7,14 @@ use axum::{
     routing::any,
 };
 use cliswitch::{proxy, storage};
-use std::sync::{
-    Arc,
-    atomic::{AtomicUsize, Ordering},
+use common::{
+    assert_no_usage_events, channel_input, spawn_upstream, spawn_upstream_capture,
+    spawn_upstream_counted, temp_db_path, wait_for_usage_event,
 };
+use std::sync::atomic::Ordering;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::time::{Duration, sleep};
 
-async fn spawn_upstream(status: StatusCode, body: &'static str) -> String {
-    let app = Router::new().route(
-        "/{*path}",
-        any(move || async move {
-            (
-                status,
-                [(axum::http::header::CONTENT_TYPE, "application/json")],
-                body,
-            )
-        }),
-    );
-
-    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
-        .await
-        .expect("bind");
-    let addr = listener.local_addr().expect("local_addr");
-    tokio::spawn(async move {
-        let _ = axum::serve(listener, app).await;
-    });
-
-    format!("http://127.0.0.1:{}", addr.port())
-}
-
-async fn spawn_upstream_counted(
-    status: StatusCode,
-    body: &'static str,
-) -> (String, Arc<AtomicUsize>) {
-    let calls = Arc::new(AtomicUsize::new(0));
-    let calls2 = calls.clone();
-    let app = Router::new().route(
-        "/{*path}",
-        any(move || {
-            let calls = calls2.clone();
-            async move {
-                calls.fetch_add(1, Ordering::Relaxed);
-                (
-                    status,
-                    [(axum::http::header::CONTENT_TYPE, "application/json")],
-                    body,
-                )
-            }
-        }),
-    );
-
-    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
-        .await
-        .expect("bind");
-    let addr = listener.local_addr().expect("local_addr");
-    tokio::spawn(async move {
-        let _ = axum::serve(listener, app).await;
-    });
-
-    (format!("http://127.0.0.1:{}", addr.port()), calls)
-}
-
-fn temp_db_path() -> std::path::PathBuf {
-    let mut p = std::env::temp_dir();
-    p.push(format!("cliswitch-test-{}.sqlite", uuid::Uuid::new_v4()));
-    p
-}
-
-async fn wait_for_usage_event(db_path: std::path::PathBuf) -> storage::UsageEvent {
-    for _ in 0..100 {
-        let events = storage::list_usage_events_recent(db_path.clone(), 10)
-            .await
-            .expect("list usage events");
-        if let Some(e) = events.into_iter().next() {
-            return e;
-        }
-        sleep(Duration::from_millis(10)).await;
-    }
-    panic!("timeout waiting for usage event");
-}
-
-async fn assert_no_usage_events(db_path: std::path::PathBuf) {
-    sleep(Duration::from_millis(50)).await;
-    let events = storage::list_usage_events_recent(db_path, 10)
-        .await
-        .expect("list usage events");
-    assert!(
-        events.is_empty(),
-        "expected no usage events, got {}",
-        events.len()
-    );
-}
-
 #[tokio::test]
 async fn failover_on_non_200_until_success() {
     let base1 = spawn_upstream(StatusCode::INTERNAL_SERVER_ERROR, r#"{"err":"c1"}"#).await;
@@ -119,6 +36,7 @@ async fn failover_on_non_200_until_success() {
             recharge_currency: None,
             real_multiplier: None,
             enabled: true,
+            ..Default::default()
         },
     )
     .await
@@ -135,6 +53,7 @@ async fn failover_on_non_200_until_success() {
             recharge_currency: None,
             real_multiplier: None,
             enabled: true,
+            ..Default::default()
         },
     )
     .await
@@ -151,6 +70,7 @@ async fn failover_on_non_200_until_success() {
             recharge_currency: None,
             real_multiplier: None,
             enabled: true,
+            ..Default::default()
         },
     )
     .await
@@ -203,6 +123,7 @@ async fn return_last_error_when_all_channels_fail() {
                 recharge_currency: None,
                 real_multiplier: None,
                 enabled: true,
+                ..Default::default()
             },
         )
         .await
@@ -276,6 +197,7 @@ async fn gemini_logs_include_model_and_cost() {
             recharge_currency: None,
             real_multiplier: None,
             enabled: true,
+            ..Default::default()
         },
     )
     .await
@@ -332,6 +254,7 @@ async fn anthropic_count_tokens_no_failover_and_no_usage_log() {
             recharge_currency: None,
             real_multiplier: None,
             enabled: true,
+            ..Default::default()
         },
     )
     .await
@@ -348,6 +271,7 @@ async fn anthropic_count_tokens_no_failover_and_no_usage_log() {
             recharge_currency: None,
             real_multiplier: None,
             enabled: true,
+            ..Default::default()
         },
     )
     .await
@@ -418,6 +342,7 @@ async fn anthropic_count_tokens_does_not_auto_disable() {
             recharge_currency: None,
             real_multiplier: None,
             enabled: true,
+            ..Default::default()
         },
     )
     .await
@@ -452,3 +377,1089 @@ async fn anthropic_count_tokens_does_not_auto_disable() {
 
     assert_no_usage_events(db_path.clone()).await;
 }
+
+#[tokio::test]
+async fn route_match_model_uses_route_channels() {
+    let (base1, c1_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"from":"c1"}"#).await;
+    let (base2, c2_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"from":"c2"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+
+    let mut channel_ids = Vec::new();
+    for (name, base, priority) in [("c1", base1, 30), ("c2", base2, 10)] {
+        let channel = storage::create_channel(
+            db_path.clone(),
+            channel_input(
+                name,
+                storage::Protocol::Openai,
+                format!("{base}/v1"),
+                "t",
+                priority,
+            ),
+        )
+        .await
+        .expect("create channel");
+        channel_ids.push(channel.id);
+    }
+
+    let route = storage::create_route(
+        db_path.clone(),
+        storage::CreateRoute {
+            name: "routed".to_string(),
+            protocol: storage::Protocol::Openai,
+            match_model: Some("gpt-routed".to_string()),
+            enabled: true,
+        },
+    )
+    .await
+    .expect("create route");
+    storage::set_route_channels(
+        db_path.clone(),
+        route.id.clone(),
+        vec![channel_ids[1].clone()],
+    )
+    .await
+    .expect("set route channels");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-routed"}"#))
+        .expect("req");
+
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    assert_eq!(std::str::from_utf8(&bytes).unwrap(), r#"{"from":"c2"}"#);
+    assert_eq!(c1_calls.load(Ordering::Relaxed), 0);
+    assert_eq!(c2_calls.load(Ordering::Relaxed), 1);
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.route_id.as_deref(), Some(route.id.as_str()));
+    assert_eq!(event.channel_id, channel_ids[1]);
+}
+
+#[tokio::test]
+async fn unmatched_model_is_rejected_unless_catch_all_route_exists() {
+    let (base1, c1_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"from":"c1"}"#).await;
+    let (base2, c2_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"from":"c2"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+
+    let mut channel_ids = Vec::new();
+    for (name, base) in [("c1", base1), ("c2", base2)] {
+        let channel = storage::create_channel(
+            db_path.clone(),
+            channel_input(
+                name,
+                storage::Protocol::Openai,
+                format!("{base}/v1"),
+                "t",
+                10,
+            ),
+        )
+        .await
+        .expect("create channel");
+        channel_ids.push(channel.id);
+    }
+
+    let create_route = |name: &str, match_model: Option<&str>, channel_id: String| {
+        let db_path = db_path.clone();
+        let input = storage::CreateRoute {
+            name: name.to_string(),
+            protocol: storage::Protocol::Openai,
+            match_model: match_model.map(str::to_string),
+            enabled: true,
+        };
+        async move {
+            let route = storage::create_route(db_path.clone(), input)
+                .await
+                .expect("create route");
+            storage::set_route_channels(db_path, route.id, vec![channel_id])
+                .await
+                .expect("set route channels");
+        }
+    };
+    create_route("routed", Some("gpt-routed"), channel_ids[1].clone()).await;
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let send = || {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"model":"gpt-other"}"#))
+            .expect("req");
+        proxy::forward(
+            &client,
+            db_path.clone(),
+            storage::Protocol::Openai,
+            "/v1",
+            req,
+        )
+    };
+
+    let err = send().await.expect_err("no route matches");
+    assert!(
+        matches!(&err, proxy::ProxyError::NoMatchingRoute(_, model) if model == "gpt-other"),
+        "unexpected error: {err}"
+    );
+    assert_eq!(c1_calls.load(Ordering::Relaxed), 0);
+    assert_eq!(c2_calls.load(Ordering::Relaxed), 0);
+
+    // 不填匹配模型的路由作为兜底
+    create_route("fallback", None, channel_ids[0].clone()).await;
+    let resp = send().await.expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(c1_calls.load(Ordering::Relaxed), 1);
+    assert_eq!(c2_calls.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn route_glob_takes_precedence_over_regex() {
+    let (base1, c1_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"from":"c1"}"#).await;
+    let (base2, c2_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"from":"c2"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+
+    let mut channel_ids = Vec::new();
+    for (name, base) in [("c1", base1), ("c2", base2)] {
+        let channel = storage::create_channel(
+            db_path.clone(),
+            channel_input(
+                name,
+                storage::Protocol::Openai,
+                format!("{base}/v1"),
+                "t",
+                10,
+            ),
+        )
+        .await
+        .expect("create channel");
+        channel_ids.push(channel.id);
+    }
+
+    for (name, pattern, channel_id) in [
+        ("a-regex", "^gpt-.*", channel_ids[1].clone()),
+        ("b-glob", "gpt-4o*", channel_ids[0].clone()),
+    ] {
+        let route = storage::create_route(
+            db_path.clone(),
+            storage::CreateRoute {
+                name: name.to_string(),
+                protocol: storage::Protocol::Openai,
+                match_model: Some(pattern.to_string()),
+                enabled: true,
+            },
+        )
+        .await
+        .expect("create route");
+        storage::set_route_channels(db_path.clone(), route.id, vec![channel_id])
+            .await
+            .expect("set route channels");
+    }
+
+    let resolved = proxy::resolve_route(
+        db_path.clone(),
+        storage::Protocol::Openai,
+        Some("gpt-4o-mini"),
+    )
+    .await
+    .expect("resolve");
+    assert_eq!(resolved.match_kind, Some(storage::ModelMatchKind::Glob));
+    assert_eq!(resolved.channels.len(), 1);
+    assert_eq!(resolved.channels[0].id, channel_ids[0]);
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-4.1"}"#))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(c1_calls.load(Ordering::Relaxed), 0);
+    assert_eq!(c2_calls.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn channel_model_map_rewrites_upstream_model() {
+    let (base, captured) = spawn_upstream_capture(StatusCode::OK, r#"{"ok":true}"#).await;
+    let (gbase, gcaptured) = spawn_upstream_capture(StatusCode::OK, r#"{"ok":true}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+
+    let mut input = channel_input(
+        "c1",
+        storage::Protocol::Openai,
+        format!("{base}/v1"),
+        "t",
+        10,
+    );
+    input
+        .model_map
+        .insert("gpt-alias".to_string(), "vendor/gpt-real".to_string());
+    storage::create_channel(db_path.clone(), input)
+        .await
+        .expect("create channel");
+
+    let mut input = channel_input(
+        "g1",
+        storage::Protocol::Gemini,
+        format!("{gbase}/v1beta"),
+        "t",
+        10,
+    );
+    input
+        .model_map
+        .insert("gemini-*".to_string(), "gemini-2.5-pro".to_string());
+    storage::create_channel(db_path.clone(), input)
+        .await
+        .expect("create channel");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-alias","messages":[]}"#))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let (_, body) = captured.lock().expect("lock").clone().expect("captured");
+    let v: serde_json::Value = serde_json::from_str(&body).expect("json body");
+    assert_eq!(v["model"], "vendor/gpt-real");
+
+    let event = wait_for_usage_event(db_path.clone()).await;
+    assert_eq!(event.model.as_deref(), Some("gpt-alias"));
+    assert_eq!(event.upstream_model.as_deref(), Some("vendor/gpt-real"));
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1beta/models/gemini-flash:generateContent")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"contents":[]}"#))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Gemini,
+        "/v1beta",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let (path, _) = gcaptured.lock().expect("lock").clone().expect("captured");
+    assert_eq!(path, "/v1beta/models/gemini-2.5-pro:generateContent");
+}
+
+#[tokio::test]
+async fn failing_key_leaves_rotation_without_disabling_channel() {
+    let app = Router::new().route(
+        "/{*path}",
+        any(|headers: axum::http::HeaderMap| async move {
+            let auth = headers
+                .get(axum::http::header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("");
+            let status = if auth == "Bearer bad-key" {
+                StatusCode::UNAUTHORIZED
+            } else {
+                StatusCode::OK
+            };
+            (
+                status,
+                [(axum::http::header::CONTENT_TYPE, "application/json")],
+                r#"{"ok":true}"#,
+            )
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let addr = listener.local_addr().expect("local_addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    let base = format!("http://127.0.0.1:{}", addr.port());
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            auto_disable_enabled: Some(true),
+            auto_disable_window_minutes: Some(3),
+            auto_disable_failure_times: Some(1),
+            auto_disable_disable_minutes: Some(30),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+
+    let channel = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "pool",
+            storage::Protocol::Openai,
+            format!("{base}/v1"),
+            "bad-key\ngood-key\n",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+    assert_eq!(channel.keys(), vec!["bad-key", "good-key"]);
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let mut statuses = Vec::new();
+    for _ in 0..3 {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"model":"gpt-test"}"#))
+            .expect("req");
+        let resp = proxy::forward(
+            &client,
+            db_path.clone(),
+            storage::Protocol::Openai,
+            "/v1",
+            req,
+        )
+        .await
+        .expect("forward");
+        statuses.push(resp.status());
+    }
+    assert_eq!(
+        statuses,
+        vec![StatusCode::UNAUTHORIZED, StatusCode::OK, StatusCode::OK]
+    );
+
+    let channel = storage::get_channel(db_path.clone(), channel.id.clone())
+        .await
+        .expect("get channel")
+        .expect("channel exists");
+    assert_eq!(channel.auto_disabled_until_ms, 0);
+
+    let bad_fp = storage::key_fingerprint("bad-key");
+    let good_fp = storage::key_fingerprint("good-key");
+    let states = storage::list_channel_key_states(db_path.clone(), channel.id.clone())
+        .await
+        .expect("list key states");
+    let bad = states
+        .iter()
+        .find(|s| s.key_fp == bad_fp)
+        .expect("bad key state");
+    assert!(bad.auto_disabled_until_ms > storage::now_ms());
+    let good = states
+        .iter()
+        .find(|s| s.key_fp == good_fp)
+        .expect("good key state");
+    assert_eq!(good.use_count, 2);
+
+    sleep(Duration::from_millis(100)).await;
+    let events = storage::list_usage_events_recent(db_path.clone(), 10)
+        .await
+        .expect("list usage events");
+    assert_eq!(events.len(), 3);
+    assert!(events.iter().all(|e| {
+        e.key_fingerprint.as_deref() == Some(if e.success { &good_fp } else { &bad_fp })
+    }));
+}
+
+#[tokio::test]
+async fn weighted_mode_splits_same_priority_group() {
+    let (heavy, heavy_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"ok":1}"#).await;
+    let (light, light_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"ok":2}"#).await;
+    let (backup, backup_calls) = spawn_upstream_counted(StatusCode::OK, r#"{"ok":3}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            load_balance_mode: Some(storage::LoadBalanceMode::Weighted),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+
+    for (name, base, priority, weight) in [
+        ("light", light, 10, 1),
+        ("heavy", heavy, 10, 1000),
+        ("backup", backup, 5, 1000),
+    ] {
+        let mut input = channel_input(
+            name,
+            storage::Protocol::Openai,
+            format!("{base}/v1"),
+            "t",
+            priority,
+        );
+        input.weight = weight;
+        storage::create_channel(db_path.clone(), input)
+            .await
+            .expect("create channel");
+    }
+
+    let client = reqwest::Client::builder().build().expect("client");
+    for _ in 0..20 {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"model":"gpt-test"}"#))
+            .expect("req");
+        let resp = proxy::forward(
+            &client,
+            db_path.clone(),
+            storage::Protocol::Openai,
+            "/v1",
+            req,
+        )
+        .await
+        .expect("forward");
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    let heavy = heavy_calls.load(Ordering::Relaxed);
+    let light = light_calls.load(Ordering::Relaxed);
+    assert_eq!(heavy + light, 20);
+    assert!(heavy >= 15, "heavy={heavy} light={light}");
+    assert_eq!(backup_calls.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn route_members_keep_route_order_despite_equal_channel_priority() {
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            load_balance_mode: Some(storage::LoadBalanceMode::Weighted),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+
+    let mut ids = Vec::new();
+    for (name, weight) in [("primary", 1), ("secondary", 1000)] {
+        let mut input = channel_input(
+            name,
+            storage::Protocol::Openai,
+            "https://api.example.com/v1".to_string(),
+            "t",
+            10,
+        );
+        input.weight = weight;
+        let created = storage::create_channel(db_path.clone(), input)
+            .await
+            .expect("create channel");
+        ids.push(created.id);
+    }
+    let route = storage::create_route(
+        db_path.clone(),
+        storage::CreateRoute {
+            name: "r1".to_string(),
+            protocol: storage::Protocol::Openai,
+            match_model: Some("gpt-*".to_string()),
+            enabled: true,
+        },
+    )
+    .await
+    .expect("create route");
+    storage::set_route_channels(db_path.clone(), route.id.clone(), ids.clone())
+        .await
+        .expect("set route channels");
+
+    // 路由成员的优先级各不相同，不应因为渠道全局 priority 相同而被打乱
+    for _ in 0..20 {
+        let resolved =
+            proxy::resolve_route(db_path.clone(), storage::Protocol::Openai, Some("gpt-test"))
+                .await
+                .expect("resolve route");
+        let order: Vec<String> = resolved.channels.iter().map(|c| c.id.clone()).collect();
+        assert_eq!(order, ids);
+    }
+}
+
+#[tokio::test]
+async fn hedged_request_prefers_faster_channel_and_records_cancelled() {
+    let slow_app = Router::new().route(
+        "/{*path}",
+        any(|| async {
+            sleep(Duration::from_millis(1500)).await;
+            (
+                StatusCode::OK,
+                [(axum::http::header::CONTENT_TYPE, "application/json")],
+                r#"{"ok":"slow"}"#,
+            )
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let addr = listener.local_addr().expect("local_addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, slow_app).await;
+    });
+    let slow = format!("http://127.0.0.1:{}", addr.port());
+    let fast = spawn_upstream(StatusCode::OK, r#"{"ok":"fast"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            hedge_enabled: Some(true),
+            hedge_delay_ms: Some(100),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+
+    let slow_channel = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "slow",
+            storage::Protocol::Openai,
+            format!("{slow}/v1"),
+            "t1",
+            20,
+        ),
+    )
+    .await
+    .expect("create slow");
+    let fast_channel = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "fast",
+            storage::Protocol::Openai,
+            format!("{fast}/v1"),
+            "t2",
+            10,
+        ),
+    )
+    .await
+    .expect("create fast");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-test"}"#))
+        .expect("req");
+
+    let started = std::time::Instant::now();
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    assert_eq!(std::str::from_utf8(&bytes).unwrap(), r#"{"ok":"fast"}"#);
+    assert!(started.elapsed() < Duration::from_millis(1000));
+
+    let mut events = Vec::new();
+    for _ in 0..100 {
+        events = storage::list_usage_events_recent(db_path.clone(), 10)
+            .await
+            .expect("list usage events");
+        if events.len() >= 2 {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].request_id, events[1].request_id);
+    let cancelled = events.iter().find(|e| e.cancelled).expect("cancelled");
+    assert_eq!(cancelled.channel_id, slow_channel.id);
+    assert!(!cancelled.success);
+    let winner = events.iter().find(|e| !e.cancelled).expect("winner");
+    assert_eq!(winner.channel_id, fast_channel.id);
+    assert!(winner.success);
+}
+
+#[tokio::test]
+async fn hedged_prepare_failure_is_not_retried_by_fallback() {
+    let ok = spawn_upstream(StatusCode::OK, r#"{"ok":true}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            hedge_enabled: Some(true),
+            hedge_delay_ms: Some(100),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+
+    let broken = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "broken",
+            storage::Protocol::Openai,
+            "not a url".to_string(),
+            "k1\nk2\n",
+            20,
+        ),
+    )
+    .await
+    .expect("create broken");
+    storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "ok",
+            storage::Protocol::Openai,
+            format!("{ok}/v1"),
+            "t2",
+            10,
+        ),
+    )
+    .await
+    .expect("create ok");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-test"}"#))
+        .expect("req");
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // 准备失败的渠道只选过一次 key，回退流程不会再准备它
+    let states = storage::list_channel_key_states(db_path.clone(), broken.id.clone())
+        .await
+        .expect("list key states");
+    assert_eq!(states.iter().map(|s| s.use_count).sum::<i64>(), 1);
+}
+
+#[tokio::test]
+async fn bad_request_is_final_unless_channel_policy_retries_it() {
+    let (base1, calls1) =
+        spawn_upstream_counted(StatusCode::BAD_REQUEST, r#"{"error":"bad"}"#).await;
+    let (base2, calls2) = spawn_upstream_counted(StatusCode::OK, r#"{"ok":true}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::update_app_settings(
+        db_path.clone(),
+        storage::AppSettingsPatch {
+            auto_disable_enabled: Some(true),
+            auto_disable_failure_times: Some(1),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("update settings");
+
+    let c1 = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c1",
+            storage::Protocol::Openai,
+            format!("{base1}/v1"),
+            "t1",
+            20,
+        ),
+    )
+    .await
+    .expect("create c1");
+    storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c2",
+            storage::Protocol::Openai,
+            format!("{base2}/v1"),
+            "t2",
+            10,
+        ),
+    )
+    .await
+    .expect("create c2");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let send = || {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"model":"gpt-test"}"#))
+            .expect("req");
+        proxy::forward(
+            &client,
+            db_path.clone(),
+            storage::Protocol::Openai,
+            "/v1",
+            req,
+        )
+    };
+
+    // 默认策略：400 是最终结果，不换渠道，也不计入失败
+    let resp = send().await.expect("forward");
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(calls1.load(Ordering::Relaxed), 1);
+    assert_eq!(calls2.load(Ordering::Relaxed), 0);
+    let c1_now = storage::get_channel(db_path.clone(), c1.id.clone())
+        .await
+        .expect("get c1")
+        .expect("c1 exists");
+    assert_eq!(c1_now.auto_disabled_until_ms, 0);
+
+    // 渠道级策略覆盖全局：把 400 视为可重试
+    let policy = storage::FailoverPolicy {
+        retry_statuses: vec!["400".to_string(), "5xx".to_string()],
+        failure_statuses: vec![],
+        ..Default::default()
+    };
+    storage::update_channel(
+        db_path.clone(),
+        c1.id.clone(),
+        serde_json::from_value(serde_json::json!({ "failover_policy": policy }))
+            .expect("update input"),
+    )
+    .await
+    .expect("update c1");
+
+    let resp = send().await.expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(calls1.load(Ordering::Relaxed), 2);
+    assert_eq!(calls2.load(Ordering::Relaxed), 1);
+}
+
+async fn spawn_upstream_sse(chunks: &'static [&'static str], fail_at_end: bool) -> String {
+    let app = Router::new().route(
+        "/{*path}",
+        any(move || async move {
+            let mut items: Vec<Result<bytes::Bytes, std::io::Error>> = chunks
+                .iter()
+                .map(|c| Ok(bytes::Bytes::from_static(c.as_bytes())))
+                .collect();
+            if fail_at_end {
+                items.push(Err(std::io::Error::other("upstream reset")));
+            }
+            (
+                StatusCode::OK,
+                [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+                // 每段之间稍作停顿，确保响应头先发出去
+                Body::from_stream(futures_util::StreamExt::then(
+                    futures_util::stream::iter(items),
+                    |item| async move {
+                        sleep(Duration::from_millis(20)).await;
+                        item
+                    },
+                )),
+            )
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let addr = listener.local_addr().expect("local_addr");
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+
+    format!("http://127.0.0.1:{}", addr.port())
+}
+
+#[tokio::test]
+async fn stream_dropped_before_first_token_fails_over() {
+    let base1 = spawn_upstream_sse(
+        &["data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"}}]}\n\n"],
+        true,
+    )
+    .await;
+    let base2 = spawn_upstream_sse(
+        &[
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"hi\"}}]}\n\n",
+            "data: [DONE]\n\n",
+        ],
+        false,
+    )
+    .await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let c1 = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c1",
+            storage::Protocol::Openai,
+            format!("{base1}/v1"),
+            "t1",
+            20,
+        ),
+    )
+    .await
+    .expect("create c1");
+    let c2 = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c2",
+            storage::Protocol::Openai,
+            format!("{base2}/v1"),
+            "t2",
+            10,
+        ),
+    )
+    .await
+    .expect("create c2");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-test","stream":true}"#))
+        .expect("req");
+
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+        .await
+        .expect("read body");
+    let text = std::str::from_utf8(&bytes).unwrap();
+    assert!(text.contains("\"content\":\"hi\""), "{text}");
+    assert_eq!(text.matches("\"role\"").count(), 1, "{text}");
+
+    let mut events = Vec::new();
+    for _ in 0..100 {
+        events = storage::list_usage_events_recent(db_path.clone(), 10)
+            .await
+            .expect("list usage events");
+        if events.len() >= 2 {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    let failed = events
+        .iter()
+        .find(|e| e.channel_id == c1.id)
+        .expect("c1 event");
+    assert!(!failed.success);
+    assert!(
+        failed
+            .error_kind
+            .as_deref()
+            .is_some_and(|k| k.starts_with("stream_error:")),
+        "{:?}",
+        failed.error_kind
+    );
+    let ok = events
+        .iter()
+        .find(|e| e.channel_id == c2.id)
+        .expect("c2 event");
+    assert!(ok.success);
+}
+
+#[tokio::test]
+async fn ttfb_timeout_fails_over_with_timeout_error_kind() {
+    let app = Router::new().route(
+        "/{*path}",
+        any(|| async {
+            sleep(Duration::from_secs(5)).await;
+            (StatusCode::OK, r#"{"slow":true}"#)
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let slow_base = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    let fast_base = spawn_upstream(StatusCode::OK, r#"{"ok":true}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let slow = storage::create_channel(
+        db_path.clone(),
+        storage::CreateChannel {
+            timeouts: Some(storage::TimeoutPolicy {
+                ttfb_ms: 200,
+                ..Default::default()
+            }),
+            ..channel_input(
+                "slow",
+                storage::Protocol::Openai,
+                format!("{slow_base}/v1"),
+                "t1",
+                20,
+            )
+        },
+    )
+    .await
+    .expect("create slow");
+    storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "fast",
+            storage::Protocol::Openai,
+            format!("{fast_base}/v1"),
+            "t2",
+            10,
+        ),
+    )
+    .await
+    .expect("create fast");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-test"}"#))
+        .expect("req");
+
+    let started = std::time::Instant::now();
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    let mut timed_out = None;
+    for _ in 0..100 {
+        let events = storage::list_usage_events_recent(db_path.clone(), 10)
+            .await
+            .expect("list usage events");
+        timed_out = events.into_iter().find(|e| e.channel_id == slow.id);
+        if timed_out.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    let timed_out = timed_out.expect("slow channel event");
+    assert!(!timed_out.success);
+    assert_eq!(timed_out.error_kind.as_deref(), Some("timeout:ttfb"));
+}
+
+#[tokio::test]
+async fn channel_proxy_url_routes_through_http_proxy() {
+    // HTTP 代理收到的是绝对路径请求，桩服务按路径直接应答即可
+    let (proxy_base, captured) = spawn_upstream_capture(StatusCode::OK, r#"{"via":"proxy"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    storage::create_channel(
+        db_path.clone(),
+        storage::CreateChannel {
+            proxy_url: Some(proxy_base),
+            ..channel_input(
+                "proxied",
+                storage::Protocol::Openai,
+                "http://upstream.invalid/v1".to_string(),
+                "t1",
+                10,
+            )
+        },
+    )
+    .await
+    .expect("create proxied");
+
+    let client = reqwest::Client::builder().build().expect("client");
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header(axum::http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"model":"gpt-test"}"#))
+        .expect("req");
+
+    let resp = proxy::forward(
+        &client,
+        db_path.clone(),
+        storage::Protocol::Openai,
+        "/v1",
+        req,
+    )
+    .await
+    .expect("forward");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), usize::MAX).await.expect("body");
+    assert_eq!(&body[..], br#"{"via":"proxy"}"#);
+
+    let (path, _) = captured
+        .lock()
+        .expect("lock")
+        .clone()
+        .expect("proxied request");
+    assert_eq!(path, "/v1/chat/completions");
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use axum::{Router, body::to_bytes, http::StatusCode, routing::any};
+use cliswitch::{proxy, storage};
+use common::{channel_input, forward_openai_chat, spawn_upstream, temp_db_path};
+
+#[tokio::test]
+async fn channel_with_exhausted_upstream_rate_limit_is_skipped() {
+    let app = Router::new().route(
+        "/{*path}",
+        any(|| async {
+            (
+                StatusCode::OK,
+                [
+                    (axum::http::header::CONTENT_TYPE, "application/json"),
+                    (
+                        axum::http::HeaderName::from_static("x-ratelimit-remaining-requests"),
+                        "0",
+                    ),
+                    (
+                        axum::http::HeaderName::from_static("x-ratelimit-reset-requests"),
+                        "1m30s",
+                    ),
+                ],
+                r#"{"from":"limited"}"#,
+            )
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let limited_base = format!(
+        "http://127.0.0.1:{}",
+        listener.local_addr().expect("local_addr").port()
+    );
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    let spare_base = spawn_upstream(StatusCode::OK, r#"{"from":"spare"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    for (name, base, priority) in [("limited", &limited_base, 10), ("spare", &spare_base, 0)] {
+        storage::create_channel(
+            db_path.clone(),
+            channel_input(
+                name,
+                storage::Protocol::Openai,
+                format!("{base}/v1"),
+                "sk-test",
+                priority,
+            ),
+        )
+        .await
+        .expect("create channel");
+    }
+
+    let body = |resp: axum::response::Response| async move {
+        let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+            .await
+            .expect("read body");
+        String::from_utf8_lossy(&bytes).to_string()
+    };
+    let first = forward_openai_chat(db_path.clone())
+        .await
+        .expect("forward ok");
+    assert!(body(first).await.contains("limited"));
+
+    // 上游报告请求额度已用完，重置前不再选中该渠道
+    let second = forward_openai_chat(db_path.clone())
+        .await
+        .expect("forward ok");
+    assert!(body(second).await.contains("spare"));
+}
+
+#[tokio::test]
+async fn rate_limited_key_does_not_block_other_keys_in_pool() {
+    // 每个 key 的响应都报告额度已用完，响应体带上所用的 key
+    let app = Router::new().route(
+        "/{*path}",
+        any(|headers: axum::http::HeaderMap| async move {
+            let auth = headers
+                .get(axum::http::header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            (
+                StatusCode::OK,
+                [
+                    (axum::http::header::CONTENT_TYPE, "application/json"),
+                    (
+                        axum::http::HeaderName::from_static("x-ratelimit-remaining-requests"),
+                        "0",
+                    ),
+                    (
+                        axum::http::HeaderName::from_static("x-ratelimit-reset-requests"),
+                        "1m30s",
+                    ),
+                ],
+                serde_json::json!({ "auth": auth }).to_string(),
+            )
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let pool_base = format!(
+        "http://127.0.0.1:{}",
+        listener.local_addr().expect("local_addr").port()
+    );
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    let spare_base = spawn_upstream(StatusCode::OK, r#"{"from":"spare"}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    for (name, base, auth_ref, priority) in [
+        ("pool", &pool_base, "sk-key-a\nsk-key-b", 10),
+        ("spare", &spare_base, "sk-test", 0),
+    ] {
+        storage::create_channel(
+            db_path.clone(),
+            channel_input(
+                name,
+                storage::Protocol::Openai,
+                format!("{base}/v1"),
+                auth_ref,
+                priority,
+            ),
+        )
+        .await
+        .expect("create channel");
+    }
+
+    let body = |resp: axum::response::Response| async move {
+        let bytes = to_bytes(resp.into_body(), 1024 * 1024)
+            .await
+            .expect("read body");
+        String::from_utf8_lossy(&bytes).to_string()
+    };
+    let mut used = Vec::new();
+    for _ in 0..2 {
+        let resp = forward_openai_chat(db_path.clone())
+            .await
+            .expect("forward ok");
+        used.push(body(resp).await);
+    }
+    used.sort();
+    assert!(used[0].contains("sk-key-a"), "{used:?}");
+    assert!(used[1].contains("sk-key-b"), "{used:?}");
+
+    // 池中的 key 全部被限速后才跳过该渠道
+    let third = forward_openai_chat(db_path.clone())
+        .await
+        .expect("forward ok");
+    assert!(body(third).await.contains("spare"));
+}
+
+#[tokio::test]
+async fn client_side_request_bucket_rejects_when_empty() {
+    let base = spawn_upstream(StatusCode::OK, r#"{"ok":true}"#).await;
+
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let mut input = channel_input(
+        "tier1",
+        storage::Protocol::Openai,
+        format!("{base}/v1"),
+        "sk-test",
+        10,
+    );
+    input.rate_limit = Some(storage::RateLimitPolicy {
+        requests_per_minute: Some(1),
+        tokens_per_minute: None,
+    });
+    storage::create_channel(db_path.clone(), input)
+        .await
+        .expect("create channel");
+
+    let first = forward_openai_chat(db_path.clone())
+        .await
+        .expect("forward ok");
+    assert_eq!(first.status(), StatusCode::OK);
+
+    let second = forward_openai_chat(db_path.clone()).await;
+    assert!(
+        matches!(second, Err(proxy::ProxyError::QuotaExceeded(_))),
+        "expected rate limited"
+    );
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use cliswitch::storage;
+use common::temp_db_path;
+
+#[tokio::test]
+async fn retention_prunes_old_records_but_keeps_rollups() {
+    let db_path = temp_db_path();
+    // 模拟旧版本创建的数据库：建表后再设置 auto_vacuum 不会生效
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute_batch("CREATE TABLE legacy_marker (x INTEGER);")
+            .expect("create legacy table");
+    }
+    storage::init_db(&db_path).expect("init_db");
+    let auto_vacuum = || -> i64 {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))
+            .expect("auto_vacuum")
+    };
+    assert_eq!(auto_vacuum(), 0);
+
+    let day_ms = 86_400_000;
+    let now = storage::now_ms();
+    for (age_days, request_id) in [(40, "old"), (10, "mid"), (0, "new")] {
+        storage::insert_usage_event(
+            db_path.clone(),
+            storage::CreateUsageEvent {
+                request_id: Some(request_id.into()),
+                attempt_index: 0,
+                ts_ms: now - age_days * day_ms,
+                protocol: storage::Protocol::Openai,
+                route_id: None,
+                channel_id: "c1".to_string(),
+                model: Some("m1".to_string()),
+                upstream_model: None,
+                success: false,
+                cancelled: false,
+                http_status: Some(500),
+                error_kind: Some("upstream_status".to_string()),
+                error_detail: Some("x".repeat(4096)),
+                latency_ms: 10,
+                ttft_ms: None,
+                prompt_tokens: None,
+                completion_tokens: None,
+                total_tokens: Some(7),
+                cache_read_tokens: None,
+                cache_write_tokens: None,
+                estimated_cost_usd: None,
+                key_fingerprint: None,
+                client_token_id: None,
+                cache_hit: false,
+            },
+        )
+        .await
+        .expect("insert usage");
+    }
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        for age_days in [40, 0] {
+            conn.execute(
+                "INSERT INTO channel_failures (channel_id, at_ms) VALUES ('c1', ?1)",
+                [now - age_days * day_ms],
+            )
+            .expect("insert failure");
+        }
+    }
+
+    let res = storage::prune_records(
+        db_path.clone(),
+        storage::RecordsRetention {
+            usage_events_before_ms: Some(now - 30 * day_ms),
+            error_detail_before_ms: Some(now - 7 * day_ms),
+            channel_failures_before_ms: Some(now - 30 * day_ms),
+        },
+    )
+    .await
+    .expect("prune");
+    assert_eq!(res.usage_events_deleted, 1);
+    // 40 天前的那条已被删除，只剩 10 天前的需要清空错误详情
+    assert_eq!(res.error_details_cleared, 1);
+    assert_eq!(res.channel_failures_deleted, 1);
+    // 后台清理不会对旧数据库做完整 VACUUM
+    assert_eq!(auto_vacuum(), 0);
+    assert_eq!(res.freed_bytes, 0);
+
+    let events = storage::list_usage_events_recent(db_path.clone(), 10)
+        .await
+        .expect("list usage");
+    let details = events
+        .iter()
+        .map(|e| {
+            (
+                e.request_id.clone().unwrap_or_default(),
+                e.error_detail.is_some(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        details,
+        vec![("new".to_string(), true), ("mid".to_string(), false)]
+    );
+
+    let summary = storage::stats_summary(
+        db_path.clone(),
+        storage::StatsWindow {
+            start_ms: now - 60 * day_ms,
+            end_ms: None,
+        },
+    )
+    .await
+    .expect("summary");
+    assert_eq!((summary.requests, summary.total_tokens), (3, 21));
+
+    let sizes = storage::db_table_sizes(db_path.clone())
+        .await
+        .expect("table sizes");
+    let usage = sizes
+        .tables
+        .iter()
+        .find(|t| t.name == "usage_events")
+        .expect("usage_events size");
+    assert_eq!(usage.rows, 2);
+    assert!(usage.bytes > 0);
+    let failures = sizes
+        .tables
+        .iter()
+        .find(|t| t.name == "channel_failures")
+        .expect("channel_failures size");
+    assert_eq!(failures.rows, 1);
+    assert!(!sizes.incremental_vacuum);
+
+    // 手动整理后切换到增量模式，之后的清理会归还空闲页
+    storage::compact_database(db_path.clone())
+        .await
+        .expect("compact");
+    assert_eq!(auto_vacuum(), 2);
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute(
+            "UPDATE usage_events SET error_detail = ?1 WHERE request_id = 'mid'",
+            ["x".repeat(256 * 1024)],
+        )
+        .expect("restore error detail");
+    }
+    let res = storage::prune_records(
+        db_path.clone(),
+        storage::RecordsRetention {
+            error_detail_before_ms: Some(now - 7 * day_ms),
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("prune");
+    assert_eq!(res.error_details_cleared, 1);
+    assert!(res.freed_bytes > 0);
+    let sizes = storage::db_table_sizes(db_path.clone())
+        .await
+        .expect("table sizes");
+    assert!(sizes.incremental_vacuum);
+    assert_eq!(sizes.free_bytes, 0);
+}
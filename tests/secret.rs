@@ -0,0 +1,159 @@
+mod common;
+
+use cliswitch::storage;
+use common::{channel_input, temp_db_path};
+
+#[tokio::test]
+async fn channel_secrets_are_encrypted_at_rest() {
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let created = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "c1",
+            storage::Protocol::Openai,
+            "https://api.example.com/v1".to_string(),
+            "sk-secret-1\nsk-secret-2",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+
+    let raw_auth_ref = |id: &str| -> String {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.query_row("SELECT auth_ref FROM channels WHERE id = ?1", [id], |row| {
+            row.get(0)
+        })
+        .expect("read auth_ref")
+    };
+    let stored = raw_auth_ref(&created.id);
+    assert!(stored.starts_with("enc:v1:"), "stored: {stored}");
+    assert!(!stored.contains("sk-secret"));
+
+    let channel = storage::get_channel(db_path.clone(), created.id.clone())
+        .await
+        .expect("get channel")
+        .expect("channel exists");
+    assert_eq!(channel.auth_ref, "sk-secret-1\nsk-secret-2");
+
+    // 旧版数据库中的明文在下次 init_db 时被加密
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute(
+            "UPDATE channels SET auth_ref = 'sk-legacy' WHERE id = ?1",
+            [&created.id],
+        )
+        .expect("write plaintext");
+    }
+    storage::init_db(&db_path).expect("init_db again");
+    assert!(raw_auth_ref(&created.id).starts_with("enc:v1:"));
+    let channels = storage::list_channels(db_path.clone())
+        .await
+        .expect("list channels");
+    assert_eq!(channels[0].auth_ref, "sk-legacy");
+
+    // 密文无法用当前密钥解开时启动直接失败，而不是生成新密钥
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute(
+            "UPDATE channels SET auth_ref = 'enc:v1:000102030405060708090a0b0c0d0e0f' WHERE id = ?1",
+            [&created.id],
+        )
+        .expect("write bad ciphertext");
+    }
+    let err = storage::init_db(&db_path).expect_err("init_db with undecryptable secret");
+    assert!(format!("{err:#}").contains("c1"), "err: {err:#}");
+}
+
+#[tokio::test]
+async fn partial_edit_of_masked_key_pool_keeps_unchanged_keys() {
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let created = storage::create_channel(
+        db_path.clone(),
+        channel_input(
+            "pool",
+            storage::Protocol::Openai,
+            "https://api.example.com/v1".to_string(),
+            "sk-aaaa-first-0001\nsk-bbbb-second-0002\nsk-cccc-third-0003",
+            10,
+        ),
+    )
+    .await
+    .expect("create channel");
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("bind");
+    let base = format!("http://{}", listener.local_addr().expect("local_addr"));
+    tokio::spawn(cliswitch::server::serve_with_listener(
+        listener,
+        db_path.clone(),
+        false,
+    ));
+    let client = reqwest::Client::new();
+
+    let channels: serde_json::Value = client
+        .get(format!("{base}/api/channels"))
+        .send()
+        .await
+        .expect("list channels")
+        .json()
+        .await
+        .expect("channels json");
+    let masked = channels[0]["auth_ref"]
+        .as_str()
+        .expect("auth_ref")
+        .to_string();
+    assert!(!masked.contains("first"), "masked: {masked}");
+
+    // 删掉第二个 key、替换第三个、追加一个新 key，第一个保持打码值不动
+    let lines: Vec<&str> = masked.lines().collect();
+    assert_eq!(lines.len(), 3);
+    let edited = format!("{}\nsk-dddd-fourth-0004\nsk-eeee-fifth-0005", lines[0]);
+    let res = client
+        .put(format!("{base}/api/channels/{}", created.id))
+        .json(&serde_json::json!({ "auth_ref": edited }))
+        .send()
+        .await
+        .expect("update channel");
+    assert!(res.status().is_success(), "status: {}", res.status());
+
+    let channel = storage::get_channel(db_path.clone(), created.id.clone())
+        .await
+        .expect("get channel")
+        .expect("channel exists");
+    assert_eq!(
+        channel.auth_ref,
+        "sk-aaaa-first-0001\nsk-dddd-fourth-0004\nsk-eeee-fifth-0005"
+    );
+
+    // 原样提交打码值不改变任何 key
+    let masked_now = client
+        .get(format!("{base}/api/channels"))
+        .send()
+        .await
+        .expect("list channels")
+        .json::<serde_json::Value>()
+        .await
+        .expect("channels json")[0]["auth_ref"]
+        .as_str()
+        .expect("auth_ref")
+        .to_string();
+    let res = client
+        .put(format!("{base}/api/channels/{}", created.id))
+        .json(&serde_json::json!({ "auth_ref": masked_now }))
+        .send()
+        .await
+        .expect("update channel");
+    assert!(res.status().is_success(), "status: {}", res.status());
+    let channel = storage::get_channel(db_path.clone(), created.id.clone())
+        .await
+        .expect("get channel")
+        .expect("channel exists");
+    assert_eq!(
+        channel.auth_ref,
+        "sk-aaaa-first-0001\nsk-dddd-fourth-0004\nsk-eeee-fifth-0005"
+    );
+}
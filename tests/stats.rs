@@ -0,0 +1,370 @@
+mod common;
+
+use cliswitch::storage;
+use common::temp_db_path;
+
+#[tokio::test]
+async fn stats_support_custom_windows_buckets_and_grouping() {
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let event = |ts_ms: i64, model: &str, success: bool, latency_ms: i64, ttft_ms: Option<i64>| {
+        storage::CreateUsageEvent {
+            request_id: None,
+            attempt_index: 0,
+            ts_ms,
+            protocol: storage::Protocol::Openai,
+            route_id: None,
+            channel_id: "c1".to_string(),
+            model: Some(model.to_string()),
+            upstream_model: None,
+            success,
+            cancelled: false,
+            http_status: Some(if success { 200 } else { 500 }),
+            error_kind: None,
+            error_detail: None,
+            latency_ms,
+            ttft_ms,
+            prompt_tokens: None,
+            completion_tokens: None,
+            total_tokens: Some(10),
+            cache_read_tokens: None,
+            cache_write_tokens: None,
+            estimated_cost_usd: None,
+            key_fingerprint: None,
+            client_token_id: None,
+            cache_hit: false,
+        }
+    };
+    // 2024-01-31 23:30Z（周三）、2024-02-01 00:30Z（周四）、2024-02-05 10:00Z（周一）
+    for e in [
+        event(1_706_743_800_000, "m1", true, 100, Some(40)),
+        event(1_706_747_400_000, "m1", true, 300, Some(60)),
+        event(1_707_127_200_000, "m2", false, 200, None),
+    ] {
+        storage::insert_usage_event(db_path.clone(), e)
+            .await
+            .expect("insert usage");
+    }
+    let all = storage::StatsWindow {
+        start_ms: 1_704_067_200_000,
+        end_ms: None,
+    };
+
+    let month_utc = storage::stats_trend(
+        db_path.clone(),
+        all,
+        storage::StatsBucket::Month,
+        storage::StatsGroupBy::Model,
+        0,
+    )
+    .await
+    .expect("trend");
+    let points = |items: &[storage::TrendPoint]| {
+        items
+            .iter()
+            .map(|p| (p.bucket_start_ms, p.stats.key.clone(), p.stats.requests))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        points(&month_utc),
+        vec![
+            (1_704_067_200_000, "m1".to_string(), 1),
+            (1_706_745_600_000, "m1".to_string(), 1),
+            (1_706_745_600_000, "m2".to_string(), 1),
+        ]
+    );
+
+    // UTC+8 下第一条已经属于二月
+    let month_cst = storage::stats_trend(
+        db_path.clone(),
+        all,
+        storage::StatsBucket::Month,
+        storage::StatsGroupBy::Model,
+        8 * 3_600_000,
+    )
+    .await
+    .expect("trend");
+    assert_eq!(
+        points(&month_cst),
+        vec![
+            (1_706_716_800_000, "m1".to_string(), 2),
+            (1_706_716_800_000, "m2".to_string(), 1),
+        ]
+    );
+    assert_eq!(month_cst[0].stats.latency_ms.p50, Some(100));
+    assert_eq!(month_cst[0].stats.latency_ms.p95, Some(300));
+    assert_eq!(month_cst[0].stats.ttft_ms.p99, Some(60));
+
+    let weekly = storage::stats_trend(
+        db_path.clone(),
+        all,
+        storage::StatsBucket::Week,
+        storage::StatsGroupBy::Channel,
+        0,
+    )
+    .await
+    .expect("trend");
+    assert_eq!(
+        points(&weekly),
+        vec![
+            (1_706_486_400_000, "c1".to_string(), 2),
+            (1_707_091_200_000, "c1".to_string(), 1),
+        ]
+    );
+
+    let feb = storage::StatsWindow {
+        start_ms: 1_706_745_600_000,
+        end_ms: Some(1_707_177_600_000),
+    };
+    let summary = storage::stats_summary(db_path.clone(), feb)
+        .await
+        .expect("summary");
+    assert_eq!(
+        (summary.requests, summary.success, summary.failed),
+        (2, 1, 1)
+    );
+    assert_eq!(summary.latency_ms.p50, Some(300));
+
+    let by_model = storage::stats_breakdown(db_path.clone(), feb, storage::StatsGroupBy::Model)
+        .await
+        .expect("breakdown");
+    let m2 = by_model.iter().find(|g| g.key == "m2").expect("m2");
+    assert_eq!((m2.requests, m2.failed), (1, 1));
+    assert_eq!(m2.latency_ms.p50, None);
+
+    assert_eq!(
+        storage::month_start_ms(1_706_743_800_000, 8 * 3_600_000),
+        1_706_716_800_000
+    );
+    assert_eq!(
+        storage::day_start_ms(1_706_747_400_000, 0),
+        1_706_745_600_000
+    );
+}
+
+#[tokio::test]
+async fn stats_read_rollups_that_outlive_raw_events() {
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    let event = |request_id: &str,
+                 attempt_index: usize,
+                 success: bool,
+                 cancelled: bool,
+                 latency_ms: i64| {
+        storage::CreateUsageEvent {
+            request_id: Some(request_id.into()),
+            attempt_index,
+            ts_ms: 1_706_747_400_000,
+            protocol: storage::Protocol::Openai,
+            route_id: None,
+            channel_id: "c1".to_string(),
+            model: Some("m1".to_string()),
+            upstream_model: None,
+            success,
+            cancelled,
+            http_status: Some(if success { 200 } else { 502 }),
+            error_kind: None,
+            error_detail: None,
+            latency_ms,
+            ttft_ms: None,
+            prompt_tokens: Some(4),
+            completion_tokens: Some(6),
+            total_tokens: Some(10),
+            cache_read_tokens: None,
+            cache_write_tokens: None,
+            estimated_cost_usd: Some("0.25".to_string()),
+            key_fingerprint: None,
+            client_token_id: None,
+            cache_hit: false,
+        }
+    };
+    // r1 故障转移后成功，r2 对冲时一方被取消，r3 失败
+    for e in [
+        event("r1", 0, false, false, 50),
+        event("r1", 1, true, false, 200),
+        event("r2", 0, true, false, 100),
+        event("r2", 1, false, true, 0),
+        event("r3", 0, false, false, 80),
+    ] {
+        storage::insert_usage_event(db_path.clone(), e)
+            .await
+            .expect("insert usage");
+    }
+    let window = storage::StatsWindow {
+        start_ms: 1_706_745_600_000,
+        end_ms: Some(1_706_832_000_000),
+    };
+    let check = |s: &storage::StatsSummary| {
+        assert_eq!((s.requests, s.success, s.failed), (3, 2, 1));
+        assert_eq!(s.total_tokens, 50);
+        assert_eq!(s.estimated_cost_usd.as_deref(), Some("1.250000"));
+        assert_eq!(s.latency_ms.p50, Some(100));
+        assert_eq!(s.latency_ms.p99, Some(200));
+    };
+    check(
+        &storage::stats_summary(db_path.clone(), window)
+            .await
+            .expect("summary"),
+    );
+    // 窗口边界落在小时行内时结果不变
+    let hourly = storage::StatsWindow {
+        start_ms: 1_706_746_000_000,
+        end_ms: Some(1_706_750_000_000),
+    };
+    check(
+        &storage::stats_summary(db_path.clone(), hourly)
+            .await
+            .expect("summary"),
+    );
+    let by_model = storage::stats_breakdown(db_path.clone(), window, storage::StatsGroupBy::Model)
+        .await
+        .expect("breakdown");
+    assert_eq!(
+        (
+            by_model[0].requests,
+            by_model[0].success,
+            by_model[0].failed
+        ),
+        (4, 2, 2)
+    );
+
+    // 汇总表丢失时启动回填
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute_batch("DELETE FROM usage_rollup_hourly;")
+            .expect("drop rollups");
+    }
+    storage::init_db(&db_path).expect("init_db again");
+    check(
+        &storage::stats_summary(db_path.clone(), window)
+            .await
+            .expect("summary"),
+    );
+
+    // 原始记录被清理后历史汇总仍在
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute("DELETE FROM usage_events", [])
+            .expect("prune usage events");
+    }
+    check(
+        &storage::stats_summary(db_path.clone(), window)
+            .await
+            .expect("summary"),
+    );
+
+    storage::clear_records(db_path.clone(), storage::RecordsClearKind::All)
+        .await
+        .expect("clear records");
+    let summary = storage::stats_summary(db_path.clone(), window)
+        .await
+        .expect("summary");
+    assert_eq!(summary.requests, 0);
+    assert_eq!(summary.latency_ms.p50, None);
+}
+
+#[tokio::test]
+async fn stats_split_partial_hours_using_raw_events() {
+    let db_path = temp_db_path();
+    storage::init_db(&db_path).expect("init_db");
+    // 2024-02-01 00:00Z
+    let h = 1_706_745_600_000i64;
+    let event = |ts_ms: i64| storage::CreateUsageEvent {
+        request_id: None,
+        attempt_index: 0,
+        ts_ms,
+        protocol: storage::Protocol::Openai,
+        route_id: None,
+        channel_id: "c1".to_string(),
+        model: Some("m1".to_string()),
+        upstream_model: None,
+        success: true,
+        cancelled: false,
+        http_status: Some(200),
+        error_kind: None,
+        error_detail: None,
+        latency_ms: 100,
+        ttft_ms: None,
+        prompt_tokens: None,
+        completion_tokens: None,
+        total_tokens: Some(10),
+        cache_read_tokens: None,
+        cache_write_tokens: None,
+        estimated_cost_usd: None,
+        key_fingerprint: None,
+        client_token_id: None,
+        cache_hit: false,
+    };
+    // 00:10Z、00:40Z、03:00Z、18:10Z、18:40Z
+    for ts_ms in [
+        h + 600_000,
+        h + 2_400_000,
+        h + 10_800_000,
+        h + 65_400_000,
+        h + 67_200_000,
+    ] {
+        storage::insert_usage_event(db_path.clone(), event(ts_ms))
+            .await
+            .expect("insert usage");
+    }
+    let requests = |start_ms: i64, end_ms: i64| {
+        let db_path = db_path.clone();
+        async move {
+            storage::stats_summary(
+                db_path,
+                storage::StatsWindow {
+                    start_ms,
+                    end_ms: Some(end_ms),
+                },
+            )
+            .await
+            .expect("summary")
+            .requests
+        }
+    };
+    assert_eq!(requests(h + 1_800_000, h + 14_400_000).await, 2);
+    assert_eq!(requests(h, h + 1_800_000).await, 1);
+
+    // UTC+5:30 的零点落在 18:30Z，同一小时内的两条记录分属两天
+    let offset_ms = 19_800_000;
+    let trend = storage::stats_trend(
+        db_path.clone(),
+        storage::StatsWindow {
+            start_ms: h - 86_400_000,
+            end_ms: Some(h + 2 * 86_400_000),
+        },
+        storage::StatsBucket::Day,
+        storage::StatsGroupBy::Channel,
+        offset_ms,
+    )
+    .await
+    .expect("trend");
+    assert_eq!(
+        trend
+            .iter()
+            .map(|p| (p.bucket_start_ms, p.stats.requests))
+            .collect::<Vec<_>>(),
+        vec![(h - offset_ms, 4), (h + 86_400_000 - offset_ms, 1)]
+    );
+
+    // 边界小时的原始记录已被清理时退回为包含整个小时
+    {
+        let conn = rusqlite::Connection::open(&db_path).expect("open db");
+        conn.execute("DELETE FROM usage_events WHERE ts_ms < ?1", [h + 1_200_000])
+            .expect("prune usage events");
+    }
+    assert_eq!(requests(h + 1_800_000, h + 14_400_000).await, 3);
+
+    // 清除一段记录只重算涉及的小时，同一天更早的汇总不受影响
+    storage::clear_records(
+        db_path.clone(),
+        storage::RecordsClearKind::DateRange {
+            start_ms: h + 10_800_000,
+            end_ms: h + 10_800_000,
+        },
+    )
+    .await
+    .expect("clear records");
+    assert_eq!(requests(h, h + 86_400_000).await, 4);
+    assert_eq!(requests(h, h + 3_600_000).await, 2);
+}
@@ -28,7 +28,11 @@ import {
 import { toast } from "sonner";
 import { getHealth, getSettings, pricingStatus, pricingSync } from "./api";
 import { logger, setLogLevel } from "@/lib/logger";
-import type { CliswitchUpdateStatusEvent } from "@/lib/cliswitchEvents";
+import type {
+  CliswitchChannelSpendWarningEvent,
+  CliswitchUpdateStatusEvent,
+} from "@/lib/cliswitchEvents";
+import { formatMoney } from "@/lib/currency";
 import { isUpdateReadyShown, markUpdateReadyShown } from "@/lib/updateReadyPrompt";
 
 import { OverviewPage } from "./pages/OverviewPage";
@@ -220,6 +224,24 @@ export default function App() {
     };
   }, []);
 
+  useEffect(() => {
+    const onSpendWarning = (e: Event) => {
+      const w = (e as CliswitchChannelSpendWarningEvent).detail;
+      if (!w) return;
+      const vars = {
+        name: w.channel_name,
+        kind: t(`channels.spendKind.${w.kind}`),
+        spent: formatMoney(w.spent, w.currency, 2),
+        limit: formatMoney(w.limit, w.currency, 2),
+      };
+      toast.warning(t(w.exhausted ? "channels.toast.spendExhausted" : "channels.toast.spendWarning", vars));
+    };
+    window.addEventListener("cliswitch-channel-spend-warning", onSpendWarning as EventListener);
+    return () => {
+      window.removeEventListener("cliswitch-channel-spend-warning", onSpendWarning as EventListener);
+    };
+  }, [t]);
+
   const toggleCollapsed = () => {
     setCollapsed((prev) => {
       const next = !prev;
@@ -1,5 +1,7 @@
 import { extractErrorMessage } from "@/lib/error";
 import { logger, type LogLevel } from "@/lib/logger";
+import { getClientToken, setClientToken } from "@/lib/clientToken";
+import { translateStatic } from "@/lib/i18n";
 
 export type Protocol = "openai" | "anthropic" | "gemini";
 
@@ -15,6 +17,23 @@ export type CloseBehavior = "ask" | "minimize_to_tray" | "quit";
 
 export type AutoStartLaunchMode = "show_window" | "minimize_to_tray";
 
+export type LoadBalanceMode = "priority" | "weighted" | "adaptive";
+
+export type TimeoutPolicy = {
+  connect_ms: number;
+  ttfb_ms: number;
+  idle_ms: number;
+  total_ms: number;
+};
+
+export type FailoverPolicy = {
+  retry_statuses: string[];
+  failure_statuses: string[];
+  retry_transport_errors: boolean;
+  count_transport_errors: boolean;
+  honor_retry_after: boolean;
+};
+
 export type AppSettings = {
   pricing_auto_update_enabled: boolean;
   pricing_auto_update_interval_hours: number;
@@ -28,6 +47,81 @@ export type AppSettings = {
   auto_disable_disable_minutes: number;
   log_level: LogLevel;
   log_retention_days: number;
+  usage_retention_days: number;
+  error_detail_retention_days: number;
+  channel_failure_retention_days: number;
+  load_balance_mode: LoadBalanceMode;
+  hedge_enabled: boolean;
+  hedge_delay_ms: number;
+  failover_policy: FailoverPolicy;
+  stream_failover_enabled: boolean;
+  stream_first_content_timeout_ms: number;
+  timeouts: TimeoutPolicy;
+  proxy_url: string;
+  client_auth_enabled: boolean;
+  usd_cny_rate: number;
+  spend_warning_percent: number;
+  response_cache_enabled: boolean;
+  response_cache_ttl_secs: number;
+  response_cache_max_mb: number;
+  capture_enabled: boolean;
+  otlp_enabled: boolean;
+  otlp_endpoint: string;
+};
+
+export type KeyStrategy = "round_robin" | "least_used" | "random";
+
+// 金额按渠道的充值货币计，留空表示不限制
+export type SpendLimit = {
+  balance?: number | null;
+  balance_set_at_ms?: number | null;
+  daily_cap?: number | null;
+  monthly_cap?: number | null;
+};
+
+export type ChannelSpend = {
+  channel_id: string;
+  currency: "USD" | "CNY";
+  spent_today: number;
+  spent_month: number;
+  spent_since_balance: number;
+  limit: SpendLimit;
+};
+
+// 客户端侧令牌桶，按上游套餐的 RPM / TPM 填写
+export type RateLimitPolicy = {
+  requests_per_minute?: number | null;
+  tokens_per_minute?: number | null;
+};
+
+export type RateLimitWindow = {
+  limit: number | null;
+  remaining: number | null;
+  reset_at_ms: number | null;
+};
+
+export type TokenBucket = {
+  capacity: number;
+  available: number;
+};
+
+// 上游限速头按 key 分别记录
+export type KeyRateLimit = {
+  key_fp: string;
+  requests: RateLimitWindow | null;
+  tokens: RateLimitWindow | null;
+  observed_at_ms: number | null;
+};
+
+// 进程内的限速状态：上游限速头与本地令牌桶
+export type RateLimitState = {
+  requests: RateLimitWindow | null;
+  tokens: RateLimitWindow | null;
+  observed_at_ms: number | null;
+  keys: KeyRateLimit[];
+  request_bucket: TokenBucket | null;
+  token_bucket: TokenBucket | null;
+  limited_until_ms: number | null;
 };
 
 export type Channel = {
@@ -42,6 +136,14 @@ export type Channel = {
   real_multiplier: number;
   enabled: boolean;
   auto_disabled_until_ms: number;
+  key_strategy: KeyStrategy;
+  weight: number;
+  failover_policy: FailoverPolicy | null;
+  timeouts: TimeoutPolicy | null;
+  proxy_url: string | null;
+  spend_limit: SpendLimit | null;
+  rate_limit: RateLimitPolicy | null;
+  rate_limit_state?: RateLimitState | null;
   created_at_ms: number;
   updated_at_ms: number;
 };
@@ -56,6 +158,11 @@ export type CreateChannelInput = {
   recharge_currency: "USD" | "CNY";
   real_multiplier: number;
   enabled: boolean;
+  key_strategy: KeyStrategy;
+  weight: number;
+  proxy_url: string | null;
+  spend_limit: SpendLimit | null;
+  rate_limit: RateLimitPolicy | null;
 };
 
 export type UpdateChannelInput = Partial<{
@@ -67,8 +174,23 @@ export type UpdateChannelInput = Partial<{
   recharge_currency: "USD" | "CNY";
   real_multiplier: number;
   enabled: boolean;
+  key_strategy: KeyStrategy;
+  weight: number;
+  failover_policy: FailoverPolicy | null;
+  timeouts: TimeoutPolicy | null;
+  proxy_url: string | null;
+  spend_limit: SpendLimit | null;
+  rate_limit: RateLimitPolicy | null;
 }>;
 
+export type ChannelKeyHealth = {
+  key_fp: string;
+  masked: string;
+  use_count: number;
+  last_used_ms: number | null;
+  auto_disabled_until_ms: number;
+};
+
 export type ChannelTestResponse = {
   reachable: boolean;
   ok: boolean;
@@ -147,9 +269,17 @@ export type UpdateDownloadResponse = {
   status: UpdateStatus;
 };
 
+// 毫秒；样本为空时为 null
+export type Percentiles = {
+  p50: number | null;
+  p95: number | null;
+  p99: number | null;
+};
+
 export type StatsSummary = {
   range: string;
   start_ms: number;
+  end_ms: number | null;
   requests: number;
   success: number;
   failed: number;
@@ -158,6 +288,8 @@ export type StatsSummary = {
   completion_tokens: number;
   total_tokens: number;
   estimated_cost_usd: string | null;
+  latency_ms: Percentiles;
+  ttft_ms: Percentiles;
 };
 
 export type ChannelStats = {
@@ -168,6 +300,8 @@ export type ChannelStats = {
   success: number;
   failed: number;
   avg_latency_ms: number | null;
+  latency_ms: Percentiles;
+  ttft_ms: Percentiles;
   total_tokens: number;
   estimated_cost_usd: string | null;
 };
@@ -175,6 +309,7 @@ export type ChannelStats = {
 export type StatsChannels = {
   range: string;
   start_ms: number;
+  end_ms: number | null;
   items: ChannelStats[];
 };
 
@@ -198,6 +333,10 @@ export type UsageEvent = {
   cache_read_tokens: number | null;
   cache_write_tokens: number | null;
   estimated_cost_usd: string | null;
+  key_fingerprint: string | null;
+  cancelled: boolean;
+  client_token_id: string | null;
+  cache_hit: boolean;
 };
 
 export type UsageListResult = {
@@ -205,24 +344,69 @@ export type UsageListResult = {
   items: UsageEvent[];
 };
 
-export type TrendPoint = {
-  bucket_start_ms: number;
-  channel_id: string;
+export type StatsBucket = "hour" | "day" | "week" | "month";
+export type StatsGroupBy = "channel" | "model" | "protocol" | "route";
+
+export type GroupStats = {
+  key: string;
   name: string;
+  requests: number;
   success: number;
+  failed: number;
+  avg_latency_ms: number | null;
+  latency_ms: Percentiles;
+  ttft_ms: Percentiles;
+  total_tokens: number;
+  estimated_cost_usd: string | null;
+};
+
+export type TrendPoint = GroupStats & {
+  bucket_start_ms: number;
 };
 
 export type StatsTrend = {
   range: string;
   start_ms: number;
-  unit: "day";
+  end_ms: number | null;
+  unit: StatsBucket;
+  group_by: StatsGroupBy;
   items: TrendPoint[];
 };
 
-async function http<T>(method: string, path: string, body?: unknown): Promise<T> {
+export type StatsBreakdown = {
+  range: string;
+  start_ms: number;
+  end_ms: number | null;
+  group_by: StatsGroupBy;
+  items: GroupStats[];
+};
+
+// 不传 range 时按 start_ms / end_ms 查询；tz_offset_minutes 为相对 UTC 向东的分钟数
+export type StatsQuery = {
+  range?: "today" | "month";
+  start_ms?: number;
+  end_ms?: number;
+  bucket?: StatsBucket;
+  group_by?: StatsGroupBy;
+  tz_offset_minutes?: number;
+};
+
+function statsQueryString(q: StatsQuery): string {
+  const params = new URLSearchParams();
+  for (const [k, v] of Object.entries(q)) {
+    if (v !== undefined) params.set(k, String(v));
+  }
+  return params.toString();
+}
+
+async function http<T>(method: string, path: string, body?: unknown, retried = false): Promise<T> {
+  const headers: Record<string, string> = {};
+  if (body) headers["content-type"] = "application/json";
+  const token = getClientToken();
+  if (token) headers.authorization = `Bearer ${token}`;
   const res = await fetch(path, {
     method,
-    headers: body ? { "content-type": "application/json" } : undefined,
+    headers,
     body: body ? JSON.stringify(body) : undefined
   });
 
@@ -231,6 +415,15 @@ async function http<T>(method: string, path: string, body?: unknown): Promise<T>
     return (await res.json()) as T;
   }
 
+  // 开启客户端鉴权后本机还没有保存令牌：询问一次后重试
+  if (res.status === 401 && !retried && path !== "/api/logs/ingest") {
+    const input = window.prompt(translateStatic("clientTokens.prompt"), token);
+    if (input && input.trim()) {
+      setClientToken(input);
+      return http<T>(method, path, body, true);
+    }
+  }
+
   const text = await res.text().catch(() => "");
   const trimmed = text.trim();
 
@@ -299,6 +492,18 @@ export function testChannel(id: string): Promise<ChannelTestResponse> {
   return http<ChannelTestResponse>("POST", `/api/channels/${encodeURIComponent(id)}/test`);
 }
 
+export function listChannelSpend(): Promise<ChannelSpend[]> {
+  return http<ChannelSpend[]>("GET", "/api/channels/spend");
+}
+
+export function revealChannelSecret(id: string): Promise<{ auth_ref: string }> {
+  return http<{ auth_ref: string }>("POST", `/api/channels/${encodeURIComponent(id)}/reveal`);
+}
+
+export function listChannelKeys(id: string): Promise<ChannelKeyHealth[]> {
+  return http<ChannelKeyHealth[]>("GET", `/api/channels/${encodeURIComponent(id)}/keys`);
+}
+
 export function reorderChannels(protocol: Protocol, channelIds: string[]): Promise<void> {
   return http<void>("POST", "/api/channels/reorder", { protocol, channel_ids: channelIds });
 }
@@ -364,8 +569,12 @@ export function statsChannels(range: "today" | "month"): Promise<StatsChannels>
   return http<StatsChannels>("GET", `/api/stats/channels?range=${encodeURIComponent(range)}`);
 }
 
-export function statsTrend(range: "month"): Promise<StatsTrend> {
-  return http<StatsTrend>("GET", `/api/stats/trend?range=${encodeURIComponent(range)}`);
+export function statsTrend(q: StatsQuery): Promise<StatsTrend> {
+  return http<StatsTrend>("GET", `/api/stats/trend?${statsQueryString(q)}`);
+}
+
+export function statsBreakdown(q: StatsQuery): Promise<StatsBreakdown> {
+  return http<StatsBreakdown>("GET", `/api/stats/breakdown?${statsQueryString(q)}`);
 }
 
 export function usageList(
@@ -394,18 +603,96 @@ export function usageList(
   return http<UsageListResult>("GET", `/api/usage/list?${p.toString()}`);
 }
 
+export type RequestCapture = {
+  request_id: string;
+  ts_ms: number;
+  protocol: Protocol;
+  method: string;
+  path: string;
+  request_headers: [string, string][];
+  request_body: string;
+  request_truncated: boolean;
+  channel_id: string | null;
+  response_status: number | null;
+  response_sse: boolean;
+  response_body: string | null;
+  response_truncated: boolean;
+  completed_at_ms: number | null;
+};
+
+export function getUsageCapture(requestId: string): Promise<RequestCapture> {
+  return http<RequestCapture>("GET", `/api/usage/${encodeURIComponent(requestId)}/capture`);
+}
+
+export type ReplayResult = {
+  status: number;
+  request_id: string | null;
+  body: string;
+};
+
+// 重放结果可能是 SSE，按文本读取
+export async function replayUsage(requestId: string, channelId: string): Promise<ReplayResult> {
+  const headers: Record<string, string> = { "content-type": "application/json" };
+  const token = getClientToken();
+  if (token) headers.authorization = `Bearer ${token}`;
+  const res = await fetch(`/api/usage/${encodeURIComponent(requestId)}/replay`, {
+    method: "POST",
+    headers,
+    body: JSON.stringify({ channel_id: channelId }),
+  });
+  const body = await res.text();
+  const requestIdHeader = res.headers.get("x-cliswitch-request-id");
+  if (!requestIdHeader && !res.ok) {
+    let msg = body;
+    try {
+      msg = (JSON.parse(body) as { error?: string }).error ?? body;
+    } catch {
+      // 保留原始文本
+    }
+    throw new Error(msg || `HTTP ${res.status}`);
+  }
+  return { status: res.status, request_id: requestIdHeader, body };
+}
+
+export type TableSize = {
+  name: string;
+  rows: number;
+  bytes: number;
+};
+
 export type DbSize = {
   path: string;
   db_bytes: number;
   wal_bytes: number;
   shm_bytes: number;
   total_bytes: number;
+  tables: TableSize[];
+  free_bytes: number;
+  incremental_vacuum: boolean;
 };
 
 export function getDbSize(): Promise<DbSize> {
   return http<DbSize>("GET", "/api/maintenance/db_size");
 }
 
+export function compactDb(): Promise<{ before_bytes: number; after_bytes: number }> {
+  return http<{ before_bytes: number; after_bytes: number }>("POST", "/api/maintenance/db_compact");
+}
+
+export type ResponseCacheStats = {
+  entries: number;
+  size_bytes: number;
+  hits: number;
+};
+
+export function getCacheStats(): Promise<ResponseCacheStats> {
+  return http<ResponseCacheStats>("GET", "/api/maintenance/cache");
+}
+
+export function clearCache(): Promise<{ deleted: number }> {
+  return http<{ deleted: number }>("POST", "/api/maintenance/cache/clear");
+}
+
 export type LogsSize = {
   path: string;
   total_bytes: number;
@@ -446,3 +733,70 @@ export function clearLogs(input: {
 }): Promise<ClearLogsResult> {
   return http<ClearLogsResult>("POST", "/api/maintenance/logs/clear", input);
 }
+
+export type ClientTokenRole = "proxy" | "read_only" | "admin";
+
+export type ClientToken = {
+  id: string;
+  name: string;
+  masked: string;
+  role: ClientTokenRole;
+  protocols: Protocol[];
+  route_ids: string[];
+  enabled: boolean;
+  expires_at_ms: number | null;
+  last_used_ms: number | null;
+  created_at_ms: number;
+  updated_at_ms: number;
+  requests_per_minute: number | null;
+  tokens_per_day: number | null;
+  usd_per_month: number | null;
+};
+
+export type ClientTokenUsage = {
+  tokens_today: number;
+  cost_usd_month: number;
+};
+
+export type ClientTokenItem = ClientToken & { usage: ClientTokenUsage };
+
+export type CreatedClientToken = ClientToken & { token: string };
+
+export type CreateClientTokenInput = {
+  name: string;
+  role: ClientTokenRole;
+  protocols: Protocol[];
+  route_ids: string[];
+  expires_at_ms: number | null;
+  requests_per_minute?: number | null;
+  tokens_per_day?: number | null;
+  usd_per_month?: number | null;
+};
+
+export type UpdateClientTokenInput = Partial<{
+  name: string;
+  role: ClientTokenRole;
+  protocols: Protocol[];
+  route_ids: string[];
+  enabled: boolean;
+  expires_at_ms: number | null;
+  requests_per_minute: number | null;
+  tokens_per_day: number | null;
+  usd_per_month: number | null;
+}>;
+
+export function listClientTokens(): Promise<ClientTokenItem[]> {
+  return http<ClientTokenItem[]>("GET", "/api/client_tokens");
+}
+
+export function createClientToken(input: CreateClientTokenInput): Promise<CreatedClientToken> {
+  return http<CreatedClientToken>("POST", "/api/client_tokens", input);
+}
+
+export function updateClientToken(id: string, input: UpdateClientTokenInput): Promise<void> {
+  return http<void>("PUT", `/api/client_tokens/${encodeURIComponent(id)}`, input);
+}
+
+export function deleteClientToken(id: string): Promise<void> {
+  return http<void>("DELETE", `/api/client_tokens/${encodeURIComponent(id)}`);
+}
@@ -24,6 +24,19 @@ export function protocolLabel(t: Translator, protocol: Protocol): string {
   return t(protocolLabelKey(protocol));
 }
 
+// 与后端 Protocol::accepts_channel 保持一致：跨协议转换允许的渠道协议
+export function routeAcceptsChannel(route: Protocol, channel: Protocol): boolean {
+  if (route === channel) return true;
+  switch (route) {
+    case "openai":
+      return channel === "anthropic" || channel === "gemini";
+    case "anthropic":
+      return channel === "openai";
+    case "gemini":
+      return channel === "openai" || channel === "anthropic";
+  }
+}
+
 export function formatDuration(ms: number | null | undefined): string {
   if (ms === null || ms === undefined) return "-";
   if (ms < 1000) return `${ms}ms`;
@@ -0,0 +1,16 @@
+// 开启客户端鉴权后，管理界面用保存在本机浏览器里的令牌访问 /api
+const STORAGE_KEY = "cliswitch-client-token";
+
+export function getClientToken(): string {
+  if (typeof window === "undefined") return "";
+  return localStorage.getItem(STORAGE_KEY) ?? "";
+}
+
+export function setClientToken(token: string) {
+  const v = token.trim();
+  if (v) {
+    localStorage.setItem(STORAGE_KEY, v);
+  } else {
+    localStorage.removeItem(STORAGE_KEY);
+  }
+}
@@ -1,5 +1,16 @@
 import type { UpdateStatus } from "@/api";
 
+export type ChannelSpendWarning = {
+  channel_id: string;
+  channel_name: string;
+  kind: "balance" | "daily" | "monthly";
+  spent: number;
+  limit: number;
+  currency: "USD" | "CNY";
+  exhausted: boolean;
+};
+
 export type CliswitchUpdateStatusEvent = CustomEvent<UpdateStatus>;
 export type CliswitchUsageChangedEvent = CustomEvent<{ at_ms: number }>;
+export type CliswitchChannelSpendWarningEvent = CustomEvent<ChannelSpendWarning>;
 
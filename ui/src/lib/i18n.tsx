@@ -69,6 +69,11 @@ function translate(locale: Locale, key: string, vars?: Record<string, string | n
   return key;
 }
 
+// 供 React 组件之外（如 api.ts）使用
+export function translateStatic(key: string, vars?: Record<string, string | number>): string {
+  return translate(getInitialLocale(), key, vars);
+}
+
 type I18nContextValue = {
   locale: Locale;
   setLocale: (next: Locale) => void;
@@ -8,6 +8,7 @@ import {
   PowerOff,
   TestTube,
   ArrowDownUp,
+  Eye,
 } from "lucide-react";
 import { toast } from "sonner";
 import {
@@ -40,7 +41,7 @@ import {
   TabsTrigger,
 } from "@/components/ui";
 import { useI18n } from "@/lib/i18n";
-import { useCurrency } from "@/lib/currency";
+import { formatMoney, useCurrency } from "@/lib/currency";
 import {
   listChannels,
   createChannel,
@@ -49,8 +50,17 @@ import {
   enableChannel,
   disableChannel,
   testChannel,
+  listChannelKeys,
+  listChannelSpend,
+  revealChannelSecret,
   reorderChannels,
   type Channel,
+  type ChannelKeyHealth,
+  type ChannelSpend,
+  type SpendLimit,
+  type RateLimitPolicy,
+  type RateLimitState,
+  type KeyStrategy,
   type CreateChannelInput,
   type Protocol,
 } from "../api";
@@ -69,9 +79,65 @@ function emptyDraft(): ChannelDraft {
     recharge_currency: "CNY",
     real_multiplier: 1,
     enabled: true,
+    key_strategy: "round_robin",
+    weight: 1,
+    proxy_url: null,
+    spend_limit: null,
+    rate_limit: null,
   };
 }
 
+function spendExhausted(s: ChannelSpend): boolean {
+  const { balance, daily_cap, monthly_cap } = s.limit;
+  return (
+    (balance != null && s.spent_since_balance >= balance) ||
+    (daily_cap != null && s.spent_today >= daily_cap) ||
+    (monthly_cap != null && s.spent_month >= monthly_cap)
+  );
+}
+
+// 三项都为空时视为未设置
+function patchSpendLimit(
+  prev: SpendLimit | null,
+  field: "balance" | "daily_cap" | "monthly_cap",
+  raw: string,
+): SpendLimit | null {
+  const n = Number(raw);
+  const value = raw.trim() && Number.isFinite(n) && n >= 0 ? n : null;
+  const next = { ...(prev ?? {}), [field]: value };
+  if (next.balance == null && next.daily_cap == null && next.monthly_cap == null) return null;
+  return next;
+}
+
+function patchRateLimit(
+  prev: RateLimitPolicy | null,
+  field: "requests_per_minute" | "tokens_per_minute",
+  raw: string,
+): RateLimitPolicy | null {
+  const n = Number(raw);
+  const value = raw.trim() && Number.isInteger(n) && n >= 1 ? n : null;
+  const next = { ...(prev ?? {}), [field]: value };
+  if (next.requests_per_minute == null && next.tokens_per_minute == null) return null;
+  return next;
+}
+
+function rateLimitRemaining(s: RateLimitState): string[] {
+  const parts: string[] = [];
+  for (const [label, w] of [
+    ["RPM", s.requests],
+    ["TPM", s.tokens],
+  ] as const) {
+    if (w?.remaining != null) {
+      parts.push(w.limit != null ? `${label} ${w.remaining}/${w.limit}` : `${label} ${w.remaining}`);
+    }
+  }
+  return parts;
+}
+
+function countKeys(authRef: string): number {
+  return authRef.split("\n").filter((k) => k.trim()).length;
+}
+
 function formatFixed2(n: number): string {
   if (!Number.isFinite(n)) return "1.00";
   return n.toFixed(2);
@@ -155,6 +221,11 @@ export function ChannelsPage() {
   const [draft, setDraft] = useState<ChannelDraft>(emptyDraft());
   const [realMultiplierInput, setRealMultiplierInput] = useState(() => formatFixed2(1));
   const [realMultiplierTip, setRealMultiplierTip] = useState<string | null>(null);
+  const [keyHealth, setKeyHealth] = useState<ChannelKeyHealth[]>([]);
+  // 接口返回的是打码后的 key；未点击显示且未修改时提交不带 auth_ref
+  const [maskedAuthRef, setMaskedAuthRef] = useState<string | null>(null);
+  const [revealing, setRevealing] = useState(false);
+  const [spendById, setSpendById] = useState<Record<string, ChannelSpend>>({});
   const [testing, setTesting] = useState<Record<string, boolean>>({});
   const [deleteOpen, setDeleteOpen] = useState(false);
   const [deleteTarget, setDeleteTarget] = useState<Channel | null>(null);
@@ -164,10 +235,14 @@ export function ChannelsPage() {
 
   async function refresh() {
     try {
-      const cs = await listChannels();
+      const [cs, spends] = await Promise.all([
+        listChannels(),
+        listChannelSpend().catch(() => [] as ChannelSpend[]),
+      ]);
       const by: Record<Protocol, Channel[]> = { openai: [], anthropic: [], gemini: [] };
       for (const c of cs) by[c.protocol].push(c);
       setChannelsByProtocol(by);
+      setSpendById(Object.fromEntries(spends.map((s) => [s.channel_id, s])));
     } catch (e) {
       toast.error(t("channels.toast.loadFail"), { description: String(e) });
     }
@@ -231,6 +306,7 @@ export function ChannelsPage() {
     });
     setRealMultiplierInput(formatFixed2(1));
     setRealMultiplierTip(null);
+    setKeyHealth([]);
     setModalOpen(true);
   }
 
@@ -247,10 +323,22 @@ export function ChannelsPage() {
       recharge_currency: c.recharge_currency ?? "CNY",
       real_multiplier: c.real_multiplier ?? 1,
       enabled: c.enabled,
+      key_strategy: c.key_strategy ?? "round_robin",
+      weight: c.weight ?? 1,
+      proxy_url: c.proxy_url ?? null,
+      spend_limit: c.spend_limit ?? null,
+      rate_limit: c.rate_limit ?? null,
     });
     setRealMultiplierInput(formatFixed2(Number(c.real_multiplier ?? 1)));
     setRealMultiplierTip(null);
+    setKeyHealth([]);
+    setMaskedAuthRef(c.auth_ref);
     setModalOpen(true);
+    if (countKeys(c.auth_ref) > 1) {
+      listChannelKeys(c.id)
+        .then(setKeyHealth)
+        .catch(() => setKeyHealth([]));
+    }
   }
 
   async function submit() {
@@ -265,7 +353,12 @@ export function ChannelsPage() {
       }
 
       if (modalMode === "create") {
-        await createChannel({ ...draft, name: draft.name.trim(), base_url: draft.base_url.trim() });
+        await createChannel({
+          ...draft,
+          name: draft.name.trim(),
+          base_url: draft.base_url.trim(),
+          proxy_url: draft.proxy_url?.trim() || null,
+        });
         toast.success(t("channels.toast.createOk"));
       } else {
         if (!editId) throw new Error(t("channels.toast.missingId"));
@@ -273,11 +366,16 @@ export function ChannelsPage() {
           name: draft.name.trim(),
           base_url: draft.base_url.trim(),
           auth_type: "auto",
-          auth_ref: draft.auth_ref,
+          auth_ref: draft.auth_ref === maskedAuthRef ? undefined : draft.auth_ref,
           priority: draft.priority,
           recharge_currency: draft.recharge_currency,
           real_multiplier: draft.real_multiplier,
           enabled: draft.enabled,
+          key_strategy: draft.key_strategy,
+          weight: draft.weight,
+          proxy_url: draft.proxy_url?.trim() || null,
+          spend_limit: draft.spend_limit,
+          rate_limit: draft.rate_limit,
         });
         toast.success(t("channels.toast.updateOk"));
       }
@@ -288,6 +386,20 @@ export function ChannelsPage() {
     }
   }
 
+  async function revealSecret() {
+    if (!editId) return;
+    setRevealing(true);
+    try {
+      const { auth_ref } = await revealChannelSecret(editId);
+      setDraft((d) => ({ ...d, auth_ref }));
+      setMaskedAuthRef(null);
+    } catch (e) {
+      toast.error(t("channels.toast.revealFail"), { description: String(e) });
+    } finally {
+      setRevealing(false);
+    }
+  }
+
   async function toggleEnabled(c: Channel) {
     try {
       const nowMs = Date.now();
@@ -500,6 +612,31 @@ export function ChannelsPage() {
                     1,
                     Math.ceil(((c.auto_disabled_until_ms ?? 0) - renderNowMs) / 60000)
                   );
+                  const spend = spendById[c.id];
+                  const spendParts: string[] = [];
+                  if (spend?.limit.balance != null) {
+                    spendParts.push(
+                      t("channels.spend.balance", {
+                        remaining: formatMoney(spend.limit.balance - spend.spent_since_balance, spend.currency, 2),
+                      })
+                    );
+                  }
+                  if (spend?.limit.daily_cap != null) {
+                    spendParts.push(
+                      t("channels.spend.daily", {
+                        spent: formatMoney(spend.spent_today, spend.currency, 2),
+                        limit: formatMoney(spend.limit.daily_cap, spend.currency, 2),
+                      })
+                    );
+                  }
+                  if (spend?.limit.monthly_cap != null) {
+                    spendParts.push(
+                      t("channels.spend.monthly", {
+                        spent: formatMoney(spend.spent_month, spend.currency, 2),
+                        limit: formatMoney(spend.limit.monthly_cap, spend.currency, 2),
+                      })
+                    );
+                  }
 
                   return (
                     <TableRow
@@ -569,6 +706,14 @@ export function ChannelsPage() {
                     </TableCell>
                     <TableCell>
                       <div className="font-medium">{c.name}</div>
+                      {spendParts.length > 0 && (
+                        <div className="text-xs text-muted-foreground">{spendParts.join(" · ")}</div>
+                      )}
+                      {c.rate_limit_state && rateLimitRemaining(c.rate_limit_state).length > 0 && (
+                        <div className="text-xs text-muted-foreground">
+                          {rateLimitRemaining(c.rate_limit_state).join(" · ")}
+                        </div>
+                      )}
                     </TableCell>
                     <TableCell className="font-mono text-sm">
                       {c.priority}
@@ -578,6 +723,14 @@ export function ChannelsPage() {
                         <Badge variant="warning">
                           {t("channels.status.autoDisabled", { minutes: autoDisabledMinutes })}
                         </Badge>
+                      ) : c.enabled && spend && spendExhausted(spend) ? (
+                        <Badge variant="warning">{t("channels.status.spendExhausted")}</Badge>
+                      ) : c.enabled && (c.rate_limit_state?.limited_until_ms ?? 0) > Date.now() ? (
+                        <Badge variant="warning">
+                          {t("channels.status.rateLimited", {
+                            time: formatDateTime(c.rate_limit_state?.limited_until_ms ?? 0),
+                          })}
+                        </Badge>
                       ) : (
                         <Badge variant={c.enabled ? "success" : "secondary"}>
                           {c.enabled ? t("common.enabled") : t("common.disabled")}
@@ -767,6 +920,31 @@ export function ChannelsPage() {
               />
             </div>
 
+            <div className="space-y-2">
+              <label className="text-sm font-medium">{t("channels.modal.weight")}</label>
+              <Input
+                type="number"
+                min={1}
+                value={String(draft.weight ?? 1)}
+                onChange={(e) => {
+                  const n = Math.floor(Number(e.target.value));
+                  setDraft((d) => ({ ...d, weight: Number.isFinite(n) && n >= 1 ? n : 1 }));
+                }}
+                placeholder="1"
+              />
+              <p className="text-xs text-muted-foreground">{t("channels.modal.weightHint")}</p>
+            </div>
+
+            <div className="space-y-2">
+              <label className="text-sm font-medium">{t("channels.modal.proxyUrl")}</label>
+              <Input
+                value={draft.proxy_url ?? ""}
+                onChange={(e) => setDraft((d) => ({ ...d, proxy_url: e.target.value }))}
+                placeholder={t("channels.modal.proxyUrlPlaceholder")}
+              />
+              <p className="text-xs text-muted-foreground">{t("channels.modal.proxyUrlHint")}</p>
+            </div>
+
             <div className="grid gap-4">
               <div className="space-y-2">
                 <label className="text-sm font-medium">
@@ -842,6 +1020,52 @@ export function ChannelsPage() {
               </p>
               </div>
 
+            <div className="space-y-2">
+              <label className="text-sm font-medium">{t("channels.modal.spendLimit")}</label>
+              <div className="grid grid-cols-3 gap-2">
+                {(["balance", "daily_cap", "monthly_cap"] as const).map((field) => (
+                  <Input
+                    key={field}
+                    type="number"
+                    min={0}
+                    step="0.01"
+                    value={draft.spend_limit?.[field] ?? ""}
+                    onChange={(e) =>
+                      setDraft((d) => ({
+                        ...d,
+                        spend_limit: patchSpendLimit(d.spend_limit, field, e.target.value),
+                      }))
+                    }
+                    placeholder={t(`channels.modal.spendLimitFields.${field}`)}
+                  />
+                ))}
+              </div>
+              <p className="text-xs text-muted-foreground">{t("channels.modal.spendLimitHint")}</p>
+            </div>
+
+            <div className="space-y-2">
+              <label className="text-sm font-medium">{t("channels.modal.rateLimit")}</label>
+              <div className="grid grid-cols-2 gap-2">
+                {(["requests_per_minute", "tokens_per_minute"] as const).map((field) => (
+                  <Input
+                    key={field}
+                    type="number"
+                    min={1}
+                    step="1"
+                    value={draft.rate_limit?.[field] ?? ""}
+                    onChange={(e) =>
+                      setDraft((d) => ({
+                        ...d,
+                        rate_limit: patchRateLimit(d.rate_limit, field, e.target.value),
+                      }))
+                    }
+                    placeholder={t(`channels.modal.rateLimitFields.${field}`)}
+                  />
+                ))}
+              </div>
+              <p className="text-xs text-muted-foreground">{t("channels.modal.rateLimitHint")}</p>
+            </div>
+
             <div className="space-y-2">
               <label className="text-sm font-medium">{t("channels.modal.baseUrl")}</label>
               <Input
@@ -852,15 +1076,80 @@ export function ChannelsPage() {
             </div>
 
             <div className="space-y-2">
-              <label className="text-sm font-medium">{t("channels.modal.apiKey")}</label>
-              <Input
-                type="password"
+              <div className="flex items-center justify-between">
+                <label className="text-sm font-medium">{t("channels.modal.apiKey")}</label>
+                {modalMode === "edit" && maskedAuthRef !== null && draft.auth_ref === maskedAuthRef && (
+                  <Button
+                    type="button"
+                    size="sm"
+                    variant="ghost"
+                    className="h-6 px-2 text-xs"
+                    onClick={revealSecret}
+                    disabled={revealing}
+                  >
+                    <Eye className="h-3.5 w-3.5 mr-1" />
+                    {t("channels.modal.revealKey")}
+                  </Button>
+                )}
+              </div>
+              <textarea
+                className="flex min-h-[72px] w-full rounded-md border border-input bg-transparent px-3 py-2 font-mono text-sm shadow-sm placeholder:text-muted-foreground focus-visible:outline-none focus-visible:ring-1 focus-visible:ring-ring"
                 value={draft.auth_ref}
                 onChange={(e) => setDraft((d) => ({ ...d, auth_ref: e.target.value }))}
                 placeholder="sk-..."
+                spellCheck={false}
+                autoComplete="off"
               />
+              <p className="text-xs text-muted-foreground">{t("channels.modal.apiKeyHint")}</p>
+              {modalMode === "edit" && maskedAuthRef !== null && (
+                <p className="text-xs text-muted-foreground">{t("channels.modal.maskedHint")}</p>
+              )}
+              {keyHealth.length > 0 && (
+                <div className="space-y-1 rounded-md border p-2 text-xs">
+                  {keyHealth.map((k) => {
+                    const disabled = k.auto_disabled_until_ms > Date.now();
+                    return (
+                      <div key={k.key_fp} className="flex items-center justify-between gap-2">
+                        <span className="font-mono">{k.masked}</span>
+                        <span className={disabled ? "text-destructive" : "text-muted-foreground"}>
+                          {disabled
+                            ? t("channels.modal.keyDisabledUntil", {
+                                time: formatDateTime(k.auto_disabled_until_ms),
+                              })
+                            : t("channels.modal.keyUseCount", { count: k.use_count })}
+                        </span>
+                      </div>
+                    );
+                  })}
+                </div>
+              )}
             </div>
 
+            {countKeys(draft.auth_ref) > 1 && (
+              <div className="space-y-2">
+                <label className="text-sm font-medium">{t("channels.modal.keyStrategy")}</label>
+                <Select
+                  value={draft.key_strategy}
+                  onValueChange={(v) => setDraft((d) => ({ ...d, key_strategy: v as KeyStrategy }))}
+                >
+                  <SelectTrigger>
+                    <SelectValue />
+                  </SelectTrigger>
+                  <SelectContent>
+                    <SelectItem value="round_robin">
+                      {t("channels.modal.keyStrategyOptions.roundRobin")}
+                    </SelectItem>
+                    <SelectItem value="least_used">
+                      {t("channels.modal.keyStrategyOptions.leastUsed")}
+                    </SelectItem>
+                    <SelectItem value="random">
+                      {t("channels.modal.keyStrategyOptions.random")}
+                    </SelectItem>
+                  </SelectContent>
+                </Select>
+              </div>
+            )}
+
             <div className="flex items-center justify-between">
               <label className="text-sm font-medium">{t("channels.modal.enabled")}</label>
               <Switch
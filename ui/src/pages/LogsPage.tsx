@@ -38,10 +38,14 @@ import { useI18n } from "@/lib/i18n";
 import { useWindowEvent } from "@/lib/useWindowEvent";
 import { formatMoney, parseDecimalLike, useCurrency } from "@/lib/currency";
 import {
+  getUsageCapture,
   listChannels,
+  replayUsage,
   usageList,
   type Channel,
   type Protocol,
+  type ReplayResult,
+  type RequestCapture,
   type UsageEvent,
 } from "../api";
 import { clampStr, formatDateTime, formatDuration, protocolLabel, protocolLabelKey } from "../lib";
@@ -56,6 +60,11 @@ export function LogsPage() {
   const [total, setTotal] = useState(0);
   const [detailOpen, setDetailOpen] = useState(false);
   const [detailEvent, setDetailEvent] = useState<UsageEvent | null>(null);
+  const [capture, setCapture] = useState<RequestCapture | null>(null);
+  const [captureLoading, setCaptureLoading] = useState(false);
+  const [replayChannelId, setReplayChannelId] = useState<string>("");
+  const [replaying, setReplaying] = useState(false);
+  const [replayResult, setReplayResult] = useState<ReplayResult | null>(null);
 
   const [dateRange, setDateRange] = useState<DateRange | undefined>(undefined);
   const [protocol, setProtocol] = useState<Protocol | "all">("all");
@@ -150,7 +159,11 @@ export function LogsPage() {
         open={detailOpen}
         onOpenChange={(v) => {
           setDetailOpen(v);
-          if (!v) setDetailEvent(null);
+          if (!v) {
+            setDetailEvent(null);
+            setCapture(null);
+            setReplayResult(null);
+          }
         }}
       >
         <DialogContent className="sm:max-w-[760px]">
@@ -245,6 +258,105 @@ export function LogsPage() {
                 <div className="text-muted-foreground">{t("logs.details.errorDetail")}</div>
                 <pre className="text-xs whitespace-pre-wrap break-words rounded border bg-muted/30 p-2">{detailEvent.error_detail ? humanizeErrorText(detailEvent.error_detail) : "-"}</pre>
               </div>
+              {detailEvent.request_id && (
+                <div className="grid grid-cols-[120px_1fr] gap-2">
+                  <div className="text-muted-foreground">{t("logs.capture.title")}</div>
+                  <div className="grid gap-2 min-w-0">
+                    {!capture ? (
+                      <div>
+                        <Button
+                          size="sm"
+                          variant="outline"
+                          disabled={captureLoading}
+                          onClick={async () => {
+                            if (!detailEvent.request_id) return;
+                            setCaptureLoading(true);
+                            try {
+                              const c = await getUsageCapture(detailEvent.request_id);
+                              setCapture(c);
+                              setReplayChannelId(c.channel_id ?? detailEvent.channel_id);
+                            } catch (e) {
+                              toast.error(t("logs.capture.loadFail"), { description: String(e) });
+                            } finally {
+                              setCaptureLoading(false);
+                            }
+                          }}
+                        >
+                          {t("logs.capture.load")}
+                        </Button>
+                      </div>
+                    ) : (
+                      <>
+                        <div className="font-mono text-xs break-all">
+                          {capture.method} {capture.path}
+                        </div>
+                        <pre className="text-xs whitespace-pre-wrap break-words rounded border bg-muted/30 p-2 max-h-40 overflow-auto">
+                          {capture.request_headers.map(([k, v]) => `${k}: ${v}`).join("\n")}
+                        </pre>
+                        <div className="text-xs text-muted-foreground">
+                          {t("logs.capture.request")}
+                          {capture.request_truncated && ` (${t("logs.capture.truncated")})`}
+                        </div>
+                        <pre className="text-xs whitespace-pre-wrap break-words rounded border bg-muted/30 p-2 max-h-60 overflow-auto">
+                          {capture.request_body || "-"}
+                        </pre>
+                        <div className="text-xs text-muted-foreground">
+                          {capture.response_sse ? t("logs.capture.responseSse") : t("logs.capture.response")}
+                          {capture.response_status != null && ` · ${capture.response_status}`}
+                          {capture.response_truncated && ` (${t("logs.capture.truncated")})`}
+                        </div>
+                        <pre className="text-xs whitespace-pre-wrap break-words rounded border bg-muted/30 p-2 max-h-60 overflow-auto">
+                          {capture.response_body ?? "-"}
+                        </pre>
+                        <div className="flex items-center gap-2">
+                          <Select value={replayChannelId} onValueChange={setReplayChannelId} disabled={replaying}>
+                            <SelectTrigger className="h-8 w-[220px]">
+                              <SelectValue placeholder={t("logs.capture.channel")} />
+                            </SelectTrigger>
+                            <SelectContent>
+                              {channels.map((c) => (
+                                <SelectItem key={c.id} value={c.id}>
+                                  {c.name}
+                                </SelectItem>
+                              ))}
+                            </SelectContent>
+                          </Select>
+                          <Button
+                            size="sm"
+                            disabled={replaying || !replayChannelId || capture.request_truncated}
+                            onClick={async () => {
+                              setReplaying(true);
+                              try {
+                                const res = await replayUsage(capture.request_id, replayChannelId);
+                                setReplayResult(res);
+                              } catch (e) {
+                                toast.error(t("logs.capture.replayFail"), { description: String(e) });
+                              } finally {
+                                setReplaying(false);
+                              }
+                            }}
+                          >
+                            {replaying ? t("logs.capture.replaying") : t("logs.capture.replay")}
+                          </Button>
+                        </div>
+                        {replayResult && (
+                          <>
+                            <div className="text-xs text-muted-foreground">
+                              {t("logs.capture.replayResult", {
+                                status: replayResult.status,
+                                id: replayResult.request_id ?? "-",
+                              })}
+                            </div>
+                            <pre className="text-xs whitespace-pre-wrap break-words rounded border bg-muted/30 p-2 max-h-60 overflow-auto">
+                              {replayResult.body || "-"}
+                            </pre>
+                          </>
+                        )}
+                      </>
+                    )}
+                  </div>
+                </div>
+              )}
             </div>
           )}
         </DialogContent>
@@ -468,6 +580,11 @@ export function LogsPage() {
                               </Badge>
                             )}
                           </div>
+                          {e.cache_hit && (
+                            <Badge variant="outline" className="text-[10px] px-1 py-0">
+                              {t("logs.cell.cacheHit")}
+                            </Badge>
+                          )}
                           {(e.error_detail || e.error_kind) && (
                             <Tooltip>
                               <TooltipTrigger asChild>
@@ -242,7 +242,7 @@ export function OverviewPage() {
       listChannels(),
       statsSummary("month"),
       statsChannels("month"),
-      statsTrend("month"),
+      statsTrend({ range: "month" }),
     ])
       .then(([cs, st, cst, tr]) => {
         setChannels(cs);
@@ -313,14 +313,14 @@ export function OverviewPage() {
     const days = buildMonthDays(startMs, new Date());
     const byDayChannel = new Map<string, number>();
     for (const it of trendItems) {
-      const k = `${localDateKey(new Date(it.bucket_start_ms))}|${it.channel_id}`;
+      const k = `${localDateKey(new Date(it.bucket_start_ms))}|${it.key}`;
       byDayChannel.set(k, (byDayChannel.get(k) ?? 0) + it.success);
     }
 
     const totals = new Map<string, { name: string; total: number }>();
     for (const it of trendItems) {
-      const cur = totals.get(it.channel_id);
-      totals.set(it.channel_id, {
+      const cur = totals.get(it.key);
+      totals.set(it.key, {
         name: it.name,
         total: (cur?.total ?? 0) + it.success,
       });
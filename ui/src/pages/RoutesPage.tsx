@@ -46,7 +46,7 @@ import {
   type CreateRouteInput,
   type Protocol,
 } from "../api";
-import { formatDateTime, protocolLabel, protocolLabelKey } from "../lib";
+import { formatDateTime, protocolLabel, protocolLabelKey, routeAcceptsChannel } from "../lib";
 
 type RouteDraft = CreateRouteInput;
 
@@ -220,7 +220,7 @@ export function RoutesPage() {
   const available = useMemo(() => {
     if (!manageRoute) return [];
     return channels
-      .filter((c) => c.protocol === manageRoute.protocol)
+      .filter((c) => routeAcceptsChannel(manageRoute.protocol, c.protocol))
       .filter((c) => !assigned.includes(c.id))
       .sort((a, b) => a.name.localeCompare(b.name));
   }, [channels, assigned, manageRoute]);
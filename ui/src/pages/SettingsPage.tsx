@@ -1,5 +1,5 @@
 import React, { useEffect, useState } from "react";
-import { Sun, Moon, Monitor, FolderOpen, Info, Database, Languages, DollarSign, RefreshCw, Shield, Power, ScrollText, Palette, Settings2, Cpu } from "lucide-react";
+import { Sun, Moon, Monitor, FolderOpen, Info, Database, Languages, DollarSign, RefreshCw, Shield, Power, ScrollText, Palette, Settings2, Cpu, KeyRound, Trash2, Zap } from "lucide-react";
 import { toast } from "sonner";
 import { format } from "date-fns";
 import type { DateRange } from "react-day-picker";
@@ -27,6 +27,12 @@ import {
   SelectItem,
   SelectTrigger,
   SelectValue,
+  Table,
+  TableBody,
+  TableCell,
+  TableHead,
+  TableHeader,
+  TableRow,
   Tabs,
   TabsContent,
   TabsList,
@@ -37,9 +43,10 @@ import { type Locale, useI18n } from "@/lib/i18n";
 import { useCurrency, type CurrencyMode } from "@/lib/currency";
 import { setLogLevel } from "@/lib/logger";
 import { formatBytes, formatDateTime } from "../lib";
-import { checkUpdate, clearLogs, clearRecords, downloadUpdate, getDbSize, getHealth, getLogsSize, getSettings, getUpdateStatus, pricingStatus, pricingSync, updateSettings, type AppSettings, type AutoStartLaunchMode, type CloseBehavior, type DbSize, type Health, type LogsSize, type PricingStatus, type RecordsClearMode, type UpdateCheck, type UpdateStatus } from "../api";
+import { checkUpdate, clearCache, clearLogs, clearRecords, compactDb, createClientToken, deleteClientToken, downloadUpdate, getCacheStats, getDbSize, getHealth, getLogsSize, getSettings, getUpdateStatus, listClientTokens, pricingStatus, pricingSync, updateClientToken, updateSettings, type AppSettings, type ClientToken, type ClientTokenItem, type ClientTokenRole, type Protocol, type AutoStartLaunchMode, type CloseBehavior, type FailoverPolicy, type LoadBalanceMode, type TimeoutPolicy, type DbSize, type Health, type LogsSize, type PricingStatus, type RecordsClearMode, type ResponseCacheStats, type UpdateCheck, type UpdateStatus } from "../api";
 import type { CliswitchUpdateStatusEvent } from "@/lib/cliswitchEvents";
 import { clearUpdateReadyShown } from "@/lib/updateReadyPrompt";
+import { getClientToken, setClientToken } from "@/lib/clientToken";
 
 function joinPath(base: string, sub: string): string {
   const sep = base.includes("\\") ? "\\" : "/";
@@ -47,6 +54,13 @@ function joinPath(base: string, sub: string): string {
   return `${base}${sep}${sub}`;
 }
 
+function splitStatuses(v: string): string[] {
+  return v
+    .split(/[,\s]+/)
+    .map((s) => s.trim())
+    .filter(Boolean);
+}
+
 export function SettingsPage() {
   const { theme, setTheme } = useTheme();
   const { locale, setLocale, locales, t } = useI18n();
@@ -61,6 +75,15 @@ export function SettingsPage() {
   const [updateDownloading, setUpdateDownloading] = useState(false);
   const [saving, setSaving] = useState(false);
   const [autoDisableSaving, setAutoDisableSaving] = useState(false);
+  const [loadBalanceSaving, setLoadBalanceSaving] = useState(false);
+  const [hedgeSaving, setHedgeSaving] = useState(false);
+  const [failoverSaving, setFailoverSaving] = useState(false);
+  const [streamFailoverSaving, setStreamFailoverSaving] = useState(false);
+  const [timeoutsSaving, setTimeoutsSaving] = useState(false);
+  const [proxyUrlSaving, setProxyUrlSaving] = useState(false);
+  const [spendSaving, setSpendSaving] = useState(false);
+  const [retryStatusesDraft, setRetryStatusesDraft] = useState<string>("");
+  const [failureStatusesDraft, setFailureStatusesDraft] = useState<string>("");
   const [closeSaving, setCloseSaving] = useState(false);
   const [autoStartSaving, setAutoStartSaving] = useState(false);
   const [autoStartLaunchSaving, setAutoStartLaunchSaving] = useState(false);
@@ -71,11 +94,18 @@ export function SettingsPage() {
   // 数据库相关 state
   const [dbSize, setDbSize] = useState<DbSize | null>(null);
   const [dbSizeLoading, setDbSizeLoading] = useState(false);
+  const [dbCompacting, setDbCompacting] = useState(false);
+  const [retentionSaving, setRetentionSaving] = useState(false);
   const [recordsScope, setRecordsScope] = useState<RecordsClearMode>("all");
   const [recordsDateRange, setRecordsDateRange] = useState<DateRange | undefined>(undefined);
   const [recordsPromptOpen, setRecordsPromptOpen] = useState(false);
   const [recordsClearing, setRecordsClearing] = useState(false);
 
+  // 响应缓存
+  const [cacheStats, setCacheStats] = useState<ResponseCacheStats | null>(null);
+  const [cacheSaving, setCacheSaving] = useState(false);
+  const [cacheClearing, setCacheClearing] = useState(false);
+
   // 日志清理相关 state
   const [logsSize, setLogsSize] = useState<LogsSize | null>(null);
   const [logsSizeLoading, setLogsSizeLoading] = useState(false);
@@ -84,6 +114,130 @@ export function SettingsPage() {
   const [logsPromptOpen, setLogsPromptOpen] = useState(false);
   const [logsClearing, setLogsClearing] = useState(false);
 
+  async function saveFailoverPolicy(patch: Partial<FailoverPolicy>) {
+    if (!appSettings) return;
+    setFailoverSaving(true);
+    try {
+      const next = await updateSettings({ failover_policy: { ...appSettings.failover_policy, ...patch } });
+      setAppSettings(next);
+      setRetryStatusesDraft(next.failover_policy.retry_statuses.join(", "));
+      setFailureStatusesDraft(next.failover_policy.failure_statuses.join(", "));
+      toast.success(t("settings.channelProtection.saved"));
+    } catch (e) {
+      setRetryStatusesDraft(appSettings.failover_policy.retry_statuses.join(", "));
+      setFailureStatusesDraft(appSettings.failover_policy.failure_statuses.join(", "));
+      toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+    } finally {
+      setFailoverSaving(false);
+    }
+  }
+
+  async function saveTimeouts() {
+    if (!appSettings) return;
+    setTimeoutsSaving(true);
+    try {
+      const next = await updateSettings({ timeouts: appSettings.timeouts });
+      setAppSettings(next);
+      toast.success(t("settings.channelProtection.saved"));
+    } catch (e) {
+      toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+    } finally {
+      setTimeoutsSaving(false);
+    }
+  }
+
+  async function saveProxyUrl() {
+    if (!appSettings) return;
+    setProxyUrlSaving(true);
+    try {
+      const next = await updateSettings({ proxy_url: appSettings.proxy_url.trim() });
+      setAppSettings(next);
+      toast.success(t("settings.channelProtection.saved"));
+    } catch (e) {
+      toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+    } finally {
+      setProxyUrlSaving(false);
+    }
+  }
+
+  async function saveOtlpEndpoint() {
+    if (!appSettings) return;
+    setLogSaving(true);
+    try {
+      const next = await updateSettings({ otlp_endpoint: appSettings.otlp_endpoint.trim() });
+      setAppSettings(next);
+      toast.success(t("settings.logging.saved"));
+    } catch (e) {
+      toast.error(t("settings.logging.saveFail"), { description: String(e) });
+    } finally {
+      setLogSaving(false);
+    }
+  }
+
+  async function saveRetentionSettings() {
+    if (!appSettings) return;
+    const usage = appSettings.usage_retention_days;
+    const values = [usage, appSettings.error_detail_retention_days, appSettings.channel_failure_retention_days];
+    // 用量记录至少保留 32 天，月度限额和配额仍按原始记录统计
+    if (values.some((n) => !Number.isInteger(n) || n < 0 || n > 3650) || (usage > 0 && usage < 32)) {
+      toast.error(t("settings.retention.invalid"));
+      return;
+    }
+    setRetentionSaving(true);
+    try {
+      const next = await updateSettings({
+        usage_retention_days: appSettings.usage_retention_days,
+        error_detail_retention_days: appSettings.error_detail_retention_days,
+        channel_failure_retention_days: appSettings.channel_failure_retention_days,
+      });
+      setAppSettings(next);
+      toast.success(t("settings.retention.saved"));
+    } catch (e) {
+      toast.error(t("settings.retention.saveFail"), { description: String(e) });
+    } finally {
+      setRetentionSaving(false);
+    }
+  }
+
+  async function saveSpendSettings() {
+    if (!appSettings) return;
+    setSpendSaving(true);
+    try {
+      const next = await updateSettings({
+        usd_cny_rate: appSettings.usd_cny_rate,
+        spend_warning_percent: appSettings.spend_warning_percent,
+      });
+      setAppSettings(next);
+      toast.success(t("settings.channelProtection.saved"));
+    } catch (e) {
+      toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+    } finally {
+      setSpendSaving(false);
+    }
+  }
+
+  async function refreshCacheStats() {
+    try {
+      setCacheStats(await getCacheStats());
+    } catch {
+      setCacheStats(null);
+    }
+  }
+
+  async function saveCacheSettings(patch: Partial<AppSettings>) {
+    if (!appSettings) return;
+    setCacheSaving(true);
+    try {
+      const next = await updateSettings(patch);
+      setAppSettings(next);
+      toast.success(t("settings.responseCache.saved"));
+    } catch (e) {
+      toast.error(t("settings.responseCache.saveFail"), { description: String(e) });
+    } finally {
+      setCacheSaving(false);
+    }
+  }
+
   async function refreshDbSize() {
     setDbSizeLoading(true);
     try {
@@ -121,6 +275,8 @@ export function SettingsPage() {
       .then((s) => {
         setAppSettings(s);
         setLogRetentionDraft(String(s.log_retention_days ?? ""));
+        setRetryStatusesDraft(s.failover_policy.retry_statuses.join(", "));
+        setFailureStatusesDraft(s.failover_policy.failure_statuses.join(", "));
         setLogLevel(s.log_level);
       })
       .catch(() => setAppSettings(null));
@@ -130,6 +286,7 @@ export function SettingsPage() {
       .catch(() => setUpdateStatus(null));
 
     void refreshDbSize();
+    void refreshCacheStats();
     void refreshLogsSize();
     // eslint-disable-next-line react-hooks/exhaustive-deps
   }, []);
@@ -481,6 +638,286 @@ export function SettingsPage() {
                   {t("common.save")}
                 </Button>
               </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.loadBalance")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.loadBalanceHint")}</div>
+                </div>
+                <div className="w-[220px]">
+                  <Select
+                    value={(appSettings?.load_balance_mode ?? "priority") as LoadBalanceMode}
+                    onValueChange={async (v) => {
+                      if (!appSettings) return;
+                      const prev = appSettings.load_balance_mode;
+                      setAppSettings({ ...appSettings, load_balance_mode: v as LoadBalanceMode });
+                      setLoadBalanceSaving(true);
+                      try {
+                        const next = await updateSettings({ load_balance_mode: v as LoadBalanceMode });
+                        setAppSettings(next);
+                        toast.success(t("settings.channelProtection.saved"));
+                      } catch (e) {
+                        setAppSettings({ ...appSettings, load_balance_mode: prev });
+                        toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+                      } finally {
+                        setLoadBalanceSaving(false);
+                      }
+                    }}
+                    disabled={!appSettings || loadBalanceSaving}
+                  >
+                    <SelectTrigger>
+                      <SelectValue />
+                    </SelectTrigger>
+                    <SelectContent>
+                      <SelectItem value="priority">{t("settings.channelProtection.loadBalanceOptions.priority")}</SelectItem>
+                      <SelectItem value="weighted">{t("settings.channelProtection.loadBalanceOptions.weighted")}</SelectItem>
+                      <SelectItem value="adaptive">{t("settings.channelProtection.loadBalanceOptions.adaptive")}</SelectItem>
+                    </SelectContent>
+                  </Select>
+                </div>
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.hedge")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.hedgeHint")}</div>
+                </div>
+                <div className="flex items-center gap-3">
+                  <Input
+                    type="number"
+                    min={0}
+                    value={appSettings?.hedge_delay_ms ?? 0}
+                    onChange={(e) => {
+                      const n = Math.floor(Number(e.target.value));
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, hedge_delay_ms: Number.isFinite(n) && n >= 0 ? n : 0 } : prev
+                      );
+                    }}
+                    onBlur={async () => {
+                      if (!appSettings) return;
+                      setHedgeSaving(true);
+                      try {
+                        const next = await updateSettings({ hedge_delay_ms: appSettings.hedge_delay_ms });
+                        setAppSettings(next);
+                        toast.success(t("settings.channelProtection.saved"));
+                      } catch (e) {
+                        toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+                      } finally {
+                        setHedgeSaving(false);
+                      }
+                    }}
+                    className="h-8 w-[120px]"
+                    placeholder={t("settings.channelProtection.hedgeDelayPlaceholder")}
+                    disabled={!appSettings || !(appSettings?.hedge_enabled ?? false) || hedgeSaving}
+                  />
+                  <Switch
+                    checked={appSettings?.hedge_enabled ?? false}
+                    onCheckedChange={async (v) => {
+                      if (!appSettings) return;
+                      setAppSettings({ ...appSettings, hedge_enabled: v });
+                      setHedgeSaving(true);
+                      try {
+                        const next = await updateSettings({ hedge_enabled: v });
+                        setAppSettings(next);
+                        toast.success(t("settings.channelProtection.saved"));
+                      } catch (e) {
+                        setAppSettings({ ...appSettings, hedge_enabled: !v });
+                        toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+                      } finally {
+                        setHedgeSaving(false);
+                      }
+                    }}
+                    disabled={!appSettings || hedgeSaving}
+                  />
+                </div>
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.retryStatuses")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.retryStatusesHint")}</div>
+                </div>
+                <Input
+                  value={retryStatusesDraft}
+                  onChange={(e) => setRetryStatusesDraft(e.target.value)}
+                  onBlur={() => void saveFailoverPolicy({ retry_statuses: splitStatuses(retryStatusesDraft) })}
+                  className="h-8 w-[220px]"
+                  placeholder="429, 5xx"
+                  disabled={!appSettings || failoverSaving}
+                />
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.failureStatuses")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.failureStatusesHint")}</div>
+                </div>
+                <Input
+                  value={failureStatusesDraft}
+                  onChange={(e) => setFailureStatusesDraft(e.target.value)}
+                  onBlur={() => void saveFailoverPolicy({ failure_statuses: splitStatuses(failureStatusesDraft) })}
+                  className="h-8 w-[220px]"
+                  placeholder="429, 5xx"
+                  disabled={!appSettings || failoverSaving}
+                />
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.honorRetryAfter")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.honorRetryAfterHint")}</div>
+                </div>
+                <Switch
+                  checked={appSettings?.failover_policy.honor_retry_after ?? true}
+                  onCheckedChange={(v) => void saveFailoverPolicy({ honor_retry_after: v })}
+                  disabled={!appSettings || failoverSaving}
+                />
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.streamFailover")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.streamFailoverHint")}</div>
+                </div>
+                <div className="flex items-center gap-3">
+                  <Input
+                    type="number"
+                    min={1000}
+                    value={appSettings?.stream_first_content_timeout_ms ?? 30000}
+                    onChange={(e) => {
+                      const n = Math.floor(Number(e.target.value));
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, stream_first_content_timeout_ms: Number.isFinite(n) ? n : 0 } : prev
+                      );
+                    }}
+                    onBlur={async () => {
+                      if (!appSettings) return;
+                      setStreamFailoverSaving(true);
+                      try {
+                        const next = await updateSettings({
+                          stream_first_content_timeout_ms: appSettings.stream_first_content_timeout_ms,
+                        });
+                        setAppSettings(next);
+                        toast.success(t("settings.channelProtection.saved"));
+                      } catch (e) {
+                        toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+                      } finally {
+                        setStreamFailoverSaving(false);
+                      }
+                    }}
+                    className="h-8 w-[120px]"
+                    placeholder={t("settings.channelProtection.streamFirstContentTimeoutPlaceholder")}
+                    disabled={!appSettings || !(appSettings?.stream_failover_enabled ?? true) || streamFailoverSaving}
+                  />
+                  <Switch
+                    checked={appSettings?.stream_failover_enabled ?? true}
+                    onCheckedChange={async (v) => {
+                      if (!appSettings) return;
+                      setAppSettings({ ...appSettings, stream_failover_enabled: v });
+                      setStreamFailoverSaving(true);
+                      try {
+                        const next = await updateSettings({ stream_failover_enabled: v });
+                        setAppSettings(next);
+                        toast.success(t("settings.channelProtection.saved"));
+                      } catch (e) {
+                        setAppSettings({ ...appSettings, stream_failover_enabled: !v });
+                        toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+                      } finally {
+                        setStreamFailoverSaving(false);
+                      }
+                    }}
+                    disabled={!appSettings || streamFailoverSaving}
+                  />
+                </div>
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.timeouts")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.timeoutsHint")}</div>
+                </div>
+                <div className="flex items-center gap-2">
+                  {(["connect_ms", "ttfb_ms", "idle_ms", "total_ms"] as (keyof TimeoutPolicy)[]).map((k) => (
+                    <Input
+                      key={k}
+                      type="number"
+                      min={0}
+                      title={t(`settings.channelProtection.timeoutFields.${k}`)}
+                      value={appSettings?.timeouts[k] ?? 0}
+                      onChange={(e) => {
+                        const n = Math.floor(Number(e.target.value));
+                        setAppSettings((prev) =>
+                          prev
+                            ? { ...prev, timeouts: { ...prev.timeouts, [k]: Number.isFinite(n) && n >= 0 ? n : 0 } }
+                            : prev
+                        );
+                      }}
+                      onBlur={() => void saveTimeouts()}
+                      className="h-8 w-[96px]"
+                      placeholder={t(`settings.channelProtection.timeoutFields.${k}`)}
+                      disabled={!appSettings || timeoutsSaving}
+                    />
+                  ))}
+                </div>
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.proxyUrl")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.proxyUrlHint")}</div>
+                </div>
+                <Input
+                  value={appSettings?.proxy_url ?? ""}
+                  onChange={(e) => setAppSettings((prev) => (prev ? { ...prev, proxy_url: e.target.value } : prev))}
+                  onBlur={() => void saveProxyUrl()}
+                  className="h-8 w-[260px]"
+                  placeholder="socks5h://127.0.0.1:1080"
+                  disabled={!appSettings || proxyUrlSaving}
+                />
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.channelProtection.spend")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.channelProtection.spendHint")}</div>
+                </div>
+                <div className="flex items-center gap-2">
+                  <Input
+                    type="number"
+                    min={0}
+                    step="0.01"
+                    title={t("settings.channelProtection.usdCnyRate")}
+                    value={appSettings?.usd_cny_rate ?? 7.2}
+                    onChange={(e) => {
+                      const n = Number(e.target.value);
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, usd_cny_rate: Number.isFinite(n) ? n : prev.usd_cny_rate } : prev
+                      );
+                    }}
+                    onBlur={() => void saveSpendSettings()}
+                    className="h-8 w-[96px]"
+                    placeholder={t("settings.channelProtection.usdCnyRate")}
+                    disabled={!appSettings || spendSaving}
+                  />
+                  <Input
+                    type="number"
+                    min={1}
+                    max={100}
+                    title={t("settings.channelProtection.spendWarningPercent")}
+                    value={appSettings?.spend_warning_percent ?? 80}
+                    onChange={(e) => {
+                      const n = Math.floor(Number(e.target.value));
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, spend_warning_percent: Number.isFinite(n) ? n : 0 } : prev
+                      );
+                    }}
+                    onBlur={() => void saveSpendSettings()}
+                    className="h-8 w-[96px]"
+                    placeholder={t("settings.channelProtection.spendWarningPercent")}
+                    disabled={!appSettings || spendSaving}
+                  />
+                </div>
+              </div>
             </CardContent>
           </Card>
 
@@ -942,6 +1379,116 @@ export function SettingsPage() {
                     {t("common.refresh")}
                   </Button>
                 </div>
+                {dbSize && dbSize.tables.length > 0 ? (
+                  <div className="rounded-md border text-xs">
+                    {dbSize.tables.map((tb) => (
+                      <div key={tb.name} className="flex items-center justify-between gap-4 px-3 py-1.5 border-b last:border-b-0">
+                        <span className="font-mono">{tb.name}</span>
+                        <span className="text-muted-foreground">
+                          {t("settings.retention.tableDetail", { rows: tb.rows, size: formatBytes(tb.bytes) })}
+                        </span>
+                      </div>
+                    ))}
+                    {dbSize.free_bytes > 0 ? (
+                      <div className="px-3 py-1.5 text-muted-foreground">
+                        {t("settings.retention.freeBytes", { size: formatBytes(dbSize.free_bytes) })}
+                      </div>
+                    ) : null}
+                  </div>
+                ) : null}
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.retention.compact")}</div>
+                  <div className="text-xs text-muted-foreground">
+                    {dbSize && !dbSize.incremental_vacuum ? t("settings.retention.compactLegacyHint") : t("settings.retention.compactHint")}
+                  </div>
+                </div>
+                <Button
+                  variant="outline"
+                  size="sm"
+                  onClick={async () => {
+                    setDbCompacting(true);
+                    try {
+                      const res = await compactDb();
+                      toast.success(
+                        t("settings.retention.compacted", {
+                          before: formatBytes(res.before_bytes),
+                          after: formatBytes(res.after_bytes),
+                        }),
+                      );
+                      await refreshDbSize();
+                    } catch (e) {
+                      toast.error(t("settings.retention.compactFail"), { description: String(e) });
+                    } finally {
+                      setDbCompacting(false);
+                    }
+                  }}
+                  disabled={dbCompacting}
+                >
+                  {t("settings.retention.compact")}
+                </Button>
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.retention.title")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.retention.hint")}</div>
+                </div>
+                <div className="flex items-center gap-2">
+                  <Input
+                    type="number"
+                    min={0}
+                    max={3650}
+                    title={t("settings.retention.usage")}
+                    value={appSettings?.usage_retention_days ?? 0}
+                    onChange={(e) => {
+                      const n = Number(e.target.value);
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, usage_retention_days: Number.isFinite(n) ? n : prev.usage_retention_days } : prev
+                      );
+                    }}
+                    onBlur={() => void saveRetentionSettings()}
+                    className="h-8 w-[96px]"
+                    placeholder={t("settings.retention.usage")}
+                    disabled={!appSettings || retentionSaving}
+                  />
+                  <Input
+                    type="number"
+                    min={0}
+                    max={3650}
+                    title={t("settings.retention.errorDetail")}
+                    value={appSettings?.error_detail_retention_days ?? 0}
+                    onChange={(e) => {
+                      const n = Number(e.target.value);
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, error_detail_retention_days: Number.isFinite(n) ? n : prev.error_detail_retention_days } : prev
+                      );
+                    }}
+                    onBlur={() => void saveRetentionSettings()}
+                    className="h-8 w-[96px]"
+                    placeholder={t("settings.retention.errorDetail")}
+                    disabled={!appSettings || retentionSaving}
+                  />
+                  <Input
+                    type="number"
+                    min={0}
+                    max={3650}
+                    title={t("settings.retention.channelFailures")}
+                    value={appSettings?.channel_failure_retention_days ?? 0}
+                    onChange={(e) => {
+                      const n = Number(e.target.value);
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, channel_failure_retention_days: Number.isFinite(n) ? n : prev.channel_failure_retention_days } : prev
+                      );
+                    }}
+                    onBlur={() => void saveRetentionSettings()}
+                    className="h-8 w-[96px]"
+                    placeholder={t("settings.retention.channelFailures")}
+                    disabled={!appSettings || retentionSaving}
+                  />
+                </div>
               </div>
 
               <Dialog
@@ -1053,6 +1600,110 @@ export function SettingsPage() {
             </CardContent>
           </Card>
 
+          {/* 响应缓存 */}
+          <Card>
+            <CardHeader>
+              <CardTitle className="flex items-center gap-2">
+                <Zap className="h-4 w-4" />
+                {t("settings.responseCache.title")}
+              </CardTitle>
+              <CardDescription>{t("settings.responseCache.subtitle")}</CardDescription>
+            </CardHeader>
+            <CardContent className="space-y-4">
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.responseCache.enabled")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.responseCache.enabledHint")}</div>
+                </div>
+                <Switch
+                  checked={appSettings?.response_cache_enabled ?? false}
+                  onCheckedChange={(v) => void saveCacheSettings({ response_cache_enabled: v })}
+                  disabled={!appSettings || cacheSaving}
+                />
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.responseCache.limits")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.responseCache.limitsHint")}</div>
+                </div>
+                <div className="flex items-center gap-2">
+                  <Input
+                    type="number"
+                    min={1}
+                    value={appSettings?.response_cache_ttl_secs ?? 3600}
+                    onChange={(e) => {
+                      const n = Math.floor(Number(e.target.value));
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, response_cache_ttl_secs: Number.isFinite(n) ? n : 0 } : prev
+                      );
+                    }}
+                    onBlur={() =>
+                      void saveCacheSettings({ response_cache_ttl_secs: appSettings?.response_cache_ttl_secs })
+                    }
+                    className="h-8 w-[120px]"
+                    placeholder={t("settings.responseCache.ttlPlaceholder")}
+                    disabled={!appSettings || !(appSettings?.response_cache_enabled ?? false) || cacheSaving}
+                  />
+                  <Input
+                    type="number"
+                    min={1}
+                    value={appSettings?.response_cache_max_mb ?? 256}
+                    onChange={(e) => {
+                      const n = Math.floor(Number(e.target.value));
+                      setAppSettings((prev) =>
+                        prev ? { ...prev, response_cache_max_mb: Number.isFinite(n) ? n : 0 } : prev
+                      );
+                    }}
+                    onBlur={() => void saveCacheSettings({ response_cache_max_mb: appSettings?.response_cache_max_mb })}
+                    className="h-8 w-[120px]"
+                    placeholder={t("settings.responseCache.maxMbPlaceholder")}
+                    disabled={!appSettings || !(appSettings?.response_cache_enabled ?? false) || cacheSaving}
+                  />
+                </div>
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.responseCache.usage")}</div>
+                  <div className="text-xs text-muted-foreground">
+                    {cacheStats
+                      ? t("settings.responseCache.usageDetail", {
+                          entries: cacheStats.entries.toLocaleString(),
+                          size: formatBytes(cacheStats.size_bytes),
+                          hits: cacheStats.hits.toLocaleString(),
+                        })
+                      : "-"}
+                  </div>
+                </div>
+                <div className="flex items-center gap-2">
+                  <Button variant="outline" size="sm" onClick={() => void refreshCacheStats()}>
+                    {t("common.refresh")}
+                  </Button>
+                  <Button
+                    variant="destructive"
+                    size="sm"
+                    onClick={async () => {
+                      setCacheClearing(true);
+                      try {
+                        const res = await clearCache();
+                        toast.success(t("settings.responseCache.cleared", { count: res.deleted }));
+                        await refreshCacheStats();
+                      } catch (e) {
+                        toast.error(t("settings.responseCache.clearFail"), { description: String(e) });
+                      } finally {
+                        setCacheClearing(false);
+                      }
+                    }}
+                    disabled={cacheClearing || !cacheStats?.entries}
+                  >
+                    {t("settings.records.clear")}
+                  </Button>
+                </div>
+              </div>
+            </CardContent>
+          </Card>
+
           {/* 系统日志 */}
           <Card>
             <CardHeader>
@@ -1102,6 +1753,70 @@ export function SettingsPage() {
                 </Select>
               </div>
 
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.logging.capture")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.logging.captureHint")}</div>
+                </div>
+                <Switch
+                  checked={appSettings?.capture_enabled ?? false}
+                  onCheckedChange={async (v) => {
+                    if (!appSettings) return;
+                    setAppSettings({ ...appSettings, capture_enabled: v });
+                    setLogSaving(true);
+                    try {
+                      const next = await updateSettings({ capture_enabled: v });
+                      setAppSettings(next);
+                      toast.success(t("settings.logging.saved"));
+                    } catch (e) {
+                      setAppSettings({ ...appSettings, capture_enabled: !v });
+                      toast.error(t("settings.logging.saveFail"), { description: String(e) });
+                    } finally {
+                      setLogSaving(false);
+                    }
+                  }}
+                  disabled={!appSettings || logSaving}
+                />
+              </div>
+
+              <div className="flex items-center justify-between gap-4">
+                <div>
+                  <div className="font-medium text-sm">{t("settings.logging.otlp")}</div>
+                  <div className="text-xs text-muted-foreground">{t("settings.logging.otlpHint")}</div>
+                </div>
+                <div className="flex items-center gap-2">
+                  <Input
+                    value={appSettings?.otlp_endpoint ?? ""}
+                    onChange={(e) =>
+                      setAppSettings((prev) => (prev ? { ...prev, otlp_endpoint: e.target.value } : prev))
+                    }
+                    onBlur={() => void saveOtlpEndpoint()}
+                    className="h-8 w-[260px]"
+                    placeholder="http://127.0.0.1:4318/v1/traces"
+                    disabled={!appSettings || logSaving}
+                  />
+                  <Switch
+                    checked={appSettings?.otlp_enabled ?? false}
+                    onCheckedChange={async (v) => {
+                      if (!appSettings) return;
+                      setAppSettings({ ...appSettings, otlp_enabled: v });
+                      setLogSaving(true);
+                      try {
+                        const next = await updateSettings({ otlp_enabled: v });
+                        setAppSettings(next);
+                        toast.success(t("settings.logging.saved"));
+                      } catch (e) {
+                        setAppSettings({ ...appSettings, otlp_enabled: !v });
+                        toast.error(t("settings.logging.saveFail"), { description: String(e) });
+                      } finally {
+                        setLogSaving(false);
+                      }
+                    }}
+                    disabled={!appSettings || logSaving}
+                  />
+                </div>
+              </div>
+
               <div className="flex items-center justify-between gap-4">
                 <div>
                   <div className="font-medium text-sm">{t("settings.logging.retentionDays")}</div>
@@ -1319,6 +2034,9 @@ export function SettingsPage() {
             </CardContent>
           </Card>
 
+          {/* 客户端访问令牌 */}
+          <ClientAccessCard appSettings={appSettings} setAppSettings={setAppSettings} />
+
           {/* 版本信息（原关于） */}
           <Card>
             <CardHeader>
@@ -1366,3 +2084,321 @@ export function SettingsPage() {
     </div>
   );
 }
+
+const TOKEN_PROTOCOLS: Protocol[] = ["openai", "anthropic", "gemini"];
+
+function ClientAccessCard({
+  appSettings,
+  setAppSettings,
+}: {
+  appSettings: AppSettings | null;
+  setAppSettings: React.Dispatch<React.SetStateAction<AppSettings | null>>;
+}) {
+  const { t } = useI18n();
+  const [tokens, setTokens] = useState<ClientTokenItem[]>([]);
+  const [authSaving, setAuthSaving] = useState(false);
+  const [browserToken, setBrowserToken] = useState(() => getClientToken());
+  const [name, setName] = useState("");
+  const [role, setRole] = useState<ClientTokenRole>("proxy");
+  const [protocols, setProtocols] = useState<Protocol[]>([]);
+  const [expiresDays, setExpiresDays] = useState("");
+  const [rpm, setRpm] = useState("");
+  const [tokensPerDay, setTokensPerDay] = useState("");
+  const [usdPerMonth, setUsdPerMonth] = useState("");
+  const [creating, setCreating] = useState(false);
+  const [created, setCreated] = useState<string | null>(null);
+
+  async function refresh() {
+    try {
+      setTokens(await listClientTokens());
+    } catch (e) {
+      toast.error(t("clientTokens.loadFail"), { description: String(e) });
+    }
+  }
+
+  useEffect(() => {
+    void refresh();
+    // eslint-disable-next-line react-hooks/exhaustive-deps
+  }, []);
+
+  async function saveAuthEnabled(v: boolean) {
+    setAuthSaving(true);
+    try {
+      const next = await updateSettings({ client_auth_enabled: v });
+      setAppSettings(next);
+      toast.success(t("settings.channelProtection.saved"));
+    } catch (e) {
+      toast.error(t("settings.channelProtection.saveFail"), { description: String(e) });
+    } finally {
+      setAuthSaving(false);
+    }
+  }
+
+  async function create() {
+    if (!name.trim()) {
+      toast.error(t("clientTokens.nameRequired"));
+      return;
+    }
+    const days = Number(expiresDays);
+    // 空或非正数视为不限制
+    const limit = (v: string) => {
+      const n = Number(v);
+      return v.trim() && Number.isFinite(n) && n > 0 ? n : null;
+    };
+    const wholeLimit = (v: string) => {
+      const n = limit(v);
+      return n === null ? null : Math.max(1, Math.floor(n));
+    };
+    setCreating(true);
+    try {
+      const res = await createClientToken({
+        name: name.trim(),
+        role,
+        protocols,
+        route_ids: [],
+        expires_at_ms: expiresDays.trim() && days > 0 ? Date.now() + days * 86_400_000 : null,
+        requests_per_minute: wholeLimit(rpm),
+        tokens_per_day: wholeLimit(tokensPerDay),
+        usd_per_month: limit(usdPerMonth),
+      });
+      // 第一个管理员令牌直接保存到本机，开启鉴权后界面仍可访问
+      if (res.role === "admin" && !getClientToken()) {
+        setClientToken(res.token);
+        setBrowserToken(res.token);
+      }
+      setCreated(res.token);
+      setName("");
+      setExpiresDays("");
+      setRpm("");
+      setTokensPerDay("");
+      setUsdPerMonth("");
+      await refresh();
+    } catch (e) {
+      toast.error(t("clientTokens.createFail"), { description: String(e) });
+    } finally {
+      setCreating(false);
+    }
+  }
+
+  async function toggle(token: ClientToken, enabled: boolean) {
+    try {
+      await updateClientToken(token.id, { enabled });
+      await refresh();
+    } catch (e) {
+      toast.error(t("clientTokens.updateFail"), { description: String(e) });
+    }
+  }
+
+  async function remove(token: ClientToken) {
+    try {
+      await deleteClientToken(token.id);
+      await refresh();
+    } catch (e) {
+      toast.error(t("clientTokens.deleteFail"), { description: String(e) });
+    }
+  }
+
+  return (
+    <Card>
+      <CardHeader>
+        <CardTitle className="flex items-center gap-2">
+          <KeyRound className="h-4 w-4" />
+          {t("clientTokens.title")}
+        </CardTitle>
+        <CardDescription>{t("clientTokens.subtitle")}</CardDescription>
+      </CardHeader>
+      <CardContent className="space-y-4">
+        <div className="flex items-center justify-between gap-4">
+          <div>
+            <div className="font-medium text-sm">{t("clientTokens.enable")}</div>
+            <div className="text-xs text-muted-foreground">{t("clientTokens.enableHint")}</div>
+          </div>
+          <Switch
+            checked={appSettings?.client_auth_enabled ?? false}
+            onCheckedChange={(v) => void saveAuthEnabled(v)}
+            disabled={!appSettings || authSaving}
+          />
+        </div>
+
+        <div className="flex items-center justify-between gap-4">
+          <div>
+            <div className="font-medium text-sm">{t("clientTokens.browserToken")}</div>
+            <div className="text-xs text-muted-foreground">{t("clientTokens.browserTokenHint")}</div>
+          </div>
+          <Input
+            type="password"
+            value={browserToken}
+            onChange={(e) => setBrowserToken(e.target.value)}
+            onBlur={() => setClientToken(browserToken)}
+            className="h-8 w-[260px] font-mono"
+            placeholder="csk-..."
+          />
+        </div>
+
+        <div className="flex flex-wrap items-center gap-2">
+          <Input
+            value={name}
+            onChange={(e) => setName(e.target.value)}
+            className="h-8 w-[160px]"
+            placeholder={t("clientTokens.namePlaceholder")}
+          />
+          <Select value={role} onValueChange={(v) => setRole(v as ClientTokenRole)}>
+            <SelectTrigger className="h-8 w-[130px]">
+              <SelectValue />
+            </SelectTrigger>
+            <SelectContent>
+              <SelectItem value="proxy">{t("clientTokens.roles.proxy")}</SelectItem>
+              <SelectItem value="read_only">{t("clientTokens.roles.read_only")}</SelectItem>
+              <SelectItem value="admin">{t("clientTokens.roles.admin")}</SelectItem>
+            </SelectContent>
+          </Select>
+          {TOKEN_PROTOCOLS.map((p) => (
+            <Button
+              key={p}
+              size="sm"
+              variant={protocols.includes(p) ? "default" : "outline"}
+              onClick={() =>
+                setProtocols((prev) => (prev.includes(p) ? prev.filter((x) => x !== p) : [...prev, p]))
+              }
+            >
+              {p}
+            </Button>
+          ))}
+          <Input
+            type="number"
+            min={1}
+            value={expiresDays}
+            onChange={(e) => setExpiresDays(e.target.value)}
+            className="h-8 w-[120px]"
+            placeholder={t("clientTokens.expiresDaysPlaceholder")}
+          />
+          <Input
+            type="number"
+            min={1}
+            value={rpm}
+            onChange={(e) => setRpm(e.target.value)}
+            className="h-8 w-[110px]"
+            placeholder={t("clientTokens.rpmPlaceholder")}
+          />
+          <Input
+            type="number"
+            min={1}
+            value={tokensPerDay}
+            onChange={(e) => setTokensPerDay(e.target.value)}
+            className="h-8 w-[130px]"
+            placeholder={t("clientTokens.tokensPerDayPlaceholder")}
+          />
+          <Input
+            type="number"
+            min={0}
+            step="0.01"
+            value={usdPerMonth}
+            onChange={(e) => setUsdPerMonth(e.target.value)}
+            className="h-8 w-[130px]"
+            placeholder={t("clientTokens.usdPerMonthPlaceholder")}
+          />
+          <Button size="sm" onClick={() => void create()} disabled={creating}>
+            {t("clientTokens.create")}
+          </Button>
+        </div>
+        <p className="text-xs text-muted-foreground">{t("clientTokens.protocolsHint")}</p>
+        <p className="text-xs text-muted-foreground">{t("clientTokens.quotaHint")}</p>
+
+        {tokens.length > 0 && (
+          <Table>
+            <TableHeader>
+              <TableRow>
+                <TableHead>{t("clientTokens.columns.name")}</TableHead>
+                <TableHead>{t("clientTokens.columns.token")}</TableHead>
+                <TableHead>{t("clientTokens.columns.role")}</TableHead>
+                <TableHead>{t("clientTokens.columns.protocols")}</TableHead>
+                <TableHead>{t("clientTokens.columns.quota")}</TableHead>
+                <TableHead>{t("clientTokens.columns.expires")}</TableHead>
+                <TableHead>{t("clientTokens.columns.lastUsed")}</TableHead>
+                <TableHead className="w-16">{t("clientTokens.columns.enabled")}</TableHead>
+                <TableHead className="w-12" />
+              </TableRow>
+            </TableHeader>
+            <TableBody>
+              {tokens.map((tk) => (
+                <TableRow key={tk.id}>
+                  <TableCell>{tk.name}</TableCell>
+                  <TableCell className="font-mono text-xs">{tk.masked}</TableCell>
+                  <TableCell>
+                    <Badge variant="outline">{t(`clientTokens.roles.${tk.role}`)}</Badge>
+                  </TableCell>
+                  <TableCell className="text-xs">
+                    {tk.protocols.length ? tk.protocols.join(", ") : t("clientTokens.allProtocols")}
+                  </TableCell>
+                  <TableCell className="text-xs">
+                    {tk.requests_per_minute || tk.tokens_per_day || tk.usd_per_month ? (
+                      <div className="space-y-0.5">
+                        {tk.requests_per_minute ? (
+                          <div>{t("clientTokens.quota.rpm", { limit: tk.requests_per_minute })}</div>
+                        ) : null}
+                        {tk.tokens_per_day ? (
+                          <div>
+                            {t("clientTokens.quota.tokensPerDay", {
+                              used: tk.usage.tokens_today,
+                              limit: tk.tokens_per_day,
+                            })}
+                          </div>
+                        ) : null}
+                        {tk.usd_per_month ? (
+                          <div>
+                            {t("clientTokens.quota.usdPerMonth", {
+                              used: tk.usage.cost_usd_month.toFixed(2),
+                              limit: tk.usd_per_month,
+                            })}
+                          </div>
+                        ) : null}
+                      </div>
+                    ) : (
+                      t("clientTokens.unlimited")
+                    )}
+                  </TableCell>
+                  <TableCell className="text-xs">
+                    {tk.expires_at_ms ? formatDateTime(tk.expires_at_ms) : t("clientTokens.never")}
+                  </TableCell>
+                  <TableCell className="text-xs">
+                    {tk.last_used_ms ? formatDateTime(tk.last_used_ms) : "-"}
+                  </TableCell>
+                  <TableCell>
+                    <Switch checked={tk.enabled} onCheckedChange={(v) => void toggle(tk, v)} />
+                  </TableCell>
+                  <TableCell>
+                    <Button variant="ghost" size="sm" onClick={() => void remove(tk)}>
+                      <Trash2 className="h-4 w-4" />
+                    </Button>
+                  </TableCell>
+                </TableRow>
+              ))}
+            </TableBody>
+          </Table>
+        )}
+
+        <Dialog open={created !== null} onOpenChange={(v) => !v && setCreated(null)}>
+          <DialogContent className="sm:max-w-[520px]">
+            <DialogHeader>
+              <DialogTitle>{t("clientTokens.createdTitle")}</DialogTitle>
+              <DialogDescription>{t("clientTokens.createdHint")}</DialogDescription>
+            </DialogHeader>
+            <Input value={created ?? ""} readOnly className="font-mono text-sm" onFocus={(e) => e.target.select()} />
+            <DialogFooter>
+              <Button
+                variant="outline"
+                onClick={() => {
+                  void navigator.clipboard?.writeText(created ?? "");
+                  toast.success(t("clientTokens.copied"));
+                }}
+              >
+                {t("clientTokens.copy")}
+              </Button>
+              <Button onClick={() => setCreated(null)}>{t("common.ok")}</Button>
+            </DialogFooter>
+          </DialogContent>
+        </Dialog>
+      </CardContent>
+    </Card>
+  );
+}